@@ -0,0 +1,94 @@
+use rusqlite::{params, Connection};
+
+// A number scraped from the overcast.fm account page.
+pub struct AccountStat {
+    pub name: &'static str,
+    pub value: f64,
+    // The text the number was found in, kept in case the page's wording changes.
+    pub text: String,
+}
+
+// Stats are recognized by words near a duration, e.g. "Smart Speed has saved you 42 hours".
+const STATS: [(&str, &str); 2] = [
+    ("smart_speed_saved_seconds", "smart speed"),
+    ("listening_seconds", "listen"),
+];
+
+// Finds the durations on the account page, converted to seconds.
+pub fn parse_account_page(html: &str) -> Vec<AccountStat> {
+    let text = html_text(html);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut stats: Vec<AccountStat> = Vec::new();
+    for (i, pair) in words.windows(2).enumerate() {
+        let value = match pair[0].replace(',', "").parse::<f64>() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        let unit = pair[1]
+            .trim_end_matches(|c: char| !c.is_alphabetic())
+            .to_lowercase();
+        let multiplier = match unit.trim_end_matches('s') {
+            "second" | "sec" => 1.0,
+            "minute" | "min" => 60.0,
+            "hour" | "hr" => 3600.0,
+            "day" => 86400.0,
+            _ => continue,
+        };
+        let context = words[i.saturating_sub(8)..(i + 10).min(words.len())].join(" ");
+        let lowercase = context.to_lowercase();
+        for (name, marker) in STATS.iter() {
+            if lowercase.contains(marker) && !stats.iter().any(|s| s.name == *name) {
+                stats.push(AccountStat {
+                    name,
+                    value: value * multiplier,
+                    text: context.clone(),
+                });
+                break;
+            }
+        }
+    }
+    stats
+}
+
+// Strips tags (and the contents of scripts and styles) from HTML, leaving its text.
+fn html_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        text.push(' ');
+        rest = &rest[start..];
+        let lowercase = rest.get(..8).unwrap_or(rest).to_lowercase();
+        let close = if lowercase.starts_with("<script") {
+            "</script>"
+        } else if lowercase.starts_with("<style") {
+            "</style>"
+        } else {
+            ">"
+        };
+        rest = match rest.find(close) {
+            Some(end) => &rest[end + close.len()..],
+            None => "",
+        };
+    }
+    text.push_str(rest);
+    text.replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .replace("&#39;", "'")
+        .replace("&rsquo;", "'")
+}
+
+// Records a snapshot of the account stats, so their history builds up over runs.
+pub fn record(
+    conn: &Connection,
+    run_at: chrono::NaiveDateTime,
+    stats: &[AccountStat],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for stat in stats {
+        conn.execute(
+            "INSERT OR REPLACE INTO account_stats(run_at, name, value, text) VALUES (?, ?, ?, ?)",
+            params![run_at, stat.name, stat.value, stat.text],
+        )?;
+    }
+    Ok(())
+}
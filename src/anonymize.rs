@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::storage::hex;
+
+// The anonymized dataset's tables. Only dates are kept, not times of day.
+const SCHEMA: &str = "
+    CREATE TABLE feeds (
+        id TEXT PRIMARY KEY, -- a salted hash of the Overcast ID
+        subscribed INTEGER,
+        added_on TEXT -- YYYY-MM-DD
+    );
+    CREATE TABLE feed_categories (
+        feed_id TEXT NOT NULL REFERENCES feeds(id),
+        category TEXT NOT NULL,
+        PRIMARY KEY(feed_id, category)
+    );
+    CREATE TABLE episodes (
+        id TEXT PRIMARY KEY, -- a salted hash of the Overcast ID
+        feed_id TEXT NOT NULL REFERENCES feeds(id),
+        published_on TEXT, -- YYYY-MM-DD
+        played INTEGER,
+        user_deleted INTEGER,
+        starred INTEGER,
+        progress INTEGER, -- seconds
+        duration INTEGER -- seconds
+    );
+    CREATE TABLE play_events (
+        episode_id TEXT NOT NULL REFERENCES episodes(id),
+        played_on TEXT NOT NULL -- YYYY-MM-DD
+    );
+";
+
+// Counts of an anonymized export.
+pub struct AnonymizeCounts {
+    pub feeds: usize,
+    pub episodes: usize,
+    pub play_events: usize,
+}
+
+// Hashes IDs with a random salt that's thrown away after the export, so they can't be matched
+// back to Overcast's (fairly guessable) IDs, or between two exports.
+struct Hasher {
+    salt: [u8; 16],
+    hashed: HashMap<i64, String>,
+}
+
+impl Hasher {
+    fn new() -> Result<Hasher, Box<dyn std::error::Error>> {
+        let mut salt = [0u8; 16];
+        openssl::rand::rand_bytes(&mut salt)?;
+        Ok(Hasher {
+            salt,
+            hashed: HashMap::new(),
+        })
+    }
+
+    fn hash(&mut self, id: i64) -> String {
+        let salt = self.salt;
+        self.hashed
+            .entry(id)
+            .or_insert_with(|| {
+                let input = [&salt[..], id.to_string().as_bytes()].concat();
+                hex(&openssl::sha::sha256(&input)[..8])
+            })
+            .clone()
+    }
+}
+
+// Writes a dataset of listening behavior to a new SQLite database at `output`, for sharing: which
+// episodes of which feeds were played, deleted or starred, how far through them you got and on
+// what day. Titles, URLs, notes, tags, settings and anything about the account are left out, IDs
+// are replaced by salted hashes, and timestamps are cut down to dates. Feed categories are kept,
+// as what the feeds are about.
+pub fn export(
+    conn: &Connection,
+    output: &Path,
+) -> Result<AnonymizeCounts, Box<dyn std::error::Error>> {
+    if output.exists() {
+        return Err(format!("{} already exists.", output.display()).into());
+    }
+    let out = Connection::open(output)?;
+    out.execute_batch(SCHEMA)?;
+    let tx = out.unchecked_transaction()?;
+    let mut feed_ids = Hasher::new()?;
+    let mut episode_ids = Hasher::new()?;
+
+    let mut stmt = conn.prepare("SELECT id, subscribed, date(added_at) FROM feeds")?;
+    let feeds = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<bool>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (id, subscribed, added_on) in &feeds {
+        out.execute(
+            "INSERT INTO feeds(id, subscribed, added_on) VALUES (?, ?, ?)",
+            params![feed_ids.hash(*id), subscribed, added_on],
+        )?;
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT fc.feed_id, c.name FROM feed_categories fc
+        JOIN categories c ON c.id = fc.category_id
+        WHERE fc.feed_id IN (SELECT id FROM feeds)",
+    )?;
+    let categories = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (feed_id, category) in categories {
+        out.execute(
+            "INSERT OR IGNORE INTO feed_categories(feed_id, category) VALUES (?, ?)",
+            params![feed_ids.hash(feed_id), category],
+        )?;
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.feed_id, date(e.published_at), e.played, e.user_deleted, e.starred,
+            e.progress, COALESCE(a.duration, d.duration)
+        FROM episodes e
+        LEFT JOIN episode_details d ON d.episode_id = e.id
+        LEFT JOIN audio_metadata a ON a.episode_id = e.id
+        WHERE e.feed_id IN (SELECT id FROM feeds)",
+    )?;
+    let mut episodes = 0;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        out.execute(
+            "INSERT INTO episodes(
+                id, feed_id, published_on, played, user_deleted, starred, progress, duration
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                episode_ids.hash(row.get(0)?),
+                feed_ids.hash(row.get(1)?),
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<bool>>(3)?,
+                row.get::<_, Option<bool>>(4)?,
+                row.get::<_, Option<bool>>(5)?,
+                row.get::<_, Option<i64>>(6)?,
+                row.get::<_, Option<i64>>(7)?,
+            ],
+        )?;
+        episodes += 1;
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT p.episode_id, date(p.played_at) FROM play_events p
+        JOIN episodes e ON e.id = p.episode_id
+        WHERE e.feed_id IN (SELECT id FROM feeds)
+        ORDER BY p.played_at",
+    )?;
+    let mut play_events = 0;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        out.execute(
+            "INSERT INTO play_events(episode_id, played_on) VALUES (?, ?)",
+            params![episode_ids.hash(row.get(0)?), row.get::<_, String>(1)?],
+        )?;
+        play_events += 1;
+    }
+    tx.commit()?;
+    Ok(AnonymizeCounts {
+        feeds: feeds.len(),
+        episodes,
+        play_events,
+    })
+}
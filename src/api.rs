@@ -0,0 +1,342 @@
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde_json::{json, Value};
+
+use crate::http::{Request, Response};
+
+// Pages are this long unless asked otherwise, and never longer than MAX_LIMIT.
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 500;
+
+// Which feeds to list.
+#[derive(Default)]
+pub struct FeedFilter {
+    pub subscribed: Option<bool>,
+    // Matched against the title, case-insensitively.
+    pub search: Option<String>,
+}
+
+// Which episodes to list. Dates are compared with the stored UTC timestamps, so "2024-01-01"
+// works as well as "2024-01-01 12:00:00".
+#[derive(Default)]
+pub struct EpisodeFilter {
+    pub feed_id: Option<i64>,
+    pub played: Option<bool>,
+    pub starred: Option<bool>,
+    // Matched against the title and description, case-insensitively.
+    pub search: Option<String>,
+    pub published_after: Option<String>,
+    pub published_before: Option<String>,
+}
+
+pub struct Page {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Page {
+    pub fn new(limit: Option<i64>, offset: Option<i64>) -> Page {
+        Page {
+            limit: limit.unwrap_or(DEFAULT_LIMIT).clamp(0, MAX_LIMIT),
+            offset: offset.unwrap_or(0).max(0),
+        }
+    }
+}
+
+// One page of feeds or episodes, and how many there are in all.
+pub struct Listing {
+    pub total: i64,
+    pub items: Vec<Value>,
+}
+
+impl Listing {
+    pub fn has_next_page(&self, page: &Page) -> bool {
+        page.offset + (self.items.len() as i64) < self.total
+    }
+}
+
+const FEED_COLUMNS: &str = "f.id, f.title, f.subscribed, f.feed_url, f.html_url, f.added_at,
+    (SELECT COUNT(*) FROM episodes WHERE feed_id = f.id),
+    (SELECT COUNT(*) FROM episodes WHERE feed_id = f.id AND NOT played AND NOT user_deleted)";
+
+fn feed_json(row: &Row) -> rusqlite::Result<Value> {
+    Ok(json!({
+        "id": row.get::<_, i64>(0)?,
+        "title": row.get::<_, Option<String>>(1)?,
+        "subscribed": row.get::<_, Option<bool>>(2)?,
+        "feedUrl": row.get::<_, Option<String>>(3)?,
+        "htmlUrl": row.get::<_, Option<String>>(4)?,
+        "addedAt": row.get::<_, Option<String>>(5)?,
+        "episodeCount": row.get::<_, i64>(6)?,
+        "unplayedCount": row.get::<_, i64>(7)?,
+    }))
+}
+
+const EPISODE_COLUMNS: &str = "e.id, e.feed_id, e.title, e.played, e.progress,
+    COALESCE(a.duration, d.duration), e.published_at, e.updated_at, e.html_url, e.overcast_url,
+    e.mp3_url, e.user_deleted, e.starred, e.starred_at, d.description";
+
+const EPISODE_JOINS: &str = "LEFT JOIN episode_details d ON d.episode_id = e.id
+    LEFT JOIN audio_metadata a ON a.episode_id = e.id";
+
+fn episode_json(row: &Row) -> rusqlite::Result<Value> {
+    Ok(json!({
+        "id": row.get::<_, i64>(0)?,
+        "feedId": row.get::<_, i64>(1)?,
+        "title": row.get::<_, Option<String>>(2)?,
+        "played": row.get::<_, Option<bool>>(3)?,
+        "progress": row.get::<_, Option<i64>>(4)?,
+        "durationSeconds": row.get::<_, Option<i64>>(5)?,
+        "publishedAt": row.get::<_, Option<String>>(6)?,
+        "updatedAt": row.get::<_, Option<String>>(7)?,
+        "htmlUrl": row.get::<_, Option<String>>(8)?,
+        "overcastUrl": row.get::<_, Option<String>>(9)?,
+        "mp3Url": row.get::<_, Option<String>>(10)?,
+        "userDeleted": row.get::<_, Option<bool>>(11)?,
+        "starred": row.get::<_, Option<bool>>(12)?,
+        "starredAt": row.get::<_, Option<String>>(13)?,
+        "description": row.get::<_, Option<String>>(14)?,
+    }))
+}
+
+// Runs a count and a page query sharing `from_where`, with `args` bound to both.
+fn list(
+    conn: &Connection,
+    columns: &str,
+    from_where: &str,
+    order: &str,
+    args: Vec<SqlValue>,
+    page: &Page,
+    to_json: fn(&Row) -> rusqlite::Result<Value>,
+) -> Result<Listing, Box<dyn std::error::Error>> {
+    let total = conn.query_row(
+        &format!("SELECT COUNT(*) {}", from_where),
+        rusqlite::params_from_iter(args.iter()),
+        |row| row.get(0),
+    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} {} ORDER BY {} LIMIT {} OFFSET {}",
+        columns, from_where, order, page.limit, page.offset
+    ))?;
+    let items = stmt
+        .query_map(rusqlite::params_from_iter(args.iter()), to_json)?
+        .collect::<Result<_, _>>()?;
+    Ok(Listing { total, items })
+}
+
+pub fn feeds(
+    conn: &Connection,
+    filter: &FeedFilter,
+    page: &Page,
+) -> Result<Listing, Box<dyn std::error::Error>> {
+    let mut conditions = vec!["1".to_string()];
+    let mut args = Vec::new();
+    if let Some(subscribed) = filter.subscribed {
+        conditions.push("f.subscribed = ?".to_string());
+        args.push(SqlValue::Integer(subscribed as i64));
+    }
+    if let Some(search) = &filter.search {
+        conditions.push("instr(lower(f.title), lower(?)) > 0".to_string());
+        args.push(SqlValue::Text(search.clone()));
+    }
+    list(
+        conn,
+        FEED_COLUMNS,
+        &format!("FROM feeds f WHERE {}", conditions.join(" AND ")),
+        "f.title COLLATE NOCASE, f.id",
+        args,
+        page,
+        feed_json,
+    )
+}
+
+pub fn feed(conn: &Connection, id: i64) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+    Ok(conn
+        .query_row(
+            &format!("SELECT {} FROM feeds f WHERE f.id = ?", FEED_COLUMNS),
+            params![id],
+            feed_json,
+        )
+        .optional()?)
+}
+
+pub fn episodes(
+    conn: &Connection,
+    filter: &EpisodeFilter,
+    page: &Page,
+) -> Result<Listing, Box<dyn std::error::Error>> {
+    let mut conditions = vec!["1".to_string()];
+    let mut args = Vec::new();
+    if let Some(feed_id) = filter.feed_id {
+        conditions.push("e.feed_id = ?".to_string());
+        args.push(SqlValue::Integer(feed_id));
+    }
+    if let Some(played) = filter.played {
+        conditions.push("e.played = ?".to_string());
+        args.push(SqlValue::Integer(played as i64));
+    }
+    if let Some(starred) = filter.starred {
+        conditions.push("COALESCE(e.starred, 0) = ?".to_string());
+        args.push(SqlValue::Integer(starred as i64));
+    }
+    if let Some(search) = &filter.search {
+        conditions.push(
+            "(instr(lower(e.title), lower(?)) > 0 OR instr(lower(d.description), lower(?)) > 0)"
+                .to_string(),
+        );
+        args.push(SqlValue::Text(search.clone()));
+        args.push(SqlValue::Text(search.clone()));
+    }
+    if let Some(after) = &filter.published_after {
+        conditions.push("e.published_at >= ?".to_string());
+        args.push(SqlValue::Text(after.clone()));
+    }
+    if let Some(before) = &filter.published_before {
+        conditions.push("e.published_at < ?".to_string());
+        args.push(SqlValue::Text(before.clone()));
+    }
+    list(
+        conn,
+        EPISODE_COLUMNS,
+        &format!(
+            "FROM episodes e {} WHERE {}",
+            EPISODE_JOINS,
+            conditions.join(" AND ")
+        ),
+        "e.published_at DESC, e.id DESC",
+        args,
+        page,
+        episode_json,
+    )
+}
+
+pub fn episode(conn: &Connection, id: i64) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+    Ok(conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM episodes e {} WHERE e.id = ?",
+                EPISODE_COLUMNS, EPISODE_JOINS
+            ),
+            params![id],
+            episode_json,
+        )
+        .optional()?)
+}
+
+// Totals for the whole archive. Cross-posted episodes are counted once, as in `stats`.
+pub fn stats(conn: &Connection) -> Result<Value, Box<dyn std::error::Error>> {
+    Ok(conn.query_row(
+        "SELECT
+            (SELECT COUNT(*) FROM feeds),
+            (SELECT COUNT(*) FROM feeds WHERE subscribed),
+            COUNT(*), COALESCE(SUM(played), 0), COALESCE(SUM(starred), 0),
+            (SELECT COALESCE(SUM(seconds), 0) FROM listening_time)
+        FROM (
+            SELECT MAX(played) AS played, MAX(starred) AS starred
+            FROM episodes
+            GROUP BY COALESCE(canonical_episode_id, id)
+        )",
+        [],
+        |row| {
+            Ok(json!({
+                "feeds": row.get::<_, i64>(0)?,
+                "subscribedFeeds": row.get::<_, i64>(1)?,
+                "episodes": row.get::<_, i64>(2)?,
+                "playedEpisodes": row.get::<_, i64>(3)?,
+                "starredEpisodes": row.get::<_, i64>(4)?,
+                "hoursListened": (row.get::<_, i64>(5)? as f64 / 360.0).round() / 10.0,
+            }))
+        },
+    )?)
+}
+
+fn listing_json(listing: Listing, page: &Page) -> Value {
+    json!({
+        "totalCount": listing.total,
+        "hasNextPage": listing.has_next_page(page),
+        "items": listing.items,
+    })
+}
+
+// A query string parameter parsed as `T`, or a 400 response naming it.
+fn param<T: std::str::FromStr>(request: &Request, name: &str) -> Result<Option<T>, Response> {
+    match request.param(name) {
+        None | Some("") => Ok(None),
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| Response::error(400, &format!("invalid {} \"{}\"", name, value))),
+    }
+}
+
+fn page(request: &Request) -> Result<Page, Response> {
+    Ok(Page::new(
+        param(request, "limit")?,
+        param(request, "offset")?,
+    ))
+}
+
+fn episode_filter(request: &Request, feed_id: Option<i64>) -> Result<EpisodeFilter, Response> {
+    Ok(EpisodeFilter {
+        feed_id: feed_id.or(param(request, "feed")?),
+        played: param(request, "played")?,
+        starred: param(request, "starred")?,
+        search: param(request, "search")?,
+        published_after: param(request, "published_after")?,
+        published_before: param(request, "published_before")?,
+    })
+}
+
+fn found(value: Option<Value>, what: &str) -> Response {
+    match value {
+        Some(value) => Response::json(200, &value),
+        None => Response::error(404, &format!("no such {}", what)),
+    }
+}
+
+// Answers the REST API under /api: lists of feeds and episodes (filtered by query string
+// parameters, and paged with `limit` and `offset`), single ones by ID, and stats.
+pub fn handle(conn: &Connection, request: &Request) -> Response {
+    if request.method != "GET" {
+        return Response::error(405, "the API is read-only");
+    }
+    let path: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let result = (|| -> Result<Response, Response> {
+        let internal = |err: Box<dyn std::error::Error>| Response::error(500, &err.to_string());
+        let id = |segment: &str| {
+            segment
+                .parse::<i64>()
+                .map_err(|_| Response::error(404, "not found"))
+        };
+        Ok(match path[1..] {
+            ["feeds"] => {
+                let filter = FeedFilter {
+                    subscribed: param(request, "subscribed")?,
+                    search: param(request, "search")?,
+                };
+                let page = page(request)?;
+                let listing = feeds(conn, &filter, &page).map_err(internal)?;
+                Response::json(200, &listing_json(listing, &page))
+            }
+            ["feeds", feed_id] => found(feed(conn, id(feed_id)?).map_err(internal)?, "feed"),
+            ["feeds", feed_id, "episodes"] => {
+                let filter = episode_filter(request, Some(id(feed_id)?))?;
+                let page = page(request)?;
+                let listing = episodes(conn, &filter, &page).map_err(internal)?;
+                Response::json(200, &listing_json(listing, &page))
+            }
+            ["episodes"] => {
+                let filter = episode_filter(request, None)?;
+                let page = page(request)?;
+                let listing = episodes(conn, &filter, &page).map_err(internal)?;
+                Response::json(200, &listing_json(listing, &page))
+            }
+            ["episodes", episode_id] => {
+                found(episode(conn, id(episode_id)?).map_err(internal)?, "episode")
+            }
+            ["stats"] => Response::json(200, &stats(conn).map_err(internal)?),
+            _ => Response::error(404, "not found"),
+        })
+    })();
+    result.unwrap_or_else(|response| response)
+}
@@ -0,0 +1,201 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::client;
+
+// Assets larger than this are left as URLs; the point is to keep the archive a single file, not
+// to make it huge.
+const MAX_ASSET_BYTES: u64 = 5 * 1024 * 1024;
+
+// Regular file, rw-r--r--, as `sqlite3 -A` would record it.
+const SQLAR_MODE: i64 = 0o100644;
+
+// A fetched asset, with a file extension for its type.
+struct Fetched {
+    data: Vec<u8>,
+    extension: String,
+}
+
+// Counts of assets stored by a run.
+#[derive(Default)]
+pub struct AssetCounts {
+    pub artwork: usize,
+    pub transcripts: usize,
+    pub show_notes: usize,
+}
+
+// Stores feed artwork, episode transcripts and show notes in the `sqlar` table, named like
+// "artwork/<feed id>.jpg", "transcripts/<episode id>.vtt" and "show-notes/<episode id>.html".
+// URLs come from enrichment, so this only finds assets for feeds archived with `--enrich`.
+//
+// Artwork and transcripts that are already stored aren't fetched again, and ones that fail to
+// fetch are reported and skipped. Show notes are rewritten whenever they change.
+pub fn store(conn: &Connection) -> Result<AssetCounts, Box<dyn std::error::Error>> {
+    let client = client::new();
+    let mut counts = AssetCounts::default();
+
+    let artwork = pending(
+        conn,
+        "SELECT feed_id, image_url FROM feed_details WHERE image_url IS NOT NULL",
+        "artwork",
+    )?;
+    for (feed_id, url) in artwork {
+        if let Some(asset) = fetch(&client, &url)? {
+            insert(
+                conn,
+                &format!("artwork/{}.{}", feed_id, asset.extension),
+                &asset.data,
+            )?;
+            counts.artwork += 1;
+        }
+    }
+
+    let transcripts = pending(
+        conn,
+        "SELECT episode_id, transcript_url FROM episode_details WHERE transcript_url IS NOT NULL",
+        "transcripts",
+    )?;
+    for (episode_id, url) in transcripts {
+        if let Some(asset) = fetch(&client, &url)? {
+            let name = format!("transcripts/{}.{}", episode_id, asset.extension);
+            insert(conn, &name, &asset.data)?;
+            counts.transcripts += 1;
+        }
+    }
+
+    counts.show_notes = conn.execute(
+        "INSERT INTO sqlar(name, mode, mtime, sz, data)
+        SELECT 'show-notes/' || episode_id || '.html', ?1, ?2, length(CAST(description AS BLOB)),
+            CAST(description AS BLOB)
+        FROM episode_details
+        WHERE description IS NOT NULL
+        ON CONFLICT(name) DO UPDATE SET
+            mtime = excluded.mtime,
+            sz = excluded.sz,
+            data = excluded.data
+        WHERE data IS NOT excluded.data",
+        params![SQLAR_MODE, chrono::Utc::now().timestamp()],
+    )?;
+    Ok(counts)
+}
+
+// IDs and URLs from `sql` that have nothing stored under `prefix` yet.
+fn pending(
+    conn: &Connection,
+    sql: &str,
+    prefix: &str,
+) -> Result<Vec<(i64, String)>, Box<dyn std::error::Error>> {
+    let mut stored = conn.prepare("SELECT 1 FROM sqlar WHERE name LIKE ? LIMIT 1")?;
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut pending = Vec::new();
+    for (id, url) in rows {
+        // The extension depends on what the server sends, so match any.
+        if !stored.exists(params![format!("{}/{}.%", prefix, id)])? {
+            pending.push((id, url));
+        }
+    }
+    Ok(pending)
+}
+
+// Fetches an asset, or returns None if it couldn't be fetched or is too large.
+fn fetch(
+    client: &reqwest::blocking::Client,
+    url: &str,
+) -> Result<Option<Fetched>, Box<dyn std::error::Error>> {
+    let resp = match client.get(url).send().and_then(|r| r.error_for_status()) {
+        Ok(resp) => resp,
+        Err(err) => {
+            eprintln!("Skipping {}: {}", url, err);
+            return Ok(None);
+        }
+    };
+    if resp.content_length().unwrap_or(0) > MAX_ASSET_BYTES {
+        eprintln!("Skipping {}: larger than {} bytes", url, MAX_ASSET_BYTES);
+        return Ok(None);
+    }
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let data = match resp.bytes() {
+        Ok(data) => data.to_vec(),
+        Err(err) => {
+            eprintln!("Skipping {}: {}", url, err);
+            return Ok(None);
+        }
+    };
+    if data.len() as u64 > MAX_ASSET_BYTES {
+        eprintln!("Skipping {}: larger than {} bytes", url, MAX_ASSET_BYTES);
+        return Ok(None);
+    }
+    Ok(Some(Fetched {
+        data,
+        extension: extension(&content_type, url),
+    }))
+}
+
+// Picks a file extension from the Content-Type, falling back to the URL's.
+fn extension(content_type: &str, url: &str) -> String {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    let known = match mime.to_lowercase().as_str() {
+        "image/jpeg" | "image/jpg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/webp" => Some("webp"),
+        "image/gif" => Some("gif"),
+        "text/vtt" => Some("vtt"),
+        "application/x-subrip" | "application/srt" => Some("srt"),
+        "application/json" => Some("json"),
+        "text/html" => Some("html"),
+        "text/plain" => Some("txt"),
+        _ => None,
+    };
+    if let Some(known) = known {
+        return known.to_string();
+    }
+    std::path::Path::new(url.split(['?', '#']).next().unwrap_or_default())
+        .extension()
+        .and_then(|e| e.to_str())
+        .filter(|e| e.len() <= 4 && e.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("bin")
+        .to_lowercase()
+}
+
+// Stores a snapshot of the raw OPML export, named like "opml/2024-05-01-120000.opml", unless it's
+// the same as the latest one. Returns whether it was stored.
+pub fn store_export(conn: &Connection, export: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let latest: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT data FROM sqlar WHERE name LIKE 'opml/%' ORDER BY name DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if latest.as_deref() == Some(export.as_bytes()) {
+        return Ok(false);
+    }
+    let name = format!("opml/{}.opml", chrono::Utc::now().format("%Y-%m-%d-%H%M%S"));
+    insert(conn, &name, export.as_bytes())?;
+    Ok(true)
+}
+
+// Stores a file in `sqlar`, uncompressed (which the format marks by `sz` equalling the data's
+// length).
+fn insert(conn: &Connection, name: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT OR REPLACE INTO sqlar(name, mode, mtime, sz, data) VALUES (?, ?, ?, ?, ?)",
+        params![
+            name,
+            SQLAR_MODE,
+            chrono::Utc::now().timestamp(),
+            data.len() as i64,
+            data
+        ],
+    )?;
+    Ok(())
+}
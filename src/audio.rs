@@ -0,0 +1,624 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+// Metadata read from an audio file's tags and headers.
+#[derive(Debug, Default)]
+pub struct AudioMetadata {
+    // "mp3" or "mp4".
+    pub format: String,
+    // In seconds.
+    pub duration: Option<i64>,
+    // The average bitrate, in bits per second.
+    pub bitrate: Option<i64>,
+    pub title: Option<String>,
+    pub chapters: Vec<Chapter>,
+    pub artwork: Option<Artwork>,
+}
+
+#[derive(Debug)]
+pub struct Chapter {
+    pub start_seconds: f64,
+    pub title: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Artwork {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+// Reads the metadata of an MP3 (ID3v2 tags, plus the first frame's header) or MP4/M4A file.
+// Returns None for other formats.
+pub fn read(path: &Path) -> Result<Option<AudioMetadata>, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut header = [0; 12];
+    let read = file.read(&mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+    if read >= 8 && &header[4..8] == b"ftyp" {
+        return Ok(Some(read_mp4(&mut file, size)?));
+    }
+    if read >= 3 && (&header[..3] == b"ID3" || is_frame_sync(&header[..2])) {
+        return Ok(Some(read_mp3(&mut file, size)?));
+    }
+    Ok(None)
+}
+
+// Stores an episode's audio metadata, replacing what was recorded for it before.
+pub fn record(
+    conn: &Connection,
+    episode_id: i64,
+    metadata: &AudioMetadata,
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO audio_metadata(
+            episode_id, format, duration, bitrate, title, artwork_type, artwork, parsed_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(episode_id) DO UPDATE SET
+            format = excluded.format,
+            duration = excluded.duration,
+            bitrate = excluded.bitrate,
+            title = excluded.title,
+            artwork_type = excluded.artwork_type,
+            artwork = excluded.artwork,
+            parsed_at = excluded.parsed_at",
+        params![
+            episode_id,
+            metadata.format,
+            metadata.duration,
+            metadata.bitrate,
+            metadata.title,
+            metadata.artwork.as_ref().map(|a| &a.mime_type),
+            metadata.artwork.as_ref().map(|a| &a.data),
+            chrono::Utc::now().naive_utc(),
+        ],
+    )?;
+    conn.execute(
+        "DELETE FROM audio_chapters WHERE episode_id = ?",
+        params![episode_id],
+    )?;
+    for (position, chapter) in metadata.chapters.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO audio_chapters(episode_id, position, start_seconds, title)
+            VALUES (?, ?, ?, ?)",
+            params![
+                episode_id,
+                position as i64,
+                chapter.start_seconds,
+                chapter.title
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn read_mp3(file: &mut File, size: u64) -> Result<AudioMetadata, Box<dyn std::error::Error>> {
+    let mut metadata = AudioMetadata {
+        format: "mp3".to_string(),
+        ..Default::default()
+    };
+    let mut header = [0; 10];
+    file.read_exact(&mut header)?;
+    let mut audio_start = 0;
+    if &header[..3] == b"ID3" {
+        let version = header[3];
+        let flags = header[5];
+        let tag_size = synchsafe(&header[6..10]) as usize;
+        let mut tag = vec![0; tag_size];
+        file.read_exact(&mut tag)?;
+        audio_start = 10 + tag_size as u64 + if flags & 0x10 != 0 { 10 } else { 0 };
+        // The extended header only carries things like CRCs, so it's skipped.
+        let mut frames = &tag[..];
+        if flags & 0x40 != 0 && frames.len() >= 4 {
+            let extended = if version == 4 {
+                synchsafe(&frames[..4]) as usize
+            } else {
+                u32_be(&frames[..4]) as usize + 4
+            };
+            frames = frames.get(extended..).unwrap_or_default();
+        }
+        for frame in id3_frames(frames, version) {
+            match frame.id.as_str() {
+                "TIT2" | "TT2" => metadata.title = id3_text(frame.body),
+                "TLEN" | "TLE" => {
+                    metadata.duration = id3_text(frame.body)
+                        .and_then(|ms| ms.trim().parse::<i64>().ok())
+                        .filter(|ms| *ms > 0)
+                        .map(|ms| ms / 1000)
+                }
+                "APIC" | "PIC" if metadata.artwork.is_none() => {
+                    metadata.artwork = id3_picture(frame.body, version)
+                }
+                "CHAP" => metadata.chapters.extend(id3_chapter(frame.body, version)),
+                _ => {}
+            }
+        }
+        metadata.chapters.sort_by(|a, b| {
+            a.start_seconds
+                .partial_cmp(&b.start_seconds)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+
+    // The first frame's header gives the bitrate, and VBR files have a Xing or VBRI header in
+    // that frame with the total number of frames.
+    file.seek(SeekFrom::Start(audio_start))?;
+    let mut buffer = vec![0; 64 * 1024];
+    let read = file.read(&mut buffer)?;
+    let buffer = &buffer[..read];
+    if let Some(offset) = (0..buffer.len().saturating_sub(4))
+        .find(|&i| is_frame_sync(&buffer[i..]) && FrameHeader::parse(&buffer[i..]).is_some())
+    {
+        let frame = FrameHeader::parse(&buffer[offset..]).unwrap();
+        let audio_bytes = size.saturating_sub(audio_start + offset as u64);
+        let total_frames = vbr_frame_count(&buffer[offset..], &frame);
+        let duration = match total_frames {
+            Some(frames) => {
+                frames as f64 * frame.samples_per_frame as f64 / frame.sample_rate as f64
+            }
+            None => audio_bytes as f64 * 8.0 / frame.bitrate as f64,
+        };
+        if duration > 0.0 {
+            metadata.duration = metadata.duration.or(Some(duration.round() as i64));
+            metadata.bitrate = Some(match total_frames {
+                Some(_) => (audio_bytes as f64 * 8.0 / duration).round() as i64,
+                None => frame.bitrate as i64,
+            });
+        }
+    }
+    Ok(metadata)
+}
+
+struct Id3Frame<'a> {
+    id: String,
+    body: &'a [u8],
+}
+
+// Splits an ID3v2 tag's contents into frames. Version 2.2 has 3-byte IDs and sizes; 2.4's sizes
+// are synchsafe.
+fn id3_frames(mut data: &[u8], version: u8) -> Vec<Id3Frame<'_>> {
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut frames = Vec::new();
+    while data.len() >= header_len && data[0] != 0 {
+        let id = String::from_utf8_lossy(&data[..id_len]).into_owned();
+        let size = match version {
+            2 => u32::from_be_bytes([0, data[3], data[4], data[5]]) as usize,
+            4 => synchsafe(&data[4..8]) as usize,
+            _ => u32_be(&data[4..8]) as usize,
+        };
+        let body = match data.get(header_len..header_len + size) {
+            Some(body) => body,
+            None => break,
+        };
+        frames.push(Id3Frame { id, body });
+        data = &data[header_len + size..];
+    }
+    frames
+}
+
+// Decodes a text frame: an encoding byte, then the text.
+fn id3_text(body: &[u8]) -> Option<String> {
+    let (&encoding, text) = body.split_first()?;
+    let text = decode_text(encoding, text);
+    let text = text.trim_matches(char::from(0)).trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+fn decode_text(encoding: u8, text: &[u8]) -> String {
+    match encoding {
+        // ISO-8859-1, whose code points are the first 256 of Unicode.
+        0 => text.iter().map(|&b| char::from(b)).collect(),
+        1 | 2 => {
+            let mut units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            // Encoding 1 starts with a byte order mark; 2 is always big-endian.
+            match units.first() {
+                Some(0xFEFF) => {
+                    units.remove(0);
+                }
+                Some(0xFFFE) => {
+                    units.remove(0);
+                    for unit in units.iter_mut() {
+                        *unit = unit.swap_bytes();
+                    }
+                }
+                _ => {}
+            }
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+// Splits off a NUL-terminated string in the given encoding (UTF-16 ends with two NULs).
+fn split_terminated(data: &[u8], encoding: u8) -> Option<(&[u8], &[u8])> {
+    if encoding == 1 || encoding == 2 {
+        let end = (0..data.len() / 2).find(|&i| data[i * 2] == 0 && data[i * 2 + 1] == 0)? * 2;
+        Some((&data[..end], &data[end + 2..]))
+    } else {
+        let end = data.iter().position(|&b| b == 0)?;
+        Some((&data[..end], &data[end + 1..]))
+    }
+}
+
+// Reads an attached picture: encoding, MIME type (or a 3-letter format in 2.2), picture type,
+// description, then the image data.
+fn id3_picture(body: &[u8], version: u8) -> Option<Artwork> {
+    let (&encoding, rest) = body.split_first()?;
+    let (mime_type, rest) = if version == 2 {
+        let format = String::from_utf8_lossy(rest.get(..3)?).to_lowercase();
+        let mime_type = match format.as_str() {
+            "png" => "image/png".to_string(),
+            _ => "image/jpeg".to_string(),
+        };
+        (mime_type, &rest[3..])
+    } else {
+        let (mime_type, rest) = split_terminated(rest, 0)?;
+        (String::from_utf8_lossy(mime_type).to_lowercase(), rest)
+    };
+    let (_, rest) = rest.split_first()?;
+    let (_, data) = split_terminated(rest, encoding)?;
+    if data.is_empty() {
+        return None;
+    }
+    Some(Artwork {
+        mime_type: if mime_type.contains('/') {
+            mime_type
+        } else {
+            format!("image/{}", mime_type)
+        },
+        data: data.to_vec(),
+    })
+}
+
+// Reads a chapter frame: an element ID, start and end times in milliseconds, byte offsets, and
+// sub-frames (usually a TIT2 with the chapter's title).
+fn id3_chapter(body: &[u8], version: u8) -> Option<Chapter> {
+    let (_, rest) = split_terminated(body, 0)?;
+    let start_ms = u32_be(rest.get(..4)?);
+    let title = id3_frames(rest.get(16..)?, version)
+        .into_iter()
+        .find(|f| f.id == "TIT2")
+        .and_then(|f| id3_text(f.body));
+    Some(Chapter {
+        start_seconds: start_ms as f64 / 1000.0,
+        title,
+    })
+}
+
+struct FrameHeader {
+    mpeg1: bool,
+    mono: bool,
+    // In bits per second.
+    bitrate: u32,
+    sample_rate: u32,
+    samples_per_frame: u32,
+}
+
+impl FrameHeader {
+    fn parse(data: &[u8]) -> Option<FrameHeader> {
+        if data.len() < 4 || !is_frame_sync(data) {
+            return None;
+        }
+        let version = (data[1] >> 3) & 0b11; // 0: MPEG 2.5, 2: MPEG 2, 3: MPEG 1
+        let layer = (data[1] >> 1) & 0b11; // 1: layer III, 2: layer II, 3: layer I
+        let bitrate_index = (data[2] >> 4) as usize;
+        let sample_rate_index = ((data[2] >> 2) & 0b11) as usize;
+        if version == 1 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 {
+            return None;
+        }
+        let mpeg1 = version == 3;
+        const MPEG1_L1: [u32; 15] = [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ];
+        const MPEG1_L2: [u32; 15] = [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ];
+        const MPEG1_L3: [u32; 15] = [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ];
+        const MPEG2_L1: [u32; 15] = [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ];
+        const MPEG2_L23: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+        let kbps = match (mpeg1, layer) {
+            (true, 3) => MPEG1_L1,
+            (true, 2) => MPEG1_L2,
+            (true, _) => MPEG1_L3,
+            (false, 3) => MPEG2_L1,
+            (false, _) => MPEG2_L23,
+        }[bitrate_index];
+        let sample_rate = [44_100, 48_000, 32_000].get(sample_rate_index)?
+            >> match version {
+                3 => 0,
+                2 => 1,
+                _ => 2,
+            };
+        let samples_per_frame = match (layer, mpeg1) {
+            (3, _) => 384,
+            (1, false) => 576,
+            _ => 1152,
+        };
+        Some(FrameHeader {
+            mpeg1,
+            mono: data[3] >> 6 == 0b11,
+            bitrate: kbps * 1000,
+            sample_rate,
+            samples_per_frame,
+        })
+    }
+}
+
+fn is_frame_sync(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0
+}
+
+// The frame count from a Xing/Info or VBRI header in the first frame, if it has one.
+fn vbr_frame_count(frame: &[u8], header: &FrameHeader) -> Option<u32> {
+    let side_info: usize = match (header.mpeg1, header.mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing = frame.get(4 + side_info..)?;
+    if xing.starts_with(b"Xing") || xing.starts_with(b"Info") {
+        let flags = u32_be(xing.get(4..8)?);
+        if flags & 1 != 0 {
+            return Some(u32_be(xing.get(8..12)?)).filter(|n| *n > 0);
+        }
+        return None;
+    }
+    let vbri = frame.get(36..)?;
+    if vbri.starts_with(b"VBRI") {
+        return Some(u32_be(vbri.get(14..18)?)).filter(|n| *n > 0);
+    }
+    None
+}
+
+fn synchsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |size, &b| (size << 7) | (b & 0x7F) as u32)
+}
+
+fn u32_be(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn u64_be(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes[..8].try_into().unwrap())
+}
+
+// The `moov` box holds all of an MP4's metadata, so it's the only box read into memory.
+const MAX_MOOV_BYTES: u64 = 64 * 1024 * 1024;
+
+fn read_mp4(file: &mut File, size: u64) -> Result<AudioMetadata, Box<dyn std::error::Error>> {
+    let mut metadata = AudioMetadata {
+        format: "mp4".to_string(),
+        ..Default::default()
+    };
+    let mut position = 0;
+    let mut moov = None;
+    while position + 8 <= size {
+        file.seek(SeekFrom::Start(position))?;
+        let mut header = [0; 16];
+        file.read_exact(&mut header[..8])?;
+        let mut box_size = u32_be(&header[..4]) as u64;
+        let mut header_len = 8;
+        if box_size == 1 {
+            file.read_exact(&mut header[8..])?;
+            box_size = u64_be(&header[8..]);
+            header_len = 16;
+        } else if box_size == 0 {
+            box_size = size - position;
+        }
+        if box_size < header_len {
+            break;
+        }
+        if &header[4..8] == b"moov" {
+            if box_size > MAX_MOOV_BYTES {
+                return Err("MP4 metadata is implausibly large".into());
+            }
+            let mut data = vec![0; (box_size - header_len) as usize];
+            file.read_exact(&mut data)?;
+            moov = Some(data);
+            break;
+        }
+        position = match position.checked_add(box_size) {
+            Some(position) => position,
+            None => break,
+        };
+    }
+    let moov = match moov {
+        Some(moov) => moov,
+        None => return Ok(metadata),
+    };
+
+    if let Some(mvhd) = find_box(&moov, &[b"mvhd"]) {
+        // Version 1 has 64-bit times.
+        let (timescale, duration) = if mvhd.first() == Some(&1) {
+            (mvhd.get(20..24).map(u32_be), mvhd.get(24..32).map(u64_be))
+        } else {
+            (
+                mvhd.get(12..16).map(u32_be),
+                mvhd.get(16..20).map(|b| u32_be(b) as u64),
+            )
+        };
+        if let (Some(timescale), Some(duration)) = (timescale, duration) {
+            if timescale > 0 && duration > 0 {
+                let seconds = duration as f64 / timescale as f64;
+                metadata.duration = Some(seconds.round() as i64);
+                metadata.bitrate = Some((size as f64 * 8.0 / seconds).round() as i64);
+            }
+        }
+    }
+
+    if let Some(chpl) = find_box(&moov, &[b"udta", b"chpl"]) {
+        metadata.chapters = nero_chapters(chpl).unwrap_or_default();
+    }
+
+    if let Some(meta) = find_box(&moov, &[b"udta", b"meta"]) {
+        // iTunes-style `meta` is a full box (with 4 bytes of version and flags), but some
+        // QuickTime files omit them.
+        let children = if meta.get(4..8) == Some(b"hdlr") {
+            meta
+        } else {
+            meta.get(4..).unwrap_or_default()
+        };
+        if let Some(ilst) = find_box(children, &[b"ilst"]) {
+            if let Some(data) = find_box(ilst, &[b"\xA9nam", b"data"]) {
+                metadata.title = data
+                    .get(8..)
+                    .map(|t| String::from_utf8_lossy(t).trim().to_string())
+                    .filter(|t| !t.is_empty());
+            }
+            if let Some(data) = find_box(ilst, &[b"covr", b"data"]) {
+                let image = data.get(8..).unwrap_or_default();
+                if !image.is_empty() {
+                    metadata.artwork = Some(Artwork {
+                        // The data type is 13 for JPEG and 14 for PNG.
+                        mime_type: match data.get(..4).map(u32_be) {
+                            Some(14) => "image/png",
+                            _ => "image/jpeg",
+                        }
+                        .to_string(),
+                        data: image.to_vec(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(metadata)
+}
+
+// Follows a path of box types down from `data`, returning the last box's contents.
+fn find_box<'a>(mut data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    for kind in path {
+        data = children(data).find(|(k, _)| k == *kind)?.1;
+    }
+    Some(data)
+}
+
+// Iterates over the boxes in `data`, as (type, contents) pairs.
+fn children(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+        let size = u32_be(&data[..4]) as usize;
+        let kind: [u8; 4] = data[4..8].try_into().unwrap();
+        let (header_len, size) = match size {
+            0 => (8, data.len()),
+            1 => (16, u64_be(data.get(8..16)?) as usize),
+            size => (8, size),
+        };
+        if size < header_len || size > data.len() {
+            return None;
+        }
+        let contents = &data[header_len..size];
+        data = &data[size..];
+        Some((kind, contents))
+    })
+}
+
+// Reads Nero-style chapters (`udta/chpl`): version and flags, a count, then each chapter's start
+// (in 100ns units) and a length-prefixed title.
+fn nero_chapters(chpl: &[u8]) -> Option<Vec<Chapter>> {
+    let version = *chpl.first()?;
+    let mut rest = chpl.get(if version == 1 { 8 } else { 4 }..)?;
+    let (&count, tail) = rest.split_first()?;
+    rest = tail;
+    let mut chapters = Vec::new();
+    for _ in 0..count {
+        let start = u64_be(rest.get(..8)?);
+        let title_len = *rest.get(8)? as usize;
+        let title = rest.get(9..9 + title_len)?;
+        chapters.push(Chapter {
+            start_seconds: start as f64 / 10_000_000.0,
+            title: Some(String::from_utf8_lossy(title).trim().to_string())
+                .filter(|t| !t.is_empty()),
+        });
+        rest = &rest[9 + title_len..];
+    }
+    Some(chapters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids_and_bodies<'a>(frames: &'a [Id3Frame<'a>]) -> Vec<(&'a str, &'a [u8])> {
+        frames.iter().map(|f| (f.id.as_str(), f.body)).collect()
+    }
+
+    #[test]
+    fn v3_frames() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"TIT2\x00\x00\x00\x06\x00\x00\x00Hello");
+        data.extend_from_slice(b"TPE1\x00\x00\x00\x03\x00\x00\x00Me");
+        // Padding ends the frames.
+        data.extend_from_slice(&[0; 16]);
+        let frames = id3_frames(&data, 3);
+        assert_eq!(
+            ids_and_bodies(&frames),
+            vec![("TIT2", &b"\x00Hello"[..]), ("TPE1", &b"\x00Me"[..])]
+        );
+        assert_eq!(id3_text(frames[0].body).as_deref(), Some("Hello"));
+    }
+
+    #[test]
+    fn v2_frames_have_short_headers() {
+        let frames = id3_frames(b"TT2\x00\x00\x03\x00HiTP1\x00\x00\x02\x00X", 2);
+        assert_eq!(
+            ids_and_bodies(&frames),
+            vec![("TT2", &b"\x00Hi"[..]), ("TP1", &b"\x00X"[..])]
+        );
+    }
+
+    #[test]
+    fn v4_sizes_are_synchsafe() {
+        // 0x01 0x00 is 128 when synchsafe, but 256 read as a plain integer.
+        let mut data = b"COMM\x00\x00\x01\x00\x00\x00".to_vec();
+        data.extend_from_slice(&[b'a'; 128]);
+        let frames = id3_frames(&data, 4);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].body.len(), 128);
+        assert!(id3_frames(&data, 3).is_empty());
+    }
+
+    #[test]
+    fn truncated_frames_are_dropped() {
+        // A frame that claims more bytes than are left.
+        assert!(id3_frames(b"TIT2\x00\x00\x00\x40\x00\x00\x00AB", 3).is_empty());
+        let frames = id3_frames(
+            b"TIT2\x00\x00\x00\x03\x00\x00\x00ABTALB\x00\x00\x00\x40\x00\x00\x00",
+            3,
+        );
+        assert_eq!(ids_and_bodies(&frames), vec![("TIT2", &b"\x00AB"[..])]);
+    }
+
+    #[test]
+    fn mp4_box_sizes_that_overflow_stop_the_scan() {
+        let path = std::env::temp_dir().join("overcast-to-sqlite-audio-test-overflow.m4a");
+        let mut data = b"\x00\x00\x00\x08free".to_vec();
+        // A 64-bit box size that runs past the end of any file.
+        data.extend_from_slice(b"\x00\x00\x00\x01mdat");
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        std::fs::write(&path, &data).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let metadata = read_mp4(&mut file, data.len() as u64).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(metadata.format, "mp4");
+        assert_eq!(metadata.duration, None);
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection};
+
+// The timestamp in backup names. It sorts in time order, which rotation relies on.
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+// What a backup run did.
+pub struct BackupResult {
+    pub path: PathBuf,
+    // Older backups deleted by rotation.
+    pub removed: Vec<PathBuf>,
+}
+
+// Copies the database into `dir` as "<name>-<YYYYMMDD-HHMMSS>.db", with VACUUM INTO, which takes
+// a consistent snapshot even while another process is writing. The copy is written under a
+// temporary name and renamed into place, so an interrupted backup never looks complete. With
+// `keep`, only that many of the newest backups of this database are kept in `dir`.
+pub fn backup(
+    conn: &Connection,
+    db_path: &str,
+    dir: &Path,
+    keep: Option<usize>,
+) -> Result<BackupResult, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir)?;
+    let stem = Path::new(db_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "podcasts".to_string());
+    let name = format!(
+        "{}-{}.db",
+        stem,
+        chrono::Utc::now().format(TIMESTAMP_FORMAT)
+    );
+    let path = dir.join(&name);
+    if path.exists() {
+        return Err(format!("{} already exists.", path.display()).into());
+    }
+    let partial = dir.join(format!("{}.partial", name));
+    if partial.exists() {
+        std::fs::remove_file(&partial)?;
+    }
+    conn.execute("VACUUM INTO ?", params![partial.to_string_lossy()])?;
+    std::fs::rename(&partial, &path)?;
+
+    let mut removed = Vec::new();
+    if let Some(keep) = keep {
+        let mut backups = backups(dir, &stem)?;
+        backups.sort();
+        let excess = backups.len().saturating_sub(keep);
+        for old in backups.into_iter().take(excess) {
+            std::fs::remove_file(&old)?;
+            removed.push(old);
+        }
+    }
+    Ok(BackupResult { path, removed })
+}
+
+// The backups of the database named `stem` in `dir`: files named like "<stem>-<timestamp>.db".
+fn backups(dir: &Path, stem: &str) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        let timestamp = name
+            .strip_prefix(stem)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .and_then(|rest| rest.strip_suffix(".db"));
+        if let Some(timestamp) = timestamp {
+            if chrono::NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).is_ok() {
+                found.push(path);
+            }
+        }
+    }
+    Ok(found)
+}
@@ -0,0 +1,80 @@
+// Minimal SVG chart rendering, so reports and exports don't need external assets.
+
+const WIDTH: f64 = 720.0;
+const HEIGHT: f64 = 260.0;
+const MARGIN_LEFT: f64 = 48.0;
+const MARGIN_BOTTOM: f64 = 48.0;
+const MARGIN_TOP: f64 = 16.0;
+
+// Renders a vertical bar chart. `format_value` is used for the y-axis maximum and bar tooltips.
+pub fn bar_chart(bars: &[(String, f64)], format_value: fn(f64) -> String) -> String {
+    let plot_width = WIDTH - MARGIN_LEFT;
+    let plot_height = HEIGHT - MARGIN_BOTTOM - MARGIN_TOP;
+    let max = bars.iter().map(|(_, v)| *v).fold(0.0, f64::max);
+    let slot = if bars.is_empty() {
+        plot_width
+    } else {
+        plot_width / bars.len() as f64
+    };
+    // Only label every nth bar so long series stay legible.
+    let label_every = ((bars.len() as f64 * 60.0 / plot_width).ceil() as usize).max(1);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {w} {h}\" width=\"{w}\" height=\"{h}\" \
+         font-family=\"sans-serif\" font-size=\"11\">",
+        w = WIDTH,
+        h = HEIGHT
+    );
+    svg.push_str(&format!(
+        "<line x1=\"{x}\" y1=\"{top}\" x2=\"{x}\" y2=\"{bottom}\" stroke=\"#999\"/>\
+         <line x1=\"{x}\" y1=\"{bottom}\" x2=\"{right}\" y2=\"{bottom}\" stroke=\"#999\"/>\
+         <text x=\"{label_x}\" y=\"{label_y}\" text-anchor=\"end\">{max}</text>",
+        x = MARGIN_LEFT,
+        top = MARGIN_TOP,
+        bottom = MARGIN_TOP + plot_height,
+        right = WIDTH,
+        label_x = MARGIN_LEFT - 4.0,
+        label_y = MARGIN_TOP + 8.0,
+        max = escape(&format_value(max)),
+    ));
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let height = if max > 0.0 {
+            value / max * plot_height
+        } else {
+            0.0
+        };
+        let x = MARGIN_LEFT + i as f64 * slot;
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#fc7e0f\">\
+             <title>{}: {}</title></rect>",
+            x + slot * 0.1,
+            MARGIN_TOP + plot_height - height,
+            slot * 0.8,
+            height,
+            escape(label),
+            escape(&format_value(*value)),
+        ));
+        if i % label_every == 0 {
+            let label_x = x + slot / 2.0;
+            let label_y = MARGIN_TOP + plot_height + 12.0;
+            svg.push_str(&format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\" transform=\"rotate(-45 {:.1} {:.1})\">{}</text>",
+                label_x,
+                label_y,
+                label_x,
+                label_y,
+                escape(label),
+            ));
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+// Escapes text for inclusion in HTML or SVG.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,98 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDateTime;
+use rusqlite::Connection;
+
+use crate::overcast::Feed;
+use crate::overrides;
+
+// How an archive differs from a fresh Overcast export.
+#[derive(Default)]
+pub struct Drift {
+    pub new_feeds: usize,
+    pub new_episodes: usize,
+    // Archived episodes whose played, progress, deleted or starred state has changed on Overcast.
+    pub changed_episodes: usize,
+    // When the oldest of those changes happened on Overcast (a new episode's publish date, or a
+    // changed one's update date), if Overcast says.
+    pub oldest_change: Option<NaiveDateTime>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.new_feeds == 0 && self.new_episodes == 0 && self.changed_episodes == 0
+    }
+
+    fn changed_at(&mut self, at: Option<NaiveDateTime>) {
+        if let Some(at) = at {
+            self.oldest_change = Some(self.oldest_change.map_or(at, |oldest| oldest.min(at)));
+        }
+    }
+}
+
+// Compares `feeds`, from an export, with the archive, without writing anything. Pruned episodes
+// aren't new, and overridden columns aren't changes.
+pub fn compare(conn: &Connection, feeds: &[Feed]) -> Result<Drift, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT CAST(id AS TEXT) FROM feeds")?;
+    let archived_feeds = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<HashSet<_>, _>>()?;
+    let mut stmt = conn.prepare("SELECT CAST(episode_id AS TEXT) FROM pruned_episodes")?;
+    let pruned = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<HashSet<_>, _>>()?;
+    let mut stmt = conn.prepare(
+        "SELECT CAST(id AS TEXT), COALESCE(played, 0), progress, COALESCE(user_deleted, 0),
+            COALESCE(starred, 0)
+        FROM episodes",
+    )?;
+    let archived_episodes = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                (
+                    row.get::<_, bool>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                    row.get::<_, bool>(3)?,
+                    row.get::<_, bool>(4)?,
+                ),
+            ))
+        })?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+
+    let mut drift = Drift::default();
+    for feed in feeds {
+        if !archived_feeds.contains(&feed.id) {
+            drift.new_feeds += 1;
+        }
+        for episode in &feed.episodes {
+            let archived = match archived_episodes.get(&episode.id) {
+                Some(archived) => archived,
+                None if pruned.contains(&episode.id) => continue,
+                None => {
+                    drift.new_episodes += 1;
+                    drift.changed_at(episode.published_at.or(episode.updated_at));
+                    continue;
+                }
+            };
+            let (played, progress, user_deleted, starred) = *archived;
+            let differs = [
+                ("played", played != episode.played),
+                ("progress", progress != episode.progress),
+                ("user_deleted", user_deleted != episode.user_deleted),
+                ("starred", starred != episode.starred_at.is_some()),
+            ];
+            let mut changed = false;
+            for (column, differs) in differs {
+                if differs && !overrides::is_overridden(conn, "episodes", &episode.id, column)? {
+                    changed = true;
+                }
+            }
+            if changed {
+                drift.changed_episodes += 1;
+                drift.changed_at(episode.updated_at);
+            }
+        }
+    }
+    Ok(drift)
+}
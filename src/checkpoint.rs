@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+
+use rusqlite::{params, Connection};
+
+// How long an interrupted pass can be resumed. Feeds finished longer ago than this are done
+// again, as what was fetched for them is getting old.
+const RESUME_WITHIN_HOURS: i64 = 24;
+
+// Per-feed completion markers for a pass over the feeds, in `sync_state`, so a run that dies
+// partway through (killed, or failed on feed 180 of 300) is picked up where it left off by the
+// next one instead of starting over. The markers are cleared once the pass finishes.
+pub struct Checkpoint<'a> {
+    conn: &'a Connection,
+    step: &'static str,
+    done: HashSet<String>,
+}
+
+impl<'a> Checkpoint<'a> {
+    // Starts `step`'s pass, or resumes its interrupted one.
+    pub fn resume(
+        conn: &'a Connection,
+        step: &'static str,
+    ) -> Result<Checkpoint<'a>, Box<dyn std::error::Error>> {
+        conn.execute(
+            "DELETE FROM sync_state WHERE step = ? AND completed_at < datetime('now', ?)",
+            params![step, format!("-{} hours", RESUME_WITHIN_HOURS)],
+        )?;
+        let mut stmt = conn.prepare("SELECT feed_id FROM sync_state WHERE step = ?")?;
+        let done = stmt
+            .query_map(params![step], |row| row.get::<_, i64>(0))?
+            .map(|id| id.map(|id| id.to_string()))
+            .collect::<Result<_, _>>()?;
+        Ok(Checkpoint { conn, step, done })
+    }
+
+    // How many feeds an interrupted pass had finished.
+    pub fn resumed(&self) -> usize {
+        self.done.len()
+    }
+
+    pub fn is_done(&self, feed_id: &str) -> bool {
+        self.done.contains(feed_id)
+    }
+
+    pub fn mark_done(&self, feed_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO sync_state (step, feed_id, completed_at)
+            VALUES (?, ?, datetime('now'))",
+            params![self.step, feed_id],
+        )?;
+        Ok(())
+    }
+
+    // Ends the pass, so the next one starts from the first feed.
+    pub fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn
+            .execute("DELETE FROM sync_state WHERE step = ?", params![self.step])?;
+        Ok(())
+    }
+}
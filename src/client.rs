@@ -0,0 +1,33 @@
+use std::sync::OnceLock;
+
+// The User-Agent sent with every HTTP request: to Overcast, feeds, enclosures and the services
+// archives are sent to. Set once, at startup, from --user-agent and --contact.
+static USER_AGENT: OnceLock<String> = OnceLock::new();
+
+const PRODUCT: &str = concat!("overcast-to-sqlite/", env!("CARGO_PKG_VERSION"));
+const HOMEPAGE: &str = "https://github.com/bcongdon/overcast-to-sqlite";
+
+// Sets the User-Agent: `user_agent` in place of the default, with `contact` (a URL or email
+// address, for hosts to reach whoever's fetching) added. Only the first call has any effect.
+pub fn set_user_agent(user_agent: Option<&str>, contact: Option<&str>) {
+    let _ = USER_AGENT.set(match (user_agent, contact) {
+        (None, None) => format!("{} (+{})", PRODUCT, HOMEPAGE),
+        (None, Some(contact)) => format!("{} (+{}; contact: {})", PRODUCT, HOMEPAGE, contact),
+        (Some(user_agent), None) => user_agent.to_string(),
+        (Some(user_agent), Some(contact)) => format!("{} (contact: {})", user_agent, contact),
+    });
+}
+
+pub fn user_agent() -> &'static str {
+    USER_AGENT.get_or_init(|| format!("{} (+{})", PRODUCT, HOMEPAGE))
+}
+
+// A client builder with the User-Agent set, for clients that need other settings too.
+pub fn builder() -> reqwest::blocking::ClientBuilder {
+    reqwest::blocking::Client::builder().user_agent(user_agent())
+}
+
+// A client with the User-Agent set, in place of `reqwest::blocking::Client::new()`.
+pub fn new() -> reqwest::blocking::Client {
+    builder().build().expect("reqwest client")
+}
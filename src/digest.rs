@@ -0,0 +1,170 @@
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection};
+
+use crate::chart::escape;
+use crate::recommend;
+use crate::report::format_seconds;
+use crate::timezone::DisplayTimezone;
+
+// How many of `recommend`'s suggestions the digest ends with.
+const RECOMMENDATIONS: usize = 5;
+
+// An episode in the digest.
+pub struct DigestEpisode {
+    pub id: i64,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub published_at: Option<NaiveDateTime>,
+    pub duration: Option<i64>,
+}
+
+// A feed with new episodes.
+pub struct DigestFeed {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub episodes: Vec<DigestEpisode>,
+}
+
+// What a digest lists: new episodes by feed, then suggestions from the backlog.
+pub struct Digest {
+    pub feeds: Vec<DigestFeed>,
+    pub recommended: Vec<(recommend::Recommendation, Option<String>)>,
+}
+
+impl Digest {
+    pub fn episode_count(&self) -> usize {
+        self.feeds.iter().map(|feed| feed.episodes.len()).sum()
+    }
+}
+
+// The unplayed episodes of subscribed feeds that no digest has listed yet, published in the last
+// `days` days (which is as far back as the first digest looks), with a few recommendations.
+pub fn new_episodes(conn: &Connection, days: u32) -> Result<Digest, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.title, COALESCE(e.html_url, e.overcast_url), e.published_at,
+            p.duration_seconds, f.id, f.title, f.html_url
+        FROM episodes e
+        JOIN feeds f ON f.id = e.feed_id
+        LEFT JOIN episode_progress p ON p.episode_id = e.id
+        WHERE f.subscribed AND NOT e.played AND NOT e.user_deleted
+            AND e.published_at >= datetime('now', ?)
+            AND e.id NOT IN (SELECT episode_id FROM digest_episodes)
+        ORDER BY f.title COLLATE NOCASE, f.id, e.published_at DESC",
+    )?;
+    let mut feeds: Vec<(i64, DigestFeed)> = Vec::new();
+    let mut rows = stmt.query(params![format!("-{} days", days)])?;
+    while let Some(row) = rows.next()? {
+        let feed_id: i64 = row.get(5)?;
+        if feeds.last().map(|(id, _)| *id) != Some(feed_id) {
+            feeds.push((
+                feed_id,
+                DigestFeed {
+                    title: row.get(6)?,
+                    url: row.get(7)?,
+                    episodes: Vec::new(),
+                },
+            ));
+        }
+        let episode = DigestEpisode {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            url: row.get(2)?,
+            published_at: row.get(3)?,
+            duration: row.get(4)?,
+        };
+        feeds.last_mut().unwrap().1.episodes.push(episode);
+    }
+
+    let mut recommended = Vec::new();
+    for r in recommend::recommend(conn, RECOMMENDATIONS)? {
+        let url = conn.query_row(
+            "SELECT COALESCE(html_url, overcast_url) FROM episodes WHERE id = ?",
+            params![r.id],
+            |row| row.get(0),
+        )?;
+        recommended.push((r, url));
+    }
+    Ok(Digest {
+        feeds: feeds.into_iter().map(|(_, feed)| feed).collect(),
+        recommended,
+    })
+}
+
+// The email's subject line.
+pub fn subject(digest: &Digest) -> String {
+    match digest.episode_count() {
+        1 => "1 new podcast episode".to_string(),
+        n => format!("{} new podcast episodes", n),
+    }
+}
+
+// An optional link around already-escaped text.
+fn link(url: &Option<String>, text: &str) -> String {
+    match url {
+        Some(url) => format!("<a href=\"{}\">{}</a>", escape(url), text),
+        None => text.to_string(),
+    }
+}
+
+// The digest as an HTML email body. Styles are inline, since most mail clients ignore <style>.
+pub fn html(digest: &Digest, tz: &DisplayTimezone) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"></head>\n\
+         <body style=\"font-family: -apple-system, Helvetica, Arial, sans-serif; \
+         max-width: 40em; margin: 0 auto; color: #222;\">\n",
+    );
+    html.push_str(&format!("<h1>{}</h1>\n", escape(&subject(digest))));
+    for feed in &digest.feeds {
+        let title = escape(feed.title.as_deref().unwrap_or("Untitled feed"));
+        html.push_str(&format!("<h2>{}</h2>\n<ul>\n", link(&feed.url, &title)));
+        for episode in &feed.episodes {
+            let title = escape(episode.title.as_deref().unwrap_or("Untitled episode"));
+            let mut details = Vec::new();
+            if let Some(duration) = episode.duration.filter(|&d| d > 0) {
+                details.push(format_seconds(duration));
+            }
+            if let Some(published_at) = &episode.published_at {
+                details.push(tz.format(published_at, "%b %-d"));
+            }
+            html.push_str(&format!(
+                "<li>{} <span style=\"color: #777;\">{}</span></li>\n",
+                link(&episode.url, &title),
+                escape(&details.join(" · "))
+            ));
+        }
+        html.push_str("</ul>\n");
+    }
+    if !digest.recommended.is_empty() {
+        html.push_str("<h2>From your backlog</h2>\n<ul>\n");
+        for (r, url) in &digest.recommended {
+            let remaining = r
+                .remaining_seconds
+                .filter(|&s| s > 0)
+                .map(|s| format!(", {} left", format_seconds(s)))
+                .unwrap_or_default();
+            html.push_str(&format!(
+                "<li>{} <span style=\"color: #777;\">{}{}</span></li>\n",
+                link(url, &escape(&r.title)),
+                escape(&r.feed_title),
+                remaining
+            ));
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+// Records the digest's episodes as sent, so the next digest starts after them.
+pub fn mark_sent(conn: &Connection, digest: &Digest) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    let sent_at = chrono::Utc::now().naive_utc();
+    for episode in digest.feeds.iter().flat_map(|feed| &feed.episodes) {
+        tx.execute(
+            "INSERT OR IGNORE INTO digest_episodes(episode_id, sent_at) VALUES (?, ?)",
+            params![episode.id, sent_at],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
@@ -0,0 +1,326 @@
+use std::time::Duration;
+
+use rusqlite::{params, Connection};
+use serde_json::Value;
+
+use crate::client;
+use crate::storage::hex;
+use crate::urls;
+
+const PODCAST_INDEX_URL: &str = "https://api.podcastindex.org/api/1.0";
+const LISTEN_NOTES_URL: &str = "https://listen-api.listennotes.com/api/v2";
+// Both APIs' free tiers are rate-limited, so requests are spaced out.
+const REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+// How many of a feed's categories to look for trending shows in, with Podcast Index.
+const CATEGORIES_PER_FEED: usize = 2;
+const SUGGESTIONS_PER_QUERY: usize = 10;
+
+// A podcast discovery API. Listen Notes has real "similar podcast" recommendations; Podcast Index
+// doesn't, so suggestions from it are the shows trending in each feed's categories.
+pub enum DiscoveryClient {
+    PodcastIndex {
+        client: reqwest::blocking::Client,
+        key: String,
+        secret: String,
+    },
+    ListenNotes {
+        client: reqwest::blocking::Client,
+        key: String,
+    },
+}
+
+// A show suggested because of one of the archive's feeds.
+pub struct Suggestion {
+    pub source_id: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub feed_url: Option<String>,
+    pub website: Option<String>,
+    // Why it was suggested, like "trending in Technology".
+    pub reason: String,
+}
+
+// Counts of a `discover` run.
+#[derive(Default)]
+pub struct DiscoverCounts {
+    pub feeds: usize,
+    pub suggestions: usize,
+}
+
+// A stored suggestion, for listing.
+pub struct Listed {
+    pub title: String,
+    pub author: Option<String>,
+    pub feed_url: Option<String>,
+    pub website: Option<String>,
+    // The titles of the feeds it was suggested because of.
+    pub based_on: Vec<String>,
+    pub subscribed: bool,
+}
+
+impl DiscoveryClient {
+    pub fn podcast_index(key: &str, secret: &str) -> DiscoveryClient {
+        DiscoveryClient::PodcastIndex {
+            client: client::new(),
+            key: key.to_string(),
+            secret: secret.to_string(),
+        }
+    }
+
+    pub fn listen_notes(key: &str) -> DiscoveryClient {
+        DiscoveryClient::ListenNotes {
+            client: client::new(),
+            key: key.to_string(),
+        }
+    }
+
+    // The name suggestions from this API are stored under.
+    pub fn source(&self) -> &'static str {
+        match self {
+            DiscoveryClient::PodcastIndex { .. } => "podcastindex",
+            DiscoveryClient::ListenNotes { .. } => "listennotes",
+        }
+    }
+
+    // Finds shows related to the feed at `feed_url`.
+    pub fn suggest(
+        &self,
+        feed_url: &str,
+        feed_title: &str,
+    ) -> Result<Vec<Suggestion>, Box<dyn std::error::Error>> {
+        match self {
+            DiscoveryClient::PodcastIndex { .. } => {
+                let feed = self.podcast_index_get("/podcasts/byfeedurl", &[("url", feed_url)])?;
+                let categories: Vec<String> = feed["feed"]["categories"]
+                    .as_object()
+                    .map(|c| {
+                        c.values()
+                            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let mut suggestions = Vec::new();
+                for category in categories.iter().take(CATEGORIES_PER_FEED) {
+                    let max = SUGGESTIONS_PER_QUERY.to_string();
+                    let trending = self.podcast_index_get(
+                        "/podcasts/trending",
+                        &[("cat", category.as_str()), ("max", max.as_str())],
+                    )?;
+                    for show in trending["feeds"].as_array().into_iter().flatten() {
+                        let title = match show["title"].as_str() {
+                            Some(title) => title,
+                            None => continue,
+                        };
+                        suggestions.push(Suggestion {
+                            source_id: show["id"].to_string(),
+                            title: title.to_string(),
+                            author: string(&show["author"]),
+                            feed_url: string(&show["url"]),
+                            website: string(&show["link"]),
+                            reason: format!("trending in {}", category),
+                        });
+                    }
+                }
+                Ok(suggestions)
+            }
+            DiscoveryClient::ListenNotes { client, key } => {
+                std::thread::sleep(REQUEST_INTERVAL);
+                let found: Value = client
+                    .post(format!("{}/podcasts", LISTEN_NOTES_URL))
+                    .header("X-ListenAPI-Key", key)
+                    .form(&[("rsses", feed_url)])
+                    .send()?
+                    .error_for_status()?
+                    .json()?;
+                let id = match found["podcasts"][0]["id"].as_str() {
+                    Some(id) => id.to_string(),
+                    None => return Ok(Vec::new()),
+                };
+                std::thread::sleep(REQUEST_INTERVAL);
+                let recommended: Value = client
+                    .get(format!(
+                        "{}/podcasts/{}/recommendations",
+                        LISTEN_NOTES_URL, id
+                    ))
+                    .header("X-ListenAPI-Key", key)
+                    .send()?
+                    .error_for_status()?
+                    .json()?;
+                Ok(recommended["recommendations"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|show| {
+                        Some(Suggestion {
+                            source_id: show["id"].as_str()?.to_string(),
+                            title: show["title"].as_str()?.to_string(),
+                            author: string(&show["publisher"]),
+                            feed_url: string(&show["rss"]),
+                            website: string(&show["website"])
+                                .or_else(|| string(&show["listennotes_url"])),
+                            reason: format!("similar to {}", feed_title),
+                        })
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    // Makes a signed Podcast Index request: the Authorization header is the SHA-1 of the key,
+    // secret and current Unix time.
+    fn podcast_index_get(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let (client, key, secret) = match self {
+            DiscoveryClient::PodcastIndex {
+                client,
+                key,
+                secret,
+            } => (client, key, secret),
+            _ => unreachable!("not a Podcast Index client"),
+        };
+        std::thread::sleep(REQUEST_INTERVAL);
+        let now = chrono::Utc::now().timestamp().to_string();
+        let signature = hex(&openssl::sha::sha1(
+            format!("{}{}{}", key, secret, now).as_bytes(),
+        ));
+        Ok(client
+            .get(format!("{}{}", PODCAST_INDEX_URL, path))
+            .query(query)
+            .header("X-Auth-Key", key)
+            .header("X-Auth-Date", &now)
+            .header(reqwest::header::AUTHORIZATION, signature)
+            .send()?
+            .error_for_status()?
+            .json()?)
+    }
+}
+
+fn string(value: &Value) -> Option<String> {
+    value
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+// Asks the discovery API for shows related to the `top_feeds` subscribed feeds you've listened to
+// the most, and stores them in `suggestions`, replacing that source's earlier suggestions for
+// those feeds. Feeds the API doesn't know are skipped.
+pub fn discover(
+    conn: &Connection,
+    client: &DiscoveryClient,
+    top_feeds: usize,
+) -> Result<DiscoverCounts, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT f.id, f.title, f.feed_url FROM feeds f
+        JOIN listening_time l ON l.feed_id = f.id
+        WHERE f.subscribed AND f.feed_url IS NOT NULL
+        GROUP BY f.id
+        ORDER BY SUM(l.seconds) DESC
+        LIMIT ?",
+    )?;
+    let feeds = stmt
+        .query_map(params![top_feeds as i64], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut counts = DiscoverCounts::default();
+    for (feed_id, title, feed_url) in feeds {
+        let suggestions = match client.suggest(&feed_url, &title) {
+            Ok(suggestions) => suggestions,
+            Err(err) => {
+                eprintln!("Skipping \"{}\": {}", title, err);
+                continue;
+            }
+        };
+        counts.feeds += 1;
+        let tx = conn.unchecked_transaction()?;
+        conn.execute(
+            "DELETE FROM suggestions WHERE source = ? AND based_on_feed_id = ?",
+            params![client.source(), feed_id],
+        )?;
+        for s in &suggestions {
+            counts.suggestions += conn.execute(
+                "INSERT OR IGNORE INTO suggestions(
+                    source, source_id, based_on_feed_id, title, author, feed_url, website, reason,
+                    suggested_at
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    client.source(),
+                    s.source_id,
+                    feed_id,
+                    s.title,
+                    s.author,
+                    s.feed_url,
+                    s.website,
+                    s.reason,
+                    chrono::Utc::now().naive_utc(),
+                ],
+            )?;
+        }
+        tx.commit()?;
+    }
+    Ok(counts)
+}
+
+// Lists stored suggestions, the ones suggested because of the most feeds first. Whether you're
+// already subscribed is decided by normalized feed URL, or failing that title.
+pub fn list(conn: &Connection, new_only: bool) -> Result<Vec<Listed>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT title, feed_url FROM feeds WHERE subscribed")?;
+    let subscriptions = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(1)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let subscribed_urls: Vec<String> = subscriptions
+        .iter()
+        .filter_map(|(_, url)| url.as_deref().map(urls::normalize))
+        .collect();
+
+    let mut stmt = conn.prepare(
+        "SELECT s.title, MAX(s.author), MAX(s.feed_url), MAX(s.website), group_concat(f.title, '\n')
+        FROM suggestions s
+        JOIN feeds f ON f.id = s.based_on_feed_id
+        GROUP BY s.title, COALESCE(s.feed_url, '')
+        ORDER BY COUNT(DISTINCT s.based_on_feed_id) DESC, s.title",
+    )?;
+    let listed = stmt
+        .query_map([], |row| {
+            let based_on: String = row.get(4)?;
+            let mut based_on: Vec<String> = based_on.split('\n').map(|s| s.to_string()).collect();
+            based_on.sort();
+            based_on.dedup();
+            Ok(Listed {
+                title: row.get(0)?,
+                author: row.get(1)?,
+                feed_url: row.get(2)?,
+                website: row.get(3)?,
+                based_on,
+                subscribed: false,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(listed
+        .into_iter()
+        .map(|mut l| {
+            l.subscribed = match &l.feed_url {
+                Some(url) => subscribed_urls.contains(&urls::normalize(url)),
+                None => subscriptions
+                    .iter()
+                    .any(|(title, _)| title.eq_ignore_ascii_case(&l.title)),
+            };
+            l
+        })
+        .filter(|l| !(new_only && l.subscribed))
+        .collect())
+}
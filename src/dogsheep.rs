@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+// The tables of the Python overcast-to-sqlite, as used by Datasette and Dogsheep Beta: Overcast's
+// OPML attribute names, with each row keyed by `overcastId`.
+const SCHEMA: &str = "
+    DROP TABLE IF EXISTS episodes;
+    DROP TABLE IF EXISTS feeds;
+    CREATE TABLE feeds (
+        overcastId INTEGER PRIMARY KEY,
+        title TEXT,
+        subscribed INTEGER,
+        overcastAddedDate TEXT,
+        notifications INTEGER,
+        xmlUrl TEXT,
+        htmlUrl TEXT,
+        dateRemoveDetected TEXT
+    );
+    CREATE TABLE episodes (
+        overcastId INTEGER PRIMARY KEY,
+        feedId INTEGER REFERENCES feeds(overcastId),
+        title TEXT,
+        url TEXT,
+        overcastUrl TEXT,
+        played INTEGER,
+        progress INTEGER,
+        pubDate TEXT,
+        userUpdatedDate TEXT,
+        userRecommendedDate TEXT,
+        userDeleted INTEGER,
+        enclosureUrl TEXT
+    );
+";
+
+// Timestamps are written like the OPML's, e.g. "2021-01-12T05:57:04+00:00", in UTC.
+const TIMESTAMP: &str = "strftime('%Y-%m-%dT%H:%M:%S+00:00', {})";
+
+// Counts of a Dogsheep export.
+pub struct DogsheepCounts {
+    pub feeds: usize,
+    pub episodes: usize,
+}
+
+// Writes the archive's feeds and episodes to the SQLite database at `output` in the Python
+// overcast-to-sqlite's layout, so Datasette metadata, canned queries and Dogsheep Beta configs
+// written for it work unchanged. Existing `feeds` and `episodes` tables there are replaced, so
+// exporting after each `archive` keeps it current; other tables are left alone.
+pub fn export(
+    conn: &Connection,
+    output: &Path,
+) -> Result<DogsheepCounts, Box<dyn std::error::Error>> {
+    let out = Connection::open(output)?;
+    let tx = out.unchecked_transaction()?;
+    tx.execute_batch(SCHEMA)?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT f.id, f.title, f.subscribed, {}, s.value, f.feed_url, f.html_url
+        FROM feeds f
+        LEFT JOIN feed_settings s ON s.feed_id = f.id AND s.name = 'notifications'",
+        TIMESTAMP.replace("{}", "f.added_at")
+    ))?;
+    let mut feeds = 0;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        // Overcast writes notifications="1" for feeds with notifications on.
+        let notifications = row
+            .get::<_, Option<String>>(4)?
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"));
+        tx.execute(
+            "INSERT INTO feeds(
+                overcastId, title, subscribed, overcastAddedDate, notifications, xmlUrl, htmlUrl
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<bool>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                notifications,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, Option<String>>(6)?,
+            ],
+        )?;
+        feeds += 1;
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, feed_id, title, html_url, overcast_url, played, progress, {}, {}, {},
+            user_deleted, mp3_url
+        FROM episodes
+        WHERE feed_id IN (SELECT id FROM feeds)",
+        TIMESTAMP.replace("{}", "published_at"),
+        TIMESTAMP.replace("{}", "updated_at"),
+        TIMESTAMP.replace("{}", "starred_at")
+    ))?;
+    let mut episodes = 0;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        tx.execute(
+            "INSERT INTO episodes(
+                overcastId, feedId, title, url, overcastUrl, played, progress, pubDate,
+                userUpdatedDate, userRecommendedDate, userDeleted, enclosureUrl
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<bool>>(5)?,
+                row.get::<_, Option<i64>>(6)?,
+                row.get::<_, Option<String>>(7)?,
+                row.get::<_, Option<String>>(8)?,
+                row.get::<_, Option<String>>(9)?,
+                row.get::<_, Option<bool>>(10)?,
+                row.get::<_, Option<String>>(11)?,
+            ],
+        )?;
+        episodes += 1;
+    }
+    tx.commit()?;
+    Ok(DogsheepCounts { feeds, episodes })
+}
@@ -0,0 +1,638 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::audio;
+use crate::client;
+use crate::export::sanitize_filename;
+use crate::sqlite;
+use crate::storage::{sha256_file, Storage};
+
+// An archived episode to download.
+pub struct DownloadEpisode {
+    pub id: i64,
+    pub title: String,
+    pub feed_id: i64,
+    pub feed_title: String,
+    pub published_at: Option<NaiveDateTime>,
+    pub mp3_url: String,
+}
+
+// The longest a file or directory name can be, in bytes. Most filesystems allow 255, and this
+// leaves room for the ".part" suffix of partial downloads.
+const MAX_NAME_BYTES: usize = 240;
+
+// Where downloaded audio is saved, relative to the download directory, like
+// "{feed_title}/{published:%Y-%m-%d} {episode_title}.{ext}". `/` separates directories.
+pub struct PathTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+enum TemplatePart {
+    Literal(String),
+    // A placeholder, with its strftime format for `published`.
+    Field(String, Option<String>),
+}
+
+const TEMPLATE_FIELDS: [&str; 6] = [
+    "feed_title",
+    "feed_id",
+    "episode_title",
+    "episode_id",
+    "published",
+    "ext",
+];
+
+impl FromStr for PathTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('/') || s.split('/').any(|c| c == ".." || c.trim().is_empty()) {
+            return Err(format!(
+                "invalid path template \"{}\": it must be a relative path without empty or \"..\" components",
+                s
+            ));
+        }
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TemplatePart::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed \"{{\" in path template \"{}\"", s))?;
+            let placeholder = &rest[start + 1..start + end];
+            let (name, format) = match placeholder.split_once(':') {
+                Some((name, format)) => (name, Some(format.to_string())),
+                None => (placeholder, None),
+            };
+            if !TEMPLATE_FIELDS.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder {{{}}} in path template; expected one of {}",
+                    name,
+                    TEMPLATE_FIELDS.join(", ")
+                ));
+            }
+            if let Some(format) = &format {
+                if name != "published" {
+                    return Err(format!("{{{}}} doesn't take a format", name));
+                }
+                if format.contains('/') || StrftimeItems::new(format).any(|i| i == Item::Error) {
+                    return Err(format!("invalid date format \"{}\"", format));
+                }
+            }
+            parts.push(TemplatePart::Field(name.to_string(), format));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Literal(rest.to_string()));
+        }
+        Ok(PathTemplate { parts })
+    }
+}
+
+impl PathTemplate {
+    // The path an episode's audio is saved to. Placeholder values are sanitized so they can't
+    // add directories, and each file or directory name is shortened to fit filesystem limits.
+    fn render(&self, episode: &DownloadEpisode) -> PathBuf {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => rendered.push_str(text),
+                TemplatePart::Field(name, format) => {
+                    let value = match name.as_str() {
+                        "feed_title" => sanitize_filename(&episode.feed_title),
+                        "feed_id" => episode.feed_id.to_string(),
+                        "episode_title" => sanitize_filename(&episode.title),
+                        "episode_id" => episode.id.to_string(),
+                        "published" => match episode.published_at {
+                            Some(published) => published
+                                .format(format.as_deref().unwrap_or("%Y-%m-%d"))
+                                .to_string()
+                                .replace(':', "-"),
+                            None => "undated".to_string(),
+                        },
+                        _ => extension(&episode.mp3_url),
+                    };
+                    rendered.push_str(&value);
+                }
+            }
+        }
+        rendered.split('/').map(truncate_name).collect()
+    }
+}
+
+// Shortens a file or directory name to MAX_NAME_BYTES, keeping its extension.
+fn truncate_name(name: &str) -> String {
+    let name = name.trim();
+    if name.len() <= MAX_NAME_BYTES {
+        return name.to_string();
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if extension.len() <= 5 => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+    let mut end = MAX_NAME_BYTES - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", stem[..end].trim_end(), extension)
+}
+
+// The outcome of a `download` run.
+#[derive(Default)]
+pub struct DownloadCounts {
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+// The outcome of verifying downloaded files.
+#[derive(Default)]
+pub struct VerifyCounts {
+    pub ok: usize,
+    pub missing: Vec<String>,
+    pub corrupt: Vec<String>,
+}
+
+// Looks up the episodes to download: those with the given IDs or in the given feeds, plus those
+// whose IDs `query` returns. With none of these, starred episodes are downloaded.
+pub fn select_episodes(
+    conn: &Connection,
+    episode_ids: &[i64],
+    feed_ids: &[i64],
+    query: Option<&str>,
+) -> Result<Vec<DownloadEpisode>, Box<dyn std::error::Error>> {
+    let mut ids: Vec<i64> = episode_ids.to_vec();
+    let mut add_ids = |sql: &str, param: Option<i64>| -> Result<(), rusqlite::Error> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = match param {
+            Some(param) => stmt
+                .query_map(params![param], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?,
+            None => stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?,
+        };
+        ids.extend(rows);
+        Ok(())
+    };
+    for feed_id in feed_ids {
+        add_ids("SELECT id FROM episodes WHERE feed_id = ?", Some(*feed_id))?;
+    }
+    if let Some(query) = query {
+        add_ids(query, None)?;
+    }
+    if episode_ids.is_empty() && feed_ids.is_empty() && query.is_none() {
+        add_ids("SELECT id FROM episodes WHERE starred", None)?;
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let mut episodes = Vec::new();
+    for id in ids {
+        if !seen.insert(id) {
+            continue;
+        }
+        let episode = conn
+            .query_row(
+                "SELECT e.title, e.feed_id, f.title, e.published_at, e.mp3_url
+                FROM episodes e
+                JOIN feeds f ON f.id = e.feed_id
+                WHERE e.id = ?",
+                params![id],
+                |row| {
+                    Ok(DownloadEpisode {
+                        id,
+                        title: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                        feed_id: row.get(1)?,
+                        feed_title: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                        published_at: row.get(3)?,
+                        mp3_url: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    })
+                },
+            )
+            .optional()?;
+        match episode {
+            Some(episode) if episode.mp3_url.is_empty() => {
+                eprintln!("Skipping \"{}\", which has no audio URL.", episode.title)
+            }
+            Some(episode) => episodes.push(episode),
+            None => return Err(format!("No episode with ID {} in the archive.", id).into()),
+        }
+    }
+    Ok(episodes)
+}
+
+// Keeps only the `max` most recently published of each feed's episodes, in their original order.
+pub fn limit_per_feed(episodes: Vec<DownloadEpisode>, max: usize) -> Vec<DownloadEpisode> {
+    let mut by_date: Vec<&DownloadEpisode> = episodes.iter().collect();
+    by_date.sort_by_key(|e| std::cmp::Reverse(e.published_at));
+    let mut per_feed: std::collections::HashMap<i64, usize> = std::collections::HashMap::new();
+    let mut kept = std::collections::HashSet::new();
+    for episode in by_date {
+        let count = per_feed.entry(episode.feed_id).or_default();
+        if *count < max {
+            *count += 1;
+            kept.insert(episode.id);
+        }
+    }
+    episodes
+        .into_iter()
+        .filter(|e| kept.contains(&e.id))
+        .collect()
+}
+
+// The audio file's extension, from its URL.
+fn extension(mp3_url: &str) -> String {
+    Path::new(mp3_url.split(['?', '#']).next().unwrap_or_default())
+        .extension()
+        .and_then(|e| e.to_str())
+        .filter(|e| e.len() <= 4 && e.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("mp3")
+        .to_lowercase()
+}
+
+// Downloads each episode's audio to its path in `storage` (per `template`), recording it in the
+// `downloads` table with its size and SHA-256. Episodes that are already downloaded are skipped.
+// Interrupted downloads are left as `.part` files and resumed with a range request next time. A
+// download whose size doesn't match the server's Content-Length is treated as failed.
+pub fn download(
+    conn: &Connection,
+    storage: &Storage,
+    template: &PathTemplate,
+    episodes: &[DownloadEpisode],
+) -> Result<DownloadCounts, Box<dyn std::error::Error>> {
+    // Audio files can take longer than reqwest's default timeout to download.
+    let client = client::builder().timeout(None).build()?;
+    let mut counts = DownloadCounts::default();
+    for (i, episode) in episodes.iter().enumerate() {
+        let mut relative = template.render(episode);
+        let taken: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM downloads WHERE path = ? AND episode_id != ?)",
+            params![relative.to_string_lossy(), episode.id],
+            |row| row.get(0),
+        )?;
+        if taken {
+            // Templates without {episode_id} can give two episodes the same path.
+            let stem = relative.file_stem().unwrap_or_default().to_string_lossy();
+            let mut name = truncate_name(&format!("{} ({})", stem, episode.id));
+            if let Some(extension) = relative.extension() {
+                name = format!("{}.{}", name, extension.to_string_lossy());
+            }
+            relative.set_file_name(name);
+        }
+        let key = relative.to_string_lossy().into_owned();
+        let recorded: Option<String> = conn
+            .query_row(
+                "SELECT path FROM downloads WHERE episode_id = ?",
+                params![episode.id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(recorded) = recorded {
+            if storage.exists(&recorded)? {
+                counts.skipped += 1;
+                continue;
+            }
+        }
+        eprintln!(
+            "[{}/{}] Downloading \"{}\"...",
+            i + 1,
+            episodes.len(),
+            episode.title
+        );
+        let path = storage.staging_path(&key);
+        match download_file(&client, &episode.mp3_url, &path) {
+            Ok(bytes) => {
+                let sha256 = sha256_file(&path)?;
+                // Metadata is a bonus, so files it can't be read from are still kept.
+                match audio::read(&path) {
+                    Ok(Some(metadata)) => audio::record(conn, episode.id, &metadata)?,
+                    Ok(None) => {}
+                    Err(err) => eprintln!("Couldn't read tags of \"{}\": {}", episode.title, err),
+                }
+                storage.store(&key)?;
+                conn.execute(
+                    "INSERT INTO downloads(
+                        episode_id, path, remote_url, url, bytes, sha256, downloaded_at
+                    ) VALUES (?, ?, ?, ?, ?, ?, ?)
+                    ON CONFLICT(episode_id) DO UPDATE SET
+                        path = excluded.path,
+                        remote_url = excluded.remote_url,
+                        url = excluded.url,
+                        bytes = excluded.bytes,
+                        sha256 = excluded.sha256,
+                        downloaded_at = excluded.downloaded_at,
+                        verified_at = NULL",
+                    params![
+                        episode.id,
+                        key,
+                        storage.remote_url(&key),
+                        episode.mp3_url,
+                        bytes as i64,
+                        sha256,
+                        chrono::Utc::now().naive_utc(),
+                    ],
+                )?;
+                counts.downloaded += 1;
+            }
+            Err(err) => {
+                eprintln!("Failed to download \"{}\": {}", episode.title, err);
+                counts.failed += 1;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+// Downloads a URL to `path`, resuming a previous partial download if there is one. Returns the
+// file's size.
+fn download_file(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    path: &Path,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut part_name = path.as_os_str().to_owned();
+    part_name.push(".part");
+    let part = PathBuf::from(part_name);
+    let existing = std::fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+
+    let mut request = client.get(url);
+    if existing > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", existing));
+    }
+    let resp = request.send()?;
+    let content_range = resp
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_content_range);
+    if existing > 0 && resp.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // A run stopped after writing the last byte, but before the rename, leaves the whole file
+        // in the .part, so there's nothing after it to send.
+        if let Some((_, Some(total))) = content_range {
+            if total == existing {
+                std::fs::rename(&part, path)?;
+                return Ok(existing);
+            }
+        }
+        std::fs::remove_file(&part)?;
+        return Err(
+            "the partial download doesn't match the file; it will be downloaded again next time"
+                .into(),
+        );
+    }
+    let mut resp = resp.error_for_status()?;
+    // Servers that ignore the range send the whole file again.
+    let resumed = resp.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    if resumed {
+        let start = content_range.and_then(|(start, _)| start);
+        if start != Some(existing) {
+            std::fs::remove_file(&part)?;
+            return Err(format!(
+                "asked to resume from byte {}, but the server sent a different range; it will be \
+                 downloaded again next time",
+                existing
+            )
+            .into());
+        }
+    }
+    let expected = resp
+        .content_length()
+        .map(|len| if resumed { len + existing } else { len });
+    let mut file = if resumed {
+        OpenOptions::new().append(true).open(&part)?
+    } else {
+        File::create(&part)?
+    };
+    let copied = std::io::copy(&mut resp, &mut file)?;
+    file.flush()?;
+    let total = if resumed { existing + copied } else { copied };
+    if let Some(expected) = expected {
+        if total != expected {
+            return Err(format!(
+                "expected {} bytes but got {}; the partial download will be resumed next time",
+                expected, total
+            )
+            .into());
+        }
+    }
+    std::fs::rename(&part, path)?;
+    Ok(total)
+}
+
+// Parses a Content-Range header, like "bytes 100-199/1000" or "bytes */1000", into the offset of
+// the first byte sent (if any were) and the file's total size (if the server knows it).
+fn parse_content_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let start = match range.trim() {
+        "*" => None,
+        range => Some(range.split_once('-')?.0.trim().parse().ok()?),
+    };
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((start, total))
+}
+
+// Hashes every recorded download, comparing against the SHA-256 taken when it was downloaded.
+// Files that match get their `verified_at` updated. Missing and corrupt files are forgotten (their
+// rows tombstoned into `downloads_deleted`), so the next `download` run fetches them again.
+pub fn verify(
+    conn: &Connection,
+    storage: &Storage,
+) -> Result<VerifyCounts, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT episode_id, path, sha256 FROM downloads ORDER BY path")?;
+    let downloads = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut counts = VerifyCounts::default();
+    for (episode_id, relative, expected) in downloads {
+        let actual = storage.sha256(&relative)?;
+        if actual.as_deref() != Some(expected.as_str()) {
+            sqlite::tombstone(conn, "downloads", "episode_id = ?", params![episode_id])?;
+            if actual.is_some() {
+                counts.corrupt.push(relative);
+            } else {
+                counts.missing.push(relative);
+            }
+        } else {
+            conn.execute(
+                "UPDATE downloads SET verified_at = ? WHERE episode_id = ?",
+                params![chrono::Utc::now().naive_utc(), episode_id],
+            )?;
+            counts.ok += 1;
+        }
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    // Serves one request with `response`, returning the URL to request.
+    fn serve_once(response: &'static str) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/episode.mp3", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    // A path in a fresh temporary directory, with `partial` already downloaded to its .part.
+    fn partial_download(name: &str, partial: &[u8]) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("overcast-to-sqlite-download-test-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let part = dir.join("episode.mp3.part");
+        std::fs::write(&part, partial).unwrap();
+        (dir.join("episode.mp3"), part)
+    }
+
+    fn episode(published_at: Option<NaiveDateTime>) -> DownloadEpisode {
+        DownloadEpisode {
+            id: 42,
+            title: "Part 1/2: Intro?".to_string(),
+            feed_id: 7,
+            feed_title: "My Show".to_string(),
+            published_at,
+            mp3_url: "https://example.com/audio/ep.M4A?token=abc".to_string(),
+        }
+    }
+
+    fn render(template: &str, episode: &DownloadEpisode) -> PathBuf {
+        template.parse::<PathTemplate>().unwrap().render(episode)
+    }
+
+    #[test]
+    fn templates_render_each_placeholder() {
+        let published = NaiveDateTime::parse_from_str("2021-03-04 05:06:07", "%Y-%m-%d %H:%M:%S");
+        let dated = episode(published.ok());
+        assert_eq!(
+            render("{feed_title}/{published} {episode_title}.{ext}", &dated),
+            PathBuf::from("My Show/2021-03-04 Part 1-2- Intro-.m4a")
+        );
+        assert_eq!(
+            render("{feed_id}/{episode_id}-{published:%H:%M}.{ext}", &dated),
+            PathBuf::from("7/42-05-06.m4a")
+        );
+        assert_eq!(
+            render("{published:%Y}/{episode_id}", &episode(None)),
+            PathBuf::from("undated/42")
+        );
+    }
+
+    #[test]
+    fn long_names_are_shortened_keeping_the_extension() {
+        let mut episode = episode(None);
+        // Titles are already cut to 120 characters, but these take two bytes each.
+        episode.title = "é".repeat(200);
+        let path = render("{feed_title}/{episode_title}.{ext}", &episode);
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(name.len(), MAX_NAME_BYTES);
+        assert!(name.ends_with("é.m4a"));
+        assert_eq!(truncate_name(&"a".repeat(300)).len(), MAX_NAME_BYTES);
+    }
+
+    #[test]
+    fn invalid_templates() {
+        for template in [
+            "/abs/{episode_id}",
+            "{feed_title}/../{episode_id}",
+            "{feed_title}//{episode_id}",
+            "{episode_id",
+            "{title}",
+            "{episode_id:%Y}",
+            "{published:%Y/%m}",
+            "{published:%Q}",
+        ] {
+            assert!(template.parse::<PathTemplate>().is_err(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn content_ranges() {
+        assert_eq!(
+            parse_content_range("bytes 100-199/1000"),
+            Some((Some(100), Some(1000)))
+        );
+        assert_eq!(parse_content_range("bytes 0-9/*"), Some((Some(0), None)));
+        assert_eq!(
+            parse_content_range("bytes */1000"),
+            Some((None, Some(1000)))
+        );
+        assert_eq!(parse_content_range("bytes 100-199"), None);
+        assert_eq!(parse_content_range("items 0-9/10"), None);
+        assert_eq!(parse_content_range("bytes x-9/10"), None);
+    }
+
+    #[test]
+    fn complete_part_is_finished_on_416() {
+        let (path, part) = partial_download("416", b"hello");
+        let url = serve_once(
+            "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */5\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        let client = reqwest::blocking::Client::new();
+        assert_eq!(download_file(&client, &url, &path).unwrap(), 5);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        assert!(!part.exists());
+    }
+
+    #[test]
+    fn mismatched_part_is_discarded_on_416() {
+        let (path, part) = partial_download("416-mismatch", b"hello");
+        let url = serve_once(
+            "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */3\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        let client = reqwest::blocking::Client::new();
+        assert!(download_file(&client, &url, &path).is_err());
+        assert!(!path.exists() && !part.exists());
+    }
+
+    #[test]
+    fn resumed_range_has_to_start_after_the_part() {
+        let (path, part) = partial_download("206", b"hello");
+        let url = serve_once(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-9/10\r\n\
+             Content-Length: 10\r\nConnection: close\r\n\r\nhelloworld",
+        );
+        let client = reqwest::blocking::Client::new();
+        assert!(download_file(&client, &url, &path).is_err());
+        assert!(!path.exists() && !part.exists());
+
+        let (path, _) = partial_download("206-resumed", b"hello");
+        let url = serve_once(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 5-9/10\r\n\
+             Content-Length: 5\r\nConnection: close\r\n\r\nworld",
+        );
+        assert_eq!(download_file(&client, &url, &path).unwrap(), 10);
+        assert_eq!(std::fs::read(&path).unwrap(), b"helloworld");
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::Write;
+use std::path::Path;
+
+use rusqlite::Connection;
+
+// Tables left out of DuckDB exports: stored files are no use for analysis.
+const SKIPPED_TABLES: [&str; 2] = ["sqlar", "sqlar_deleted"];
+
+// A DuckDB script that copies the archive's tables into a DuckDB database, through DuckDB's
+// `sqlite` extension. Timestamp columns (the ones named "..._at") become TIMESTAMPs and boolean
+// columns BOOLEANs, so they work with DuckDB's date functions and aggregates directly.
+pub fn script(conn: &Connection, db_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let db_path = std::fs::canonicalize(db_path)?;
+    let mut sql = format!(
+        "INSTALL sqlite;\nLOAD sqlite;\nATTACH {} AS overcast (TYPE SQLITE, READ_ONLY);\n",
+        quote(&db_path.to_string_lossy())
+    );
+    let mut stmt = conn.prepare(
+        "SELECT name, sql FROM main.sqlite_master
+        WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+        ORDER BY rowid",
+    )?;
+    let tables = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (table, definition) in tables {
+        if SKIPPED_TABLES.contains(&table.as_str()) {
+            continue;
+        }
+        let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let columns: Vec<String> = columns
+            .iter()
+            .map(|column| {
+                if column.ends_with("_at") {
+                    format!("TRY_CAST(\"{0}\" AS TIMESTAMP) AS \"{0}\"", column)
+                } else if definition.contains(&format!("CHECK ({} IN (0, 1))", column)) {
+                    format!("CAST(\"{0}\" AS BOOLEAN) AS \"{0}\"", column)
+                } else {
+                    format!("\"{}\"", column)
+                }
+            })
+            .collect();
+        sql.push_str(&format!(
+            "CREATE OR REPLACE TABLE \"{0}\" AS SELECT {1} FROM overcast.\"{0}\";\n",
+            table,
+            columns.join(", ")
+        ));
+    }
+    sql.push_str("DETACH overcast;\n");
+    Ok(sql)
+}
+
+// Runs the `script` in the `duckdb` CLI, creating (or updating) the DuckDB database at `output`.
+pub fn export(
+    conn: &Connection,
+    db_path: &Path,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sql = script(conn, db_path)?;
+    let mut child = std::process::Command::new("duckdb")
+        .arg(output)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            format!(
+                "The duckdb format needs the duckdb CLI (https://duckdb.org) on your PATH: {}",
+                err
+            )
+        })?;
+    child
+        .stdin
+        .take()
+        .ok_or("couldn't write to duckdb")?
+        .write_all(sql.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("duckdb failed ({})", status).into());
+    }
+    Ok(())
+}
+
+// A DuckDB string literal.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
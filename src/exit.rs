@@ -0,0 +1,74 @@
+use crate::overcast::{AuthFailed, ExportThrottled, LoginBlocked};
+
+// Exit codes, so cron wrappers can tell failures worth retrying (network errors) from ones that
+// need a person (a changed password, a corrupt database). 0 is success.
+pub const FAILURE: i32 = 1;
+pub const AUTH_FAILURE: i32 = 2;
+pub const NETWORK_ERROR: i32 = 3;
+pub const PARSE_ERROR: i32 = 4;
+pub const DATABASE_ERROR: i32 = 5;
+pub const PARTIAL_SUCCESS: i32 = 6;
+pub const OUT_OF_DATE: i32 = 7;
+
+// The error for a run that did most of its work but not all of it, like a download run where
+// some episodes failed.
+#[derive(Debug)]
+pub struct PartialSuccess(pub String);
+
+impl std::fmt::Display for PartialSuccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PartialSuccess {}
+
+// The error for `check` finding the archive further behind Overcast than it allows.
+#[derive(Debug)]
+pub struct OutOfDate(pub String);
+
+impl std::fmt::Display for OutOfDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for OutOfDate {}
+
+// The exit code for a run that failed with `err`, going by the first error in its chain of
+// sources that falls into one of the classes above.
+pub fn code(err: &(dyn std::error::Error + 'static)) -> i32 {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if err.is::<AuthFailed>() {
+            return AUTH_FAILURE;
+        }
+        if err.is::<PartialSuccess>() {
+            return PARTIAL_SUCCESS;
+        }
+        if err.is::<OutOfDate>() {
+            return OUT_OF_DATE;
+        }
+        if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+            return if err.is_decode() {
+                PARSE_ERROR
+            } else {
+                NETWORK_ERROR
+            };
+        }
+        if err.is::<ExportThrottled>() || err.is::<LoginBlocked>() {
+            return NETWORK_ERROR;
+        }
+        if err.is::<roxmltree::Error>()
+            || err.is::<serde_json::Error>()
+            || err.is::<chrono::ParseError>()
+        {
+            return PARSE_ERROR;
+        }
+        if err.is::<rusqlite::Error>() {
+            return DATABASE_ERROR;
+        }
+        next = err.source();
+    }
+    FAILURE
+}
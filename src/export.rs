@@ -0,0 +1,620 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use rusqlite::Connection;
+
+use crate::chart::escape;
+use crate::timezone::DisplayTimezone;
+
+// The formats `export` can write.
+pub enum Format {
+    Markdown,
+    Notion,
+    Rss,
+    Gpodder,
+    Opml,
+    Instapaper,
+    Raindrop,
+    Readwise,
+    Duckdb,
+    Jsonl,
+    Ics,
+    // A format added by a plugin, looked up by name when exporting.
+    Plugin(String),
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" => Ok(Format::Markdown),
+            "notion" => Ok(Format::Notion),
+            "rss" => Ok(Format::Rss),
+            "gpodder" => Ok(Format::Gpodder),
+            "opml" => Ok(Format::Opml),
+            "instapaper" => Ok(Format::Instapaper),
+            "raindrop" => Ok(Format::Raindrop),
+            "readwise" => Ok(Format::Readwise),
+            "duckdb" => Ok(Format::Duckdb),
+            "jsonl" => Ok(Format::Jsonl),
+            "ics" => Ok(Format::Ics),
+            _ => Ok(Format::Plugin(s.to_string())),
+        }
+    }
+}
+
+// The error for a format that's neither built in nor one of the `plugins`.
+pub fn unknown_format(name: &str, plugins: &[&str]) -> String {
+    let mut expected = vec![
+        "markdown",
+        "notion",
+        "rss",
+        "gpodder",
+        "opml",
+        "instapaper",
+        "raindrop",
+        "readwise",
+        "duckdb",
+        "jsonl",
+        "ics",
+    ];
+    expected.extend_from_slice(plugins);
+    let last = expected.pop().unwrap_or_default();
+    format!(
+        "unknown format \"{}\" (expected {} or {})",
+        name,
+        expected.join(", "),
+        last
+    )
+}
+
+// Table layouts `export --schema` can write an archive's feeds and episodes in.
+pub enum Schema {
+    // The Python overcast-to-sqlite's, used by Dogsheep.
+    Dogsheep,
+}
+
+impl FromStr for Schema {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dogsheep" => Ok(Schema::Dogsheep),
+            _ => Err(format!("unknown schema \"{}\" (expected dogsheep)", s)),
+        }
+    }
+}
+
+// Which episodes an RSS export includes.
+pub enum Filter {
+    Starred,
+    Played,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "starred" => Ok(Filter::Starred),
+            "played" => Ok(Filter::Played),
+            _ => Err(format!(
+                "unknown filter \"{}\" (expected starred or played)",
+                s
+            )),
+        }
+    }
+}
+
+struct FeedRow {
+    id: i64,
+    title: String,
+    html_url: Option<String>,
+    feed_url: Option<String>,
+    subscribed: bool,
+}
+
+// Writes one note per episode, plus an index note per feed, into `dir`. Notes are laid out as
+// `<feed>/<feed>.md` and `<feed>/<date> <episode>.md`, with YAML front matter so tools like
+// Obsidian's Dataview can query them. Existing notes are overwritten.
+pub fn export_markdown(conn: &Connection, dir: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut stmt =
+        conn.prepare("SELECT id, title, html_url, feed_url, subscribed FROM feeds ORDER BY title")?;
+    let feeds = stmt
+        .query_map([], |row| {
+            Ok(FeedRow {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                html_url: row.get(2)?,
+                feed_url: row.get(3)?,
+                subscribed: row.get::<_, Option<bool>>(4)?.unwrap_or(false),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut written = 0;
+    let mut feed_names = HashSet::new();
+    for feed in feeds {
+        let feed_name = unique_name(&mut feed_names, &sanitize_filename(&feed.title), feed.id);
+        let feed_dir = dir.join(&feed_name);
+        std::fs::create_dir_all(&feed_dir)?;
+
+        let mut stmt = conn.prepare(
+            "SELECT e.id, e.title, e.published_at, e.played, e.progress, e.starred, e.user_deleted,
+                e.html_url, e.overcast_url, e.mp3_url, d.duration, d.description
+            FROM episodes e
+            LEFT JOIN episode_details d ON d.episode_id = e.id
+            WHERE e.feed_id = ?
+            ORDER BY e.published_at DESC",
+        )?;
+        let mut rows = stmt.query([feed.id])?;
+        let mut episode_names = HashSet::new();
+        let mut index_lines = Vec::new();
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let title: String = row.get(1)?;
+            let published_at: Option<String> = row.get(2)?;
+            let date = published_at.as_deref().map(|p| &p[..p.len().min(10)]);
+            let played = row.get::<_, Option<bool>>(3)?.unwrap_or(false);
+            let progress: Option<i64> = row.get(4)?;
+            let starred = row.get::<_, Option<bool>>(5)?.unwrap_or(false);
+            let deleted = row.get::<_, Option<bool>>(6)?.unwrap_or(false);
+            let html_url: Option<String> = row.get(7)?;
+            let overcast_url: Option<String> = row.get(8)?;
+            let mp3_url: Option<String> = row.get(9)?;
+            let duration: Option<i64> = row.get(10)?;
+            let description: Option<String> = row.get(11)?;
+
+            let base = match date {
+                Some(date) => format!("{} {}", date, title),
+                None => title.clone(),
+            };
+            let name = unique_name(&mut episode_names, &sanitize_filename(&base), id);
+
+            let mut note = String::from("---\n");
+            front_matter(&mut note, "title", Some(&title));
+            front_matter(&mut note, "feed", Some(&format!("[[{}]]", feed_name)));
+            front_matter(&mut note, "overcast_id", Some(&id.to_string()));
+            front_matter(&mut note, "published", date);
+            note.push_str(&format!("played: {}\n", played));
+            note.push_str(&format!("starred: {}\n", starred));
+            note.push_str(&format!("deleted: {}\n", deleted));
+            if let Some(progress) = progress {
+                note.push_str(&format!("progress: {}\n", progress));
+            }
+            if let Some(duration) = duration {
+                note.push_str(&format!("duration: {}\n", duration));
+            }
+            note.push_str("tags: [podcast]\n---\n\n");
+            note.push_str(&format!("# {}\n\n", title));
+            for (label, url) in &[
+                ("Web", &html_url),
+                ("Overcast", &overcast_url),
+                ("Audio", &mp3_url),
+            ] {
+                if let Some(url) = url {
+                    note.push_str(&format!("- {}: <{}>\n", label, url));
+                }
+            }
+            if let Some(description) = description {
+                note.push_str("\n## Show notes\n\n");
+                note.push_str(description.trim());
+                note.push('\n');
+            }
+            std::fs::write(feed_dir.join(format!("{}.md", name)), note)?;
+            written += 1;
+
+            index_lines.push(format!(
+                "- [{}] [[{}|{}]]{}\n",
+                if played { "x" } else { " " },
+                name,
+                title.replace('|', "-"),
+                date.map(|d| format!(" ({})", d)).unwrap_or_default(),
+            ));
+        }
+
+        let mut index = String::from("---\n");
+        front_matter(&mut index, "title", Some(&feed.title));
+        front_matter(&mut index, "overcast_id", Some(&feed.id.to_string()));
+        front_matter(&mut index, "feed_url", feed.feed_url.as_deref());
+        front_matter(&mut index, "website", feed.html_url.as_deref());
+        index.push_str(&format!("subscribed: {}\n", feed.subscribed));
+        index.push_str("tags: [podcast-feed]\n---\n\n");
+        index.push_str(&format!("# {}\n\n", feed.title));
+        for line in index_lines {
+            index.push_str(&line);
+        }
+        std::fs::write(feed_dir.join(format!("{}.md", feed_name)), index)?;
+    }
+    Ok(written)
+}
+
+// Appends a string-valued front matter key. Values are written as JSON strings, which are also
+// valid (escaped) YAML.
+fn front_matter(note: &mut String, key: &str, value: Option<&str>) {
+    if let Some(value) = value {
+        note.push_str(&format!(
+            "{}: {}\n",
+            key,
+            serde_json::to_string(value).expect("string serialization")
+        ));
+    }
+}
+
+// Replaces characters that are invalid in filenames (or in Obsidian links), and limits length.
+pub fn sanitize_filename(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let cleaned: String = cleaned
+        .trim()
+        .trim_start_matches('.')
+        .chars()
+        .take(120)
+        .collect();
+    let cleaned = cleaned.trim().to_string();
+    if cleaned.is_empty() {
+        "untitled".to_string()
+    } else {
+        cleaned
+    }
+}
+
+// Disambiguates a name that's already been used by appending the row's ID.
+fn unique_name(used: &mut HashSet<String>, name: &str, id: i64) -> String {
+    let name = if used.contains(&name.to_lowercase()) {
+        format!("{} ({})", name, id)
+    } else {
+        name.to_string()
+    };
+    used.insert(name.to_lowercase());
+    name
+}
+
+// Renders an RSS 2.0 feed of the selected episodes, with their original enclosures, so they can be
+// subscribed to in any podcast app. Each item credits the show it came from with `<source>`.
+pub fn export_rss(
+    conn: &Connection,
+    filter: &Filter,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (title, condition, order) = match filter {
+        Filter::Starred => ("Starred episodes", "e.starred", "e.starred_at"),
+        Filter::Played => ("Played episodes", "e.played", "e.updated_at"),
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT e.id, e.title, e.published_at, e.overcast_url, e.html_url, e.mp3_url,
+            f.title, f.feed_url, d.description
+        FROM episodes e
+        JOIN feeds f ON f.id = e.feed_id
+        LEFT JOIN episode_details d ON d.episode_id = e.id
+        WHERE {} AND e.mp3_url IS NOT NULL
+        ORDER BY {} DESC",
+        condition, order
+    ))?;
+    let mut rows = stmt.query([])?;
+
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n<channel>\n\
+         <title>{}</title>\n<link>https://overcast.fm</link>\n\
+         <description>{} from my Overcast archive.</description>\n\
+         <generator>overcast-to-sqlite</generator>\n",
+        title, title
+    );
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let episode_title: String = row.get(1)?;
+        let published_at: Option<NaiveDateTime> = row.get(2)?;
+        let overcast_url: Option<String> = row.get(3)?;
+        let html_url: Option<String> = row.get(4)?;
+        let mp3_url: String = row.get(5)?;
+        let feed_title: String = row.get(6)?;
+        let feed_url: Option<String> = row.get(7)?;
+        let description: Option<String> = row.get(8)?;
+
+        xml.push_str("<item>\n");
+        xml.push_str(&format!(
+            "<title>{}</title>\n",
+            escape(&format!("{}: {}", feed_title, episode_title))
+        ));
+        if let Some(link) = overcast_url.or(html_url) {
+            xml.push_str(&format!("<link>{}</link>\n", escape(&link)));
+        }
+        xml.push_str(&format!(
+            "<guid isPermaLink=\"false\">overcast-{}</guid>\n",
+            id
+        ));
+        if let Some(published_at) = published_at {
+            xml.push_str(&format!(
+                "<pubDate>{}</pubDate>\n",
+                published_at.format("%a, %d %b %Y %H:%M:%S +0000")
+            ));
+        }
+        xml.push_str(&format!(
+            "<enclosure url=\"{}\" length=\"0\" type=\"{}\"/>\n",
+            escape(&mp3_url),
+            enclosure_type(&mp3_url)
+        ));
+        if let Some(feed_url) = feed_url {
+            xml.push_str(&format!(
+                "<source url=\"{}\">{}</source>\n",
+                escape(&feed_url),
+                escape(&feed_title)
+            ));
+        }
+        if let Some(description) = description {
+            xml.push_str(&format!(
+                "<description>{}</description>\n",
+                escape(&description)
+            ));
+        }
+        xml.push_str("</item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    Ok(xml)
+}
+
+// Renders subscribed feeds as OPML, which AntennaPod (and most podcast apps) can import. Each
+// feed's episodes are nested beneath it with their listening state, in the same attributes
+// Overcast's extended export uses; apps that only import subscriptions ignore them.
+pub fn export_opml(conn: &Connection) -> Result<String, Box<dyn std::error::Error>> {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<opml version=\"1.0\">\n\
+         <head><title>Overcast podcasts</title></head>\n<body>\n<outline text=\"feeds\">\n",
+    );
+    let mut feeds = conn.prepare(
+        "SELECT id, title, feed_url, html_url FROM feeds
+        WHERE subscribed AND feed_url IS NOT NULL
+        ORDER BY title",
+    )?;
+    let mut episodes = conn.prepare(
+        "SELECT id, title, published_at, updated_at, mp3_url, overcast_url, played, progress, user_deleted
+        FROM episodes
+        WHERE feed_id = ?
+        ORDER BY published_at DESC",
+    )?;
+    let mut rows = feeds.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let title: String = row.get(1)?;
+        let feed_url: String = row.get(2)?;
+        let html_url: Option<String> = row.get(3)?;
+        xml.push_str(&format!(
+            "<outline type=\"rss\" overcastId=\"{}\" text=\"{}\" title=\"{}\" xmlUrl=\"{}\"{}>\n",
+            id,
+            escape(&title),
+            escape(&title),
+            escape(&feed_url),
+            html_url
+                .map(|u| format!(" htmlUrl=\"{}\"", escape(&u)))
+                .unwrap_or_default(),
+        ));
+        let mut episode_rows = episodes.query([id])?;
+        while let Some(episode) = episode_rows.next()? {
+            let mut attributes = format!(
+                "type=\"podcast-episode\" overcastId=\"{}\" title=\"{}\"",
+                episode.get::<_, i64>(0)?,
+                escape(&episode.get::<_, String>(1)?)
+            );
+            let dates: [(&str, Option<NaiveDateTime>); 2] = [
+                ("pubDate", episode.get(2)?),
+                ("userUpdatedDate", episode.get(3)?),
+            ];
+            for (name, date) in &dates {
+                if let Some(date) = date {
+                    attributes.push_str(&format!(
+                        " {}=\"{}\"",
+                        name,
+                        date.format("%Y-%m-%dT%H:%M:%SZ")
+                    ));
+                }
+            }
+            let urls: [(&str, Option<String>); 2] = [
+                ("enclosureUrl", episode.get(4)?),
+                ("overcastUrl", episode.get(5)?),
+            ];
+            for (name, url) in &urls {
+                if let Some(url) = url {
+                    attributes.push_str(&format!(" {}=\"{}\"", name, escape(url)));
+                }
+            }
+            if episode.get::<_, Option<bool>>(6)?.unwrap_or(false) {
+                attributes.push_str(" played=\"1\"");
+            }
+            if let Some(progress) = episode.get::<_, Option<i64>>(7)? {
+                attributes.push_str(&format!(" progress=\"{}\"", progress));
+            }
+            if episode.get::<_, Option<bool>>(8)?.unwrap_or(false) {
+                attributes.push_str(" userDeleted=\"1\"");
+            }
+            xml.push_str(&format!("<outline {}/>\n", attributes));
+        }
+        xml.push_str("</outline>\n");
+    }
+    xml.push_str("</outline>\n</body>\n</opml>\n");
+    Ok(xml)
+}
+
+// How far back `export_ics` lists released episodes.
+const ICS_RECENT_DAYS: i64 = 30;
+
+// Renders an iCalendar file of subscribed shows' releases: the episodes published in the last
+// `ICS_RECENT_DAYS` days, at the time they came out, and each show's next episode as an all-day
+// event on the date its cadence predicts (in `tz`), unless it's on hiatus. Predictions are
+// from `feed_cadence`, as of the last `archive` run.
+pub fn export_ics(
+    conn: &Connection,
+    tz: &DisplayTimezone,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let now = chrono::Utc::now().naive_utc();
+    let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//overcast-to-sqlite//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:Podcast releases".to_string(),
+    ];
+
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.title, e.published_at, COALESCE(e.overcast_url, e.html_url), f.title,
+            COALESCE(a.duration, d.duration)
+        FROM episodes e
+        JOIN feeds f ON f.id = e.feed_id
+        LEFT JOIN episode_details d ON d.episode_id = e.id
+        LEFT JOIN audio_metadata a ON a.episode_id = e.id
+        WHERE f.subscribed AND e.published_at >= ?
+        ORDER BY e.published_at",
+    )?;
+    let mut rows = stmt.query([now - chrono::Duration::days(ICS_RECENT_DAYS)])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let title: Option<String> = row.get(1)?;
+        let published_at: NaiveDateTime = row.get(2)?;
+        let url: Option<String> = row.get(3)?;
+        let feed_title: Option<String> = row.get(4)?;
+        let duration: Option<i64> = row.get(5)?;
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:episode-{}@overcast-to-sqlite", id));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART:{}", published_at.format("%Y%m%dT%H%M%SZ")));
+        if let Some(duration) = duration.filter(|duration| *duration > 0) {
+            lines.push(format!("DURATION:PT{}S", duration));
+        }
+        lines.push(format!(
+            "SUMMARY:{}",
+            ics_escape(&format!(
+                "{}: {}",
+                feed_title.as_deref().unwrap_or("Untitled feed"),
+                title.as_deref().unwrap_or("Untitled episode")
+            ))
+        ));
+        // URL's value is a URI, not text, so it's written as is.
+        if let Some(url) = url {
+            lines.push(format!("URL:{}", url));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT f.id, f.title, c.next_expected_at, c.interval_seconds
+        FROM feed_cadence c
+        JOIN feeds f ON f.id = c.feed_id
+        WHERE f.subscribed AND NOT c.on_hiatus
+        ORDER BY c.next_expected_at",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let feed_id: i64 = row.get(0)?;
+        let feed_title: Option<String> = row.get(1)?;
+        let expected_at: NaiveDateTime = row.get(2)?;
+        let interval: i64 = row.get(3)?;
+        lines.push("BEGIN:VEVENT".to_string());
+        // One per feed, so calendar apps move the prediction rather than adding another.
+        lines.push(format!("UID:expected-{}@overcast-to-sqlite", feed_id));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!(
+            "DTSTART;VALUE=DATE:{}",
+            tz.format(&expected_at, "%Y%m%d")
+        ));
+        lines.push(format!(
+            "SUMMARY:{}",
+            ics_escape(&format!(
+                "{} (expected)",
+                feed_title.as_deref().unwrap_or("Untitled feed")
+            ))
+        ));
+        lines.push(format!(
+            "DESCRIPTION:{}",
+            ics_escape(&format!(
+                "A new episode is likely, going by its usual gap of {:.1} days between episodes.",
+                interval as f64 / 86400.0
+            ))
+        ));
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in lines {
+        ics.push_str(&fold(&line));
+        ics.push_str("\r\n");
+    }
+    Ok(ics)
+}
+
+// Escapes text for an iCalendar property value.
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+// Folds an iCalendar content line into 75-byte pieces, continued with a leading space, without
+// splitting a UTF-8 character.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+// Guesses an enclosure's MIME type from its file extension.
+fn enclosure_type(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    match path.rsplit('.').next().map(|e| e.to_lowercase()).as_deref() {
+        Some("m4a") | Some("mp4") => "audio/mp4",
+        Some("aac") => "audio/aac",
+        Some("ogg") | Some("oga") => "audio/ogg",
+        Some("opus") => "audio/opus",
+        _ => "audio/mpeg",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_lines_arent_folded() {
+        assert_eq!(fold("SUMMARY:Short"), "SUMMARY:Short");
+        let line = "x".repeat(75);
+        assert_eq!(fold(&line), line);
+    }
+
+    #[test]
+    fn long_lines_are_folded() {
+        let folded = fold(&"x".repeat(160));
+        let pieces: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(pieces.len(), 3);
+        assert_eq!(pieces[0].len(), 75);
+        assert!(pieces[1..]
+            .iter()
+            .all(|p| p.starts_with(' ') && p.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), "x".repeat(160));
+    }
+
+    #[test]
+    fn folding_keeps_characters_whole() {
+        // 74 bytes, then a 2-byte character that would end on byte 76.
+        let line = format!("{}é", "x".repeat(74));
+        assert_eq!(fold(&line), format!("{}\r\n é", "x".repeat(74)));
+    }
+}
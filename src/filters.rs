@@ -0,0 +1,104 @@
+use regex::Regex;
+
+use crate::overcast::Feed;
+
+// Title filters for what gets archived. A feed or episode is kept if its title matches one of
+// the includes (or there are none) and none of the excludes.
+#[derive(Default)]
+pub struct Filters {
+    include_feeds: Vec<Regex>,
+    exclude_feeds: Vec<Regex>,
+    include_episodes: Vec<Regex>,
+    exclude_episodes: Vec<Regex>,
+}
+
+// How many feeds and episodes a set of filters dropped.
+#[derive(Default)]
+pub struct FilterCounts {
+    pub feeds: usize,
+    pub episodes: usize,
+}
+
+impl Filters {
+    pub fn new(
+        include_feeds: &[String],
+        exclude_feeds: &[String],
+        include_episodes: &[String],
+        exclude_episodes: &[String],
+    ) -> Result<Filters, Box<dyn std::error::Error>> {
+        Ok(Filters {
+            include_feeds: compile(include_feeds)?,
+            exclude_feeds: compile(exclude_feeds)?,
+            include_episodes: compile(include_episodes)?,
+            exclude_episodes: compile(exclude_episodes)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include_feeds.is_empty()
+            && self.exclude_feeds.is_empty()
+            && self.include_episodes.is_empty()
+            && self.exclude_episodes.is_empty()
+    }
+
+    // Drops the feeds, and the episodes of the remaining feeds, that the filters don't keep.
+    pub fn apply(&self, feeds: &mut Vec<Feed>) -> FilterCounts {
+        let mut counts = FilterCounts::default();
+        let before = feeds.len();
+        feeds.retain(|feed| keeps(&self.include_feeds, &self.exclude_feeds, &feed.title));
+        counts.feeds = before - feeds.len();
+        for feed in feeds.iter_mut() {
+            let before = feed.episodes.len();
+            feed.episodes.retain(|episode| {
+                keeps(
+                    &self.include_episodes,
+                    &self.exclude_episodes,
+                    &episode.title,
+                )
+            });
+            counts.episodes += before - feed.episodes.len();
+        }
+        counts
+    }
+}
+
+// Feed title regexes picking the feeds whose new episodes are worth a notification. With none,
+// every feed's are.
+pub struct FeedSelection(Vec<Regex>);
+
+impl FeedSelection {
+    pub fn new(patterns: &[String]) -> Result<FeedSelection, Box<dyn std::error::Error>> {
+        Ok(FeedSelection(compile(patterns)?))
+    }
+
+    pub fn selects(&self, feed_title: Option<&str>) -> bool {
+        keeps(&self.0, &[], feed_title.unwrap_or_default())
+    }
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Regex>, Box<dyn std::error::Error>> {
+    patterns
+        .iter()
+        .map(|p| Regex::new(p).map_err(|err| format!("invalid filter \"{}\": {}", p, err).into()))
+        .collect()
+}
+
+fn keeps(include: &[Regex], exclude: &[Regex], title: &str) -> bool {
+    (include.is_empty() || include.iter().any(|r| r.is_match(title)))
+        && !exclude.iter().any(|r| r.is_match(title))
+}
+
+// Keeps only each feed's `max` most recently published episodes, returning how many were dropped.
+// Episodes without a publish date count as the oldest.
+pub fn limit_episodes(feeds: &mut [Feed], max: usize) -> usize {
+    let mut dropped = 0;
+    for feed in feeds {
+        if feed.episodes.len() > max {
+            feed.episodes
+                .sort_by_key(|e| std::cmp::Reverse(e.published_at));
+            dropped += feed.episodes.len() - max;
+            feed.episodes.truncate(max);
+        }
+    }
+    dropped
+}
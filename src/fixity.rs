@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::storage::{hex, sha256_file, Storage};
+
+// The outcome of a fixity audit.
+#[derive(Default)]
+pub struct FixityCounts {
+    pub ok: usize,
+    // Audio that wasn't checked, because no download directory was given.
+    pub skipped: usize,
+    // (kind, name) of each artifact that failed.
+    pub missing: Vec<(String, String)>,
+    pub corrupt: Vec<(String, String)>,
+}
+
+// Brings the `fixity` manifest up to date with the archive's stored artifacts: downloaded audio
+// ('audio', by path), assets in `sqlar` ('asset', by name) and WARC files ('warc', by path).
+// Artifacts are added the first time they're seen. A re-download, or an asset the archive
+// rewrote itself (like show notes that changed), replaces its checksum; anything else that
+// changes later is corruption, for `verify` to report.
+pub fn record(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let now = chrono::Utc::now().naive_utc();
+    conn.execute(
+        "INSERT INTO fixity(kind, name, sha256, bytes, recorded_at)
+        SELECT 'audio', path, sha256, bytes, downloaded_at FROM downloads WHERE true
+        ON CONFLICT(kind, name) DO UPDATE SET
+            sha256 = excluded.sha256,
+            bytes = excluded.bytes,
+            recorded_at = excluded.recorded_at,
+            verified_at = NULL
+        WHERE excluded.recorded_at > fixity.recorded_at",
+        [],
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT s.name, s.mtime, s.data FROM sqlar s
+        LEFT JOIN fixity f ON f.kind = 'asset' AND f.name = s.name
+        WHERE f.name IS NULL OR f.mtime IS NOT s.mtime",
+    )?;
+    let assets = stmt
+        .query_map([], |row| {
+            let data: Vec<u8> = row.get::<_, Option<Vec<u8>>>(2)?.unwrap_or_default();
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                hex(&openssl::sha::sha256(&data)),
+                data.len() as i64,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (name, mtime, sha256, bytes) in assets {
+        conn.execute(
+            "INSERT OR REPLACE INTO fixity(kind, name, sha256, bytes, mtime, recorded_at)
+            VALUES ('asset', ?, ?, ?, ?, ?)",
+            params![name, sha256, bytes, mtime, now],
+        )?;
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT warc_file FROM warc_records
+        WHERE warc_file NOT IN (SELECT name FROM fixity WHERE kind = 'warc')",
+    )?;
+    let warcs = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for path in warcs {
+        let file = Path::new(&path);
+        if !file.exists() {
+            eprintln!("Can't record a checksum for {}: the file is missing.", path);
+            continue;
+        }
+        conn.execute(
+            "INSERT INTO fixity(kind, name, sha256, bytes, recorded_at) VALUES ('warc', ?, ?, ?, ?)",
+            params![
+                path,
+                sha256_file(file)?,
+                std::fs::metadata(file)?.len() as i64,
+                now
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+// Re-hashes every artifact in the `fixity` manifest (after recording any new ones), reporting
+// missing and corrupt ones and updating `verified_at` for the rest. Audio is only checked with the
+// `storage` it was downloaded to. Unlike `verify-downloads`, nothing is forgotten, so an audit can
+// be repeated after repairing the files.
+pub fn verify(
+    conn: &Connection,
+    storage: Option<&Storage>,
+) -> Result<FixityCounts, Box<dyn std::error::Error>> {
+    record(conn)?;
+    let mut stmt = conn.prepare("SELECT kind, name, sha256 FROM fixity ORDER BY kind, name")?;
+    let entries = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut counts = FixityCounts::default();
+    for (kind, name, expected) in entries {
+        let actual = match kind.as_str() {
+            "audio" => match storage {
+                Some(storage) => storage.sha256(&name)?,
+                None => {
+                    counts.skipped += 1;
+                    continue;
+                }
+            },
+            "asset" => conn
+                .query_row(
+                    "SELECT data FROM sqlar WHERE name = ?",
+                    params![name],
+                    |row| row.get::<_, Option<Vec<u8>>>(0),
+                )
+                .optional()?
+                .map(|data| hex(&openssl::sha::sha256(&data.unwrap_or_default()))),
+            _ => {
+                let path = Path::new(&name);
+                if path.exists() {
+                    Some(sha256_file(path)?)
+                } else {
+                    None
+                }
+            }
+        };
+        match actual {
+            Some(actual) if actual == expected => {
+                conn.execute(
+                    "UPDATE fixity SET verified_at = ? WHERE kind = ? AND name = ?",
+                    params![chrono::Utc::now().naive_utc(), kind, name],
+                )?;
+                counts.ok += 1;
+            }
+            Some(_) => counts.corrupt.push((kind, name)),
+            None => counts.missing.push((kind, name)),
+        }
+    }
+    Ok(counts)
+}
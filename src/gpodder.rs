@@ -0,0 +1,150 @@
+use chrono::NaiveDateTime;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::client;
+
+// gpodder.net limits how many episode actions a single upload may contain.
+const ACTIONS_PER_UPLOAD: usize = 1000;
+
+// An episode action, as defined by the gpodder.net API v2.
+#[derive(Serialize)]
+pub struct EpisodeAction {
+    pub podcast: String,
+    pub episode: String,
+    pub device: String,
+    pub action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+}
+
+// The feed URLs of subscribed feeds.
+pub fn subscriptions(conn: &Connection) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut stmt =
+        conn.prepare("SELECT feed_url FROM feeds WHERE subscribed AND feed_url IS NOT NULL")?;
+    let urls = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(urls)
+}
+
+// Builds episode actions for every episode with listening state. Played episodes are reported as
+// played to the end (which is how gPodder clients like AntennaPod mark them as played), partially
+// played ones at their current position, and deleted ones as deletions.
+pub fn episode_actions(
+    conn: &Connection,
+    device: &str,
+) -> Result<Vec<EpisodeAction>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT f.feed_url, e.mp3_url, e.played, e.progress, e.user_deleted, e.updated_at, d.duration
+        FROM episodes e
+        JOIN feeds f ON f.id = e.feed_id
+        LEFT JOIN episode_details d ON d.episode_id = e.id
+        WHERE f.feed_url IS NOT NULL AND e.mp3_url IS NOT NULL
+            AND (e.played OR e.progress > 0 OR e.user_deleted)",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut actions = Vec::new();
+    while let Some(row) = rows.next()? {
+        let podcast: String = row.get(0)?;
+        let episode: String = row.get(1)?;
+        let played = row.get::<_, Option<bool>>(2)?.unwrap_or(false);
+        let progress: Option<i64> = row.get(3)?;
+        let deleted = row.get::<_, Option<bool>>(4)?.unwrap_or(false);
+        let updated_at: Option<NaiveDateTime> = row.get(5)?;
+        let duration: Option<i64> = row.get(6)?;
+        let timestamp = updated_at.map(|u| u.format("%Y-%m-%dT%H:%M:%S").to_string());
+
+        if played || progress.unwrap_or(0) > 0 {
+            let position = if played {
+                duration.or(progress).unwrap_or(0)
+            } else {
+                progress.unwrap_or(0)
+            };
+            actions.push(EpisodeAction {
+                podcast: podcast.clone(),
+                episode: episode.clone(),
+                device: device.to_string(),
+                action: "play",
+                timestamp: timestamp.clone(),
+                started: Some(0),
+                position: Some(position),
+                total: duration.or(if played { Some(position) } else { None }),
+            });
+        }
+        if deleted {
+            actions.push(EpisodeAction {
+                podcast,
+                episode,
+                device: device.to_string(),
+                action: "delete",
+                timestamp,
+                started: None,
+                position: None,
+                total: None,
+            });
+        }
+    }
+    Ok(actions)
+}
+
+// A gpodder.net-compatible server, like gpodder.net itself or oPodSync.
+pub struct GpodderClient {
+    client: reqwest::blocking::Client,
+    server: String,
+    username: String,
+    password: String,
+}
+
+impl GpodderClient {
+    pub fn new(server: &str, username: &str, password: &str) -> GpodderClient {
+        GpodderClient {
+            client: client::new(),
+            server: server.trim_end_matches('/').to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    // Adds the given feeds to the device's subscriptions.
+    pub fn upload_subscriptions(
+        &self,
+        device: &str,
+        urls: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.client
+            .post(format!(
+                "{}/api/2/subscriptions/{}/{}.json",
+                self.server, self.username, device
+            ))
+            .basic_auth(&self.username, Some(&self.password))
+            .json(&serde_json::json!({ "add": urls, "remove": [] }))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    pub fn upload_episode_actions(
+        &self,
+        actions: &[EpisodeAction],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for chunk in actions.chunks(ACTIONS_PER_UPLOAD) {
+            self.client
+                .post(format!(
+                    "{}/api/2/episodes/{}.json",
+                    self.server, self.username
+                ))
+                .basic_auth(&self.username, Some(&self.password))
+                .json(chunk)
+                .send()?
+                .error_for_status()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,840 @@
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+use serde_json::{json, Map, Value};
+
+use crate::api::{self, EpisodeFilter, FeedFilter, Page};
+use crate::http::{Request, Response};
+
+// The schema, as served at /graphql/schema. Queries are checked against `field_type` below,
+// which follows it.
+pub const SCHEMA: &str = r#"type Query {
+  feeds(subscribed: Boolean, search: String, first: Int = 50, offset: Int = 0): FeedPage!
+  feed(id: ID!): Feed
+  episodes(
+    feedId: ID
+    played: Boolean
+    starred: Boolean
+    search: String
+    publishedAfter: String
+    publishedBefore: String
+    first: Int = 50
+    offset: Int = 0
+  ): EpisodePage!
+  episode(id: ID!): Episode
+  stats: Stats!
+}
+
+type FeedPage {
+  totalCount: Int!
+  hasNextPage: Boolean!
+  nodes: [Feed!]!
+}
+
+type EpisodePage {
+  totalCount: Int!
+  hasNextPage: Boolean!
+  nodes: [Episode!]!
+}
+
+type Feed {
+  id: ID!
+  title: String
+  subscribed: Boolean
+  feedUrl: String
+  htmlUrl: String
+  addedAt: String
+  episodeCount: Int!
+  unplayedCount: Int!
+  episodes(
+    played: Boolean
+    starred: Boolean
+    search: String
+    publishedAfter: String
+    publishedBefore: String
+    first: Int = 50
+    offset: Int = 0
+  ): EpisodePage!
+}
+
+type Episode {
+  id: ID!
+  feedId: ID!
+  feed: Feed
+  title: String
+  played: Boolean
+  progress: Int
+  durationSeconds: Int
+  publishedAt: String
+  updatedAt: String
+  htmlUrl: String
+  overcastUrl: String
+  mp3Url: String
+  userDeleted: Boolean
+  starred: Boolean
+  starredAt: String
+  description: String
+}
+
+type Stats {
+  feeds: Int!
+  subscribedFeeds: Int!
+  episodes: Int!
+  playedEpisodes: Int!
+  starredEpisodes: Int!
+  hoursListened: Float!
+}
+"#;
+
+// The type of `field` on `parent`: None if there's no such field, Some(None) for scalars, and
+// Some(Some(type)) for objects (or lists of them), which need a selection of their own.
+fn field_type(parent: &str, field: &str) -> Option<Option<&'static str>> {
+    let object = match (parent, field) {
+        (_, "__typename") => None,
+        ("Query", "feeds") => Some("FeedPage"),
+        ("Query", "feed") | ("Episode", "feed") | ("FeedPage", "nodes") => Some("Feed"),
+        ("Query", "episodes") | ("Feed", "episodes") => Some("EpisodePage"),
+        ("Query", "episode") | ("EpisodePage", "nodes") => Some("Episode"),
+        ("Query", "stats") => Some("Stats"),
+        ("FeedPage", "totalCount" | "hasNextPage") => None,
+        ("EpisodePage", "totalCount" | "hasNextPage") => None,
+        (
+            "Feed",
+            "id" | "title" | "subscribed" | "feedUrl" | "htmlUrl" | "addedAt" | "episodeCount"
+            | "unplayedCount",
+        ) => None,
+        (
+            "Episode",
+            "id" | "feedId" | "title" | "played" | "progress" | "durationSeconds" | "publishedAt"
+            | "updatedAt" | "htmlUrl" | "overcastUrl" | "mp3Url" | "userDeleted" | "starred"
+            | "starredAt" | "description",
+        ) => None,
+        (
+            "Stats",
+            "feeds" | "subscribedFeeds" | "episodes" | "playedEpisodes" | "starredEpisodes"
+            | "hoursListened",
+        ) => None,
+        _ => return None,
+    };
+    Some(object)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Punct(char),
+    Spread,
+    Name(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            // Commas are insignificant in GraphQL, like whitespace.
+            c if c.is_whitespace() || c == ',' || c == '\u{feff}' => {
+                chars.next();
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '{' | '}' | '(' | ')' | '[' | ']' | ':' | '$' | '!' | '=' | '@' => {
+                tokens.push(Token::Punct(c));
+                chars.next();
+            }
+            '.' => {
+                let dots: String = (0..3).filter_map(|_| chars.next()).collect();
+                if dots != "..." {
+                    return Err("unexpected \".\"".to_string());
+                }
+                tokens.push(Token::Spread);
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some('r') => text.push('\r'),
+                            Some('b') => text.push('\u{8}'),
+                            Some('f') => text.push('\u{c}'),
+                            Some('u') => {
+                                let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+                                let c = u32::from_str_radix(&hex, 16)
+                                    .ok()
+                                    .and_then(std::char::from_u32)
+                                    .ok_or_else(|| format!("invalid escape \\u{}", hex))?;
+                                text.push(c);
+                            }
+                            Some(c) => text.push(c),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some('\n') | None => return Err("unterminated string".to_string()),
+                        Some(c) => text.push(c),
+                    }
+                }
+                tokens.push(Token::Str(text));
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() || "-+.eE".contains(c) {
+                        number.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(match number.parse::<i64>() {
+                    Ok(n) => Token::Int(n),
+                    Err(_) => Token::Float(
+                        number
+                            .parse()
+                            .map_err(|_| format!("invalid number \"{}\"", number))?,
+                    ),
+                });
+            }
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '_' || c.is_ascii_alphanumeric() {
+                        name.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Name(name));
+            }
+            c => return Err(format!("unexpected \"{}\"", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+// An argument value as written, before variables are filled in.
+#[derive(Debug)]
+enum Literal {
+    Variable(String),
+    Value(Value),
+    List(Vec<Literal>),
+    Object(Vec<(String, Literal)>),
+}
+
+#[derive(Debug)]
+struct Field {
+    alias: Option<String>,
+    name: String,
+    arguments: Vec<(String, Literal)>,
+    selections: Vec<Field>,
+}
+
+#[derive(Debug)]
+struct Operation {
+    name: Option<String>,
+    // Declared variables and their default values.
+    variables: Vec<(String, Option<Literal>)>,
+    selections: Vec<Field>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or("unexpected end of query")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn at(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Punct(found) if found == c => Ok(()),
+            token => Err(format!("expected \"{}\", found {:?}", c, token)),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Name(name) => Ok(name),
+            token => Err(format!("expected a name, found {:?}", token)),
+        }
+    }
+
+    fn document(&mut self) -> Result<Vec<Operation>, String> {
+        let mut operations = Vec::new();
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Punct('{') => operations.push(Operation {
+                    name: None,
+                    variables: Vec::new(),
+                    selections: self.selection_set()?,
+                }),
+                Token::Name(keyword) if keyword == "query" => {
+                    self.next()?;
+                    let name = match self.peek() {
+                        Some(Token::Name(_)) => Some(self.name()?),
+                        _ => None,
+                    };
+                    let variables = if self.at('(') {
+                        self.variable_definitions()?
+                    } else {
+                        Vec::new()
+                    };
+                    if self.at('@') {
+                        return Err("directives aren't supported".to_string());
+                    }
+                    operations.push(Operation {
+                        name,
+                        variables,
+                        selections: self.selection_set()?,
+                    });
+                }
+                Token::Name(keyword) if keyword == "mutation" || keyword == "subscription" => {
+                    return Err(format!(
+                        "{}s aren't supported; the API is read-only",
+                        keyword
+                    ))
+                }
+                Token::Name(keyword) if keyword == "fragment" => {
+                    return Err("fragments aren't supported".to_string())
+                }
+                token => return Err(format!("unexpected {:?}", token)),
+            }
+        }
+        if operations.is_empty() {
+            return Err("the query has no operations".to_string());
+        }
+        Ok(operations)
+    }
+
+    fn variable_definitions(&mut self) -> Result<Vec<(String, Option<Literal>)>, String> {
+        self.expect('(')?;
+        let mut variables = Vec::new();
+        while !self.at(')') {
+            self.expect('$')?;
+            let name = self.name()?;
+            self.expect(':')?;
+            self.skip_type()?;
+            let default = if self.at('=') {
+                self.next()?;
+                Some(self.value()?)
+            } else {
+                None
+            };
+            variables.push((name, default));
+        }
+        self.expect(')')?;
+        Ok(variables)
+    }
+
+    // Variables' types aren't checked; arguments are checked when they're used.
+    fn skip_type(&mut self) -> Result<(), String> {
+        if self.at('[') {
+            self.next()?;
+            self.skip_type()?;
+            self.expect(']')?;
+        } else {
+            self.name()?;
+        }
+        if self.at('!') {
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<Field>, String> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        while !self.at('}') {
+            if self.peek() == Some(&Token::Spread) {
+                return Err("fragments aren't supported".to_string());
+            }
+            let mut name = self.name()?;
+            let mut alias = None;
+            if self.at(':') {
+                self.next()?;
+                alias = Some(name);
+                name = self.name()?;
+            }
+            let mut arguments = Vec::new();
+            if self.at('(') {
+                self.next()?;
+                while !self.at(')') {
+                    let name = self.name()?;
+                    self.expect(':')?;
+                    arguments.push((name, self.value()?));
+                }
+                self.expect(')')?;
+            }
+            if self.at('@') {
+                return Err("directives aren't supported".to_string());
+            }
+            let selections = if self.at('{') {
+                self.selection_set()?
+            } else {
+                Vec::new()
+            };
+            fields.push(Field {
+                alias,
+                name,
+                arguments,
+                selections,
+            });
+        }
+        self.expect('}')?;
+        Ok(fields)
+    }
+
+    fn value(&mut self) -> Result<Literal, String> {
+        Ok(match self.next()? {
+            Token::Punct('$') => Literal::Variable(self.name()?),
+            Token::Int(n) => Literal::Value(json!(n)),
+            Token::Float(n) => Literal::Value(json!(n)),
+            Token::Str(text) => Literal::Value(json!(text)),
+            Token::Name(name) => Literal::Value(match name.as_str() {
+                "true" => json!(true),
+                "false" => json!(false),
+                "null" => Value::Null,
+                // Enum values; our schema has none, but they'd arrive as strings.
+                _ => json!(name),
+            }),
+            Token::Punct('[') => {
+                let mut items = Vec::new();
+                while !self.at(']') {
+                    items.push(self.value()?);
+                }
+                self.expect(']')?;
+                Literal::List(items)
+            }
+            Token::Punct('{') => {
+                let mut fields = Vec::new();
+                while !self.at('}') {
+                    let name = self.name()?;
+                    self.expect(':')?;
+                    fields.push((name, self.value()?));
+                }
+                self.expect('}')?;
+                Literal::Object(fields)
+            }
+            token => return Err(format!("expected a value, found {:?}", token)),
+        })
+    }
+}
+
+fn resolve(literal: &Literal, variables: &HashMap<String, Value>) -> Value {
+    match literal {
+        Literal::Variable(name) => variables.get(name).cloned().unwrap_or(Value::Null),
+        Literal::Value(value) => value.clone(),
+        Literal::List(items) => {
+            Value::Array(items.iter().map(|item| resolve(item, variables)).collect())
+        }
+        Literal::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), resolve(value, variables)))
+                .collect(),
+        ),
+    }
+}
+
+// A field's arguments, with variables filled in.
+struct Arguments(Map<String, Value>);
+
+impl Arguments {
+    fn bool(&self, name: &str) -> Result<Option<bool>, String> {
+        match self.0.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Bool(value)) => Ok(Some(*value)),
+            Some(value) => Err(format!("{} should be a Boolean, not {}", name, value)),
+        }
+    }
+
+    fn int(&self, name: &str) -> Result<Option<i64>, String> {
+        match self.0.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => value
+                .as_i64()
+                .map(Some)
+                .ok_or_else(|| format!("{} should be an Int, not {}", name, value)),
+        }
+    }
+
+    fn string(&self, name: &str) -> Result<Option<String>, String> {
+        match self.0.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            Some(value) => Err(format!("{} should be a String, not {}", name, value)),
+        }
+    }
+
+    // IDs may be given as strings or numbers, as GraphQL allows.
+    fn id(&self, name: &str) -> Result<Option<i64>, String> {
+        match self.0.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(value)) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("no such {} \"{}\"", name, value)),
+            Some(value) => value
+                .as_i64()
+                .map(Some)
+                .ok_or_else(|| format!("{} should be an ID, not {}", name, value)),
+        }
+    }
+
+    fn page(&self) -> Result<Page, String> {
+        Ok(Page::new(self.int("first")?, self.int("offset")?))
+    }
+
+    fn episode_filter(&self, feed_id: Option<i64>) -> Result<EpisodeFilter, String> {
+        Ok(EpisodeFilter {
+            feed_id: feed_id.or(self.id("feedId")?),
+            played: self.bool("played")?,
+            starred: self.bool("starred")?,
+            search: self.string("search")?,
+            published_after: self.string("publishedAfter")?,
+            published_before: self.string("publishedBefore")?,
+        })
+    }
+}
+
+fn page_json(listing: api::Listing, page: &Page) -> Value {
+    json!({
+        "totalCount": listing.total,
+        "hasNextPage": listing.has_next_page(page),
+        "nodes": listing.items,
+    })
+}
+
+struct Executor<'a> {
+    conn: &'a Connection,
+    variables: HashMap<String, Value>,
+}
+
+impl Executor<'_> {
+    // The value of `field` on `parent`, an object of type `parent_type` as fetched.
+    fn fetch(
+        &self,
+        parent_type: &str,
+        parent: &Map<String, Value>,
+        field: &Field,
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let args = Arguments(
+            field
+                .arguments
+                .iter()
+                .map(|(name, value)| (name.clone(), resolve(value, &self.variables)))
+                .collect(),
+        );
+        Ok(match (parent_type, field.name.as_str()) {
+            (_, "__typename") => json!(parent_type),
+            ("Query", "feeds") => {
+                let filter = FeedFilter {
+                    subscribed: args.bool("subscribed")?,
+                    search: args.string("search")?,
+                };
+                let page = args.page()?;
+                page_json(api::feeds(self.conn, &filter, &page)?, &page)
+            }
+            ("Query", "feed") => {
+                let id = args.id("id")?.ok_or("feed needs an id")?;
+                api::feed(self.conn, id)?.unwrap_or(Value::Null)
+            }
+            ("Query", "episodes") => {
+                let page = args.page()?;
+                page_json(
+                    api::episodes(self.conn, &args.episode_filter(None)?, &page)?,
+                    &page,
+                )
+            }
+            ("Feed", "episodes") => {
+                let page = args.page()?;
+                let feed_id = parent["id"].as_i64();
+                page_json(
+                    api::episodes(self.conn, &args.episode_filter(feed_id)?, &page)?,
+                    &page,
+                )
+            }
+            ("Query", "episode") => {
+                let id = args.id("id")?.ok_or("episode needs an id")?;
+                api::episode(self.conn, id)?.unwrap_or(Value::Null)
+            }
+            ("Episode", "feed") => match parent["feedId"].as_i64() {
+                Some(feed_id) => api::feed(self.conn, feed_id)?.unwrap_or(Value::Null),
+                None => Value::Null,
+            },
+            ("Query", "stats") => api::stats(self.conn)?,
+            (_, name) => parent.get(name).cloned().unwrap_or(Value::Null),
+        })
+    }
+
+    // The fields selected from an object of type `object_type`.
+    fn select(
+        &self,
+        object_type: &str,
+        object: &Map<String, Value>,
+        selections: &[Field],
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let mut result = Map::new();
+        for field in selections {
+            let field_type = field_type(object_type, &field.name).ok_or_else(|| {
+                format!(
+                    "Cannot query field \"{}\" on type \"{}\".",
+                    field.name, object_type
+                )
+            })?;
+            let value = self.fetch(object_type, object, field)?;
+            let value = match field_type {
+                Some(child_type) => {
+                    if field.selections.is_empty() {
+                        return Err(format!(
+                            "Field \"{}\" of type \"{}\" must have a selection of subfields.",
+                            field.name, child_type
+                        )
+                        .into());
+                    }
+                    self.select_value(child_type, &value, &field.selections)?
+                }
+                None => {
+                    if !field.selections.is_empty() {
+                        return Err(format!(
+                            "Field \"{}\" must not have a selection since it has no subfields.",
+                            field.name
+                        )
+                        .into());
+                    }
+                    // IDs are strings in GraphQL.
+                    if field.name == "id" || field.name == "feedId" {
+                        value
+                            .as_i64()
+                            .map_or(Value::Null, |id| json!(id.to_string()))
+                    } else {
+                        value
+                    }
+                }
+            };
+            result.insert(
+                field.alias.clone().unwrap_or_else(|| field.name.clone()),
+                value,
+            );
+        }
+        Ok(Value::Object(result))
+    }
+
+    fn select_value(
+        &self,
+        object_type: &str,
+        value: &Value,
+        selections: &[Field],
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        match value {
+            Value::Object(object) => self.select(object_type, object, selections),
+            Value::Array(items) => Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.select_value(object_type, item, selections))
+                    .collect::<Result<_, _>>()?,
+            )),
+            _ => Ok(Value::Null),
+        }
+    }
+}
+
+// Runs a GraphQL request (a JSON object with `query` and optionally `variables` and
+// `operationName`) against the archive, returning the response body. Queries may use aliases,
+// arguments and variables; fragments, directives and introspection aren't supported, so tools
+// should be pointed at SCHEMA instead.
+pub fn execute(conn: &Connection, request: &Value) -> Value {
+    match run(conn, request) {
+        Ok(data) => json!({ "data": data }),
+        Err(err) => json!({ "errors": [{ "message": err.to_string() }], "data": null }),
+    }
+}
+
+fn run(conn: &Connection, request: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+    let query = request["query"]
+        .as_str()
+        .ok_or("The request has no query.")?;
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        position: 0,
+    };
+    let operations = parser.document()?;
+    let operation = match request["operationName"].as_str() {
+        Some(name) => operations
+            .iter()
+            .find(|operation| operation.name.as_deref() == Some(name))
+            .ok_or_else(|| format!("Unknown operation named \"{}\".", name))?,
+        None if operations.len() == 1 => &operations[0],
+        None => return Err("Must provide operationName with several operations.".into()),
+    };
+    let given = request["variables"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    let mut variables = HashMap::new();
+    for (name, default) in &operation.variables {
+        let value = match (given.get(name), default) {
+            (Some(value), _) => value.clone(),
+            (None, Some(default)) => resolve(default, &HashMap::new()),
+            (None, None) => Value::Null,
+        };
+        variables.insert(name.clone(), value);
+    }
+    let executor = Executor { conn, variables };
+    executor.select("Query", &Map::new(), &operation.selections)
+}
+
+// Answers /graphql: queries POSTed as JSON or passed in the query string (with `variables` as
+// JSON), and the schema at /graphql/schema.
+pub fn handle(conn: &Connection, request: &Request) -> Response {
+    if request.path.trim_end_matches('/') == "/graphql/schema" {
+        return Response::new(200, "text/plain; charset=utf-8", SCHEMA);
+    }
+    if request.path.trim_end_matches('/') != "/graphql" {
+        return Response::error(404, "not found");
+    }
+    let body = match request.method.as_str() {
+        "POST" => match serde_json::from_slice(&request.body) {
+            Ok(body) => body,
+            Err(err) => return Response::error(400, &format!("invalid JSON: {}", err)),
+        },
+        "GET" => json!({
+            "query": request.param("query"),
+            "operationName": request.param("operationName"),
+            "variables": request
+                .param("variables")
+                .and_then(|variables| serde_json::from_str::<Value>(variables).ok()),
+        }),
+        _ => return Response::error(405, "use GET or POST"),
+    };
+    Response::json(200, &execute(conn, &body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> Result<Vec<Operation>, String> {
+        Parser {
+            tokens: tokenize(query)?,
+            position: 0,
+        }
+        .document()
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize("{ a(n: -3, f: 1.5e2) } # comment\n\"x\\n\\u00e9\" ...").unwrap(),
+            vec![
+                Token::Punct('{'),
+                Token::Name("a".to_string()),
+                Token::Punct('('),
+                Token::Name("n".to_string()),
+                Token::Punct(':'),
+                Token::Int(-3),
+                Token::Name("f".to_string()),
+                Token::Punct(':'),
+                Token::Float(150.0),
+                Token::Punct(')'),
+                Token::Punct('}'),
+                Token::Str("x\né".to_string()),
+                Token::Spread,
+            ]
+        );
+        assert!(tokenize("\"unterminated").is_err());
+        assert!(tokenize("{ a.b }").is_err());
+        assert!(tokenize("{ % }").is_err());
+    }
+
+    #[test]
+    fn shorthand_query() {
+        let operations = parse("{ feeds(first: 2) { id title } }").unwrap();
+        assert_eq!(operations.len(), 1);
+        let feeds = &operations[0].selections[0];
+        assert_eq!(feeds.name, "feeds");
+        assert_eq!(feeds.alias, None);
+        assert!(matches!(
+            feeds.arguments.as_slice(),
+            [(name, Literal::Value(value))] if name == "first" && value == &json!(2)
+        ));
+        let fields: Vec<&str> = feeds.selections.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(fields, vec!["id", "title"]);
+    }
+
+    #[test]
+    fn named_query_with_variables() {
+        let operations = parse(
+            "query Recent($feed: ID!, $first: Int = 5, $ids: [ID!]) {
+                latest: episodes(feed: $feed, first: $first, filter: {played: false, tags: [\"a\"]}) {
+                    id
+                }
+            }",
+        )
+        .unwrap();
+        let operation = &operations[0];
+        assert_eq!(operation.name.as_deref(), Some("Recent"));
+        let variables: Vec<&str> = operation
+            .variables
+            .iter()
+            .map(|(n, _)| n.as_str())
+            .collect();
+        assert_eq!(variables, vec!["feed", "first", "ids"]);
+        assert!(matches!(
+            &operation.variables[1].1,
+            Some(Literal::Value(value)) if value == &json!(5)
+        ));
+        let episodes = &operation.selections[0];
+        assert_eq!(episodes.alias.as_deref(), Some("latest"));
+        assert_eq!(episodes.name, "episodes");
+        let feed = &episodes.arguments[0].1;
+        assert!(matches!(feed, Literal::Variable(name) if name == "feed"));
+
+        let mut variables = HashMap::new();
+        variables.insert("feed".to_string(), json!("7"));
+        assert_eq!(resolve(feed, &variables), json!("7"));
+        assert_eq!(
+            resolve(&episodes.arguments[2].1, &variables),
+            json!({"played": false, "tags": ["a"]})
+        );
+    }
+
+    #[test]
+    fn unsupported_queries() {
+        for query in [
+            "",
+            "mutation { a }",
+            "subscription { a }",
+            "fragment F on Feed { id }",
+            "{ feeds { ...F } }",
+            "{ feeds @skip(if: true) { id } }",
+            "{ feeds { id }",
+        ] {
+            assert!(parse(query).is_err(), "{:?} parsed", query);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use crate::client;
+
+// Pings aren't worth holding up a run for.
+const TIMEOUT: Duration = Duration::from_secs(10);
+// Healthchecks.io keeps up to 100 KB of a ping's body.
+const MAX_BODY: usize = 10_000;
+
+// Healthchecks.io-style endpoints for a run: `<url>/start` when it starts, then `<url>` or
+// `<url>/fail`. Cronitor and others accept the same layout.
+pub enum Ping {
+    Start,
+    Success,
+    Fail,
+}
+
+// Pings the check at `url`, with `body` (a failed run's error, say) for its event log.
+pub fn ping(url: &str, ping: Ping, body: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let url = url.trim_end_matches('/');
+    let url = match ping {
+        Ping::Start => format!("{}/start", url),
+        Ping::Success => url.to_string(),
+        Ping::Fail => format!("{}/fail", url),
+    };
+    let body: String = body.chars().take(MAX_BODY).collect();
+    client::builder()
+        .timeout(TIMEOUT)
+        .build()?
+        .post(&url)
+        .body(body)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use std::io::Write;
+
+use crate::runs::Summary;
+
+// Runs `command` with the shell (sh, or cmd on Windows), with the summary as JSON on its stdin.
+// Its output goes to ours, and a failing command fails the run.
+pub fn run_command(command: &str, summary: &Summary) -> Result<(), Box<dyn std::error::Error>> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = std::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("couldn't run the post-run command: {}", err))?;
+    let input = serde_json::to_vec(&summary.to_json())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Commands that don't read their input close it early; that's fine.
+        if let Err(err) = stdin.write_all(&input) {
+            if err.kind() != std::io::ErrorKind::BrokenPipe {
+                return Err(err.into());
+            }
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("The post-run command failed ({}).", status).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,584 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Deserialize;
+
+// Counts of rows written by an import.
+#[derive(Default)]
+pub struct ImportCounts {
+    pub feeds: usize,
+    pub episodes: usize,
+    // Episodes skipped because the archive already has them (matched by enclosure URL).
+    pub duplicates: usize,
+}
+
+// Derives a stable ID for a row imported from another app. Imported rows get negative IDs, so
+// they can never collide with Overcast's (positive) ones, and re-importing the same data
+// updates rows rather than duplicating them.
+pub fn imported_id(source: &str, key: &str) -> i64 {
+    // FNV-1a, which is stable across Rust versions (unlike `DefaultHasher`).
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in source.bytes().chain(std::iter::once(0)).chain(key.bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    -((hash >> 1) as i64) - 1
+}
+
+// A podcast from an imported subscription list.
+pub struct ImportedFeed {
+    pub title: String,
+    pub feed_url: Option<String>,
+    pub html_url: Option<String>,
+    pub subscribed: bool,
+}
+
+// Finds the feed an imported podcast corresponds to, by feed URL and then by title, or creates
+// it. Returns the feed's ID.
+pub fn find_or_create_feed(
+    conn: &Connection,
+    source: &str,
+    key: &str,
+    feed: &ImportedFeed,
+    counts: &mut ImportCounts,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let existing = match &feed.feed_url {
+        Some(url) => conn
+            .query_row(
+                "SELECT id FROM feeds WHERE feed_url = ?",
+                params![url],
+                |row| row.get(0),
+            )
+            .optional()?,
+        None => None,
+    };
+    let existing = match existing {
+        Some(id) => Some(id),
+        None => conn
+            .query_row(
+                "SELECT id FROM feeds WHERE title = ? COLLATE NOCASE",
+                params![feed.title],
+                |row| row.get(0),
+            )
+            .optional()?,
+    };
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    let id = imported_id(source, key);
+    conn.execute(
+        "INSERT INTO feeds(id, title, subscribed, feed_url, html_url, source) VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            id,
+            feed.title,
+            feed.subscribed,
+            feed.feed_url,
+            feed.html_url,
+            source
+        ],
+    )?;
+    counts.feeds += 1;
+    Ok(id)
+}
+
+// An episode from an imported listening history.
+pub struct ImportedEpisode {
+    pub title: String,
+    pub mp3_url: Option<String>,
+    pub html_url: Option<String>,
+    pub published_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub played: bool,
+    pub progress: Option<i64>,
+    pub duration: Option<i64>,
+    pub user_deleted: bool,
+    pub starred: bool,
+}
+
+// Upserts an imported episode, unless the archive already has an episode with the same
+// enclosure URL (e.g. from Overcast), in which case the existing row wins. URLs are compared
+// once normalized, since the same episode's URL often has different tracking redirects in
+// different apps. Returns the ID of the
+// archived episode either way.
+pub fn upsert_episode(
+    conn: &Connection,
+    source: &str,
+    key: &str,
+    feed_id: i64,
+    episode: &ImportedEpisode,
+    counts: &mut ImportCounts,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let id = imported_id(source, key);
+    if let Some(mp3_url) = &episode.mp3_url {
+        let duplicate: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM episodes
+                WHERE (mp3_url = ?1 OR normalized_mp3_url = ?2) AND id != ?3
+                ORDER BY id DESC",
+                params![mp3_url, crate::urls::normalize(mp3_url), id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(duplicate) = duplicate {
+            counts.duplicates += 1;
+            return Ok(duplicate);
+        }
+    }
+    conn.execute(
+        "INSERT INTO episodes(
+            id, title, played, feed_id, published_at, updated_at, html_url, mp3_url, progress,
+            user_deleted, starred, source
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            title = excluded.title,
+            played = excluded.played,
+            feed_id = excluded.feed_id,
+            published_at = excluded.published_at,
+            updated_at = excluded.updated_at,
+            html_url = excluded.html_url,
+            mp3_url = excluded.mp3_url,
+            progress = excluded.progress,
+            user_deleted = excluded.user_deleted,
+            starred = excluded.starred,
+            source = excluded.source",
+        params![
+            id,
+            episode.title,
+            episode.played,
+            feed_id,
+            episode.published_at,
+            episode.updated_at,
+            episode.html_url,
+            episode.mp3_url,
+            episode.progress,
+            episode.user_deleted,
+            episode.starred,
+            source,
+        ],
+    )?;
+    if episode.duration.is_some() {
+        conn.execute(
+            "INSERT INTO episode_details(episode_id, duration, fetched_at) VALUES (?, ?, ?)
+            ON CONFLICT(episode_id) DO UPDATE SET
+                duration = excluded.duration,
+                fetched_at = excluded.fetched_at",
+            params![id, episode.duration, chrono::Utc::now().naive_utc()],
+        )?;
+    }
+    counts.episodes += 1;
+    Ok(id)
+}
+
+// Records that an episode was played at a known time, unless that play is already recorded.
+pub fn record_play(
+    conn: &Connection,
+    episode_id: i64,
+    played_at: chrono::NaiveDateTime,
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO play_events(episode_id, played_at)
+        SELECT ?1, ?2 WHERE NOT EXISTS (
+            SELECT 1 FROM play_events WHERE episode_id = ?1 AND played_at = ?2
+        )",
+        params![episode_id, played_at],
+    )?;
+    Ok(())
+}
+
+// Parses the feeds in an OPML subscription list.
+pub fn parse_opml(contents: &str) -> Result<Vec<ImportedFeed>, Box<dyn std::error::Error>> {
+    let tree = roxmltree::Document::parse(contents)?;
+    let feeds = tree
+        .descendants()
+        .filter(|n| n.has_tag_name("outline"))
+        .filter_map(|n| {
+            let feed_url = n.attribute("xmlUrl")?;
+            Some(ImportedFeed {
+                title: n
+                    .attribute("title")
+                    .or_else(|| n.attribute("text"))
+                    .unwrap_or(feed_url)
+                    .to_string(),
+                feed_url: Some(feed_url.to_string()),
+                html_url: n.attribute("htmlUrl").map(|s| s.to_string()),
+                subscribed: true,
+            })
+        })
+        .collect();
+    Ok(feeds)
+}
+
+// An episode in Pocket Casts' listening history, as returned by its `user/history` API.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PocketCastsEpisode {
+    uuid: String,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    published: Option<String>,
+    #[serde(default)]
+    duration: Option<i64>,
+    // 1 = unplayed, 2 = in progress, 3 = completed.
+    #[serde(default)]
+    playing_status: i64,
+    #[serde(default)]
+    played_up_to: Option<i64>,
+    #[serde(default)]
+    starred: bool,
+    #[serde(default)]
+    is_deleted: bool,
+    podcast_uuid: String,
+    #[serde(default)]
+    podcast_title: String,
+}
+
+#[derive(Deserialize)]
+struct PocketCastsHistory {
+    episodes: Vec<PocketCastsEpisode>,
+}
+
+// Imports a Pocket Casts OPML export and/or listening history into the archive, tagging new rows
+// with `source = 'pocketcasts'`.
+pub fn import_pocketcasts(
+    conn: &Connection,
+    opml: Option<&str>,
+    history: Option<&str>,
+) -> Result<ImportCounts, Box<dyn std::error::Error>> {
+    const SOURCE: &str = "pocketcasts";
+    let mut counts = ImportCounts::default();
+    if let Some(opml) = opml {
+        for feed in parse_opml(opml)? {
+            let key = feed.feed_url.clone().unwrap_or_default();
+            find_or_create_feed(conn, SOURCE, &key, &feed, &mut counts)?;
+        }
+    }
+    if let Some(history) = history {
+        let history: PocketCastsHistory = serde_json::from_str(history)?;
+        for episode in history.episodes {
+            let feed_id = find_or_create_feed(
+                conn,
+                SOURCE,
+                &episode.podcast_uuid,
+                &ImportedFeed {
+                    title: episode.podcast_title.clone(),
+                    feed_url: None,
+                    html_url: None,
+                    subscribed: false,
+                },
+                &mut counts,
+            )?;
+            let imported = ImportedEpisode {
+                title: episode.title,
+                mp3_url: episode.url,
+                html_url: None,
+                published_at: episode.published.as_deref().and_then(|p| {
+                    chrono::DateTime::parse_from_rfc3339(p)
+                        .map(|d| d.naive_utc())
+                        .ok()
+                }),
+                updated_at: None,
+                played: episode.playing_status == 3,
+                progress: episode.played_up_to,
+                duration: episode.duration.filter(|d| *d > 0),
+                user_deleted: episode.is_deleted,
+                starred: episode.starred,
+            };
+            upsert_episode(conn, SOURCE, &episode.uuid, feed_id, &imported, &mut counts)?;
+        }
+    }
+    Ok(counts)
+}
+
+// Apple Podcasts stores dates as Core Data timestamps: seconds since 2001-01-01 UTC.
+const CORE_DATA_EPOCH: i64 = 978_307_200;
+
+fn core_data_date(seconds: Option<f64>) -> Option<chrono::NaiveDateTime> {
+    let seconds = seconds.filter(|s| *s > 0.0)?;
+    chrono::DateTime::from_timestamp(CORE_DATA_EPOCH + seconds as i64, 0).map(|d| d.naive_utc())
+}
+
+// Returns `column` if the table has it, or NULL, since Apple Podcasts' schema varies between
+// macOS versions.
+fn column_or_null(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let found = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .any(|c| c == column);
+    Ok(if found {
+        column.to_string()
+    } else {
+        "NULL".to_string()
+    })
+}
+
+// Imports subscriptions and play history from the Apple Podcasts library on macOS
+// (`MTLibrary.sqlite`), tagging new rows with `source = 'apple_podcasts'`. Plays of episodes that
+// are already archived are still recorded in play_events.
+pub fn import_apple_podcasts(
+    conn: &Connection,
+    library: &Connection,
+) -> Result<ImportCounts, Box<dyn std::error::Error>> {
+    const SOURCE: &str = "apple_podcasts";
+    let mut counts = ImportCounts::default();
+
+    let mut feed_ids = std::collections::HashMap::new();
+    let mut stmt = library.prepare(&format!(
+        "SELECT Z_PK, ZTITLE, ZFEEDURL, {}, {}, {} FROM ZMTPODCAST",
+        column_or_null(library, "ZMTPODCAST", "ZWEBPAGEURL")?,
+        column_or_null(library, "ZMTPODCAST", "ZSUBSCRIBED")?,
+        column_or_null(library, "ZMTPODCAST", "ZUUID")?,
+    ))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let pk: i64 = row.get(0)?;
+        let feed_url: Option<String> = row.get(2)?;
+        let uuid: Option<String> = row.get(5)?;
+        let feed = ImportedFeed {
+            title: row
+                .get::<_, Option<String>>(1)?
+                .unwrap_or_else(|| "Untitled".to_string()),
+            feed_url: feed_url.clone(),
+            html_url: row.get(3)?,
+            subscribed: row.get::<_, Option<bool>>(4)?.unwrap_or(false),
+        };
+        let key = uuid.or(feed_url).unwrap_or_else(|| pk.to_string());
+        let id = find_or_create_feed(conn, SOURCE, &key, &feed, &mut counts)?;
+        feed_ids.insert(pk, id);
+    }
+
+    let mut stmt = library.prepare(&format!(
+        "SELECT Z_PK, ZPODCAST, ZTITLE, ZENCLOSUREURL, {}, {}, {}, {}, {}, {}, {}
+        FROM ZMTEPISODE",
+        column_or_null(library, "ZMTEPISODE", "ZWEBPAGEURL")?,
+        column_or_null(library, "ZMTEPISODE", "ZPUBDATE")?,
+        column_or_null(library, "ZMTEPISODE", "ZDURATION")?,
+        column_or_null(library, "ZMTEPISODE", "ZPLAYHEAD")?,
+        column_or_null(library, "ZMTEPISODE", "ZHASBEENPLAYED")?,
+        column_or_null(library, "ZMTEPISODE", "ZLASTDATEPLAYED")?,
+        column_or_null(library, "ZMTEPISODE", "ZUUID")?,
+    ))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let pk: i64 = row.get(0)?;
+        let feed_id = match row.get::<_, Option<i64>>(1)?.and_then(|p| feed_ids.get(&p)) {
+            Some(id) => *id,
+            None => continue,
+        };
+        let played = row.get::<_, Option<bool>>(8)?.unwrap_or(false);
+        let last_played = core_data_date(row.get(9)?);
+        let uuid: Option<String> = row.get(10)?;
+        let episode = ImportedEpisode {
+            title: row
+                .get::<_, Option<String>>(2)?
+                .unwrap_or_else(|| "Untitled".to_string()),
+            mp3_url: row.get(3)?,
+            html_url: row.get(4)?,
+            published_at: core_data_date(row.get(5)?),
+            updated_at: last_played,
+            played,
+            progress: row
+                .get::<_, Option<f64>>(7)?
+                .map(|p| p as i64)
+                .filter(|p| *p > 0),
+            duration: row
+                .get::<_, Option<f64>>(6)?
+                .map(|d| d as i64)
+                .filter(|d| *d > 0),
+            user_deleted: false,
+            starred: false,
+        };
+        let key = uuid.unwrap_or_else(|| pk.to_string());
+        let id = upsert_episode(conn, SOURCE, &key, feed_id, &episode, &mut counts)?;
+        if let (true, Some(last_played)) = (played, last_played) {
+            record_play(conn, id, last_played)?;
+        }
+    }
+    Ok(counts)
+}
+
+// Converts a timestamp from the Python overcast-to-sqlite, which kept Overcast's OPML values as
+// they were ("2021-01-12T00:57:04-05:00"), to UTC. sqlite-utils' own formats are accepted too.
+fn legacy_date(value: Option<String>) -> Option<chrono::NaiveDateTime> {
+    let value = value?;
+    let value = value.trim();
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|d| d.naive_utc())
+        .ok()
+        .or_else(|| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").ok())
+        .or_else(|| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").ok())
+}
+
+// sqlite-utils stored OPML attributes as it found them, so booleans may be 0/1 or "1", "true"...
+fn legacy_bool(value: Option<rusqlite::types::Value>) -> Option<bool> {
+    use rusqlite::types::Value;
+    match value? {
+        Value::Integer(i) => Some(i != 0),
+        Value::Real(f) => Some(f != 0.0),
+        Value::Text(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" => Some(true),
+            "0" | "false" | "no" | "" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Imports the feeds and episodes of a database made by the Python overcast-to-sqlite (the layout
+// `export --schema dogsheep` writes). Its rows came from Overcast too, so they keep their
+// Overcast IDs (`overcastId`, stored as text or integers) and `source = 'overcast'`; rows the
+// archive already has are left as they are, since the archive is the newer copy. Played episodes
+// get a play event at their `userUpdatedDate`, the closest thing to a play time it has.
+pub fn import_legacy_db(
+    conn: &Connection,
+    legacy: &Connection,
+) -> Result<ImportCounts, Box<dyn std::error::Error>> {
+    let mut counts = ImportCounts::default();
+    let tables: Vec<String> = legacy
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    if !tables.iter().any(|t| t == "feeds") || !tables.iter().any(|t| t == "episodes") {
+        return Err(
+            "This isn't a Python overcast-to-sqlite database (no feeds and episodes tables)."
+                .into(),
+        );
+    }
+
+    let mut stmt = legacy.prepare(&format!(
+        "SELECT CAST(overcastId AS INTEGER), {}, {}, {}, {}, {}, {} FROM feeds
+        WHERE CAST(overcastId AS INTEGER) > 0",
+        column_or_null(legacy, "feeds", "title")?,
+        column_or_null(legacy, "feeds", "subscribed")?,
+        column_or_null(legacy, "feeds", "overcastAddedDate")?,
+        column_or_null(legacy, "feeds", "xmlUrl")?,
+        column_or_null(legacy, "feeds", "htmlUrl")?,
+        column_or_null(legacy, "feeds", "notifications")?,
+    ))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let inserted = conn.execute(
+            "INSERT INTO feeds(id, title, subscribed, added_at, feed_url, html_url)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO NOTHING",
+            params![
+                id,
+                row.get::<_, Option<String>>(1)?,
+                legacy_bool(row.get(2)?).unwrap_or(false),
+                legacy_date(row.get(3)?),
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<String>>(5)?,
+            ],
+        )?;
+        if inserted == 0 {
+            continue;
+        }
+        if let Some(notifications) = legacy_bool(row.get(6)?) {
+            conn.execute(
+                "INSERT OR IGNORE INTO feed_settings(feed_id, name, value, updated_at)
+                VALUES (?, 'notifications', ?, ?)",
+                params![
+                    id,
+                    if notifications { "1" } else { "0" },
+                    chrono::Utc::now().naive_utc()
+                ],
+            )?;
+        }
+        counts.feeds += 1;
+    }
+
+    let mut stmt = legacy.prepare(&format!(
+        "SELECT CAST(overcastId AS INTEGER), CAST(feedId AS INTEGER), {}, {}, {}, {}, {}, {}, {},
+            {}, {}, {}
+        FROM episodes
+        WHERE CAST(overcastId AS INTEGER) > 0",
+        column_or_null(legacy, "episodes", "title")?,
+        column_or_null(legacy, "episodes", "url")?,
+        column_or_null(legacy, "episodes", "overcastUrl")?,
+        column_or_null(legacy, "episodes", "played")?,
+        column_or_null(legacy, "episodes", "progress")?,
+        column_or_null(legacy, "episodes", "pubDate")?,
+        column_or_null(legacy, "episodes", "userUpdatedDate")?,
+        column_or_null(legacy, "episodes", "userRecommendedDate")?,
+        column_or_null(legacy, "episodes", "userDeleted")?,
+        column_or_null(legacy, "episodes", "enclosureUrl")?,
+    ))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let feed_id: Option<i64> = row.get(1)?;
+        let feed_exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM feeds WHERE id = ?)",
+            params![feed_id],
+            |row| row.get(0),
+        )?;
+        if !feed_exists {
+            continue;
+        }
+        let played = legacy_bool(row.get(5)?).unwrap_or(false);
+        let updated_at = legacy_date(row.get(8)?);
+        let starred_at = legacy_date(row.get(9)?);
+        let progress: Option<i64> =
+            row.get::<_, Option<rusqlite::types::Value>>(6)?
+                .and_then(|value| match value {
+                    rusqlite::types::Value::Integer(i) => Some(i),
+                    rusqlite::types::Value::Real(f) => Some(f as i64),
+                    rusqlite::types::Value::Text(s) => s.trim().parse().ok(),
+                    _ => None,
+                });
+        let inserted = conn.execute(
+            "INSERT INTO episodes(
+                id, title, played, feed_id, published_at, updated_at, html_url, overcast_url, mp3_url,
+                progress, user_deleted, starred, starred_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO NOTHING",
+            params![
+                id,
+                row.get::<_, Option<String>>(2)?,
+                played,
+                feed_id,
+                legacy_date(row.get(7)?),
+                updated_at,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<String>>(11)?,
+                progress,
+                legacy_bool(row.get(10)?).unwrap_or(false),
+                starred_at.is_some(),
+                starred_at,
+            ],
+        )?;
+        if inserted == 0 {
+            counts.duplicates += 1;
+            continue;
+        }
+        if let (true, Some(updated_at)) = (played, updated_at) {
+            record_play(conn, id, updated_at)?;
+        }
+        counts.episodes += 1;
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imported_ids_are_negative_and_stable() {
+        assert_eq!(imported_id("pocketcasts", "abc"), -9034636664535476263);
+        assert!(imported_id("pocketcasts", "") < 0);
+        assert_ne!(imported_id("ab", "c"), imported_id("a", "bc"));
+        assert_ne!(
+            imported_id("pocketcasts", "abc"),
+            imported_id("podcastaddict", "abc")
+        );
+    }
+}
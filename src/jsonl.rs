@@ -0,0 +1,69 @@
+use std::io::Write;
+
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use serde_json::{Map, Value};
+
+use crate::storage::hex;
+
+// Episode columns written as JSON booleans rather than 0 or 1.
+const BOOLEAN_COLUMNS: [&str; 3] = ["played", "user_deleted", "starred"];
+
+// Writes every episode, with its feed's title, to `out` as one JSON object per line. Rows are
+// read and written one at a time, so memory use doesn't grow with the archive. `filter` is a SQL
+// expression over the `episodes` and `feeds` columns, like "episodes.starred = 1", added to the
+// query's WHERE clause so SQLite does the filtering (and can use its indexes). Returns the number
+// of episodes written; a closed pipe (`| head`, say) just ends the export early.
+pub fn export(
+    conn: &Connection,
+    filter: Option<&str>,
+    out: &mut dyn Write,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT episodes.*, feeds.title AS feed_title
+        FROM episodes
+        JOIN feeds ON feeds.id = episodes.feed_id
+        WHERE {}
+        ORDER BY episodes.id",
+        filter.map_or("1".to_string(), |filter| format!("({})", filter))
+    ))?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.query([])?;
+    let mut out = std::io::BufWriter::new(out);
+    let mut written = 0;
+    while let Some(row) = rows.next()? {
+        let mut object = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            let value = match row.get_ref(i)? {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(n) if BOOLEAN_COLUMNS.contains(&column.as_str()) => {
+                    Value::Bool(n != 0)
+                }
+                ValueRef::Integer(n) => Value::from(n),
+                ValueRef::Real(f) => Value::from(f),
+                ValueRef::Text(text) => {
+                    let text = String::from_utf8_lossy(text);
+                    if column == "raw_attributes" {
+                        serde_json::from_str(&text).unwrap_or_else(|_| Value::from(text))
+                    } else {
+                        Value::from(text)
+                    }
+                }
+                ValueRef::Blob(bytes) => Value::from(hex(bytes)),
+            };
+            object.insert(column.clone(), value);
+        }
+        let result = serde_json::to_writer(&mut out, &object)
+            .map_err(std::io::Error::from)
+            .and_then(|_| out.write_all(b"\n"));
+        match result {
+            Ok(()) => written += 1,
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => return Ok(written),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    match out.flush() {
+        Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => Err(err.into()),
+        _ => Ok(written),
+    }
+}
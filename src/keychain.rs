@@ -0,0 +1,45 @@
+// Where the database key is kept in the OS keychain (the macOS Keychain, the Secret Service on
+// Linux or the Windows Credential Manager).
+#[cfg(feature = "sqlcipher")]
+const SERVICE: &str = "overcast-to-sqlite";
+#[cfg(feature = "sqlcipher")]
+const ACCOUNT: &str = "database-key";
+
+// The key to encrypt the archive with: from the keychain, or else prompted for and saved there,
+// so it's only asked for once. If the keychain can't be used, the key is prompted for every time.
+#[cfg(feature = "sqlcipher")]
+pub fn database_key() -> Result<String, Box<dyn std::error::Error>> {
+    let entry = match keyring::Entry::new(SERVICE, ACCOUNT) {
+        Ok(entry) => entry,
+        Err(err) => {
+            eprintln!("Couldn't open the keychain: {}", err);
+            return prompt();
+        }
+    };
+    match entry.get_password() {
+        Ok(key) => Ok(key),
+        Err(keyring::Error::NoEntry) => {
+            let key = prompt()?;
+            entry.set_password(&key)?;
+            Ok(key)
+        }
+        Err(err) => {
+            eprintln!("Couldn't read the database key from the keychain: {}", err);
+            prompt()
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+pub fn database_key() -> Result<String, Box<dyn std::error::Error>> {
+    Err("Encrypting the database needs a build with the `sqlcipher` feature".into())
+}
+
+#[cfg(feature = "sqlcipher")]
+fn prompt() -> Result<String, Box<dyn std::error::Error>> {
+    let key = rpassword::prompt_password_stdout("Database key: ")?;
+    if key.is_empty() {
+        return Err("The database key can't be empty.".into());
+    }
+    Ok(key)
+}
@@ -0,0 +1,74 @@
+// The overcast-to-sqlite command line, as a library: `run` is the whole CLI, and `plugin` has the
+// traits for adding enrichers, exporters and importers to it in a build of your own.
+
+mod account;
+mod anonymize;
+mod api;
+mod assets;
+mod audio;
+mod backup;
+mod chart;
+mod check;
+mod checkpoint;
+mod cli;
+mod client;
+mod digest;
+mod discover;
+mod dogsheep;
+mod download;
+mod duckdb;
+mod export;
+mod filters;
+mod fixity;
+mod gpodder;
+mod graphql;
+mod healthcheck;
+mod hooks;
+mod http;
+mod import;
+mod jsonl;
+mod keychain;
+mod linkcheck;
+mod maintain;
+mod mcp;
+mod merge;
+mod metrics;
+mod mqtt;
+mod notes;
+mod notion;
+mod overcast;
+mod overrides;
+mod pages;
+mod pgsync;
+mod pick;
+mod prune;
+mod push;
+mod readlater;
+mod readwise;
+mod recommend;
+mod report;
+mod rss;
+mod runs;
+mod schedule;
+mod smtp;
+mod sqlite;
+mod stats;
+mod storage;
+mod tags;
+mod takeout;
+mod timezone;
+mod tls;
+mod ui;
+mod urls;
+mod warc;
+mod wayback;
+mod webhook;
+mod wrapped;
+mod zip;
+
+pub mod exit;
+pub mod plugin;
+
+pub use cli::run;
+// Plugins get connections from this version of rusqlite.
+pub use rusqlite;
@@ -0,0 +1,133 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+use rusqlite::{params, Connection};
+
+use crate::client;
+
+// What to check: episodes' web pages and audio, and the links in their show notes.
+const URLS: &str = "
+    SELECT html_url AS url FROM episodes WHERE html_url IS NOT NULL
+    UNION SELECT mp3_url FROM episodes WHERE mp3_url IS NOT NULL
+    UNION SELECT url FROM links";
+
+// The outcome of a `check-links` run.
+#[derive(Default)]
+pub struct CheckCounts {
+    pub ok: usize,
+    pub broken: usize,
+    pub failed: usize,
+}
+
+// Probes stored URLs that haven't been checked in the last `recheck_days` days, recording each
+// result in `link_checks`. Requests to the same host are at least `interval` apart, and URLs are
+// interleaved across hosts so the wait is rarely needed.
+pub fn check_links(
+    conn: &Connection,
+    recheck_days: i64,
+    interval: Duration,
+    limit: Option<usize>,
+) -> Result<CheckCounts, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT url FROM ({}) WHERE url NOT IN (
+            SELECT url FROM link_checks WHERE checked_at > datetime('now', ?)
+        )",
+        URLS
+    ))?;
+    let urls = stmt
+        .query_map(params![format!("-{} days", recheck_days)], |row| {
+            row.get::<_, String>(0)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut urls = interleave_hosts(urls);
+    if let Some(limit) = limit {
+        urls.truncate(limit);
+    }
+
+    let client = client::builder().timeout(Duration::from_secs(30)).build()?;
+    let mut last_request: HashMap<String, Instant> = HashMap::new();
+    let mut counts = CheckCounts::default();
+    for (i, url) in urls.iter().enumerate() {
+        if i % 100 == 0 {
+            eprintln!("Checking links ({}/{})...", i, urls.len());
+        }
+        let host = host(url);
+        if let Some(last) = last_request.get(&host) {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                std::thread::sleep(interval - elapsed);
+            }
+        }
+        last_request.insert(host, Instant::now());
+
+        let (status, final_url, error) = match probe(&client, url) {
+            Ok((status, final_url)) => {
+                if (200..400).contains(&status) {
+                    counts.ok += 1;
+                } else {
+                    counts.broken += 1;
+                }
+                (Some(status), Some(final_url).filter(|f| f != url), None)
+            }
+            Err(err) => {
+                counts.failed += 1;
+                (None, None, Some(err.to_string()))
+            }
+        };
+        conn.execute(
+            "INSERT OR REPLACE INTO link_checks(url, checked_at, status, final_url, error)
+            VALUES (?, ?, ?, ?, ?)",
+            params![
+                url,
+                chrono::Utc::now().naive_utc(),
+                status,
+                final_url,
+                error
+            ],
+        )?;
+    }
+    Ok(counts)
+}
+
+// Requests a URL, following redirects, and returns the final status and URL. Some servers don't
+// support HEAD, so those get a GET for the first byte instead.
+fn probe(client: &reqwest::blocking::Client, url: &str) -> Result<(u16, String), reqwest::Error> {
+    let resp = client.head(url).send()?;
+    let status = resp.status().as_u16();
+    if status == 405 || status == 501 {
+        let resp = client
+            .get(url)
+            .header(reqwest::header::RANGE, "bytes=0-0")
+            .send()?;
+        return Ok((resp.status().as_u16(), resp.url().to_string()));
+    }
+    Ok((status, resp.url().to_string()))
+}
+
+fn host(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+        .unwrap_or_default()
+}
+
+// Reorders URLs round-robin across their hosts: one from each host, then the next from each, and
+// so on.
+fn interleave_hosts(urls: Vec<String>) -> Vec<String> {
+    let mut by_host: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for url in urls.into_iter().rev() {
+        by_host.entry(host(&url)).or_default().push(url);
+    }
+    let mut interleaved = Vec::new();
+    while !by_host.is_empty() {
+        by_host.retain(|_, urls| match urls.pop() {
+            Some(url) => {
+                interleaved.push(url);
+                true
+            }
+            None => false,
+        });
+    }
+    interleaved
+}
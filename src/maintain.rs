@@ -0,0 +1,86 @@
+use rusqlite::Connection;
+
+// The row count and on-disk size of a table.
+pub struct TableSize {
+    pub name: String,
+    pub rows: i64,
+    // None when SQLite was built without the `dbstat` virtual table.
+    pub bytes: Option<i64>,
+}
+
+// Runs `PRAGMA integrity_check`, returning the problems found (none if the database is fine).
+pub fn integrity_check(conn: &Connection) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let messages = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(messages.into_iter().filter(|m| m != "ok").collect())
+}
+
+// Refreshes the statistics the query planner uses to pick indexes.
+pub fn analyze(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute_batch("ANALYZE")?;
+    Ok(())
+}
+
+// Rebuilds the database file, reclaiming the space left by deleted and replaced rows.
+pub fn vacuum(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute_batch("VACUUM")?;
+    Ok(())
+}
+
+// The size of the database file, in bytes.
+pub fn database_size(conn: &Connection) -> Result<i64, Box<dyn std::error::Error>> {
+    let size = conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(size)
+}
+
+// Row counts and sizes (including indexes) of every table, largest first.
+pub fn table_sizes(conn: &Connection) -> Result<Vec<TableSize>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+    )?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let has_dbstat = conn.prepare("SELECT 1 FROM dbstat LIMIT 1").is_ok();
+    let mut sizes = Vec::new();
+    for name in tables {
+        let rows = conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", name), [], |row| {
+            row.get(0)
+        })?;
+        let bytes = if has_dbstat {
+            Some(conn.query_row(
+                "SELECT COALESCE(SUM(pgsize), 0) FROM dbstat
+                WHERE name = ?1 OR name IN (SELECT name FROM sqlite_master WHERE tbl_name = ?1)",
+                [&name],
+                |row| row.get(0),
+            )?)
+        } else {
+            None
+        };
+        sizes.push(TableSize { name, rows, bytes });
+    }
+    sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.rows.cmp(&a.rows)));
+    Ok(sizes)
+}
+
+// Formats a byte count for display, like "1.5 MB".
+pub fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,324 @@
+use std::io::{BufRead, Write};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+
+// The Model Context Protocol versions we speak: the first, and later ones, which only add
+// features we don't use. We answer with the client's version when it's one of these.
+const PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26", "2025-06-18"];
+// Keeps a long transcript or show notes page from filling the assistant's context.
+const MAX_NOTES_CHARS: usize = 50_000;
+
+// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// Serves the archive to an LLM assistant over MCP's stdio transport: JSON-RPC requests, one per
+// line on `input`, and responses, one per line on `output`, until the input ends. Everything is
+// read-only.
+pub fn serve(
+    conn: &Connection,
+    input: impl BufRead,
+    output: &mut dyn Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => handle(conn, &request),
+            Err(err) => Some(error(Value::Null, PARSE_ERROR, &err.to_string())),
+        };
+        if let Some(response) = response {
+            serde_json::to_writer(&mut *output, &response)?;
+            output.write_all(b"\n")?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+// The response to a request, or None for a notification, which gets none.
+fn handle(conn: &Connection, request: &Value) -> Option<Value> {
+    let id = request.get("id")?.clone();
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let result = match request["method"].as_str().unwrap_or_default() {
+        "initialize" => {
+            let requested = params["protocolVersion"].as_str().unwrap_or_default();
+            let version = if PROTOCOL_VERSIONS.contains(&requested) {
+                requested
+            } else {
+                PROTOCOL_VERSIONS[0]
+            };
+            json!({
+                "protocolVersion": version,
+                "capabilities": { "tools": {} },
+                "serverInfo": {
+                    "name": "overcast-to-sqlite",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })
+        }
+        "ping" => json!({}),
+        "tools/list" => json!({ "tools": tools() }),
+        "tools/call" => {
+            let name = params["name"].as_str().unwrap_or_default();
+            let args = &params["arguments"];
+            let called = match name {
+                "search_episodes" => search_episodes(conn, args),
+                "get_listening_stats" => listening_stats(conn, args),
+                "get_show_notes" => show_notes(conn, args),
+                _ => {
+                    return Some(error(
+                        id,
+                        INVALID_PARAMS,
+                        &format!("unknown tool \"{}\"", name),
+                    ))
+                }
+            };
+            // Tool failures go back to the assistant as results, so it can see what went wrong
+            // and try again.
+            match called {
+                Ok(value) => json!({
+                    "content": [{ "type": "text", "text": value.to_string() }],
+                    "structuredContent": value,
+                }),
+                Err(err) => json!({
+                    "content": [{ "type": "text", "text": err.to_string() }],
+                    "isError": true,
+                }),
+            }
+        }
+        method => {
+            return Some(error(
+                id,
+                METHOD_NOT_FOUND,
+                &format!("unknown method \"{}\"", method),
+            ))
+        }
+    };
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "search_episodes",
+            "description": "Search the podcast episodes in the user's Overcast archive by title, \
+                show or show notes. Returns the newest matches first, with whether and how far \
+                they've been played.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Words to look for." },
+                    "feed": {
+                        "type": "string",
+                        "description": "Only episodes of shows whose title contains this."
+                    },
+                    "played": {
+                        "type": "boolean",
+                        "description": "Only played (true) or unplayed (false) episodes."
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "How many episodes to return, up to 100. Defaults to 20."
+                    }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "get_listening_stats",
+            "description": "Summarize the user's podcast listening: episodes played, hours \
+                listened, the most-listened shows and hours per month, optionally between two \
+                dates.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "since": {
+                        "type": "string",
+                        "description": "Only count listening on or after this date, YYYY-MM-DD."
+                    },
+                    "until": {
+                        "type": "string",
+                        "description": "Only count listening before this date, YYYY-MM-DD."
+                    }
+                }
+            }
+        },
+        {
+            "name": "get_show_notes",
+            "description": "Get an episode's show notes: its feed description and, if it was \
+                archived, its web page as Markdown. Episode IDs come from search_episodes.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "episode_id": { "type": "integer", "description": "The episode's ID." }
+                },
+                "required": ["episode_id"]
+            }
+        }
+    ])
+}
+
+fn search_episodes(conn: &Connection, args: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+    let query = args["query"]
+        .as_str()
+        .filter(|query| !query.trim().is_empty())
+        .ok_or("query is required")?;
+    let limit = args["limit"].as_u64().unwrap_or(20).min(100) as i64;
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.title, f.title, e.published_at, e.played, p.progress_seconds,
+            p.duration_seconds, COALESCE(e.html_url, e.overcast_url)
+        FROM episodes e
+        JOIN feeds f ON f.id = e.feed_id
+        LEFT JOIN episode_progress p ON p.episode_id = e.id
+        LEFT JOIN episode_details d ON d.episode_id = e.id
+        WHERE (instr(lower(e.title), lower(?1)) > 0
+                OR instr(lower(f.title), lower(?1)) > 0
+                OR instr(lower(d.description), lower(?1)) > 0)
+            AND (?2 IS NULL OR instr(lower(f.title), lower(?2)) > 0)
+            AND (?3 IS NULL OR e.played = ?3)
+        ORDER BY e.published_at DESC
+        LIMIT ?4",
+    )?;
+    let episodes = stmt
+        .query_map(
+            params![
+                query,
+                args["feed"].as_str(),
+                args["played"].as_bool(),
+                limit
+            ],
+            |row| {
+                Ok(json!({
+                    "id": row.get::<_, i64>(0)?,
+                    "title": row.get::<_, Option<String>>(1)?,
+                    "feed": row.get::<_, Option<String>>(2)?,
+                    "publishedAt": row.get::<_, Option<String>>(3)?,
+                    "played": row.get::<_, Option<bool>>(4)?,
+                    "progressSeconds": row.get::<_, Option<i64>>(5)?,
+                    "durationSeconds": row.get::<_, Option<i64>>(6)?,
+                    "url": row.get::<_, Option<String>>(7)?,
+                }))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(json!({ "episodes": episodes }))
+}
+
+fn listening_stats(conn: &Connection, args: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+    for key in &["since", "until"] {
+        if let Some(date) = args[*key].as_str() {
+            chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| format!("{} should be a date like 2024-01-31", key))?;
+        }
+    }
+    let since = args["since"].as_str();
+    let until = args["until"].as_str();
+    let range = "(?1 IS NULL OR l.listened_at >= ?1) AND (?2 IS NULL OR l.listened_at < ?2)";
+
+    let (episodes, seconds): (i64, i64) = conn.query_row(
+        &format!(
+            "SELECT COUNT(*), COALESCE(SUM(seconds), 0) FROM listening_time l WHERE {}",
+            range
+        ),
+        params![since, until],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT f.title, COUNT(*), SUM(l.seconds)
+        FROM listening_time l
+        JOIN feeds f ON f.id = l.feed_id
+        WHERE {}
+        GROUP BY f.id
+        ORDER BY SUM(l.seconds) DESC
+        LIMIT 10",
+        range
+    ))?;
+    let top_feeds = stmt
+        .query_map(params![since, until], |row| {
+            Ok(json!({
+                "feed": row.get::<_, Option<String>>(0)?,
+                "episodesPlayed": row.get::<_, i64>(1)?,
+                "hours": (row.get::<_, i64>(2)? as f64 / 360.0).round() / 10.0,
+            }))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT strftime('%Y-%m', l.listened_at), COUNT(*), SUM(l.seconds)
+        FROM listening_time l
+        WHERE l.listened_at IS NOT NULL AND {}
+        GROUP BY 1
+        ORDER BY 1",
+        range
+    ))?;
+    let per_month = stmt
+        .query_map(params![since, until], |row| {
+            Ok(json!({
+                "month": row.get::<_, String>(0)?,
+                "episodesPlayed": row.get::<_, i64>(1)?,
+                "hours": (row.get::<_, i64>(2)? as f64 / 360.0).round() / 10.0,
+            }))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let (subscribed, unplayed): (i64, i64) = conn.query_row(
+        "SELECT
+            (SELECT COUNT(*) FROM feeds WHERE subscribed),
+            (SELECT COUNT(*) FROM episodes e JOIN feeds f ON f.id = e.feed_id
+                WHERE f.subscribed AND NOT e.played AND NOT e.user_deleted)",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(json!({
+        "episodesPlayed": episodes,
+        "hoursListened": (seconds as f64 / 360.0).round() / 10.0,
+        "topFeeds": top_feeds,
+        "perMonth": per_month,
+        "subscribedFeeds": subscribed,
+        "unplayedEpisodes": unplayed,
+    }))
+}
+
+fn show_notes(conn: &Connection, args: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+    let id = args["episode_id"]
+        .as_i64()
+        .ok_or("episode_id is required")?;
+    let notes = conn
+        .query_row(
+            "SELECT e.title, f.title, COALESCE(e.html_url, e.overcast_url), d.description,
+                pg.markdown
+            FROM episodes e
+            JOIN feeds f ON f.id = e.feed_id
+            LEFT JOIN episode_details d ON d.episode_id = e.id
+            LEFT JOIN episode_pages pg ON pg.episode_id = e.id
+            WHERE e.id = ?",
+            params![id],
+            |row| {
+                let truncate = |text: Option<String>| {
+                    text.map(|text| text.chars().take(MAX_NOTES_CHARS).collect::<String>())
+                };
+                Ok(json!({
+                    "id": id,
+                    "title": row.get::<_, Option<String>>(0)?,
+                    "feed": row.get::<_, Option<String>>(1)?,
+                    "url": row.get::<_, Option<String>>(2)?,
+                    "description": truncate(row.get(3)?),
+                    "pageMarkdown": truncate(row.get(4)?),
+                }))
+            },
+        )
+        .optional()?
+        .ok_or_else(|| format!("no episode with ID {}", id))?;
+    Ok(notes)
+}
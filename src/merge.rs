@@ -0,0 +1,431 @@
+use rusqlite::{params, Connection};
+
+use crate::overrides;
+use crate::schedule;
+use crate::sqlite;
+
+// Counts of rows merged in from the other archive.
+pub struct MergeCounts {
+    pub feeds: usize,
+    pub episodes: usize,
+    pub play_events: usize,
+    pub progress_history: usize,
+}
+
+// Merges another archive into this one. Rows only in the other archive are copied over. When an
+// episode is in both, the one with the newest `updated_at` wins; feeds follow whichever archive
+// has the most recently updated episode for them, and enrichment follows the newest fetch.
+// History (play events, progress and renames), notes and tags are the union of both archives.
+// Overrides are too, the newest winning for a column overridden in both, and are applied again
+// afterwards, so merged-in rows keep the corrections.
+//
+// The other archive is never written to. One made by an older version is copied, and the copy
+// migrated, so both have the same columns.
+//
+// Accounts, categories, people and tags have IDs local to each archive, so they're matched by name
+// instead.
+pub fn merge(
+    conn: &Connection,
+    other_path: &str,
+    pragmas: &[sqlite::Pragma],
+) -> Result<MergeCounts, Box<dyn std::error::Error>> {
+    let (other, outdated) = sqlite::open_read_only_unmigrated(other_path, pragmas)?;
+    if !outdated {
+        drop(other);
+        return merge_from(conn, &sqlite::read_only_uri(other_path));
+    }
+    let copy = std::env::temp_dir().join(format!(
+        "overcast-to-sqlite-merge-{}.db",
+        std::process::id()
+    ));
+    let copy_path = copy.to_string_lossy().to_string();
+    let _ = std::fs::remove_file(&copy);
+    eprintln!(
+        "{} was created by an older version; merging from an updated copy of it.",
+        other_path
+    );
+    let result = other
+        .execute("VACUUM INTO ?", params![copy_path])
+        .map_err(Into::into)
+        .and_then(|_| {
+            drop(other);
+            drop(sqlite::open_existing(&copy_path, pragmas)?);
+            merge_from(conn, &copy_path)
+        });
+    for suffix in &["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", copy_path, suffix));
+    }
+    result
+}
+
+// Merges the archive at `path` (a file name or URI) into this one.
+fn merge_from(conn: &Connection, path: &str) -> Result<MergeCounts, Box<dyn std::error::Error>> {
+    conn.execute("ATTACH DATABASE ? AS other", params![path])?;
+    let result = merge_attached(conn);
+    conn.execute("DETACH DATABASE other", [])?;
+    let counts = result?;
+    sqlite::refresh_episode_urls(conn)?;
+    sqlite::refresh_listening_time(conn)?;
+    schedule::refresh_cadence(conn, chrono::Utc::now().naive_utc())?;
+    Ok(counts)
+}
+
+fn merge_attached(conn: &Connection) -> Result<MergeCounts, Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    let feeds = upsert_from_other(
+        conn,
+        "feeds",
+        &["id"],
+        "(SELECT MAX(updated_at) FROM other.episodes WHERE feed_id = excluded.id)
+            > COALESCE((SELECT MAX(updated_at) FROM main.episodes WHERE feed_id = excluded.id), '')",
+    )?;
+    let episodes = upsert_from_other(
+        conn,
+        "episodes",
+        &["id"],
+        "excluded.updated_at > COALESCE(episodes.updated_at, '')",
+    )?;
+    merge_accounts(conn)?;
+    upsert_from_other(
+        conn,
+        "feed_details",
+        &["feed_id"],
+        "excluded.fetched_at > COALESCE(feed_details.fetched_at, '')",
+    )?;
+    upsert_from_other(
+        conn,
+        "episode_details",
+        &["episode_id"],
+        "excluded.fetched_at > COALESCE(episode_details.fetched_at, '')",
+    )?;
+
+    let play_events = conn.execute(
+        "INSERT INTO main.play_events(episode_id, played_at)
+        SELECT o.episode_id, o.played_at FROM other.play_events o
+        WHERE NOT EXISTS (
+            SELECT 1 FROM main.play_events p
+            WHERE p.episode_id = o.episode_id AND p.played_at = o.played_at
+        )",
+        [],
+    )?;
+    let progress_history = conn.execute(
+        "INSERT OR IGNORE INTO main.progress_history(episode_id, run_at, progress)
+        SELECT episode_id, run_at, progress FROM other.progress_history",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO main.renames(feed_id, episode_id, old_title, new_title, renamed_at)
+        SELECT o.feed_id, o.episode_id, o.old_title, o.new_title, o.renamed_at
+        FROM other.renames o
+        WHERE NOT EXISTS (
+            SELECT 1 FROM main.renames r
+            WHERE r.feed_id IS o.feed_id AND r.episode_id IS o.episode_id
+                AND r.renamed_at = o.renamed_at
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO main.notes(episode_id, text, created_at)
+        SELECT o.episode_id, o.text, o.created_at FROM other.notes o
+        WHERE NOT EXISTS (
+            SELECT 1 FROM main.notes n
+            WHERE n.episode_id = o.episode_id AND n.text = o.text AND n.created_at = o.created_at
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.tags(name) SELECT name FROM other.tags",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO main.taggings(tag_id, feed_id, episode_id, created_at)
+        SELECT t.id, o.feed_id, o.episode_id, o.created_at
+        FROM other.taggings o
+        JOIN other.tags ot ON ot.id = o.tag_id
+        JOIN main.tags t ON t.name = ot.name
+        WHERE NOT EXISTS (
+            SELECT 1 FROM main.taggings g
+            WHERE g.tag_id = t.id AND g.feed_id IS o.feed_id AND g.episode_id IS o.episode_id
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.notion_pages(kind, row_id, page_id, properties, synced_at)
+        SELECT kind, row_id, page_id, properties, synced_at FROM other.notion_pages",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.readwise_highlights(kind, row_id, sent_at)
+        SELECT kind, row_id, sent_at FROM other.readwise_highlights",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.read_later(service, episode_id, item_id, sent_at)
+        SELECT service, episode_id, item_id, sent_at FROM other.read_later",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.feed_settings(feed_id, name, value, updated_at)
+        SELECT feed_id, name, value, updated_at FROM other.feed_settings",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO main.funding(feed_id, position, kind, method, url, name, split)
+        SELECT feed_id, position, kind, method, url, name, split FROM other.funding
+        WHERE feed_id NOT IN (SELECT feed_id FROM main.funding)",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.episode_pages(episode_id, url, status, html, markdown, fetched_at)
+        SELECT episode_id, url, status, html, markdown, fetched_at FROM other.episode_pages",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.warc_records(
+            episode_id, url, status, warc_file, offset, length, record_id, fetched_at
+        )
+        SELECT episode_id, url, status, warc_file, offset, length, record_id, fetched_at
+        FROM other.warc_records",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.overcast_pages(
+            episode_id, url, status, description, duration, artwork_url, fetched_at
+        )
+        SELECT episode_id, url, status, description, duration, artwork_url, fetched_at
+        FROM other.overcast_pages",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.pruned_episodes(episode_id, pruned_at)
+        SELECT episode_id, pruned_at FROM other.pruned_episodes",
+        [],
+    )?;
+    // Episodes pruned from either archive stay pruned, tombstoned like `prune` leaves them.
+    sqlite::tombstone_episodes(
+        conn,
+        "id IN (SELECT episode_id FROM main.pruned_episodes)",
+        &[],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.links(episode_id, source, position, url, text)
+        SELECT episode_id, source, position, url, text FROM other.links",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.sqlar(name, mode, mtime, sz, data)
+        SELECT name, mode, mtime, sz, data FROM other.sqlar",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO main.wayback_snapshots(url, snapshot_url, submitted_at, error)
+        SELECT url, snapshot_url, submitted_at, error FROM other.wayback_snapshots
+        WHERE snapshot_url IS NOT NULL AND url NOT IN (
+            SELECT url FROM main.wayback_snapshots WHERE snapshot_url IS NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.suggestions(
+            source, source_id, based_on_feed_id, title, author, feed_url, website, reason, suggested_at
+        )
+        SELECT source, source_id, based_on_feed_id, title, author, feed_url, website, reason,
+            suggested_at
+        FROM other.suggestions",
+        [],
+    )?;
+
+    conn.execute(
+        "INSERT INTO main.categories(name, parent)
+        SELECT DISTINCT o.name, o.parent FROM other.categories o
+        WHERE NOT EXISTS (
+            SELECT 1 FROM main.categories c WHERE c.name = o.name AND c.parent IS o.parent
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.feed_categories(feed_id, category_id)
+        SELECT fc.feed_id, c.id
+        FROM other.feed_categories fc
+        JOIN other.categories o ON o.id = fc.category_id
+        JOIN main.categories c ON c.name = o.name AND c.parent IS o.parent",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO main.people(name, href, img)
+        SELECT o.name, o.href, MAX(o.img) FROM other.people o
+        WHERE NOT EXISTS (
+            SELECT 1 FROM main.people p WHERE p.name = o.name AND p.href IS o.href
+        )
+        GROUP BY o.name, o.href",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO main.episode_people(episode_id, person_id, role, person_group)
+        SELECT ep.episode_id, p.id, ep.role, ep.person_group
+        FROM other.episode_people ep
+        JOIN other.people o ON o.id = ep.person_id
+        JOIN main.people p ON p.name = o.name AND p.href IS o.href",
+        [],
+    )?;
+
+    upsert_from_other(
+        conn,
+        "overrides",
+        &["table_name", "row_id", "column_name"],
+        "excluded.created_at > overrides.created_at",
+    )?;
+    // The feed and episode upserts overwrite overridden columns with the other archive's values,
+    // so the corrections are written back over them.
+    overrides::apply(conn)?;
+
+    tx.commit()?;
+    Ok(MergeCounts {
+        feeds,
+        episodes,
+        play_events,
+        progress_history,
+    })
+}
+
+// Copies the other archive's accounts, and assigns them to its feeds and episodes that don't have
+// an account here.
+fn merge_accounts(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT OR IGNORE INTO main.accounts(name, username, created_at)
+        SELECT name, username, created_at FROM other.accounts",
+        [],
+    )?;
+    for table in &["feeds", "episodes"] {
+        conn.execute(
+            &format!(
+                "UPDATE main.{table} SET account_id = (
+                    SELECT a.id FROM other.{table} o
+                    JOIN other.accounts oa ON oa.id = o.account_id
+                    JOIN main.accounts a ON a.name = oa.name
+                    WHERE o.id = {table}.id
+                )
+                WHERE account_id IS NULL
+                    AND id IN (SELECT id FROM other.{table} WHERE account_id IS NOT NULL)",
+                table = table
+            ),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+// Copies a table's rows from the other archive, updating rows that already exist when
+// `other_wins` holds. `excluded` refers to the other archive's row, and the bare table name to
+// this one's. Returns the number of rows inserted or updated.
+fn upsert_from_other(
+    conn: &Connection,
+    table: &str,
+    key: &[&str],
+    other_wins: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let other_columns = table_columns(conn, "other", table)?;
+    let columns: Vec<String> = table_columns(conn, "main", table)?
+        .into_iter()
+        // Account IDs are local to each archive; merge_accounts maps them by name.
+        .filter(|c| other_columns.contains(c) && c != "account_id")
+        .collect();
+    let updates: Vec<String> = columns
+        .iter()
+        .filter(|c| !key.contains(&c.as_str()))
+        .map(|c| format!("{} = excluded.{}", c, c))
+        .collect();
+    let sql = format!(
+        // `WHERE true` disambiguates the upsert clause from a join constraint.
+        "INSERT INTO main.{table}({columns}) SELECT {columns} FROM other.{table} WHERE true
+        ON CONFLICT({key}) DO UPDATE SET {updates} WHERE {other_wins}",
+        table = table,
+        columns = columns.join(", "),
+        key = key.join(", "),
+        updates = updates.join(", "),
+        other_wins = other_wins,
+    );
+    Ok(conn.execute(&sql, [])?)
+}
+
+fn table_columns(
+    conn: &Connection,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {}.table_info({})", schema, table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::Target;
+
+    // An archive file in a fresh temporary directory, with one feed and episode.
+    fn archive(name: &str, episode_updated_at: &str) -> (Connection, String) {
+        let dir = std::env::temp_dir().join(format!("overcast-to-sqlite-merge-test-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("archive.db").to_string_lossy().to_string();
+        let conn = sqlite::open(&path, &[]).unwrap();
+        conn.execute_batch(&format!(
+            "INSERT INTO feeds(id, title) VALUES (1, 'Feed');
+            INSERT INTO episodes(id, feed_id, title, played, updated_at)
+            VALUES (10, 1, 'Typo', 0, '{}');",
+            episode_updated_at
+        ))
+        .unwrap();
+        (conn, path)
+    }
+
+    fn title(conn: &Connection, table: &str) -> String {
+        conn.query_row(&format!("SELECT title FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn overrides_survive_a_merge() {
+        let (dest, _) = archive("overrides-dest", "2024-01-01 00:00:00");
+        overrides::set(&dest, &Target::Episode(10), "title", Some("Fixed")).unwrap();
+        let (other, other_path) = archive("overrides-other", "2024-02-01 00:00:00");
+        other.execute("UPDATE episodes SET played = 1", []).unwrap();
+        overrides::set(&other, &Target::Feed(1), "title", Some("Feed (fixed)")).unwrap();
+        drop(other);
+
+        merge(&dest, &other_path, &[]).unwrap();
+        assert_eq!(title(&dest, "episodes"), "Fixed");
+        assert_eq!(title(&dest, "feeds"), "Feed (fixed)");
+        let played: bool = dest
+            .query_row("SELECT played FROM episodes", [], |row| row.get(0))
+            .unwrap();
+        assert!(played);
+        assert_eq!(overrides::list(&dest).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn the_other_archive_isnt_modified() {
+        let (dest, _) = archive("untouched-dest", "2024-01-01 00:00:00");
+        for (name, version) in &[("untouched-current", None), ("untouched-old", Some(3))] {
+            let (other, other_path) = archive(name, "2024-02-01 00:00:00");
+            if let Some(version) = version {
+                other
+                    .execute_batch(&format!("PRAGMA user_version = {}", version))
+                    .unwrap();
+            }
+            drop(other);
+            let before = std::fs::read(&other_path).unwrap();
+
+            let counts = merge(&dest, &other_path, &[]).unwrap();
+            assert_eq!(counts.episodes, 1);
+            assert_eq!(std::fs::read(&other_path).unwrap(), before);
+            dest.execute("UPDATE episodes SET updated_at = '2024-01-01 00:00:00'", [])
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::client;
+use crate::runs::Summary;
+
+// When `command` last succeeded on this archive, going by `runs`.
+pub fn last_success(
+    conn: &Connection,
+    command: &str,
+) -> Result<Option<NaiveDateTime>, Box<dyn std::error::Error>> {
+    let finished_at: Option<String> = conn
+        .query_row(
+            "SELECT MAX(finished_at) FROM runs WHERE command = ? AND exit_code = 0",
+            params![command],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    Ok(finished_at.and_then(|at| NaiveDateTime::parse_from_str(&at, "%Y-%m-%d %H:%M:%S").ok()))
+}
+
+// The run's metrics in Prometheus' text format, labeled with the command. Counts are of the whole
+// archive (`..._total`) and of what the run added (`..._new`).
+pub fn render(summary: &Summary, last_success: Option<NaiveDateTime>) -> String {
+    let mut metrics = vec![
+        (
+            "feeds_total",
+            "Feeds in the archive.",
+            summary.after.feeds as f64,
+        ),
+        (
+            "episodes_total",
+            "Episodes in the archive.",
+            summary.after.episodes as f64,
+        ),
+        (
+            "downloads_total",
+            "Downloaded episodes.",
+            summary.after.downloads as f64,
+        ),
+        (
+            "feeds_new",
+            "Feeds the last run added.",
+            (summary.after.feeds - summary.before.feeds) as f64,
+        ),
+        (
+            "episodes_new",
+            "Episodes the last run added.",
+            (summary.after.episodes - summary.before.episodes) as f64,
+        ),
+        (
+            "downloads_new",
+            "Episodes the last run downloaded.",
+            (summary.after.downloads - summary.before.downloads) as f64,
+        ),
+        (
+            "run_duration_seconds",
+            "How long the last run took.",
+            (summary.finished_at - summary.started_at).num_milliseconds() as f64 / 1000.0,
+        ),
+        (
+            "last_run_exit_code",
+            "The last run's exit code; 0 is success.",
+            summary.exit_code as f64,
+        ),
+        (
+            "last_run_timestamp_seconds",
+            "When the last run finished, in Unix time.",
+            summary.finished_at.and_utc().timestamp() as f64,
+        ),
+    ];
+    if let Some(last_success) = last_success {
+        metrics.push((
+            "last_success_timestamp_seconds",
+            "When a run last succeeded, in Unix time.",
+            last_success.and_utc().timestamp() as f64,
+        ));
+    }
+    let mut text = String::new();
+    for (name, help, value) in metrics {
+        text.push_str(&format!(
+            "# HELP overcast_to_sqlite_{name} {help}\n\
+             # TYPE overcast_to_sqlite_{name} gauge\n\
+             overcast_to_sqlite_{name}{{command=\"{command}\"}} {value}\n",
+            name = name,
+            help = help,
+            command = summary.command,
+            value = value
+        ));
+    }
+    text
+}
+
+// Writes metrics for node_exporter's textfile collector. The file is replaced atomically, so the
+// collector never reads half of it.
+pub fn write_textfile(path: &str, metrics: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let partial = format!("{}.partial", path);
+    std::fs::write(&partial, metrics)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+// Pushes metrics to a Prometheus Pushgateway, replacing the ones from the command's last run.
+pub fn push(url: &str, command: &str, metrics: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let url = format!(
+        "{}/metrics/job/overcast-to-sqlite/command/{}",
+        url.trim_end_matches('/'),
+        command.replace(' ', "-")
+    );
+    client::new()
+        .put(&url)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(metrics.to_string())
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+// A note the user attached to an episode.
+pub struct Note {
+    pub id: i64,
+    pub episode_id: i64,
+    pub episode_title: String,
+    pub text: String,
+    pub created_at: String,
+}
+
+// Attaches a note to an episode, returning the note's ID.
+pub fn add(
+    conn: &Connection,
+    episode_id: i64,
+    text: &str,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM episodes WHERE id = ?",
+            params![episode_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+    if exists.is_none() {
+        return Err(format!("No episode with ID {} in the archive.", episode_id).into());
+    }
+    conn.execute(
+        "INSERT INTO notes(episode_id, text, created_at) VALUES (?, ?, ?)",
+        params![episode_id, text, chrono::Utc::now().naive_utc()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+// Lists notes, oldest first, optionally only those on one episode.
+pub fn list(
+    conn: &Connection,
+    episode_id: Option<i64>,
+) -> Result<Vec<Note>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.episode_id, e.title, n.text, n.created_at
+        FROM notes n
+        JOIN episodes e ON e.id = n.episode_id
+        WHERE ?1 IS NULL OR n.episode_id = ?1
+        ORDER BY n.created_at, n.id",
+    )?;
+    let notes = stmt
+        .query_map(params![episode_id], |row| {
+            Ok(Note {
+                id: row.get(0)?,
+                episode_id: row.get(1)?,
+                episode_title: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                text: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(notes)
+}
+
+// Deletes a note, returning whether it existed.
+pub fn delete(conn: &Connection, id: i64) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(conn.execute("DELETE FROM notes WHERE id = ?", params![id])? > 0)
+}
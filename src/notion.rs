@@ -0,0 +1,212 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+
+use crate::client;
+
+const API_URL: &str = "https://api.notion.com/v1";
+const API_VERSION: &str = "2022-06-28";
+// Notion allows an average of three requests per second per integration.
+const REQUEST_INTERVAL: std::time::Duration = std::time::Duration::from_millis(350);
+// Notion rejects rich text longer than this.
+const MAX_TEXT_LENGTH: usize = 2000;
+
+// Counts of pages touched by a sync.
+#[derive(Default)]
+pub struct SyncCounts {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+pub struct NotionClient {
+    client: reqwest::blocking::Client,
+    token: String,
+}
+
+impl NotionClient {
+    pub fn new(token: &str) -> NotionClient {
+        NotionClient {
+            client: client::new(),
+            token: token.to_string(),
+        }
+    }
+
+    fn send(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: &Value,
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        std::thread::sleep(REQUEST_INTERVAL);
+        let resp = self
+            .client
+            .request(method, format!("{}{}", API_URL, path))
+            .bearer_auth(&self.token)
+            .header("Notion-Version", API_VERSION)
+            .json(body)
+            .send()?;
+        let status = resp.status();
+        let body: Value = resp.json()?;
+        if !status.is_success() {
+            return Err(format!(
+                "Notion API error ({}): {}",
+                status,
+                body["message"].as_str().unwrap_or("unknown error")
+            )
+            .into());
+        }
+        Ok(body)
+    }
+}
+
+// Pushes feeds and episodes into the given Notion databases. The page created for each row is
+// remembered in the `notion_pages` table, along with the properties last sent, so later runs
+// only update pages whose data has changed.
+//
+// The feeds database needs the properties: Name (title), Overcast ID (number), Subscribed
+// (checkbox), Feed URL (url) and Website (url). The episodes database needs: Name (title),
+// Overcast ID (number), Feed (rich text), Published (date), Played (checkbox), Progress
+// (number), Starred (checkbox) and Overcast URL (url).
+pub fn sync(
+    conn: &Connection,
+    notion: &NotionClient,
+    feeds_database: &str,
+    episodes_database: &str,
+) -> Result<SyncCounts, Box<dyn std::error::Error>> {
+    let mut counts = SyncCounts::default();
+
+    let mut stmt = conn.prepare("SELECT id, title, subscribed, feed_url, html_url FROM feeds")?;
+    let feeds = stmt
+        .query_map([], |row| {
+            let id: i64 = row.get(0)?;
+            let title: String = row.get(1)?;
+            let properties = json!({
+                "Name": title_property(&title),
+                "Overcast ID": { "number": id },
+                "Subscribed": { "checkbox": row.get::<_, Option<bool>>(2)?.unwrap_or(false) },
+                "Feed URL": { "url": row.get::<_, Option<String>>(3)? },
+                "Website": { "url": row.get::<_, Option<String>>(4)? },
+            });
+            Ok((id, properties))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (id, properties) in feeds {
+        sync_page(
+            conn,
+            notion,
+            "feed",
+            id,
+            feeds_database,
+            properties,
+            &mut counts,
+        )?;
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.title, f.title, e.published_at, e.played, e.progress, e.starred, e.overcast_url
+        FROM episodes e
+        JOIN feeds f ON f.id = e.feed_id",
+    )?;
+    let episodes = stmt
+        .query_map([], |row| {
+            let id: i64 = row.get(0)?;
+            let title: String = row.get(1)?;
+            let feed_title: String = row.get(2)?;
+            let published_at: Option<String> = row.get(3)?;
+            let properties = json!({
+                "Name": title_property(&title),
+                "Overcast ID": { "number": id },
+                "Feed": { "rich_text": [{ "text": { "content": truncate(&feed_title) } }] },
+                "Published": {
+                    "date": published_at.map(|p| json!({ "start": p[..p.len().min(10)].to_string() }))
+                },
+                "Played": { "checkbox": row.get::<_, Option<bool>>(4)?.unwrap_or(false) },
+                "Progress": { "number": row.get::<_, Option<i64>>(5)? },
+                "Starred": { "checkbox": row.get::<_, Option<bool>>(6)?.unwrap_or(false) },
+                "Overcast URL": { "url": row.get::<_, Option<String>>(7)? },
+            });
+            Ok((id, properties))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (id, properties) in episodes {
+        sync_page(
+            conn,
+            notion,
+            "episode",
+            id,
+            episodes_database,
+            properties,
+            &mut counts,
+        )?;
+    }
+    Ok(counts)
+}
+
+fn sync_page(
+    conn: &Connection,
+    notion: &NotionClient,
+    kind: &str,
+    row_id: i64,
+    database_id: &str,
+    properties: Value,
+    counts: &mut SyncCounts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let serialized = properties.to_string();
+    let existing = conn
+        .query_row(
+            "SELECT page_id, properties FROM notion_pages WHERE kind = ? AND row_id = ?",
+            params![kind, row_id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()?;
+    let page_id = match existing {
+        Some((_, ref synced)) if *synced == serialized => {
+            counts.unchanged += 1;
+            return Ok(());
+        }
+        Some((page_id, _)) => {
+            notion.send(
+                reqwest::Method::PATCH,
+                &format!("/pages/{}", page_id),
+                &json!({ "properties": properties }),
+            )?;
+            counts.updated += 1;
+            page_id
+        }
+        None => {
+            let page = notion.send(
+                reqwest::Method::POST,
+                "/pages",
+                &json!({
+                    "parent": { "database_id": database_id },
+                    "properties": properties,
+                }),
+            )?;
+            counts.created += 1;
+            page["id"]
+                .as_str()
+                .ok_or("Notion didn't return a page ID")?
+                .to_string()
+        }
+    };
+    conn.execute(
+        "INSERT OR REPLACE INTO notion_pages(kind, row_id, page_id, properties, synced_at)
+        VALUES (?, ?, ?, ?, ?)",
+        params![
+            kind,
+            row_id,
+            page_id,
+            serialized,
+            chrono::Utc::now().naive_utc()
+        ],
+    )?;
+    Ok(())
+}
+
+fn title_property(title: &str) -> Value {
+    json!({ "title": [{ "text": { "content": truncate(title) } }] })
+}
+
+fn truncate(text: &str) -> String {
+    text.chars().take(MAX_TEXT_LENGTH).collect()
+}
@@ -1,16 +1,236 @@
 use chrono::{DateTime, NaiveDateTime};
 use std::collections::HashMap;
 
-pub struct OvercastClient(reqwest::blocking::Client);
+use crate::client;
+use crate::pages::{attribute, decode_entities};
+
+// The progress Overcast's web player reports for an episode that's been played to the end.
+const PLAYED_PROGRESS: i64 = 2_147_483_647;
+// The extended OPML attributes stored in their own columns. Anything else is kept as JSON in
+// `raw_attributes`, so attributes Overcast adds later aren't lost.
+const FEED_ATTRIBUTES: &[&str] = &[
+    "type",
+    "text",
+    "title",
+    "overcastId",
+    "xmlUrl",
+    "htmlUrl",
+    "subscribed",
+    "overcastAddedDate",
+    "sortOrder",
+];
+// Per-feed settings in the extended OPML, stored in `feed_settings`. Only notifications are
+// exported; Smart Speed and Voice Boost overrides live on the device and aren't in the export or
+// on the website.
+const FEED_SETTINGS: &[&str] = &["notifications"];
+const EPISODE_ATTRIBUTES: &[&str] = &[
+    "type",
+    "title",
+    "overcastId",
+    "pubDate",
+    "url",
+    "overcastUrl",
+    "enclosureUrl",
+    "userUpdatedDate",
+    "userRecommendedDate",
+    "progress",
+    "played",
+    "userDeleted",
+];
+// Pause between write requests, to go easy on overcast.fm when changing many episodes.
+const WRITE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+// Pause between fetching podcast pages, for the same reason.
+const PAGE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+pub struct OvercastClient {
+    http: reqwest::blocking::Client,
+    // With 1 (-v), logins say how Overcast answered; with 2 (-vv), its responses are also saved
+    // to files, for debugging login failures.
+    verbosity: u64,
+}
+
+// The error `get_export` returns when Overcast refuses the OPML export, which it only allows
+// about once a day.
+#[derive(Debug)]
+pub struct ExportThrottled;
+
+impl std::fmt::Display for ExportThrottled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Overcast's OPML export is rate limited (it allows about one export a day)"
+        )
+    }
+}
+
+impl std::error::Error for ExportThrottled {}
+
+// The error for missing or rejected Overcast credentials.
+#[derive(Debug)]
+pub struct AuthFailed(pub String);
+
+impl std::fmt::Display for AuthFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for AuthFailed {}
+
+// The error for a login Overcast turned away for now, with a CAPTCHA, rate limiting or an outage.
+// Unlike AuthFailed, it's usually worth trying again later.
+#[derive(Debug)]
+pub struct LoginBlocked(pub String);
+
+impl std::fmt::Display for LoginBlocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for LoginBlocked {}
+
+// What went wrong with a login, going by Overcast's response.
+#[derive(Debug, PartialEq)]
+pub enum LoginProblem {
+    WrongCredentials,
+    // Turned away for now; see LoginBlocked.
+    Blocked(String),
+    // A response this version doesn't understand, likely a change to Overcast's login.
+    Changed(String),
+}
+
+// Checks the response to a login (after redirects: the final URL and its status and body) for
+// signs it didn't work. Overcast answers a successful login by redirecting to the podcasts page
+// and a wrong password with the login page and an apology, but a CAPTCHA, a rate limit or a new
+// login flow can come back as a 200 too, so the page is checked for those as well.
+pub fn login_problem(status: u16, url: &reqwest::Url, body: &str) -> Option<LoginProblem> {
+    let page = body.to_lowercase();
+    if status == 429
+        || (page.contains("too many") && (page.contains("attempts") || page.contains("requests")))
+    {
+        return Some(LoginProblem::Blocked(
+            "Overcast is rate limiting logins. Wait an hour or so before trying again.".to_string(),
+        ));
+    }
+    if page.contains("captcha")
+        || page.contains("challenge-platform")
+        || page.contains("<title>just a moment")
+    {
+        return Some(LoginProblem::Blocked(
+            "Overcast asked for a CAPTCHA, which can't be answered from here. Log in once at \
+             https://overcast.fm/login in a browser, then wait a while before trying again."
+                .to_string(),
+        ));
+    }
+    if status >= 500 {
+        return Some(LoginProblem::Blocked(format!(
+            "Overcast's login failed with HTTP {}; it may be down. Try again later.",
+            status
+        )));
+    }
+    if page.contains("sorry, there was a problem looking up your overcast account") {
+        return Some(LoginProblem::WrongCredentials);
+    }
+    if !(200..300).contains(&status) {
+        return Some(LoginProblem::Changed(format!(
+            "Overcast's login answered with HTTP {}, which this version doesn't expect.",
+            status
+        )));
+    }
+    let host = url.host_str().unwrap_or_default();
+    if host != "overcast.fm" && !host.ends_with(".overcast.fm") {
+        return Some(LoginProblem::Changed(format!(
+            "Overcast's login redirected to {}, a login flow this version doesn't support.",
+            url
+        )));
+    }
+    if url.path().starts_with("/login/") {
+        return Some(LoginProblem::Changed(format!(
+            "Overcast asked for another login step ({}), which this version doesn't support. Log \
+             in once at https://overcast.fm/login in a browser, then try again.",
+            url.path()
+        )));
+    }
+    if url.path() == "/login" || page.contains("action=\"/login\"") {
+        return Some(LoginProblem::Changed(
+            "Overcast showed the login page again without saying what was wrong; its login may \
+             have changed."
+                .to_string(),
+        ));
+    }
+    None
+}
+
+// Saves a response to a file in the temporary directory, for debugging, and returns its path.
+// Set-Cookie headers are left out, as they'd hold the session.
+fn save_response(
+    name: &str,
+    status: reqwest::StatusCode,
+    url: &reqwest::Url,
+    headers: &reqwest::header::HeaderMap,
+    body: &str,
+) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join(format!(
+        "overcast-to-sqlite-{}-{}.txt",
+        name,
+        chrono::Utc::now().format("%Y%m%dT%H%M%S")
+    ));
+    let mut dump = format!("{}\nHTTP {}\n", url, status);
+    for (name, value) in headers {
+        if name != reqwest::header::SET_COOKIE {
+            dump.push_str(&format!(
+                "{}: {}\n",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            ));
+        }
+    }
+    dump.push('\n');
+    dump.push_str(body);
+    std::fs::write(&path, dump)?;
+    Ok(path)
+}
+
+// An episode's state as listed on a podcast's overcast.fm page.
+pub struct ListedEpisode {
+    pub overcast_url: String,
+    pub played: bool,
+    pub user_deleted: bool,
+    // The time left to listen to, for started episodes.
+    pub seconds_left: Option<i64>,
+}
+
+// A podcast in Overcast's directory.
+pub struct SearchResult {
+    pub title: String,
+    pub author: Option<String>,
+    pub itunes_id: Option<i64>,
+}
+
+impl SearchResult {
+    // The podcast's page on overcast.fm, when it's known.
+    pub fn overcast_url(&self) -> Option<String> {
+        self.itunes_id
+            .map(|id| format!("https://overcast.fm/itunes{}", id))
+    }
+}
 
 impl OvercastClient {
     pub fn new() -> OvercastClient {
-        OvercastClient(
-            reqwest::blocking::Client::builder()
+        OvercastClient {
+            http: client::builder()
                 .cookie_store(true)
                 .build()
                 .expect("reqwest client"),
-        )
+            verbosity: 0,
+        }
+    }
+
+    // Sets how much the client says about what it's doing; see `verbosity`.
+    pub fn verbose(mut self, verbosity: u64) -> OvercastClient {
+        self.verbosity = verbosity;
+        self
     }
 
     // Authenticates the client with Overcast. Authentication is persisted with cookies.
@@ -23,78 +243,389 @@ impl OvercastClient {
         data.insert("email", username);
         data.insert("password", password);
         let resp = self
-            .0
+            .http
             .post("https://overcast.fm/login")
             .form(&data)
             .send()?;
-        if resp
-            .text()?
-            .contains(&"Sorry, there was a problem looking up your Overcast account".to_string())
-        {
-            return Err("unable to authenticate with Overcast".into());
+        let status = resp.status();
+        let url = resp.url().clone();
+        let headers = resp.headers().clone();
+        let body = resp.text()?;
+        if self.verbosity >= 1 {
+            eprintln!("Overcast's login answered HTTP {} from {}.", status, url);
+        }
+        let saved = if self.verbosity >= 2 {
+            match save_response("login", status, &url, &headers, &body) {
+                Ok(path) => Some(path),
+                Err(err) => {
+                    eprintln!("Couldn't save Overcast's login response: {}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let hint = match saved {
+            Some(path) => format!(" Overcast's response is saved in {}.", path.display()),
+            None => " Run with -vv to save Overcast's response for a bug report.".to_string(),
+        };
+        match login_problem(status.as_u16(), &url, &body) {
+            None => Ok(()),
+            Some(LoginProblem::WrongCredentials) => Err(AuthFailed(
+                "Overcast didn't accept the username and password. Run `auth` to update them."
+                    .to_string(),
+            )
+            .into()),
+            Some(LoginProblem::Blocked(message)) => {
+                Err(LoginBlocked(format!("{}{}", message, hint)).into())
+            }
+            Some(LoginProblem::Changed(message)) => {
+                Err(AuthFailed(format!("{}{}", message, hint)).into())
+            }
         }
+    }
+
+    // Sets an episode's playback position, in seconds, as the web player does.
+    pub fn set_progress(
+        &self,
+        episode_id: i64,
+        seconds: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        std::thread::sleep(WRITE_INTERVAL);
+        self.http
+            .post(format!(
+                "https://overcast.fm/podcasts/set_progress/{}",
+                episode_id
+            ))
+            .form(&[("p", seconds.to_string()), ("speed", "0".to_string())])
+            .send()?
+            .error_for_status()?;
         Ok(())
     }
 
-    pub fn get_podcasts(&self) -> Result<Vec<Feed>, Box<dyn std::error::Error>> {
-        let podcast_contents = self
-            .0
-            .get("https://overcast.fm/account/export_opml/extended")
+    // Marks an episode as played.
+    pub fn mark_played(&self, episode_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_progress(episode_id, PLAYED_PROGRESS)
+    }
+
+    // Deletes an episode, as the web player's delete button does.
+    pub fn delete_episode(&self, episode_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+        std::thread::sleep(WRITE_INTERVAL);
+        self.http
+            .post(format!(
+                "https://overcast.fm/podcasts/delete_item/{}",
+                episode_id
+            ))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // Brings back a deleted (or played) episode. The web player has no undelete, but resetting an
+    // episode's progress to the start returns it to the unplayed episodes.
+    pub fn restore_episode(&self, episode_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_progress(episode_id, 0)
+    }
+
+    // Subscribes to a podcast by its feed URL, as the web player's "Add URL" form does.
+    pub fn subscribe(&self, feed_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::thread::sleep(WRITE_INTERVAL);
+        self.http
+            .post("https://overcast.fm/podcasts/add_url")
+            .form(&[("url", feed_url)])
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // Unsubscribes from a podcast. Its played episodes stay in the account's history.
+    pub fn unsubscribe(&self, feed_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+        std::thread::sleep(WRITE_INTERVAL);
+        self.http
+            .post(format!(
+                "https://overcast.fm/podcasts/unsubscribe/{}",
+                feed_id
+            ))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // Searches Overcast's podcast directory, as the web player's "Add podcast" search box does.
+    pub fn search(&self, query: &str) -> Result<Vec<SearchResult>, Box<dyn std::error::Error>> {
+        let results: serde_json::Value = self
+            .http
+            .get("https://overcast.fm/podcasts/search_autocomplete")
+            .query(&[("q", query)])
             .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(results
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|result| {
+                Some(SearchResult {
+                    title: result["title"].as_str()?.to_string(),
+                    author: result["author"]
+                        .as_str()
+                        .filter(|a| !a.is_empty())
+                        .map(|a| a.to_string()),
+                    // The ID is a number in some responses and a string in others.
+                    itunes_id: result["itunesID"]
+                        .as_i64()
+                        .or_else(|| result["itunesID"].as_str()?.parse().ok()),
+                })
+            })
+            .collect())
+    }
+
+    // Fetches the account page, which shows totals like the time saved by Smart Speed.
+    pub fn get_account_page(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let page = self
+            .http
+            .get("https://overcast.fm/account")
+            .send()?
+            .error_for_status()?
             .text()?;
-        let tree = roxmltree::Document::parse(&podcast_contents)?;
-        let feeds = tree
-            .descendants()
-            .find(|n| n.tag_name().name() == "outline" && n.attribute("text") == Some("feeds"))
-            .unwrap();
-
-        let mut out = Vec::new();
-        for feed in feeds.children() {
-            let title = feed.attribute("title");
-            let id = feed.attribute("overcastId");
-            if title.is_none() || id.is_none() {
-                continue;
+        Ok(page)
+    }
+
+    // Lists the episodes on each of the account's podcast pages, with whether they're played,
+    // deleted or part way through. This is much less than the OPML export has, but the pages
+    // aren't rate limited.
+    pub fn get_listed_episodes(&self) -> Result<Vec<ListedEpisode>, Box<dyn std::error::Error>> {
+        let podcasts = self
+            .http
+            .get("https://overcast.fm/podcasts")
+            .send()?
+            .error_for_status()?
+            .text()?;
+        let mut episodes = Vec::new();
+        for link in links_with_class(&podcasts, "feedcell") {
+            std::thread::sleep(PAGE_INTERVAL);
+            let page = self
+                .http
+                .get(format!("https://overcast.fm{}", link.href))
+                .send()?
+                .error_for_status()?
+                .text()?;
+            episodes.extend(parse_podcast_page(&page));
+        }
+        Ok(episodes)
+    }
+
+    // Fetches the extended OPML export of the account's feeds and episodes.
+    pub fn get_export(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let resp = self
+            .http
+            .get("https://overcast.fm/account/export_opml/extended")
+            .send()?;
+        if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(ExportThrottled.into());
+        }
+        let podcast_contents = resp.text()?;
+        // When throttled, the export is sometimes an HTML error page instead.
+        if !podcast_contents.trim_start().starts_with("<?xml") {
+            return Err(ExportThrottled.into());
+        }
+        Ok(podcast_contents)
+    }
+}
+
+// An export saved by `save_export` less than `max_age` ago, if there is one.
+pub fn cached_export(path: &std::path::Path, max_age: std::time::Duration) -> Option<String> {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    // A modification time in the future (a clock change, say) doesn't count as fresh.
+    if modified.elapsed().ok()? >= max_age {
+        return None;
+    }
+    std::fs::read_to_string(path).ok()
+}
+
+// Saves an export from `get_export`, so runs soon after can use it instead of asking Overcast
+// again. The file's modification time is when it was fetched.
+pub fn save_export(path: &std::path::Path, export: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let partial = path.with_extension("opml.partial");
+    std::fs::write(&partial, export)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+// Parses the feeds and episodes in an OPML export from `get_export`.
+pub fn parse_export(podcast_contents: &str) -> Result<Vec<Feed>, Box<dyn std::error::Error>> {
+    let tree = roxmltree::Document::parse(podcast_contents)?;
+    let feeds = tree
+        .descendants()
+        .find(|n| n.tag_name().name() == "outline" && n.attribute("text") == Some("feeds"))
+        .unwrap();
+
+    let mut out = Vec::new();
+    for feed in feeds.children() {
+        let title = feed.attribute("title");
+        let id = feed.attribute("overcastId");
+        if title.is_none() || id.is_none() {
+            continue;
+        }
+
+        let mut episodes = Vec::new();
+        for episode in feed.children() {
+            if let [Some(title), Some(id)] =
+                [episode.attribute("title"), episode.attribute("overcastId")]
+            {
+                episodes.push(Episode {
+                    id: id.to_string(),
+                    played: episode.attribute("played") == Some("1"),
+                    title: title.to_string(),
+                    updated_at: date_attribute(episode, "userUpdatedDate"),
+                    published_at: date_attribute(episode, "pubDate"),
+                    mp3_url: episode.attribute("enclosureUrl").map(|s| s.to_string()),
+                    overcast_url: episode.attribute("overcastUrl").map(|s| s.to_string()),
+                    html_url: episode.attribute("url").map(|s| s.to_string()),
+                    progress: episode
+                        .attribute("progress")
+                        .and_then(|p| p.parse::<i64>().ok()),
+                    user_deleted: episode.attribute("userDeleted") == Some("1"),
+                    starred_at: date_attribute(episode, "userRecommendedDate"),
+                    raw_attributes: raw_attributes(episode, EPISODE_ATTRIBUTES),
+                });
             }
+        }
+        out.push(Feed {
+            id: id.unwrap().to_string(),
+            title: title.unwrap().to_string(),
+            subscribed: feed.attribute("subscribed") == Some("1"),
+            episodes,
+            feed_url: feed.attribute("xmlUrl").map(|s| s.to_string()),
+            html_url: feed.attribute("htmlUrl").map(|s| s.to_string()),
+            added_at: date_attribute(feed, "overcastAddedDate"),
+            sort_order: feed
+                .attribute("sortOrder")
+                .and_then(|s| s.parse::<i64>().ok()),
+            raw_attributes: raw_attributes(feed, &[FEED_ATTRIBUTES, FEED_SETTINGS].concat()),
+            settings: FEED_SETTINGS
+                .iter()
+                .filter_map(|name| Some((name.to_string(), feed.attribute(*name)?.to_string())))
+                .collect(),
+        });
+    }
+    Ok(out)
+}
 
-            let mut episodes = Vec::new();
-            for episode in feed.children() {
-                if let [Some(title), Some(id)] =
-                    [episode.attribute("title"), episode.attribute("overcastId")]
-                {
-                    episodes.push(Episode {
-                        id: id.to_string(),
-                        played: episode.attribute("played") == Some("1"),
-                        title: title.to_string(),
-                        updated_at: episode.attribute("userUpdatedDate").and_then(|u| {
-                            DateTime::parse_from_rfc3339(u)
-                                .map(|d| d.naive_local())
-                                .ok()
-                        }),
-                        published_at: episode.attribute("pubDate").and_then(|u| {
-                            DateTime::parse_from_rfc3339(u)
-                                .map(|d| d.naive_local())
-                                .ok()
-                        }),
-                        mp3_url: episode.attribute("enclosureUrl").map(|s| s.to_string()),
-                        overcast_url: episode.attribute("overcastUrl").map(|s| s.to_string()),
-                        html_url: episode.attribute("url").map(|s| s.to_string()),
-                        progress: episode
-                            .attribute("progress")
-                            .and_then(|p| p.parse::<i64>().ok()),
-                        user_deleted: episode.attribute("userDeleted") == Some("1"),
-                    });
-                }
+// A link in one of overcast.fm's pages.
+struct PageLink<'a> {
+    classes: String,
+    href: String,
+    // The HTML inside the link.
+    contents: &'a str,
+}
+
+impl PageLink<'_> {
+    fn has_class(&self, class: &str) -> bool {
+        self.classes.split_whitespace().any(|c| c == class)
+    }
+
+    // The link's text, with tags dropped.
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for part in self.contents.split('<') {
+            let part = part.split_once('>').map_or(part, |(_, t)| t);
+            text.push_str(&decode_entities(part));
+            text.push(' ');
+        }
+        text
+    }
+}
+
+// The links in a page whose class includes `class`.
+fn links_with_class<'a>(html: &'a str, class: &str) -> Vec<PageLink<'a>> {
+    let mut links = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("<a ") {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let attributes = &rest[start + 2..end];
+        rest = &rest[end + 1..];
+        let contents = &rest[..rest.find("</a>").unwrap_or(rest.len())];
+        if let (Some(classes), Some(href)) = (
+            attribute(attributes, "class"),
+            attribute(attributes, "href"),
+        ) {
+            let link = PageLink {
+                classes,
+                href,
+                contents,
+            };
+            if link.has_class(class) {
+                links.push(link);
             }
-            out.push(Feed {
-                id: id.unwrap().to_string(),
-                title: title.unwrap().to_string(),
-                subscribed: feed.attribute("subscribed") == Some("1"),
-                episodes,
-                feed_url: feed.attribute("xmlUrl").map(|s| s.to_string()),
-                html_url: feed.attribute("htmlUrl").map(|s| s.to_string()),
-            });
         }
-        Ok(out)
+    }
+    links
+}
+
+// Reads the episodes listed on a podcast's overcast.fm page. Each is a link to its `/+` page, its
+// class saying whether it's been played or deleted, followed by a caption like "Oct 3 • 25 min
+// left" for started episodes.
+pub fn parse_podcast_page(html: &str) -> Vec<ListedEpisode> {
+    links_with_class(html, "extendedepisodecell")
+        .into_iter()
+        .filter(|link| link.href.starts_with("/+"))
+        .map(|link| ListedEpisode {
+            overcast_url: format!("https://overcast.fm{}", link.href),
+            played: link.has_class("userplayedepisode"),
+            user_deleted: link.has_class("userdeletedepisode"),
+            seconds_left: time_left(&link.text()),
+        })
+        .collect()
+}
+
+// Parses the time left in a caption like "Oct 3 • 1 hr 5 min left".
+fn time_left(text: &str) -> Option<i64> {
+    let before = text
+        .split(" left")
+        .next()
+        .filter(|_| text.contains(" left"))?;
+    let listed = before.rsplit('•').next()?;
+    let words: Vec<&str> = listed.split_whitespace().collect();
+    let mut seconds = None;
+    for pair in words.windows(2) {
+        let unit = match pair[1] {
+            "hr" | "hrs" => 3600,
+            "min" | "mins" => 60,
+            "sec" | "secs" => 1,
+            _ => continue,
+        };
+        if let Ok(n) = pair[0].parse::<i64>() {
+            seconds = Some(seconds.unwrap_or(0) + n * unit);
+        }
+    }
+    seconds
+}
+
+// Parses an RFC 3339 timestamp attribute, as the extended OPML uses for dates, converting it to
+// UTC. The export gives times in the account's timezone, which may not be this machine's.
+fn date_attribute(node: roxmltree::Node, name: &str) -> Option<NaiveDateTime> {
+    node.attribute(name)
+        .and_then(|u| DateTime::parse_from_rfc3339(u).ok())
+        .map(|d| d.naive_utc())
+}
+
+// Collects an outline's attributes that aren't in `known` into a JSON object, or None if there
+// aren't any.
+fn raw_attributes(node: roxmltree::Node, known: &[&str]) -> Option<String> {
+    let unknown: serde_json::Map<String, serde_json::Value> = node
+        .attributes()
+        .iter()
+        .filter(|a| !known.contains(&a.name()))
+        .map(|a| (a.name().to_string(), a.value().into()))
+        .collect();
+    if unknown.is_empty() {
+        None
+    } else {
+        Some(serde_json::Value::Object(unknown).to_string())
     }
 }
 
@@ -106,6 +637,14 @@ pub struct Feed {
     pub episodes: Vec<Episode>,
     pub feed_url: Option<String>,
     pub html_url: Option<String>,
+    // When the feed was added to the account (`overcastAddedDate`).
+    pub added_at: Option<NaiveDateTime>,
+    // The feed's position in Overcast's podcast list, if the export includes one.
+    pub sort_order: Option<i64>,
+    // Attributes not modeled above, as a JSON object.
+    pub raw_attributes: Option<String>,
+    // Per-feed settings, as (name, value) pairs.
+    pub settings: Vec<(String, String)>,
 }
 
 #[derive(Debug)]
@@ -120,4 +659,115 @@ pub struct Episode {
     pub mp3_url: Option<String>,
     pub user_deleted: bool,
     pub progress: Option<i64>,
+    // When the episode was recommended ("starred"), if it has been.
+    pub starred_at: Option<NaiveDateTime>,
+    // Attributes not modeled above, as a JSON object.
+    pub raw_attributes: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(status: u16, url: &str, body: &str) -> Option<LoginProblem> {
+        login_problem(status, &reqwest::Url::parse(url).unwrap(), body)
+    }
+
+    #[test]
+    fn successful_login() {
+        assert_eq!(
+            problem(200, "https://overcast.fm/podcasts", "<h2>Your podcasts</h2>"),
+            None
+        );
+    }
+
+    #[test]
+    fn wrong_credentials() {
+        let body = "<div class=\"alert\">Sorry, there was a problem looking up your Overcast \
+                    account.</div><form action=\"/login\">";
+        assert_eq!(
+            problem(200, "https://overcast.fm/login", body),
+            Some(LoginProblem::WrongCredentials)
+        );
+    }
+
+    #[test]
+    fn blocked_logins() {
+        for (status, body) in [
+            (429, ""),
+            (200, "Too many login attempts"),
+            (200, "<div class=\"g-recaptcha\"></div>"),
+            (403, "<title>Just a moment...</title>"),
+            (503, ""),
+        ] {
+            assert!(
+                matches!(
+                    problem(status, "https://overcast.fm/login", body),
+                    Some(LoginProblem::Blocked(_))
+                ),
+                "{} {}",
+                status,
+                body
+            );
+        }
+    }
+
+    #[test]
+    fn changed_logins() {
+        for (status, url, body) in [
+            (404, "https://overcast.fm/login", ""),
+            (200, "https://accounts.example.com/sso", ""),
+            (200, "https://overcast.fm/login/verify", ""),
+            (200, "https://overcast.fm/login", "<form action=\"/login\">"),
+            (200, "https://overcast.fm/", "<form action=\"/login\">"),
+        ] {
+            assert!(
+                matches!(
+                    problem(status, url, body),
+                    Some(LoginProblem::Changed(_))
+                ),
+                "{} {}",
+                url,
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn podcast_pages() {
+        let html = r#"
+            <a class="extendedepisodecell usernewepisode" href="/+abc">
+                <div class="title">New</div><div class="caption2">Oct 3 &bull; 45 min</div>
+            </a>
+            <a class="extendedepisodecell userplayedepisode" href="/+def">Played</a>
+            <a href="/+ghi" class="extendedepisodecell userdeletedepisode">
+                <div class="caption2">Sep 1 &bull; 1 hr 5 min left</div>
+            </a>
+            <a class="extendedepisodecell" href="https://example.com/">Elsewhere</a>
+            <a class="otherlink" href="/+jkl">Not an episode</a>
+        "#;
+        let episodes = parse_podcast_page(html);
+        let urls: Vec<&str> = episodes.iter().map(|e| e.overcast_url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://overcast.fm/+abc",
+                "https://overcast.fm/+def",
+                "https://overcast.fm/+ghi"
+            ]
+        );
+        assert!(!episodes[0].played && !episodes[0].user_deleted);
+        assert_eq!(episodes[0].seconds_left, None);
+        assert!(episodes[1].played);
+        assert!(episodes[2].user_deleted);
+        assert_eq!(episodes[2].seconds_left, Some(3900));
+    }
+
+    #[test]
+    fn times_left() {
+        assert_eq!(time_left("Oct 3 • 25 min left"), Some(1500));
+        assert_eq!(time_left("Oct 3 • 2 hrs 1 min 30 sec left"), Some(7290));
+        assert_eq!(time_left("Oct 3 • 25 min"), None);
+        assert_eq!(time_left("Oct 3 • almost done left"), None);
+    }
 }
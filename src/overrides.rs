@@ -0,0 +1,124 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::tags::Target;
+
+// A value set by hand that wins over Overcast's.
+pub struct Override {
+    pub table: String,
+    pub row_id: i64,
+    pub column: String,
+    pub value: Option<String>,
+}
+
+fn table(target: &Target) -> (&'static str, i64) {
+    match target {
+        Target::Feed(id) => ("feeds", *id),
+        Target::Episode(id) => ("episodes", *id),
+    }
+}
+
+// Sets a column of a feed or episode to `value` (NULL for None), now and after every later
+// `archive` run, so corrections like a fixed title aren't overwritten by the next export. Values
+// are converted to the column's type, and rejected if they don't fit.
+pub fn set(
+    conn: &Connection,
+    target: &Target,
+    column: &str,
+    value: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (table, id) = table(target);
+    let exists = conn
+        .query_row(
+            &format!("SELECT 1 FROM {} WHERE id = ?", table),
+            params![id],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+    if exists.is_none() {
+        return Err(format!("No row with ID {} in {}.", id, table).into());
+    }
+    let known: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info(?) WHERE name = ?)",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    if !known || column == "id" {
+        return Err(format!("{} has no column {} that can be overridden.", table, column).into());
+    }
+    crate::sqlite::atomically(conn, || {
+        conn.execute(
+            "INSERT OR REPLACE INTO overrides(table_name, row_id, column_name, value, created_at)
+            VALUES (?, ?, ?, ?, ?)",
+            params![table, id, column, value, chrono::Utc::now().naive_utc()],
+        )?;
+        apply(conn)?;
+        Ok(())
+    })
+}
+
+// Stops overriding a column. The overridden value stays until the next run replaces it.
+// Returns false if the column wasn't overridden.
+pub fn remove(
+    conn: &Connection,
+    target: &Target,
+    column: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let (table, id) = table(target);
+    let removed = conn.execute(
+        "DELETE FROM overrides WHERE table_name = ? AND row_id = ? AND column_name = ?",
+        params![table, id, column],
+    )?;
+    Ok(removed > 0)
+}
+
+pub fn list(conn: &Connection) -> Result<Vec<Override>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT table_name, row_id, column_name, value FROM overrides
+        ORDER BY table_name, row_id, column_name",
+    )?;
+    let overrides = stmt
+        .query_map([], |row| {
+            Ok(Override {
+                table: row.get(0)?,
+                row_id: row.get(1)?,
+                column: row.get(2)?,
+                value: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(overrides)
+}
+
+// Whether a column of a row is overridden, so a change to it from Overcast isn't real.
+pub fn is_overridden(
+    conn: &Connection,
+    table: &str,
+    id: &str,
+    column: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let overridden = conn.query_row(
+        "SELECT EXISTS (
+            SELECT 1 FROM overrides WHERE table_name = ? AND row_id = ? AND column_name = ?
+        )",
+        params![table, id, column],
+        |row| row.get(0),
+    )?;
+    Ok(overridden)
+}
+
+// Writes every override back over the values the last upsert stored. Overrides of rows that are
+// gone are skipped. Returns how many rows changed.
+pub fn apply(conn: &Connection) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut changed = 0;
+    for o in list(conn)? {
+        changed += conn.execute(
+            &format!(
+                "UPDATE {table} SET \"{column}\" = ?1 WHERE id = ?2 AND \"{column}\" IS NOT ?1",
+                table = o.table,
+                column = o.column
+            ),
+            params![o.value, o.row_id],
+        )?;
+    }
+    Ok(changed)
+}
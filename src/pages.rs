@@ -0,0 +1,662 @@
+use std::path::Path;
+
+use reqwest::Url;
+use rusqlite::{params, Connection};
+
+use crate::client;
+use crate::warc::{http_head, WarcWriter};
+
+// Elements whose contents are never part of an article's text.
+const SKIPPED_ELEMENTS: [&str; 11] = [
+    "script", "style", "noscript", "nav", "header", "footer", "aside", "form", "svg", "iframe",
+    "template",
+];
+
+const HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+// Elements that start a new paragraph.
+const BLOCKS: [&str; 7] = ["p", "div", "section", "blockquote", "ul", "ol", "table"];
+
+// Counts of episode pages fetched by a run.
+#[derive(Default)]
+pub struct PageCounts {
+    pub fetched: usize,
+    pub failed: usize,
+}
+
+// Fetches the web page (`html_url`) of every episode that doesn't have one stored yet, keeping the
+// raw HTML and a Markdown version of its main content in `episode_pages`. Pages are only fetched
+// once, so a copy survives the original going away; pages that failed to fetch (recorded with
+// their HTTP status, or none if the request itself failed) are retried on later runs.
+//
+// With a `warc_dir`, every response (failed ones included) is also written, headers and all, to a
+// new WARC file there, and each record's location is stored in `warc_records`.
+pub fn fetch_pages(
+    conn: &Connection,
+    warc_dir: Option<&Path>,
+) -> Result<PageCounts, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.html_url FROM episodes e
+        LEFT JOIN episode_pages p ON p.episode_id = e.id
+        WHERE e.html_url IS NOT NULL
+            AND (p.episode_id IS NULL OR p.status IS NULL OR p.status NOT BETWEEN 200 AND 299)
+        ORDER BY e.published_at DESC",
+    )?;
+    let pending = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut warc = match warc_dir {
+        Some(dir) if !pending.is_empty() => Some(WarcWriter::create(dir)?),
+        _ => None,
+    };
+    let client = client::new();
+    let mut counts = PageCounts::default();
+    for (i, (episode_id, url)) in pending.iter().enumerate() {
+        if i % 100 == 0 {
+            eprintln!("Fetching episode pages ({}/{})...", i, pending.len());
+        }
+        let (status, html) = match client.get(url).send() {
+            Ok(resp) => {
+                let status = resp.status();
+                let html = match &mut warc {
+                    // The raw bytes are needed for the WARC record, so the page is decoded as
+                    // UTF-8 rather than by its declared charset.
+                    Some(warc) => {
+                        let head = http_head(&resp);
+                        let body = resp.bytes().ok();
+                        if let Some(body) = &body {
+                            let record = warc.write_response(url, &head, body)?;
+                            conn.execute(
+                                "INSERT INTO warc_records(
+                                    episode_id, url, status, warc_file, offset, length, record_id,
+                                    fetched_at
+                                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                                params![
+                                    episode_id,
+                                    url,
+                                    status.as_u16(),
+                                    warc.path().to_string_lossy(),
+                                    record.offset as i64,
+                                    record.length as i64,
+                                    record.record_id,
+                                    chrono::Utc::now().naive_utc()
+                                ],
+                            )?;
+                        }
+                        body.filter(|_| status.is_success())
+                            .map(|body| String::from_utf8_lossy(&body).into_owned())
+                    }
+                    None if status.is_success() => resp.text().ok(),
+                    None => None,
+                };
+                (Some(status.as_u16()), html)
+            }
+            Err(err) => {
+                eprintln!("Couldn't fetch {}: {}", url, err);
+                (None, None)
+            }
+        };
+        let markdown = html.as_deref().map(|html| readable_markdown(html, url));
+        if let Some(html) = &html {
+            record_links(
+                conn,
+                episode_id,
+                "page",
+                &extract_links(main_content(html), Some(url)),
+            )?;
+        }
+        if html.is_some() {
+            counts.fetched += 1;
+        } else {
+            counts.failed += 1;
+        }
+        conn.execute(
+            "INSERT INTO episode_pages(episode_id, url, status, html, markdown, fetched_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(episode_id) DO UPDATE SET
+                url = excluded.url,
+                status = excluded.status,
+                html = excluded.html,
+                markdown = excluded.markdown,
+                fetched_at = excluded.fetched_at",
+            params![
+                episode_id,
+                url,
+                status,
+                html,
+                markdown,
+                chrono::Utc::now().naive_utc()
+            ],
+        )?;
+    }
+    Ok(counts)
+}
+
+// Overcast's episode pages are public, but it's a one-person service: go easy on it.
+const OVERCAST_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+// How many Overcast episode pages to fetch per run; later runs pick up the rest.
+const OVERCAST_BATCH_SIZE: usize = 500;
+
+// Counts of Overcast episode pages fetched by a run.
+#[derive(Default)]
+pub struct OvercastPageCounts {
+    pub fetched: usize,
+    pub failed: usize,
+    pub remaining: usize,
+}
+
+// What an overcast.fm episode page says about the episode.
+#[derive(Default)]
+pub struct OvercastPage {
+    pub description: Option<String>,
+    // In seconds.
+    pub duration: Option<i64>,
+    pub artwork_url: Option<String>,
+}
+
+// Fetches the overcast.fm page (`overcast_url`) of episodes missing a duration or description,
+// caches what it says in `overcast_pages`, and fills the gaps in `episode_details` from it. This
+// gets details Overcast's OPML leaves out without needing the feed, which may be long gone; the
+// feed's own details, from --enrich, take precedence. Each page is fetched once, newest episodes
+// first, and failed fetches are retried on later runs.
+pub fn fetch_overcast_pages(
+    conn: &Connection,
+) -> Result<OvercastPageCounts, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.overcast_url FROM episodes e
+        LEFT JOIN episode_details d ON d.episode_id = e.id
+        LEFT JOIN overcast_pages p ON p.episode_id = e.id
+        WHERE e.overcast_url IS NOT NULL
+            AND (d.duration IS NULL OR d.description IS NULL)
+            AND (p.episode_id IS NULL OR p.status IS NULL OR p.status NOT BETWEEN 200 AND 299)
+        ORDER BY e.published_at DESC",
+    )?;
+    let pending = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let client = client::new();
+    let mut counts = OvercastPageCounts {
+        remaining: pending.len().saturating_sub(OVERCAST_BATCH_SIZE),
+        ..Default::default()
+    };
+    let batch = &pending[..pending.len().min(OVERCAST_BATCH_SIZE)];
+    for (i, (episode_id, url)) in batch.iter().enumerate() {
+        if i % 100 == 0 {
+            eprintln!("Fetching Overcast episode pages ({}/{})...", i, batch.len());
+        }
+        std::thread::sleep(OVERCAST_INTERVAL);
+        let (status, page) = match client.get(url).send() {
+            Ok(resp) => {
+                let status = resp.status();
+                let page = if status.is_success() {
+                    resp.text().ok().map(|html| parse_overcast_page(&html))
+                } else {
+                    None
+                };
+                (Some(status.as_u16()), page)
+            }
+            Err(err) => {
+                eprintln!("Couldn't fetch {}: {}", url, err);
+                (None, None)
+            }
+        };
+        if page.is_some() {
+            counts.fetched += 1;
+        } else {
+            counts.failed += 1;
+        }
+        let page = page.unwrap_or_default();
+        conn.execute(
+            "INSERT INTO overcast_pages(
+                episode_id, url, status, description, duration, artwork_url, fetched_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(episode_id) DO UPDATE SET
+                url = excluded.url,
+                status = excluded.status,
+                description = excluded.description,
+                duration = excluded.duration,
+                artwork_url = excluded.artwork_url,
+                fetched_at = excluded.fetched_at",
+            params![
+                episode_id,
+                url,
+                status,
+                page.description,
+                page.duration,
+                page.artwork_url,
+                chrono::Utc::now().naive_utc()
+            ],
+        )?;
+    }
+    fill_episode_details(conn)?;
+    Ok(counts)
+}
+
+// Fills in the durations and descriptions missing from `episode_details` with the ones from
+// cached Overcast episode pages. This runs after every fetch, since enriching from the feed
+// replaces an episode's details.
+pub fn fill_episode_details(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO episode_details(episode_id, duration, description, fetched_at)
+        SELECT episode_id, duration, description, fetched_at FROM overcast_pages
+        WHERE status BETWEEN 200 AND 299 AND (duration IS NOT NULL OR description IS NOT NULL)
+        ON CONFLICT(episode_id) DO UPDATE SET
+            duration = COALESCE(episode_details.duration, excluded.duration),
+            description = COALESCE(episode_details.description, excluded.description)",
+        [],
+    )?;
+    Ok(())
+}
+
+// Reads the description, duration and artwork from an overcast.fm episode page. The description
+// and artwork are in its Open Graph tags; the duration is in the line under the title, like
+// "October 12, 2021 • 1 hr 5 min", when Overcast knows it.
+pub fn parse_overcast_page(html: &str) -> OvercastPage {
+    let description = meta_content(html, "og:description")
+        .or_else(|| meta_content(html, "description"))
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty());
+    let lowercase = html.to_ascii_lowercase();
+    let duration = lowercase.find("lighttext").and_then(|start| {
+        let start = start + html[start..].find('>')? + 1;
+        let end = start + html[start..].find('<')?;
+        decode_entities(&html[start..end])
+            .split('•')
+            .find_map(parse_listed_duration)
+    });
+    OvercastPage {
+        description,
+        duration,
+        artwork_url: meta_content(html, "og:image"),
+    }
+}
+
+// The content of a page's first `<meta>` tag with the given name (or property).
+fn meta_content(html: &str, name: &str) -> Option<String> {
+    let lowercase = html.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(found) = lowercase[search..].find("<meta") {
+        let start = search + found + "<meta".len();
+        let end = start + lowercase[start..].find('>')?;
+        search = end;
+        let attributes = &html[start..end];
+        let tag_name = attribute(attributes, "name").or_else(|| attribute(attributes, "property"));
+        if tag_name.is_some_and(|n| n.eq_ignore_ascii_case(name)) {
+            return attribute(attributes, "content");
+        }
+    }
+    None
+}
+
+// Parses a length of time the way Overcast lists it, like "1 hr 5 min" or "45 min".
+fn parse_listed_duration(text: &str) -> Option<i64> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut seconds = 0;
+    let mut found = false;
+    for pair in words.windows(2) {
+        let unit = match pair[1].trim_end_matches('.') {
+            "hr" | "hrs" | "hour" | "hours" => 3600,
+            "min" | "mins" | "minute" | "minutes" => 60,
+            "sec" | "secs" | "seconds" => 1,
+            _ => continue,
+        };
+        if let Ok(n) = pair[0].parse::<i64>() {
+            seconds += n * unit;
+            found = true;
+        }
+    }
+    Some(seconds).filter(|_| found)
+}
+
+// Extracts a page's main content as Markdown: the `<article>` (or `<main>`, or `<body>`), without
+// navigation, scripts and other page furniture. Headings, paragraphs, lists, links and emphasis are
+// kept; everything else becomes plain text. Relative links are resolved against `base_url`.
+pub fn readable_markdown(html: &str, base_url: &str) -> String {
+    let base = Url::parse(base_url).ok();
+    let mut out = Writer::default();
+    // The hrefs of open links, so the closing tag can write them out.
+    let mut links: Vec<Option<String>> = Vec::new();
+    walk(main_content(html), |token| match token {
+        Token::Text(text) => out.text(&text),
+        Token::Tag(tag) => match (tag.name.as_str(), tag.closing) {
+            (name, false) if HEADINGS.contains(&name) => {
+                let level = tag.name[1..].parse().unwrap_or(1);
+                out.paragraph_break();
+                out.open(&format!("{} ", "#".repeat(level)));
+            }
+            ("li", false) => {
+                out.line_break();
+                out.open("- ");
+            }
+            ("br", _) | ("tr", true) => out.line_break(),
+            (name, _) if BLOCKS.contains(&name) || HEADINGS.contains(&name) => {
+                out.paragraph_break()
+            }
+            ("strong", false) | ("b", false) => out.open("**"),
+            ("strong", true) | ("b", true) => out.close("**"),
+            ("em", false) | ("i", false) => out.open("_"),
+            ("em", true) | ("i", true) => out.close("_"),
+            ("a", false) => {
+                let href = resolve(base.as_ref(), tag.href);
+                if href.is_some() {
+                    out.open("[");
+                }
+                links.push(href);
+            }
+            ("a", true) => {
+                if let Some(Some(href)) = links.pop() {
+                    out.close(&format!("]({})", href));
+                }
+            }
+            _ => {}
+        },
+    });
+    out.out.trim().to_string()
+}
+
+// A link found in a page or show notes, with its text.
+pub struct Link {
+    pub url: String,
+    pub text: String,
+}
+
+// Finds the http(s) links in some HTML, in order of first appearance. Relative links are resolved
+// against `base_url`, and each URL is only listed once.
+pub fn extract_links(html: &str, base_url: Option<&str>) -> Vec<Link> {
+    let base = base_url.and_then(|url| Url::parse(url).ok());
+    let mut links: Vec<Link> = Vec::new();
+    let mut open: Option<Link> = None;
+    walk(html, |token| match token {
+        Token::Text(text) => {
+            if let Some(link) = &mut open {
+                link.text.push_str(&text);
+            }
+        }
+        Token::Tag(tag) if tag.name == "a" => {
+            if let Some(mut link) = open.take() {
+                link.text = link.text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !links.iter().any(|l| l.url == link.url) {
+                    links.push(link);
+                }
+            }
+            if !tag.closing {
+                open = resolve(base.as_ref(), tag.href)
+                    .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+                    .map(|url| Link {
+                        url,
+                        text: String::new(),
+                    });
+            }
+        }
+        Token::Tag(_) => {}
+    });
+    links
+}
+
+// Stores the links found in an episode's show notes (`source` "show_notes") or web page
+// ("page"), replacing the ones found there before.
+pub fn record_links(
+    conn: &Connection,
+    episode_id: impl rusqlite::ToSql,
+    source: &str,
+    links: &[Link],
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "DELETE FROM links WHERE episode_id = ? AND source = ?",
+        params![episode_id, source],
+    )?;
+    for (position, link) in links.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO links(episode_id, source, position, url, text) VALUES (?, ?, ?, ?, ?)",
+            params![
+                episode_id,
+                source,
+                position as i64,
+                link.url,
+                Some(&link.text).filter(|t| !t.is_empty())
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+// Resolves an href against the page's URL, dropping in-page anchors and scripts.
+fn resolve(base: Option<&Url>, href: Option<String>) -> Option<String> {
+    let href = href.filter(|href| !href.starts_with('#') && !href.starts_with("javascript:"))?;
+    Some(match base {
+        Some(base) => base.join(&href).map(|u| u.to_string()).unwrap_or(href),
+        None => href,
+    })
+}
+
+enum Token {
+    Text(String),
+    Tag(Tag),
+}
+
+// Calls `f` with each tag and (entity-decoded) run of text in some HTML, leaving out comments and
+// the contents of SKIPPED_ELEMENTS.
+fn walk(html: &str, mut f: impl FnMut(Token)) {
+    // Nesting depth inside SKIPPED_ELEMENTS.
+    let mut skipping = 0;
+    let mut rest = html;
+    while !rest.is_empty() {
+        let start = rest.find('<').unwrap_or(rest.len());
+        if skipping == 0 && start > 0 {
+            f(Token::Text(decode_entities(&rest[..start])));
+        }
+        rest = &rest[start..];
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map(|end| &rest[end + 3..]).unwrap_or("");
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = Tag::parse(&rest[1..end]);
+        rest = &rest[end + 1..];
+        let tag = match tag {
+            Some(tag) => tag,
+            None => continue,
+        };
+        if SKIPPED_ELEMENTS.contains(&tag.name.as_str()) {
+            if tag.closing {
+                skipping = std::cmp::max(skipping, 1) - 1;
+            } else if !tag.self_closing {
+                skipping += 1;
+            }
+            continue;
+        }
+        if skipping == 0 {
+            f(Token::Tag(tag));
+        }
+    }
+}
+
+// The part of a page most likely to hold the show notes.
+fn main_content(html: &str) -> &str {
+    // ASCII lowercasing keeps byte offsets the same.
+    let lowercase = html.to_ascii_lowercase();
+    for element in &["article", "main", "body"] {
+        let open = format!("<{}", element);
+        let close = format!("</{}>", element);
+        if let (Some(start), Some(end)) = (lowercase.find(&open), lowercase.rfind(&close)) {
+            if start < end {
+                return &html[start..end];
+            }
+        }
+    }
+    html
+}
+
+struct Tag {
+    name: String,
+    closing: bool,
+    self_closing: bool,
+    href: Option<String>,
+}
+
+impl Tag {
+    // Parses what's between a tag's angle brackets. Returns None for doctypes and the like.
+    fn parse(tag: &str) -> Option<Tag> {
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/');
+        let name: String = tag
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        if name.is_empty() {
+            return None;
+        }
+        let href = if name == "a" && !closing {
+            attribute(&tag[name.len()..], "href")
+        } else {
+            None
+        };
+        Some(Tag {
+            name,
+            closing,
+            self_closing: tag.ends_with('/'),
+            href,
+        })
+    }
+}
+
+// Finds an attribute's value in a tag's attribute list.
+pub fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let lowercase = attributes.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(found) = lowercase[search..].find(name) {
+        let start = search + found;
+        search = start + name.len();
+        let preceded = start == 0 || lowercase[..start].ends_with(char::is_whitespace);
+        let rest = attributes[search..].trim_start();
+        if !preceded || !rest.starts_with('=') {
+            continue;
+        }
+        let rest = rest[1..].trim_start();
+        let value = match rest.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => rest[1..].split(quote).next(),
+            _ => rest.split(char::is_whitespace).next(),
+        }?;
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+// Builds up Markdown, collapsing whitespace the way a browser would.
+#[derive(Default)]
+struct Writer {
+    out: String,
+    // Whether there was whitespace since the last word, to be written before the next one.
+    space: bool,
+}
+
+impl Writer {
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    fn text(&mut self, text: &str) {
+        for (i, word) in text.split_whitespace().enumerate() {
+            if i > 0 || text.starts_with(char::is_whitespace) {
+                self.space = true;
+            }
+            self.word(word);
+        }
+        if text.ends_with(char::is_whitespace) {
+            self.space = true;
+        }
+    }
+
+    fn word(&mut self, word: &str) {
+        if self.space && !self.at_line_start() {
+            self.out.push(' ');
+        }
+        self.space = false;
+        self.out.push_str(word);
+    }
+
+    // Opening markup attaches to the following word.
+    fn open(&mut self, markup: &str) {
+        self.word(markup);
+    }
+
+    // Closing markup attaches to the preceding one.
+    fn close(&mut self, markup: &str) {
+        self.out.push_str(markup);
+    }
+
+    fn line_break(&mut self) {
+        self.space = false;
+        if !self.at_line_start() {
+            self.out.push('\n');
+        }
+    }
+
+    fn paragraph_break(&mut self) {
+        self.line_break();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+}
+
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';').filter(|end| *end < 12) {
+            Some(end) => end,
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "rsquo" | "lsquo" => Some('\''),
+            "rdquo" | "ldquo" => Some('"'),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "hellip" => Some('…'),
+            "bull" => Some('•'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|n| n.parse().ok()))
+                .and_then(std::char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
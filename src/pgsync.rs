@@ -0,0 +1,314 @@
+use rusqlite::Connection;
+
+// Counts of a sync.
+pub struct SyncCounts {
+    pub tables: usize,
+    pub rows: usize,
+}
+
+// Mirrors the archive's tables into the Postgres database at `url`: tables and new columns are
+// created as needed, rows are upserted by primary key (tables without one are replaced), and rows
+// that are gone from the archive are deleted, all in one transaction. Timestamp columns (the ones
+// named "..._at") become TIMESTAMPs and boolean columns BOOLEANs. Stored assets aren't synced, and
+// neither are views or foreign keys.
+#[cfg(feature = "postgres")]
+pub fn sync(conn: &Connection, url: &str) -> Result<SyncCounts, Box<dyn std::error::Error>> {
+    enabled::sync(conn, url)
+}
+
+#[cfg(not(feature = "postgres"))]
+pub fn sync(_conn: &Connection, _url: &str) -> Result<SyncCounts, Box<dyn std::error::Error>> {
+    Err("Syncing to Postgres needs a build with the `postgres` feature".into())
+}
+
+#[cfg(feature = "postgres")]
+mod enabled {
+    use chrono::NaiveDateTime;
+    use postgres::types::ToSql;
+    use rusqlite::types::Value;
+    use rusqlite::Connection;
+
+    use super::SyncCounts;
+
+    // Tables left out of the mirror: stored files are no use to a dashboard.
+    const SKIPPED_TABLES: [&str; 2] = ["sqlar", "sqlar_deleted"];
+    // Rows are inserted this many at a time, well under Postgres's limit on parameters.
+    const BATCH_ROWS: usize = 200;
+
+    // A column's type in the mirror.
+    #[derive(Clone, Copy)]
+    enum Kind {
+        Integer,
+        Real,
+        Text,
+        Blob,
+        Boolean,
+        Timestamp,
+    }
+
+    impl Kind {
+        fn postgres_type(self) -> &'static str {
+            match self {
+                Kind::Integer => "BIGINT",
+                Kind::Real => "DOUBLE PRECISION",
+                Kind::Text => "TEXT",
+                Kind::Blob => "BYTEA",
+                Kind::Boolean => "BOOLEAN",
+                Kind::Timestamp => "TIMESTAMP",
+            }
+        }
+
+        // Converts a SQLite value to this type, or NULL if it doesn't fit.
+        fn convert(self, value: Value) -> Box<dyn ToSql + Sync> {
+            match (self, value) {
+                (Kind::Integer, Value::Integer(i)) => Box::new(Some(i)),
+                (Kind::Integer, Value::Real(f)) => Box::new(Some(f as i64)),
+                (Kind::Integer, Value::Text(s)) => Box::new(s.parse::<i64>().ok()),
+                (Kind::Integer, _) => Box::new(None::<i64>),
+                (Kind::Real, Value::Integer(i)) => Box::new(Some(i as f64)),
+                (Kind::Real, Value::Real(f)) => Box::new(Some(f)),
+                (Kind::Real, Value::Text(s)) => Box::new(s.parse::<f64>().ok()),
+                (Kind::Real, _) => Box::new(None::<f64>),
+                (Kind::Text, Value::Text(s)) => Box::new(Some(s)),
+                (Kind::Text, Value::Integer(i)) => Box::new(Some(i.to_string())),
+                (Kind::Text, Value::Real(f)) => Box::new(Some(f.to_string())),
+                (Kind::Text, _) => Box::new(None::<String>),
+                (Kind::Blob, Value::Blob(b)) => Box::new(Some(b)),
+                (Kind::Blob, Value::Text(s)) => Box::new(Some(s.into_bytes())),
+                (Kind::Blob, _) => Box::new(None::<Vec<u8>>),
+                (Kind::Boolean, Value::Integer(i)) => Box::new(Some(i != 0)),
+                (Kind::Boolean, _) => Box::new(None::<bool>),
+                (Kind::Timestamp, Value::Text(s)) => Box::new(
+                    NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S%.f")
+                        .or_else(|_| NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M:%S%.f"))
+                        .ok(),
+                ),
+                (Kind::Timestamp, _) => Box::new(None::<NaiveDateTime>),
+            }
+        }
+    }
+
+    struct Table {
+        name: String,
+        columns: Vec<(String, Kind)>,
+        primary_key: Vec<String>,
+    }
+
+    // The archive's tables, with their columns' types and primary keys.
+    fn tables(conn: &Connection) -> Result<Vec<Table>, Box<dyn std::error::Error>> {
+        let mut stmt = conn.prepare(
+            "SELECT name, sql FROM main.sqlite_master
+            WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+            ORDER BY rowid",
+        )?;
+        let definitions = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut tables = Vec::new();
+        for (name, definition) in definitions {
+            if SKIPPED_TABLES.contains(&name.as_str()) {
+                continue;
+            }
+            let mut stmt = conn
+                .prepare("SELECT name, upper(type), pk FROM pragma_table_info(?) ORDER BY cid")?;
+            let info = stmt
+                .query_map(rusqlite::params![name], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut primary_key: Vec<(i64, String)> = info
+                .iter()
+                .filter(|(_, _, pk)| *pk > 0)
+                .map(|(column, _, pk)| (*pk, column.clone()))
+                .collect();
+            primary_key.sort();
+            let columns = info
+                .into_iter()
+                .map(|(column, declared, _)| {
+                    let kind = if definition.contains(&format!("CHECK ({} IN (0, 1))", column)) {
+                        Kind::Boolean
+                    } else if declared.starts_with("INT") {
+                        Kind::Integer
+                    } else if declared == "REAL" {
+                        Kind::Real
+                    } else if declared == "BLOB" {
+                        Kind::Blob
+                    } else if column.ends_with("_at") {
+                        Kind::Timestamp
+                    } else {
+                        Kind::Text
+                    };
+                    (column, kind)
+                })
+                .collect();
+            tables.push(Table {
+                name,
+                columns,
+                primary_key: primary_key.into_iter().map(|(_, column)| column).collect(),
+            });
+        }
+        Ok(tables)
+    }
+
+    fn quote(identifier: &str) -> String {
+        format!("\"{}\"", identifier.replace('"', "\"\""))
+    }
+
+    pub fn sync(conn: &Connection, url: &str) -> Result<SyncCounts, Box<dyn std::error::Error>> {
+        let tables = tables(conn)?;
+        let mut client = postgres::Client::connect(url, postgres::NoTls)?;
+        let mut tx = client.transaction()?;
+        let mut rows = 0;
+        for table in &tables {
+            let name = quote(&table.name);
+            let columns: Vec<String> = table.columns.iter().map(|(c, _)| quote(c)).collect();
+            let column_list = columns.join(", ");
+
+            let mut create: Vec<String> = table
+                .columns
+                .iter()
+                .map(|(column, kind)| format!("{} {}", quote(column), kind.postgres_type()))
+                .collect();
+            if !table.primary_key.is_empty() {
+                let key: Vec<String> = table.primary_key.iter().map(|c| quote(c)).collect();
+                create.push(format!("PRIMARY KEY ({})", key.join(", ")));
+            }
+            tx.batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {} ({})",
+                name,
+                create.join(", ")
+            ))?;
+            for (column, kind) in &table.columns {
+                // Mirrors of archives from before columns were snake_case get theirs renamed,
+                // rather than a second copy of each column.
+                let legacy = crate::sqlite::legacy_column_name(column);
+                if legacy != *column {
+                    tx.batch_execute(&format!(
+                        "DO $$ BEGIN
+                            IF EXISTS (
+                                SELECT 1 FROM information_schema.columns
+                                WHERE table_schema = current_schema()
+                                    AND table_name = '{}' AND column_name = '{}'
+                            ) THEN
+                                ALTER TABLE {} RENAME COLUMN {} TO {};
+                            END IF;
+                        END $$",
+                        table.name.replace('\'', "''"),
+                        legacy.replace('\'', "''"),
+                        name,
+                        quote(&legacy),
+                        quote(column)
+                    ))?;
+                }
+                tx.batch_execute(&format!(
+                    "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} {}",
+                    name,
+                    quote(column),
+                    kind.postgres_type()
+                ))?;
+            }
+
+            // Rows go into a temporary copy first, so the real table can be reconciled with it
+            // in a couple of statements.
+            let staging = quote(&format!("{}_sync", table.name));
+            tx.batch_execute(&format!(
+                "CREATE TEMP TABLE {} (LIKE {}) ON COMMIT DROP",
+                staging, name
+            ))?;
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM main.{}", column_list, name))?;
+            let mut source = stmt.query([])?;
+            let mut batch: Vec<Box<dyn ToSql + Sync>> = Vec::new();
+            let mut batch_rows = 0;
+            loop {
+                let row = source.next()?;
+                if let Some(row) = row {
+                    for (i, (_, kind)) in table.columns.iter().enumerate() {
+                        batch.push(kind.convert(row.get::<_, Value>(i)?));
+                    }
+                    batch_rows += 1;
+                    rows += 1;
+                }
+                if batch_rows == BATCH_ROWS || (row.is_none() && batch_rows > 0) {
+                    let width = table.columns.len();
+                    let values: Vec<String> = (0..batch_rows)
+                        .map(|r| {
+                            let placeholders: Vec<String> =
+                                (1..=width).map(|c| format!("${}", r * width + c)).collect();
+                            format!("({})", placeholders.join(", "))
+                        })
+                        .collect();
+                    let params: Vec<&(dyn ToSql + Sync)> =
+                        batch.iter().map(|value| value.as_ref()).collect();
+                    tx.execute(
+                        format!(
+                            "INSERT INTO {} ({}) VALUES {}",
+                            staging,
+                            column_list,
+                            values.join(", ")
+                        )
+                        .as_str(),
+                        &params,
+                    )?;
+                    batch.clear();
+                    batch_rows = 0;
+                }
+                if row.is_none() {
+                    break;
+                }
+            }
+
+            if table.primary_key.is_empty() {
+                tx.batch_execute(&format!(
+                    "DELETE FROM {0}; INSERT INTO {0} ({1}) SELECT {1} FROM {2}",
+                    name, column_list, staging
+                ))?;
+                continue;
+            }
+            let key: Vec<String> = table.primary_key.iter().map(|c| quote(c)).collect();
+            let updated: Vec<&String> = columns.iter().filter(|c| !key.contains(c)).collect();
+            let on_conflict = if updated.is_empty() {
+                "DO NOTHING".to_string()
+            } else {
+                let set: Vec<String> = updated
+                    .iter()
+                    .map(|c| format!("{0} = EXCLUDED.{0}", c))
+                    .collect();
+                let current: Vec<String> =
+                    updated.iter().map(|c| format!("{}.{}", name, c)).collect();
+                let excluded: Vec<String> =
+                    updated.iter().map(|c| format!("EXCLUDED.{}", c)).collect();
+                format!(
+                    "DO UPDATE SET {} WHERE ({}) IS DISTINCT FROM ({})",
+                    set.join(", "),
+                    current.join(", "),
+                    excluded.join(", ")
+                )
+            };
+            let matches: Vec<String> = key
+                .iter()
+                .map(|c| format!("s.{0} = {1}.{0}", c, name))
+                .collect();
+            tx.batch_execute(&format!(
+                "INSERT INTO {0} ({1}) SELECT {1} FROM {2} ON CONFLICT ({3}) {4};
+                DELETE FROM {0} WHERE NOT EXISTS (SELECT 1 FROM {2} s WHERE {5})",
+                name,
+                column_list,
+                staging,
+                key.join(", "),
+                on_conflict,
+                matches.join(" AND ")
+            ))?;
+        }
+        tx.commit()?;
+        Ok(SyncCounts {
+            tables: tables.len(),
+            rows,
+        })
+    }
+}
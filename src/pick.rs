@@ -0,0 +1,160 @@
+use std::str::FromStr;
+
+use rusqlite::{Connection, OptionalExtension, ToSql};
+
+// A length of time given on the command line, like "40m", "1h30m" or "90s". A bare number is
+// minutes, and a bare 0 is the only way to give no time at all; nothing's negative.
+#[derive(Clone, Copy)]
+pub struct Duration(pub i64);
+
+impl FromStr for Duration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid duration \"{}\" (expected e.g. 40m or 1h30m)", s);
+        if let Ok(minutes) = s.trim().parse::<i64>() {
+            return match minutes.checked_mul(60) {
+                Some(seconds) if seconds >= 0 => Ok(Duration(seconds)),
+                _ => Err(error()),
+            };
+        }
+        let mut seconds = 0;
+        let mut number = String::new();
+        for c in s.trim().chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            let unit = match c {
+                'h' => 3600,
+                'm' => 60,
+                's' => 1,
+                _ => return Err(error()),
+            };
+            seconds = number
+                .parse::<i64>()
+                .ok()
+                .and_then(|n| n.checked_mul(unit))
+                .and_then(|n| n.checked_add(seconds))
+                .ok_or_else(error)?;
+            number.clear();
+        }
+        if !number.is_empty() || seconds == 0 {
+            return Err(error());
+        }
+        Ok(Duration(seconds))
+    }
+}
+
+// An episode picked at random.
+pub struct Pick {
+    pub id: i64,
+    pub title: String,
+    pub feed_title: String,
+    pub remaining_seconds: Option<i64>,
+    pub url: Option<String>,
+}
+
+// Picks a random unplayed, undeleted episode, optionally from one feed and with no more than
+// `max_duration` left to listen to. Episodes with an unknown duration only qualify when there's
+// no limit.
+pub fn pick(
+    conn: &Connection,
+    feed_id: Option<i64>,
+    max_duration: Option<Duration>,
+) -> Result<Option<Pick>, Box<dyn std::error::Error>> {
+    let mut conditions = vec!["NOT p.played", "NOT p.user_deleted"];
+    let mut params: Vec<&dyn ToSql> = Vec::new();
+    if let Some(feed_id) = &feed_id {
+        conditions.push("p.feed_id = ?");
+        params.push(feed_id);
+    }
+    let max_seconds = max_duration.map(|d| d.0);
+    if let Some(max_seconds) = &max_seconds {
+        conditions.push("p.remaining_seconds <= ?");
+        params.push(max_seconds);
+    }
+    let pick = conn
+        .query_row(
+            &format!(
+                "SELECT p.episode_id, p.title, f.title, p.remaining_seconds,
+                    COALESCE(e.overcast_url, e.html_url)
+                FROM episode_progress p
+                JOIN episodes e ON e.id = p.episode_id
+                JOIN feeds f ON f.id = p.feed_id
+                WHERE {}
+                ORDER BY RANDOM()
+                LIMIT 1",
+                conditions.join(" AND ")
+            ),
+            params.as_slice(),
+            |row| {
+                Ok(Pick {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    feed_title: row.get(2)?,
+                    remaining_seconds: row.get(3)?,
+                    url: row.get(4)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(pick)
+}
+
+// Opens a URL in the default browser.
+pub fn open_url(url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let status = if cfg!(target_os = "macos") {
+        std::process::Command::new("open").arg(url).status()?
+    } else if cfg!(windows) {
+        std::process::Command::new("cmd")
+            .args(["/C", "start", "", url])
+            .status()?
+    } else {
+        std::process::Command::new("xdg-open").arg(url).status()?
+    };
+    if !status.success() {
+        return Err(format!("couldn't open {}", url).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(duration: &str) -> i64 {
+        duration.parse::<Duration>().unwrap().0
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(seconds("40"), 40 * 60);
+        assert_eq!(seconds("40m"), 40 * 60);
+        assert_eq!(seconds("1h30m"), 90 * 60);
+        assert_eq!(seconds("90s"), 90);
+        assert_eq!(seconds(" 2h "), 7200);
+        assert_eq!(seconds("0"), 0);
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for duration in [
+            "",
+            "0m",
+            "-5",
+            "-5m",
+            "1x",
+            "h",
+            "10m5",
+            "999999999999999999",
+            "9999999999999999h",
+        ] {
+            assert!(
+                duration.parse::<Duration>().is_err(),
+                "{:?} parsed",
+                duration
+            );
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use rusqlite::Connection;
+
+pub use crate::import::ImportCounts;
+
+// Extension points for building your own overcast-to-sqlite with extra enrichers, exporters and
+// importers, without forking it: implement the traits, add them to a `Registry` and pass it to
+// `overcast_to_sqlite::run` from your own `main`. The archive's tables are documented in the
+// README; plugins read and write them through the `Connection` they're given.
+
+// Adds to the archive after `archive` has stored feeds and episodes, and after the built-in
+// steps, e.g. by fetching more metadata from another service. Enrichers run on every archive run,
+// in the order they were registered.
+pub trait Enricher {
+    // A short name, shown in progress output.
+    fn name(&self) -> &str;
+
+    fn enrich(&self, conn: &Connection) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+// Writes the archive out in another format, as `export --format <name>`. `output` is the
+// --output path, if one was given.
+pub trait Exporter {
+    // The --format value that selects this exporter.
+    fn name(&self) -> &str;
+
+    fn export(
+        &self,
+        conn: &Connection,
+        output: Option<&Path>,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+// Imports subscriptions or listening history from another app, as `import plugin <name>
+// <db_path> <source>`. Rows from other apps should get IDs from `imported_id`, so they never
+// collide with Overcast's.
+pub trait Importer {
+    // The name that selects this importer.
+    fn name(&self) -> &str;
+
+    fn import(
+        &self,
+        conn: &Connection,
+        source: &Path,
+    ) -> Result<ImportCounts, Box<dyn std::error::Error>>;
+}
+
+// A stable, negative ID for a row imported with the `source` app's `key` for it.
+pub fn imported_id(source: &str, key: &str) -> i64 {
+    crate::import::imported_id(source, key)
+}
+
+// The plugins a build of overcast-to-sqlite runs with.
+#[derive(Default)]
+pub struct Registry {
+    enrichers: Vec<Box<dyn Enricher>>,
+    exporters: Vec<Box<dyn Exporter>>,
+    importers: Vec<Box<dyn Importer>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    pub fn add_enricher(&mut self, enricher: Box<dyn Enricher>) {
+        self.enrichers.push(enricher);
+    }
+
+    pub fn add_exporter(&mut self, exporter: Box<dyn Exporter>) {
+        self.exporters.push(exporter);
+    }
+
+    pub fn add_importer(&mut self, importer: Box<dyn Importer>) {
+        self.importers.push(importer);
+    }
+
+    pub(crate) fn enrichers(&self) -> &[Box<dyn Enricher>] {
+        &self.enrichers
+    }
+
+    pub(crate) fn exporter(&self, name: &str) -> Option<&dyn Exporter> {
+        self.exporters
+            .iter()
+            .find(|exporter| exporter.name() == name)
+            .map(|exporter| exporter.as_ref())
+    }
+
+    pub(crate) fn importer(&self, name: &str) -> Option<&dyn Importer> {
+        self.importers
+            .iter()
+            .find(|importer| importer.name() == name)
+            .map(|importer| importer.as_ref())
+    }
+
+    // The registered exporters' or importers' names, for error messages.
+    pub(crate) fn exporter_names(&self) -> Vec<&str> {
+        self.exporters
+            .iter()
+            .map(|exporter| exporter.name())
+            .collect()
+    }
+
+    pub(crate) fn importer_names(&self) -> Vec<&str> {
+        self.importers
+            .iter()
+            .map(|importer| importer.name())
+            .collect()
+    }
+}
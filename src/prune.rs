@@ -0,0 +1,259 @@
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection};
+
+use crate::sqlite;
+
+// How old an episode has to be to be pruned, like "2y", "6m", "4w" or "90d". Months are 30 days
+// and years 365.
+#[derive(Clone, Copy)]
+pub struct Age(chrono::Duration);
+
+impl FromStr for Age {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+        let err = || format!("unknown age \"{}\" (expected like 2y, 6m, 4w or 90d)", s);
+        let number: i64 = number.parse().map_err(|_| err())?;
+        let days = match unit {
+            "d" => 1,
+            "w" => 7,
+            "m" => 30,
+            "y" => 365,
+            _ => return Err(err()),
+        };
+        let age = number
+            .checked_mul(days)
+            .and_then(chrono::Duration::try_days)
+            .map(Age)
+            .ok_or_else(|| format!("age \"{}\" is too long", s))?;
+        age.cutoff(chrono::Utc::now().naive_utc())?;
+        Ok(age)
+    }
+}
+
+impl Age {
+    // When something has to be from before to be this old, as of `now`.
+    pub fn cutoff(&self, now: NaiveDateTime) -> Result<NaiveDateTime, String> {
+        now.checked_sub_signed(self.0)
+            .ok_or_else(|| format!("{} days ago is too long ago", self.0.num_days()))
+    }
+}
+
+// Which episodes to prune.
+pub struct Policy {
+    pub older_than: Age,
+    pub played_only: bool,
+}
+
+// What a prune removed, or would remove.
+#[derive(Default)]
+pub struct Pruned {
+    pub episodes: usize,
+    // Stored assets (transcripts and show notes) of the pruned episodes.
+    pub assets: usize,
+    // Paths of the pruned episodes' downloaded audio, relative to the download directory.
+    pub audio: Vec<String>,
+    pub audio_bytes: i64,
+}
+
+// Removes episodes published longer ago than the policy's age (by `updated_at` when there's no
+// publish date), along with everything stored about them. Starred episodes, and episodes with
+// notes or tags, are always kept. Removed rows are tombstoned into `*_deleted` tables rather than
+// deleted, until `purge`. Pruned IDs are recorded in `pruned_episodes`, so later runs don't archive
+// them again. With `forget_audio`, the pruned audio's `fixity` entries go too, for when the files
+// are being deleted. Everything happens in one transaction, which a dry run rolls back.
+pub fn prune(
+    conn: &Connection,
+    policy: &Policy,
+    forget_audio: bool,
+    dry_run: bool,
+) -> Result<Pruned, Box<dyn std::error::Error>> {
+    let now = chrono::Utc::now().naive_utc();
+    let cutoff = policy.older_than.cutoff(now)?;
+    let tx = conn.unchecked_transaction()?;
+    conn.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS prune_candidates(id INTEGER PRIMARY KEY);
+        DELETE FROM prune_candidates;",
+    )?;
+    conn.execute(
+        "INSERT INTO prune_candidates(id)
+        SELECT e.id FROM episodes e
+        WHERE COALESCE(e.published_at, e.updated_at) < ?
+            AND (e.played OR NOT ?)
+            AND NOT COALESCE(e.starred, 0)
+            AND NOT EXISTS (SELECT 1 FROM notes n WHERE n.episode_id = e.id)
+            AND NOT EXISTS (SELECT 1 FROM taggings t WHERE t.episode_id = e.id)",
+        params![cutoff, policy.played_only],
+    )?;
+
+    let mut pruned = Pruned::default();
+    let mut stmt = conn.prepare(
+        "SELECT path, bytes FROM downloads WHERE episode_id IN (SELECT id FROM prune_candidates)",
+    )?;
+    let audio = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (path, bytes) in audio {
+        pruned.audio.push(path);
+        pruned.audio_bytes += bytes;
+    }
+    if forget_audio {
+        sqlite::tombstone(
+            conn,
+            "fixity",
+            "kind = 'audio' AND name IN (
+                SELECT path FROM downloads WHERE episode_id IN (SELECT id FROM prune_candidates)
+            )",
+            &[],
+        )?;
+    }
+    pruned.assets = sqlite::tombstone(
+        conn,
+        "sqlar",
+        "EXISTS (
+            SELECT 1 FROM prune_candidates c
+            WHERE sqlar.name LIKE 'transcripts/' || c.id || '.%'
+                OR sqlar.name LIKE 'show-notes/' || c.id || '.%'
+        )",
+        &[],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO pruned_episodes(episode_id, pruned_at)
+        SELECT id, ? FROM prune_candidates",
+        params![now],
+    )?;
+    pruned.episodes =
+        sqlite::tombstone_episodes(conn, "id IN (SELECT id FROM prune_candidates)", &[])?;
+    conn.execute("DELETE FROM prune_candidates", [])?;
+    if dry_run {
+        tx.rollback()?;
+    } else {
+        tx.commit()?;
+    }
+    Ok(pruned)
+}
+
+// Permanently deletes tombstoned rows from every `*_deleted` table, or with `older_than` only
+// those deleted longer ago than that. Returns how many rows each table lost (or would lose, for a
+// dry run), skipping tables that lost none.
+pub fn purge(
+    conn: &Connection,
+    older_than: Option<Age>,
+    dry_run: bool,
+) -> Result<Vec<(String, usize)>, Box<dyn std::error::Error>> {
+    let cutoff = older_than
+        .map(|age| age.cutoff(chrono::Utc::now().naive_utc()))
+        .transpose()?;
+    let tx = conn.unchecked_transaction()?;
+    let mut stmt = conn.prepare(
+        r"SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE '%\_deleted' ESCAPE '\'
+        ORDER BY name",
+    )?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut purged = Vec::new();
+    for table in tables {
+        let count = conn.execute(
+            &format!("DELETE FROM {} WHERE deleted_at < ? OR ? IS NULL", table),
+            params![cutoff, cutoff],
+        )?;
+        if count > 0 {
+            purged.push((table, count));
+        }
+    }
+    if dry_run {
+        tx.rollback()?;
+    } else {
+        tx.commit()?;
+    }
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn days(age: &str) -> i64 {
+        age.parse::<Age>().unwrap().0.num_days()
+    }
+
+    #[test]
+    fn ages() {
+        assert_eq!(days("90d"), 90);
+        assert_eq!(days("4w"), 28);
+        assert_eq!(days("6m"), 180);
+        assert_eq!(days("2y"), 730);
+        assert_eq!(days("0d"), 0);
+    }
+
+    #[test]
+    fn ages_too_long_ago() {
+        for age in ["1000000y", "9999999999999999999d", "999999999999999999y"] {
+            assert!(age.parse::<Age>().is_err(), "{:?} parsed", age);
+        }
+    }
+
+    #[test]
+    fn invalid_ages() {
+        for age in ["", "30", "d", "-1d", "2 y", "3h", "1.5y"] {
+            assert!(age.parse::<Age>().is_err(), "{:?} parsed", age);
+        }
+    }
+
+    #[test]
+    fn purging_tombstones() {
+        let conn = Connection::open_in_memory().unwrap();
+        let now = chrono::Utc::now().naive_utc();
+        conn.execute_batch(
+            "CREATE TABLE episodes_deleted(deleted_at TEXT NOT NULL, id INTEGER);
+            CREATE TABLE feeds_deleted(deleted_at TEXT NOT NULL, id INTEGER);
+            CREATE TABLE not_deleted_at_all(deleted_at TEXT NOT NULL);",
+        )
+        .unwrap();
+        for (table, days_ago) in [
+            ("episodes_deleted", 100),
+            ("episodes_deleted", 1),
+            ("feeds_deleted", 1),
+            ("not_deleted_at_all", 100),
+        ] {
+            conn.execute(
+                &format!("INSERT INTO {}(deleted_at) VALUES (?)", table),
+                params![now - chrono::Duration::days(days_ago)],
+            )
+            .unwrap();
+        }
+        let remaining = |table: &str| -> i64 {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+
+        let older = Some("30d".parse().unwrap());
+        assert_eq!(
+            purge(&conn, older, true).unwrap(),
+            vec![("episodes_deleted".to_string(), 1)]
+        );
+        assert_eq!(remaining("episodes_deleted"), 2);
+        assert_eq!(
+            purge(&conn, older, false).unwrap(),
+            vec![("episodes_deleted".to_string(), 1)]
+        );
+        assert_eq!(remaining("episodes_deleted"), 1);
+        assert_eq!(
+            purge(&conn, None, false).unwrap(),
+            vec![
+                ("episodes_deleted".to_string(), 1),
+                ("feeds_deleted".to_string(), 1)
+            ]
+        );
+        // Only tombstone tables are purged.
+        assert_eq!(remaining("not_deleted_at_all"), 1);
+    }
+}
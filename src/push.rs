@@ -0,0 +1,250 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::overcast::OvercastClient;
+
+// A change to make to episodes on overcast.fm.
+pub enum Action {
+    Played,
+    Delete,
+    Restore,
+}
+
+// An archived episode to change on overcast.fm.
+pub struct PushEpisode {
+    pub id: i64,
+    pub title: String,
+}
+
+// Looks up the episodes to push: the given IDs, plus the IDs returned by `query` (the first
+// column of each row), if any.
+pub fn select_episodes(
+    conn: &Connection,
+    ids: &[i64],
+    query: Option<&str>,
+) -> Result<Vec<PushEpisode>, Box<dyn std::error::Error>> {
+    let mut ids = ids.to_vec();
+    if let Some(query) = query {
+        ids.extend(query_column::<i64>(conn, query)?);
+    }
+    let mut seen = std::collections::HashSet::new();
+    let mut episodes = Vec::new();
+    for id in ids {
+        if seen.insert(id) {
+            episodes.push(find_episode(conn, id)?);
+        }
+    }
+    Ok(episodes)
+}
+
+// Looks up the playback positions to push: the given episode and position, plus the (episode ID,
+// seconds) pairs returned by `query`, if any. A later position for the same episode wins.
+pub fn select_progress(
+    conn: &Connection,
+    progress: Option<(i64, i64)>,
+    query: Option<&str>,
+) -> Result<Vec<(PushEpisode, i64)>, Box<dyn std::error::Error>> {
+    let mut pairs: Vec<(i64, i64)> = progress.into_iter().collect();
+    if let Some(query) = query {
+        let mut stmt = conn.prepare(query)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        pairs.extend(rows);
+    }
+    let mut positions = std::collections::BTreeMap::new();
+    for (id, seconds) in pairs {
+        positions.insert(id, seconds);
+    }
+    positions
+        .into_iter()
+        .map(|(id, seconds)| Ok((find_episode(conn, id)?, seconds)))
+        .collect()
+}
+
+fn find_episode(conn: &Connection, id: i64) -> Result<PushEpisode, Box<dyn std::error::Error>> {
+    let title: Option<Option<String>> = conn
+        .query_row(
+            "SELECT title FROM episodes WHERE id = ? AND source = 'overcast'",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match title {
+        Some(title) => Ok(PushEpisode {
+            id,
+            title: title.unwrap_or_default(),
+        }),
+        None => Err(format!("No Overcast episode with ID {} in the archive.", id).into()),
+    }
+}
+
+// Sets playback positions on overcast.fm, and in the archive.
+pub fn set_progress(
+    conn: &Connection,
+    client: &OvercastClient,
+    positions: &[(PushEpisode, i64)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (i, (episode, seconds)) in positions.iter().enumerate() {
+        eprintln!(
+            "[{}/{}] Setting \"{}\" to {}s...",
+            i + 1,
+            positions.len(),
+            episode.title,
+            seconds
+        );
+        client.set_progress(episode.id, *seconds)?;
+        conn.execute(
+            "UPDATE episodes SET progress = ? WHERE id = ?",
+            params![seconds, episode.id],
+        )?;
+    }
+    Ok(())
+}
+
+// Applies a change to episodes on overcast.fm, and to the archive so it reflects the change before
+// the next `archive` run. Episodes marked played weren't listened to, so no play events are
+// recorded for them.
+pub fn apply(
+    conn: &Connection,
+    client: &OvercastClient,
+    action: &Action,
+    episodes: &[PushEpisode],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (verb, update) = match action {
+        Action::Played => (
+            "Marking as played",
+            "UPDATE episodes SET played = 1 WHERE id = ?",
+        ),
+        Action::Delete => (
+            "Deleting",
+            "UPDATE episodes SET user_deleted = 1 WHERE id = ?",
+        ),
+        Action::Restore => (
+            "Restoring",
+            "UPDATE episodes SET user_deleted = 0, played = 0, progress = 0 WHERE id = ?",
+        ),
+    };
+    for (i, episode) in episodes.iter().enumerate() {
+        eprintln!(
+            "[{}/{}] {} \"{}\"...",
+            i + 1,
+            episodes.len(),
+            verb,
+            episode.title
+        );
+        match action {
+            Action::Played => client.mark_played(episode.id)?,
+            Action::Delete => client.delete_episode(episode.id)?,
+            Action::Restore => client.restore_episode(episode.id)?,
+        }
+        conn.execute(update, params![episode.id])?;
+    }
+    Ok(())
+}
+
+// An archived feed to change on overcast.fm.
+pub struct PushFeed {
+    pub id: i64,
+    pub title: String,
+}
+
+// Looks up the feeds to push: the given IDs, plus the IDs returned by `query`, if any.
+pub fn select_feeds(
+    conn: &Connection,
+    ids: &[i64],
+    query: Option<&str>,
+) -> Result<Vec<PushFeed>, Box<dyn std::error::Error>> {
+    let mut ids = ids.to_vec();
+    if let Some(query) = query {
+        ids.extend(query_column::<i64>(conn, query)?);
+    }
+    let mut seen = std::collections::HashSet::new();
+    let mut feeds = Vec::new();
+    for id in ids {
+        if !seen.insert(id) {
+            continue;
+        }
+        let title: Option<Option<String>> = conn
+            .query_row(
+                "SELECT title FROM feeds WHERE id = ? AND source = 'overcast'",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match title {
+            Some(title) => feeds.push(PushFeed {
+                id,
+                title: title.unwrap_or_default(),
+            }),
+            None => return Err(format!("No Overcast feed with ID {} in the archive.", id).into()),
+        }
+    }
+    Ok(feeds)
+}
+
+// The feed URLs to subscribe to: the given ones, plus those returned by `query`, if any.
+pub fn select_feed_urls(
+    conn: &Connection,
+    urls: &[String],
+    query: Option<&str>,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut urls = urls.to_vec();
+    if let Some(query) = query {
+        urls.extend(query_column::<String>(conn, query)?);
+    }
+    let mut seen = std::collections::HashSet::new();
+    urls.retain(|url| seen.insert(url.clone()));
+    Ok(urls)
+}
+
+// Unsubscribes from feeds on overcast.fm, and marks them unsubscribed in the archive.
+pub fn unsubscribe(
+    conn: &Connection,
+    client: &OvercastClient,
+    feeds: &[PushFeed],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (i, feed) in feeds.iter().enumerate() {
+        eprintln!(
+            "[{}/{}] Unsubscribing from \"{}\"...",
+            i + 1,
+            feeds.len(),
+            feed.title
+        );
+        client.unsubscribe(feed.id)?;
+        conn.execute(
+            "UPDATE feeds SET subscribed = 0 WHERE id = ?",
+            params![feed.id],
+        )?;
+    }
+    Ok(())
+}
+
+// Subscribes to feeds on overcast.fm. Feeds already in the archive are marked subscribed; new
+// ones are added by the next `archive` run, once Overcast has assigned them an ID.
+pub fn subscribe(
+    conn: &Connection,
+    client: &OvercastClient,
+    urls: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (i, url) in urls.iter().enumerate() {
+        eprintln!("[{}/{}] Subscribing to {}...", i + 1, urls.len(), url);
+        client.subscribe(url)?;
+        conn.execute(
+            "UPDATE feeds SET subscribed = 1 WHERE feed_url = ? AND source = 'overcast'",
+            params![url],
+        )?;
+    }
+    Ok(())
+}
+
+// The first column of every row a query returns.
+fn query_column<T: rusqlite::types::FromSql>(
+    conn: &Connection,
+    query: &str,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(query)?;
+    let values = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(values)
+}
@@ -0,0 +1,173 @@
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+use crate::client;
+
+const INSTAPAPER_URL: &str = "https://www.instapaper.com/api/add";
+const RAINDROP_URL: &str = "https://api.raindrop.io/rest/v1/raindrop";
+// Both services rate-limit their APIs, so requests are spaced out.
+const REQUEST_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+// The tag bookmarks are saved with, where the service has tags.
+const TAG: &str = "overcast";
+
+// A read-later service to send episodes to.
+pub enum ReadLaterClient {
+    Instapaper {
+        client: reqwest::blocking::Client,
+        username: String,
+        password: String,
+    },
+    Raindrop {
+        client: reqwest::blocking::Client,
+        token: String,
+    },
+}
+
+// An episode to save, with its notes.
+pub struct Bookmark {
+    pub episode_id: i64,
+    pub title: String,
+    pub url: String,
+    pub notes: Option<String>,
+}
+
+// Counts of a read-later export.
+#[derive(Default)]
+pub struct SendCounts {
+    pub sent: usize,
+    pub already_sent: usize,
+}
+
+impl ReadLaterClient {
+    pub fn instapaper(username: &str, password: &str) -> ReadLaterClient {
+        ReadLaterClient::Instapaper {
+            client: client::new(),
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    pub fn raindrop(token: &str) -> ReadLaterClient {
+        ReadLaterClient::Raindrop {
+            client: client::new(),
+            token: token.to_string(),
+        }
+    }
+
+    // The name sent episodes are recorded under in `read_later`.
+    pub fn service(&self) -> &'static str {
+        match self {
+            ReadLaterClient::Instapaper { .. } => "instapaper",
+            ReadLaterClient::Raindrop { .. } => "raindrop",
+        }
+    }
+
+    // Saves a bookmark, returning the service's ID for it, if it gives one.
+    fn save(&self, bookmark: &Bookmark) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        std::thread::sleep(REQUEST_INTERVAL);
+        match self {
+            // Instapaper's Simple API has no notes field, so notes go in the "selection", which
+            // is shown as the bookmark's description.
+            ReadLaterClient::Instapaper {
+                client,
+                username,
+                password,
+            } => {
+                let mut form = vec![("url", bookmark.url.as_str()), ("title", &bookmark.title)];
+                if let Some(notes) = &bookmark.notes {
+                    form.push(("selection", notes));
+                }
+                let resp = client
+                    .post(INSTAPAPER_URL)
+                    .basic_auth(username, Some(password))
+                    .form(&form)
+                    .send()?;
+                if !resp.status().is_success() {
+                    return Err(format!("Instapaper API error ({})", resp.status()).into());
+                }
+                Ok(None)
+            }
+            ReadLaterClient::Raindrop { client, token } => {
+                let resp = client
+                    .post(RAINDROP_URL)
+                    .bearer_auth(token)
+                    .json(&json!({
+                        "link": bookmark.url,
+                        "title": bookmark.title,
+                        "note": bookmark.notes,
+                        "tags": [TAG],
+                    }))
+                    .send()?;
+                let status = resp.status();
+                let body: Value = resp.json()?;
+                if !status.is_success() {
+                    return Err(format!(
+                        "Raindrop API error ({}): {}",
+                        status,
+                        body["errorMessage"].as_str().unwrap_or("unknown error")
+                    )
+                    .into());
+                }
+                Ok(body["item"]["_id"].as_i64().map(|id| id.to_string()))
+            }
+        }
+    }
+}
+
+// The starred episodes, and episodes with notes, that have a URL to save: their Overcast page,
+// or failing that their web page. An episode's notes are joined into one, oldest first.
+pub fn bookmarks(conn: &Connection) -> Result<Vec<Bookmark>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.title, COALESCE(e.overcast_url, e.html_url),
+            (SELECT group_concat(text, char(10) || char(10))
+                FROM (SELECT text FROM notes n WHERE n.episode_id = e.id ORDER BY n.created_at))
+        FROM episodes e
+        WHERE COALESCE(e.overcast_url, e.html_url) IS NOT NULL
+            AND (e.starred OR EXISTS (SELECT 1 FROM notes n WHERE n.episode_id = e.id))
+        ORDER BY COALESCE(e.starred_at, e.updated_at)",
+    )?;
+    let bookmarks = stmt
+        .query_map([], |row| {
+            Ok(Bookmark {
+                episode_id: row.get(0)?,
+                title: row.get(1)?,
+                url: row.get(2)?,
+                notes: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(bookmarks)
+}
+
+// Saves starred and annotated episodes to a read-later service. Each episode is only sent once
+// per service, as recorded in `read_later`, so it's safe to run on a schedule; notes added after
+// an episode was sent aren't sent again.
+pub fn send(
+    conn: &Connection,
+    client: &ReadLaterClient,
+) -> Result<SendCounts, Box<dyn std::error::Error>> {
+    let mut counts = SendCounts::default();
+    for bookmark in bookmarks(conn)? {
+        let sent: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM read_later WHERE service = ? AND episode_id = ?)",
+            params![client.service(), bookmark.episode_id],
+            |row| row.get(0),
+        )?;
+        if sent {
+            counts.already_sent += 1;
+            continue;
+        }
+        let item_id = client.save(&bookmark)?;
+        conn.execute(
+            "INSERT INTO read_later(service, episode_id, item_id, sent_at) VALUES (?, ?, ?, ?)",
+            params![
+                client.service(),
+                bookmark.episode_id,
+                item_id,
+                chrono::Utc::now().naive_utc()
+            ],
+        )?;
+        counts.sent += 1;
+    }
+    Ok(counts)
+}
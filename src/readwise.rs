@@ -0,0 +1,161 @@
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+use crate::client;
+
+const API_URL: &str = "https://readwise.io/api/v2/highlights/";
+// Readwise allows 240 requests a minute; highlights are sent in batches well under that.
+const REQUEST_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+const BATCH_SIZE: usize = 100;
+// Readwise rejects highlights longer than this.
+const MAX_TEXT_LENGTH: usize = 8191;
+
+// A note or starred episode, as a highlight of the episode.
+struct Highlight {
+    // 'note' or 'starred', and the note or episode ID, for `readwise_highlights`.
+    kind: &'static str,
+    row_id: i64,
+    body: Value,
+}
+
+pub struct ReadwiseClient {
+    client: reqwest::blocking::Client,
+    token: String,
+}
+
+impl ReadwiseClient {
+    pub fn new(token: &str) -> ReadwiseClient {
+        ReadwiseClient {
+            client: client::new(),
+            token: token.to_string(),
+        }
+    }
+
+    fn create(&self, highlights: &[Value]) -> Result<(), Box<dyn std::error::Error>> {
+        std::thread::sleep(REQUEST_INTERVAL);
+        let resp = self
+            .client
+            .post(API_URL)
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("Token {}", self.token),
+            )
+            .json(&json!({ "highlights": highlights }))
+            .send()?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(format!("Readwise API error ({}): {}", status, resp.text()?).into());
+        }
+        Ok(())
+    }
+}
+
+// Sends episode notes, and starred episodes without notes, to Readwise as highlights of the
+// episode, in Readwise's "podcasts" category under the episode's title, with the feed as the
+// author. A starred episode's highlight is its description, or its title failing that. Each
+// highlight is only sent once, as recorded in `readwise_highlights`. Returns how many were sent.
+pub fn sync(
+    conn: &Connection,
+    readwise: &ReadwiseClient,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut highlights = Vec::new();
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.text, n.created_at, e.title, f.title, COALESCE(e.overcast_url, e.html_url),
+            d.image_url
+        FROM notes n
+        JOIN episodes e ON e.id = n.episode_id
+        JOIN feeds f ON f.id = e.feed_id
+        LEFT JOIN feed_details d ON d.feed_id = f.id
+        WHERE n.id NOT IN (SELECT row_id FROM readwise_highlights WHERE kind = 'note')
+        ORDER BY n.created_at",
+    )?;
+    let notes = stmt.query_map([], |row| {
+        let text: String = row.get(1)?;
+        let created_at: String = row.get(2)?;
+        Ok(Highlight {
+            kind: "note",
+            row_id: row.get(0)?,
+            body: highlight(
+                &text,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+                Some(created_at),
+                None,
+            ),
+        })
+    })?;
+    for note in notes {
+        highlights.push(note?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT e.id, COALESCE(ed.description, e.title), e.starred_at, e.title, f.title,
+            COALESCE(e.overcast_url, e.html_url), d.image_url
+        FROM episodes e
+        JOIN feeds f ON f.id = e.feed_id
+        LEFT JOIN episode_details ed ON ed.episode_id = e.id
+        LEFT JOIN feed_details d ON d.feed_id = f.id
+        WHERE e.starred
+            AND NOT EXISTS (SELECT 1 FROM notes n WHERE n.episode_id = e.id)
+            AND e.id NOT IN (SELECT row_id FROM readwise_highlights WHERE kind = 'starred')
+        ORDER BY e.starred_at",
+    )?;
+    let starred = stmt.query_map([], |row| {
+        let text: String = row.get(1)?;
+        Ok(Highlight {
+            kind: "starred",
+            row_id: row.get(0)?,
+            body: highlight(
+                &text,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+                row.get(2)?,
+                Some("Starred in Overcast"),
+            ),
+        })
+    })?;
+    for episode in starred {
+        highlights.push(episode?);
+    }
+
+    for batch in highlights.chunks(BATCH_SIZE) {
+        let bodies: Vec<Value> = batch.iter().map(|h| h.body.clone()).collect();
+        readwise.create(&bodies)?;
+        let synced_at = chrono::Utc::now().naive_utc();
+        for h in batch {
+            conn.execute(
+                "INSERT OR REPLACE INTO readwise_highlights(kind, row_id, sent_at) VALUES (?, ?, ?)",
+                params![h.kind, h.row_id, synced_at],
+            )?;
+        }
+    }
+    Ok(highlights.len())
+}
+
+fn highlight(
+    text: &str,
+    title: String,
+    author: String,
+    source_url: Option<String>,
+    image_url: Option<String>,
+    highlighted_at: Option<String>,
+    note: Option<&str>,
+) -> Value {
+    let text: String = text.chars().take(MAX_TEXT_LENGTH).collect();
+    json!({
+        "text": text,
+        "title": title,
+        "author": author,
+        "category": "podcasts",
+        "source_type": "overcast-to-sqlite",
+        "source_url": source_url,
+        "image_url": image_url,
+        // Stored timestamps are UTC.
+        "highlighted_at": highlighted_at.map(|t| format!("{}Z", t.replace(' ', "T"))),
+        "note": note,
+    })
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use rusqlite::Connection;
+
+// How quickly new episodes stop getting a boost for being recent: an episode this many days old
+// scores half of a brand new one.
+const RECENCY_HALF_LIFE_DAYS: f64 = 30.0;
+// Started episodes get a boost, since they're already part way done.
+const STARTED_BOOST: f64 = 1.5;
+// The scores used when a factor can't be worked out, like an episode with no duration.
+const UNKNOWN_RECENCY: f64 = 0.5;
+const UNKNOWN_FIT: f64 = 0.75;
+
+// An unplayed episode, with how strongly it's recommended.
+pub struct Recommendation {
+    pub id: i64,
+    pub title: String,
+    pub feed_title: String,
+    pub remaining_seconds: Option<i64>,
+    pub score: f64,
+}
+
+struct Candidate {
+    id: i64,
+    title: String,
+    feed_id: i64,
+    feed_title: String,
+    published_at: Option<NaiveDateTime>,
+    duration: Option<i64>,
+    progress_seconds: Option<i64>,
+    remaining_seconds: Option<i64>,
+}
+
+// Ranks unplayed, undeleted episodes from subscribed feeds by how likely they are to be worth
+// listening to, judging only by the archive's own history. An episode scores higher when:
+//
+// - its feed's episodes usually get finished rather than deleted,
+// - it was published recently,
+// - its length is close to the length of episodes you usually finish, and
+// - it's already started.
+pub fn recommend(
+    conn: &Connection,
+    limit: usize,
+) -> Result<Vec<Recommendation>, Box<dyn std::error::Error>> {
+    // Played and deleted-without-playing counts per feed. Smoothing keeps a feed with one played
+    // episode from outranking one with a hundred played and a few deleted.
+    let mut stmt = conn.prepare(
+        "SELECT feed_id, SUM(played), SUM(NOT played AND user_deleted)
+        FROM episodes
+        GROUP BY feed_id",
+    )?;
+    let completion: HashMap<i64, f64> = stmt
+        .query_map([], |row| {
+            let played: i64 = row.get(1)?;
+            let deleted: i64 = row.get(2)?;
+            Ok((
+                row.get(0)?,
+                (played as f64 + 1.0) / ((played + deleted) as f64 + 2.0),
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT duration_seconds FROM episode_progress
+        WHERE played AND duration_seconds > 0
+        ORDER BY duration_seconds",
+    )?;
+    let finished: Vec<i64> = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let typical_duration = finished.get(finished.len() / 2).copied();
+
+    let mut stmt = conn.prepare(
+        "SELECT p.episode_id, p.title, p.feed_id, f.title, e.published_at, p.duration_seconds,
+            COALESCE(p.progress_seconds, e.progress), p.remaining_seconds
+        FROM episode_progress p
+        JOIN episodes e ON e.id = p.episode_id
+        JOIN feeds f ON f.id = p.feed_id
+        WHERE NOT p.played AND NOT p.user_deleted AND f.subscribed",
+    )?;
+    let candidates = stmt
+        .query_map([], |row| {
+            Ok(Candidate {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                feed_id: row.get(2)?,
+                feed_title: row.get(3)?,
+                published_at: row.get(4)?,
+                duration: row.get(5)?,
+                progress_seconds: row.get(6)?,
+                remaining_seconds: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let mut recommendations: Vec<Recommendation> = candidates
+        .into_iter()
+        .map(|c| {
+            let recency = c
+                .published_at
+                .map(|published| {
+                    let age_days = (now - published).num_hours().max(0) as f64 / 24.0;
+                    0.5f64.powf(age_days / RECENCY_HALF_LIFE_DAYS)
+                })
+                .unwrap_or(UNKNOWN_RECENCY);
+            let fit = match (c.duration, typical_duration) {
+                (Some(duration), Some(typical)) if duration > 0 => {
+                    duration.min(typical) as f64 / duration.max(typical) as f64
+                }
+                _ => UNKNOWN_FIT,
+            };
+            let started = if c.progress_seconds.unwrap_or(0) > 0 {
+                STARTED_BOOST
+            } else {
+                1.0
+            };
+            let completion = completion.get(&c.feed_id).copied().unwrap_or(0.5);
+            Recommendation {
+                id: c.id,
+                title: c.title,
+                feed_title: c.feed_title,
+                remaining_seconds: c.remaining_seconds,
+                score: completion * recency * fit * started,
+            }
+        })
+        .collect();
+    recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap().then(a.id.cmp(&b.id)));
+    recommendations.truncate(limit);
+    Ok(recommendations)
+}
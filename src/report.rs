@@ -0,0 +1,133 @@
+use rusqlite::Connection;
+
+use crate::chart::{self, escape};
+use crate::timezone::DisplayTimezone;
+
+pub const STYLE: &str = "body { font-family: -apple-system, sans-serif; max-width: 760px; margin: 2em auto; color: #222; }
+h1, h2 { font-weight: 600; }
+table { border-collapse: collapse; width: 100%; }
+td, th { text-align: left; padding: 4px 8px; border-bottom: 1px solid #eee; }
+td.num, th.num { text-align: right; }
+a { color: #fc7e0f; }";
+
+// Renders a self-contained HTML report of the archive: top shows, listening per month, and
+// recently played episodes. Dates are shown, and months grouped, in the given timezone.
+pub fn html_report(
+    conn: &Connection,
+    tz: &DisplayTimezone,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Overcast listening report</title>\
+         <style>{}</style></head><body>\n<h1>Overcast listening report</h1>\n",
+        STYLE
+    );
+    html.push_str(&format!(
+        "<p>Generated {}.</p>\n",
+        tz.format(&chrono::Utc::now().naive_utc(), "%Y-%m-%d %H:%M")
+    ));
+
+    html.push_str("<h2>Listening per month</h2>\n");
+    let months = monthly_listening(conn, tz)?;
+    if months.is_empty() {
+        html.push_str("<p>No listening time recorded yet.</p>\n");
+    } else {
+        html.push_str(&chart::bar_chart(&months, |hours| format!("{:.1}h", hours)));
+        html.push('\n');
+    }
+
+    html.push_str(
+        "<h2>Top shows</h2>\n<table><tr><th>Show</th><th class=\"num\">Played</th>\
+         <th class=\"num\">Listened</th></tr>\n",
+    );
+    let mut stmt = conn.prepare(
+        "SELECT f.title, SUM(e.played), SUM(COALESCE(l.seconds, 0)) AS seconds
+        FROM feeds f
+        JOIN episodes e ON e.feed_id = f.id
+        LEFT JOIN listening_time l ON l.episode_id = e.id
+        GROUP BY f.id
+        HAVING seconds > 0
+        ORDER BY seconds DESC
+        LIMIT 15",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let title: String = row.get(0)?;
+        let played: i64 = row.get(1)?;
+        let seconds: i64 = row.get(2)?;
+        html.push_str(&format!(
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+            escape(&title),
+            played,
+            format_seconds(seconds)
+        ));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str(
+        "<h2>Recently played</h2>\n<table><tr><th>Episode</th><th>Show</th><th>Played</th></tr>\n",
+    );
+    let mut stmt = conn.prepare(&format!(
+        "SELECT e.title, f.title, e.overcast_url, date(played_at, '{}') FROM (
+            SELECT e.*,
+                COALESCE((SELECT MAX(played_at) FROM play_events WHERE episode_id = e.id), e.updated_at) AS played_at
+            FROM episodes e
+        ) e
+        JOIN feeds f ON f.id = e.feed_id
+        WHERE e.played
+        ORDER BY played_at DESC
+        LIMIT 20",
+        tz.sql_modifier()
+    ))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let title: String = row.get(0)?;
+        let feed_title: String = row.get(1)?;
+        let url: Option<String> = row.get(2)?;
+        let played_at: Option<String> = row.get(3)?;
+        let title = match url {
+            Some(url) => format!("<a href=\"{}\">{}</a>", escape(&url), escape(&title)),
+            None => escape(&title),
+        };
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            title,
+            escape(&feed_title),
+            escape(played_at.as_deref().unwrap_or("")),
+        ));
+    }
+    html.push_str("</table>\n</body></html>\n");
+    Ok(html)
+}
+
+// Hours listened per month, oldest first, for the last two years of listening.
+pub fn monthly_listening(
+    conn: &Connection,
+    tz: &DisplayTimezone,
+) -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT * FROM (
+            SELECT strftime('%Y-%m', listened_at, '{}') AS month, SUM(seconds) / 3600.0
+            FROM listening_time
+            WHERE listened_at IS NOT NULL
+            GROUP BY month
+            ORDER BY month DESC
+            LIMIT 24
+        ) ORDER BY month",
+        tz.sql_modifier()
+    ))?;
+    let months = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(months)
+}
+
+// Formats a number of seconds as e.g. "12h 5m".
+pub fn format_seconds(seconds: i64) -> String {
+    let hours = seconds / 3600;
+    let minutes = (seconds % 3600) / 60;
+    if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
@@ -0,0 +1,322 @@
+use roxmltree::Node;
+
+use crate::overcast::Episode;
+
+const ITUNES_NS: &str = "http://www.itunes.com/dtds/podcast-1.0.dtd";
+const CONTENT_NS: &str = "http://purl.org/rss/1.0/modules/content/";
+const PODCAST_NS: &str = "https://podcastindex.org/namespace/1.0";
+// Feeds published before the namespace settled on podcastindex.org still use this URI.
+const PODCAST_NS_LEGACY: &str =
+    "https://github.com/Podcastindex-org/podcast-namespace/blob/main/docs/1.0.md";
+
+// Channel-level metadata parsed from a feed's RSS.
+#[derive(Debug, Default)]
+pub struct Channel {
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub author: Option<String>,
+    pub copyright: Option<String>,
+    pub link: Option<String>,
+    pub image_url: Option<String>,
+    pub categories: Vec<Category>,
+    pub funding: Vec<Funding>,
+    pub items: Vec<Item>,
+}
+
+impl Channel {
+    // Finds the RSS item for an Overcast episode, matching by enclosure URL and falling back to
+    // the episode title.
+    pub fn find_item(&self, episode: &Episode) -> Option<&Item> {
+        let by_enclosure = episode.mp3_url.as_ref().and_then(|mp3_url| {
+            self.items
+                .iter()
+                .find(|i| i.enclosure_url.as_ref() == Some(mp3_url))
+        });
+        by_enclosure.or_else(|| self.items.iter().find(|i| i.title == episode.title))
+    }
+}
+
+// A single `<item>` in the feed.
+#[derive(Debug)]
+pub struct Item {
+    pub title: String,
+    pub enclosure_url: Option<String>,
+    // The `<itunes:duration>`, in seconds.
+    pub duration: Option<i64>,
+    // The show notes, as HTML.
+    pub description: Option<String>,
+    // The first `<podcast:transcript>`'s URL.
+    pub transcript_url: Option<String>,
+    // Item-level `<podcast:person>`s, or the channel's if the item doesn't list any.
+    pub people: Vec<Person>,
+}
+
+// An `<itunes:category>`, flattened so that subcategories point at their parent's name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Category {
+    pub name: String,
+    pub parent: Option<String>,
+}
+
+// A `<podcast:person>` credited on an episode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Person {
+    pub name: String,
+    pub role: String,
+    pub group: String,
+    pub href: Option<String>,
+    pub img: Option<String>,
+}
+
+// A way to support a show directly: a `<podcast:funding>` link, or a recipient in a
+// `<podcast:value>` block (streaming payments, e.g. over Lightning).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Funding {
+    // "funding" for links, otherwise the value block's type, like "lightning".
+    pub kind: String,
+    // The value block's method, like "keysend".
+    pub method: Option<String>,
+    // The funding page, or the recipient's address.
+    pub url: Option<String>,
+    // The link's text, or the recipient's name.
+    pub name: Option<String>,
+    // The recipient's share of payments.
+    pub split: Option<i64>,
+}
+
+// Fetches and parses the RSS feed at the given URL.
+pub fn fetch_channel(
+    client: &reqwest::blocking::Client,
+    url: &str,
+) -> Result<Channel, Box<dyn std::error::Error>> {
+    let contents = client.get(url).send()?.error_for_status()?.text()?;
+    parse_channel(&contents)
+}
+
+pub fn parse_channel(contents: &str) -> Result<Channel, Box<dyn std::error::Error>> {
+    let tree = roxmltree::Document::parse(contents)?;
+    let channel = tree
+        .descendants()
+        .find(|n| n.has_tag_name("channel"))
+        .ok_or("RSS feed has no <channel> element")?;
+
+    let mut categories = Vec::new();
+    for node in channel.children().filter(|n| is_itunes(n, "category")) {
+        collect_categories(node, None, &mut categories);
+    }
+
+    let channel_people = parse_people(channel);
+    let items = channel
+        .children()
+        .filter(|n| n.has_tag_name("item"))
+        .map(|item| {
+            let people = parse_people(item);
+            Item {
+                title: child_text(item, "title").unwrap_or_default(),
+                enclosure_url: item
+                    .children()
+                    .find(|n| n.has_tag_name("enclosure"))
+                    .and_then(|n| n.attribute("url"))
+                    .map(|s| s.to_string()),
+                duration: itunes_text(item, "duration").and_then(|d| parse_duration(&d)),
+                description: item
+                    .children()
+                    .find(|n| {
+                        n.tag_name().name() == "encoded"
+                            && n.tag_name().namespace() == Some(CONTENT_NS)
+                    })
+                    .and_then(element_text)
+                    .or_else(|| child_text(item, "description"))
+                    .or_else(|| itunes_text(item, "summary")),
+                transcript_url: item
+                    .children()
+                    .find(|n| is_podcast(n, "transcript"))
+                    .and_then(|n| n.attribute("url"))
+                    .map(|s| s.to_string()),
+                people: if people.is_empty() {
+                    channel_people.clone()
+                } else {
+                    people
+                },
+            }
+        })
+        .collect();
+    Ok(Channel {
+        description: child_text(channel, "description").or_else(|| itunes_text(channel, "summary")),
+        language: child_text(channel, "language"),
+        author: itunes_text(channel, "author").or_else(|| child_text(channel, "managingEditor")),
+        copyright: child_text(channel, "copyright"),
+        link: child_text(channel, "link"),
+        image_url: channel
+            .children()
+            .find(|n| is_itunes(n, "image"))
+            .and_then(|n| n.attribute("href"))
+            .map(|s| s.to_string())
+            .or_else(|| {
+                channel
+                    .children()
+                    .find(|n| n.tag_name().name() == "image" && n.tag_name().namespace().is_none())
+                    .and_then(|n| child_text(n, "url"))
+            }),
+        categories,
+        funding: parse_funding(channel),
+        items,
+    })
+}
+
+// Parses a channel's `<podcast:funding>` links and `<podcast:value>` recipients. A value block
+// without recipients is still recorded, since it says which payment method the show accepts.
+fn parse_funding(channel: Node) -> Vec<Funding> {
+    let mut funding = Vec::new();
+    for node in channel.children() {
+        if is_podcast(&node, "funding") {
+            let url = match node.attribute("url").map(str::trim) {
+                Some(url) if !url.is_empty() => url.to_string(),
+                _ => continue,
+            };
+            funding.push(Funding {
+                kind: "funding".to_string(),
+                method: None,
+                url: Some(url),
+                name: element_text(node),
+                split: None,
+            });
+        } else if is_podcast(&node, "value") {
+            let kind = node.attribute("type").unwrap_or("unknown").to_lowercase();
+            let method = node.attribute("method").map(|s| s.to_lowercase());
+            let recipients: Vec<Funding> = node
+                .children()
+                .filter(|n| is_podcast(n, "valueRecipient"))
+                .map(|n| Funding {
+                    kind: kind.clone(),
+                    method: method.clone(),
+                    url: n.attribute("address").map(|s| s.to_string()),
+                    name: n.attribute("name").map(|s| s.to_string()),
+                    split: n.attribute("split").and_then(|s| s.trim().parse().ok()),
+                })
+                .collect();
+            if recipients.is_empty() {
+                funding.push(Funding {
+                    kind,
+                    method,
+                    url: None,
+                    name: None,
+                    split: None,
+                });
+            } else {
+                funding.extend(recipients);
+            }
+        }
+    }
+    funding
+}
+
+// Parses the `<podcast:person>` children of a channel or item. Role and group default to
+// "host" and "cast", per the podcast namespace spec.
+fn parse_people(node: Node) -> Vec<Person> {
+    node.children()
+        .filter(|n| is_podcast(n, "person"))
+        .filter_map(|n| {
+            let name = n.text()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            Some(Person {
+                name: name.to_string(),
+                role: n.attribute("role").unwrap_or("host").to_lowercase(),
+                group: n.attribute("group").unwrap_or("cast").to_lowercase(),
+                href: n.attribute("href").map(|s| s.to_string()),
+                img: n.attribute("img").map(|s| s.to_string()),
+            })
+        })
+        .collect()
+}
+
+// Returns the trimmed text of the first un-namespaced child with the given name, if non-empty.
+// Text is read across child nodes so CDATA sections are included.
+fn child_text(node: Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|n| n.tag_name().name() == name && n.tag_name().namespace().is_none())
+        .and_then(element_text)
+}
+
+fn itunes_text(node: Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|n| is_itunes(n, name))
+        .and_then(element_text)
+}
+
+fn element_text(node: Node) -> Option<String> {
+    let text: String = node
+        .children()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect();
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+// Parses an `<itunes:duration>`, which is either a number of seconds or `[HH:]MM:SS`.
+fn parse_duration(duration: &str) -> Option<i64> {
+    let mut seconds = 0;
+    for part in duration.split(':') {
+        let value = part.trim().split('.').next()?.parse::<i64>().ok()?;
+        seconds = seconds * 60 + value;
+    }
+    Some(seconds)
+}
+
+fn collect_categories(node: Node, parent: Option<&str>, out: &mut Vec<Category>) {
+    let name = match node.attribute("text") {
+        Some(name) if !name.trim().is_empty() => name.trim(),
+        _ => return,
+    };
+    let category = Category {
+        name: name.to_string(),
+        parent: parent.map(|p| p.to_string()),
+    };
+    if !out.contains(&category) {
+        out.push(category);
+    }
+    for child in node.children().filter(|n| is_itunes(n, "category")) {
+        collect_categories(child, Some(name), out);
+    }
+}
+
+fn is_itunes(node: &Node, name: &str) -> bool {
+    node.tag_name().name() == name && node.tag_name().namespace() == Some(ITUNES_NS)
+}
+
+fn is_podcast(node: &Node, name: &str) -> bool {
+    node.tag_name().name() == name
+        && matches!(
+            node.tag_name().namespace(),
+            Some(PODCAST_NS) | Some(PODCAST_NS_LEGACY)
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("3600"), Some(3600));
+        assert_eq!(parse_duration("45:30"), Some(45 * 60 + 30));
+        assert_eq!(parse_duration("1:02:03"), Some(3723));
+        assert_eq!(parse_duration(" 1 : 05 "), Some(65));
+        assert_eq!(parse_duration("12.5"), Some(12));
+        assert_eq!(parse_duration("00:01:30.250"), Some(90));
+    }
+
+    #[test]
+    fn unparseable_durations() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("an hour"), None);
+        assert_eq!(parse_duration("1::30"), None);
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection};
+use serde_json::json;
+
+// Counts of what's in an archive, taken before and after a run to work out what it added.
+#[derive(Default)]
+pub struct Totals {
+    pub feeds: i64,
+    pub episodes: i64,
+    pub downloads: i64,
+}
+
+impl Totals {
+    pub fn of(conn: &Connection) -> Result<Totals, Box<dyn std::error::Error>> {
+        Ok(conn.query_row(
+            "SELECT
+                (SELECT COUNT(*) FROM main.feeds),
+                (SELECT COUNT(*) FROM main.episodes),
+                (SELECT COUNT(*) FROM main.downloads)",
+            [],
+            |row| {
+                Ok(Totals {
+                    feeds: row.get(0)?,
+                    episodes: row.get(1)?,
+                    downloads: row.get(2)?,
+                })
+            },
+        )?)
+    }
+}
+
+// The IDs of the archive's episodes, taken before a run so the episodes it added can be listed.
+pub fn episode_ids(conn: &Connection) -> Result<HashSet<i64>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT id FROM main.episodes")?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(ids)
+}
+
+// An episode a run added to the archive.
+pub struct NewEpisode {
+    pub id: i64,
+    pub title: Option<String>,
+    pub feed_id: i64,
+    pub feed_title: Option<String>,
+    pub url: Option<String>,
+}
+
+impl NewEpisode {
+    // The episode as printed by `archive --output json` and published over MQTT.
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "title": self.title,
+            "feedId": self.feed_id,
+            "feed": self.feed_title,
+            "url": self.url,
+        })
+    }
+}
+
+// How `archive --output` prints the episodes a run added.
+pub enum Output {
+    // One JSON object per line.
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Output::Json),
+            _ => Err(format!("unknown output \"{}\" (expected json)", s)),
+        }
+    }
+}
+
+// The archive's episodes that aren't among the `before` IDs, by feed and newest first.
+pub fn new_episodes(
+    conn: &Connection,
+    before: &HashSet<i64>,
+) -> Result<Vec<NewEpisode>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.title, f.id, f.title, COALESCE(e.html_url, e.overcast_url)
+        FROM main.episodes e
+        JOIN main.feeds f ON f.id = e.feed_id
+        ORDER BY f.title COLLATE NOCASE, f.id, e.published_at DESC",
+    )?;
+    let episodes = stmt
+        .query_map([], |row| {
+            Ok(NewEpisode {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                feed_id: row.get(2)?,
+                feed_title: row.get(3)?,
+                url: row.get(4)?,
+            })
+        })?
+        .filter(|episode| {
+            episode
+                .as_ref()
+                .map_or(true, |episode| !before.contains(&episode.id))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(episodes)
+}
+
+// A run of a command on an archive, as recorded in `runs` and told to post-run commands.
+pub struct Summary<'a> {
+    pub command: &'a str,
+    pub db_path: &'a str,
+    pub started_at: NaiveDateTime,
+    pub finished_at: NaiveDateTime,
+    // The run's error, if it failed, and the exit code it fails with.
+    pub error: Option<String>,
+    pub exit_code: i32,
+    pub before: Totals,
+    pub after: Totals,
+}
+
+impl Summary<'_> {
+    // What's in the archive after the run, and what the run added.
+    pub fn counts(&self) -> serde_json::Value {
+        json!({
+            "feeds": self.after.feeds,
+            "episodes": self.after.episodes,
+            "downloads": self.after.downloads,
+            "newFeeds": self.after.feeds - self.before.feeds,
+            "newEpisodes": self.after.episodes - self.before.episodes,
+            "newDownloads": self.after.downloads - self.before.downloads,
+        })
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut summary = json!({
+            "command": self.command,
+            "dbPath": self.db_path,
+            "success": self.error.is_none(),
+            "error": self.error,
+            "exitCode": self.exit_code,
+            "startedAt": self.started_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            "finishedAt": self.finished_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            "durationSeconds":
+                (self.finished_at - self.started_at).num_milliseconds() as f64 / 1000.0,
+        });
+        if let (Some(summary), serde_json::Value::Object(counts)) =
+            (summary.as_object_mut(), self.counts())
+        {
+            summary.extend(counts);
+        }
+        summary
+    }
+}
+
+// Records a run in the archive's `runs` table.
+pub fn record(conn: &Connection, summary: &Summary) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO runs(command, started_at, finished_at, exit_code, error, counts, version)
+        VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![
+            summary.command,
+            summary.started_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            summary.finished_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            summary.exit_code,
+            summary.error,
+            summary.counts().to_string(),
+            env!("CARGO_PKG_VERSION"),
+        ],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use chrono::{Datelike, Duration, NaiveDateTime};
+use rusqlite::{params, Connection, OptionalExtension};
+
+// The longest a feed goes between fetches, however dormant it is.
+const MAX_INTERVAL: i64 = 7 * 24 * 3600;
+// How many of a feed's latest episodes its cadence is judged by.
+const RECENT_EPISODES: usize = 10;
+// Feeds are checked about this many times per expected release, so a new episode waits a
+// fraction of the feed's cadence to be fetched.
+const CHECKS_PER_RELEASE: i64 = 4;
+// A feed that's gone this many of its usual gaps without an episode is treated as dormant.
+const DORMANT_GAPS: i64 = 3;
+// The share of a feed's latest episodes that have to come out on the same weekday for it to be
+// the feed's release day.
+const RELEASE_DAY_SHARE: f64 = 0.75;
+
+// A feed's release schedule, judged by its latest episodes.
+pub struct Cadence {
+    // The median gap between them, in seconds.
+    pub interval_seconds: i64,
+    // The weekday (0 for Monday, in UTC) most of them came out on, if there is one.
+    pub weekday: Option<u32>,
+    pub last_published_at: NaiveDateTime,
+    // Whether the feed has gone `DORMANT_GAPS` of its usual gaps without an episode.
+    pub on_hiatus: bool,
+    // A gap after the last episode, moved to the nearest release day for feeds that publish
+    // weekly or less often.
+    pub next_expected_at: NaiveDateTime,
+}
+
+// A feed's cadence as of `now`, or None if it doesn't have two dated episodes to judge by.
+pub fn cadence(
+    conn: &Connection,
+    feed_id: &str,
+    now: NaiveDateTime,
+) -> Result<Option<Cadence>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT published_at FROM episodes
+        WHERE feed_id = ? AND published_at IS NOT NULL
+        ORDER BY published_at DESC
+        LIMIT ?",
+    )?;
+    let published = stmt
+        .query_map(params![feed_id, RECENT_EPISODES as i64], |row| {
+            row.get::<_, NaiveDateTime>(0)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    if published.len() < 2 {
+        return Ok(None);
+    }
+    let mut gaps: Vec<i64> = published
+        .windows(2)
+        .map(|pair| (pair[0] - pair[1]).num_seconds())
+        .collect();
+    gaps.sort_unstable();
+    let interval = gaps[gaps.len() / 2].max(1);
+
+    let mut per_weekday = [0; 7];
+    for at in &published {
+        per_weekday[at.weekday().num_days_from_monday() as usize] += 1;
+    }
+    let weekday = (0..7u32)
+        .max_by_key(|day| per_weekday[*day as usize])
+        .filter(|day| {
+            per_weekday[*day as usize] as f64 >= published.len() as f64 * RELEASE_DAY_SHARE
+        });
+
+    let last = published[0];
+    let mut next = last + Duration::seconds(interval);
+    if let Some(day) = weekday.filter(|_| interval >= Duration::days(6).num_seconds()) {
+        let shift = (day as i64 - next.weekday().num_days_from_monday() as i64).rem_euclid(7);
+        next += Duration::days(if shift > 3 { shift - 7 } else { shift });
+    }
+    Ok(Some(Cadence {
+        interval_seconds: interval,
+        weekday,
+        last_published_at: last,
+        on_hiatus: (now - last).num_seconds() > interval * DORMANT_GAPS,
+        next_expected_at: next,
+    }))
+}
+
+// Rebuilds `feed_cadence` from every feed's episodes, as of `now`. Returns how many feeds have a
+// cadence.
+pub fn refresh_cadence(
+    conn: &Connection,
+    now: NaiveDateTime,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    crate::sqlite::atomically(conn, || {
+        conn.execute("DELETE FROM feed_cadence", [])?;
+        let mut stmt = conn.prepare("SELECT id FROM feeds")?;
+        let feed_ids = stmt
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut count = 0;
+        for feed_id in feed_ids {
+            let cadence = match cadence(conn, &feed_id.to_string(), now)? {
+                Some(cadence) => cadence,
+                None => continue,
+            };
+            conn.execute(
+                "INSERT INTO feed_cadence(
+                    feed_id, interval_seconds, weekday, last_published_at, next_expected_at,
+                    on_hiatus, computed_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    feed_id,
+                    cadence.interval_seconds,
+                    cadence.weekday,
+                    cadence.last_published_at,
+                    cadence.next_expected_at,
+                    cadence.on_hiatus,
+                    now
+                ],
+            )?;
+            count += 1;
+        }
+        Ok(count)
+    })
+}
+
+// How long to wait before fetching a feed again, going by when its episodes were published: a
+// fraction of its typical gap between episodes, but no less than `every` (the daemon's interval)
+// and no more than a week. Dormant feeds, and ones without enough episodes to tell, get the week.
+pub fn interval(
+    conn: &Connection,
+    feed_id: &str,
+    every: i64,
+    now: NaiveDateTime,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let longest = MAX_INTERVAL.max(every);
+    match cadence(conn, feed_id, now)? {
+        Some(cadence) if !cadence.on_hiatus => {
+            Ok((cadence.interval_seconds / CHECKS_PER_RELEASE).clamp(every, longest))
+        }
+        _ => Ok(longest),
+    }
+}
+
+// Whether a feed is due to be fetched: it never has been, or its next fetch time has come.
+pub fn is_due(
+    conn: &Connection,
+    feed_id: &str,
+    now: NaiveDateTime,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let next: Option<NaiveDateTime> = conn
+        .query_row(
+            "SELECT next_fetch_at FROM feed_schedule WHERE feed_id = ?",
+            params![feed_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(next.is_none_or(|next| next <= now))
+}
+
+// Records that a feed was fetched at `now`, scheduling its next fetch after `interval` seconds.
+pub fn record_fetch(
+    conn: &Connection,
+    feed_id: &str,
+    now: NaiveDateTime,
+    interval: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO feed_schedule (feed_id, interval_seconds, last_fetched_at, next_fetch_at)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT (feed_id) DO UPDATE SET
+            interval_seconds = excluded.interval_seconds,
+            last_fetched_at = excluded.last_fetched_at,
+            next_fetch_at = excluded.next_fetch_at",
+        params![feed_id, interval, now, now + Duration::seconds(interval)],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn archive(published: &[NaiveDateTime]) -> Connection {
+        let conn = crate::sqlite::open(":memory:", &[]).unwrap();
+        conn.execute("INSERT INTO feeds(id, title) VALUES (1, 'Feed')", [])
+            .unwrap();
+        for (id, at) in published.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO episodes(id, feed_id, published_at) VALUES (?, 1, ?)",
+                params![id as i64 + 10, at],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    fn cadence_at(conn: &Connection, now: NaiveDateTime) -> Cadence {
+        cadence(conn, "1", now).unwrap().unwrap()
+    }
+
+    #[test]
+    fn weekly_feed() {
+        // Mondays, with one episode a day late.
+        let conn = archive(&[at(1, 9), at(8, 9), at(16, 9), at(22, 9), at(29, 9)]);
+        let cadence = cadence_at(&conn, at(30, 0));
+        assert_eq!(cadence.interval_seconds, 7 * 24 * 3600);
+        assert_eq!(cadence.weekday, Some(0));
+        assert_eq!(cadence.last_published_at, at(29, 9));
+        assert!(!cadence.on_hiatus);
+        assert_eq!(cadence.next_expected_at, at(29, 9) + Duration::days(7));
+        let cadence = cadence_at(&conn, at(29, 9) + Duration::days(22));
+        assert!(cadence.on_hiatus);
+    }
+
+    #[test]
+    fn next_episode_moves_to_the_release_day() {
+        // Thursdays, but a short gap last time would put the next one on a Wednesday.
+        let conn = archive(&[at(4, 9), at(11, 9), at(18, 9), at(25, 9), at(31, 9)]);
+        let cadence = cadence_at(&conn, at(31, 12));
+        assert_eq!(cadence.weekday, Some(3));
+        assert_eq!(cadence.next_expected_at, at(31, 9) + Duration::days(8));
+    }
+
+    #[test]
+    fn daily_feed_isnt_moved() {
+        let conn = archive(&[at(1, 6), at(2, 6), at(3, 6), at(4, 6), at(5, 6)]);
+        let cadence = cadence_at(&conn, at(5, 7));
+        assert_eq!(cadence.interval_seconds, 24 * 3600);
+        assert_eq!(cadence.weekday, None);
+        assert_eq!(cadence.next_expected_at, at(6, 6));
+    }
+
+    #[test]
+    fn too_few_episodes() {
+        let conn = archive(&[at(1, 9)]);
+        assert!(cadence(&conn, "1", at(2, 0)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,189 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use crate::tls::Stream;
+
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+// How the connection to the mail server is secured.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Security {
+    // TLS from the start, usually on port 465.
+    Tls,
+    // A plain connection upgraded with STARTTLS, usually on port 587.
+    StartTls,
+    // No encryption, for a relay on localhost.
+    None,
+}
+
+impl FromStr for Security {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tls" => Ok(Security::Tls),
+            "starttls" => Ok(Security::StartTls),
+            "none" => Ok(Security::None),
+            _ => Err(format!(
+                "unknown SMTP security \"{}\" (expected tls, starttls or none)",
+                s
+            )),
+        }
+    }
+}
+
+impl Security {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Security::Tls => 465,
+            Security::StartTls => 587,
+            Security::None => 25,
+        }
+    }
+}
+
+// A mail server to send through.
+pub struct Server {
+    pub host: String,
+    pub port: u16,
+    pub security: Security,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+// Reads one reply, which may span several lines ("250-..." until "250 ..."), and fails unless
+// its code is in the same class as `expected` (2xx for most commands, 3xx for DATA).
+fn reply(stream: &mut Stream, expected: u32) -> Result<String, Box<dyn std::error::Error>> {
+    let mut text = String::new();
+    loop {
+        // Replies are short, so reading a byte at a time is fine, and it never reads past the
+        // reply into a TLS handshake.
+        let mut line = Vec::new();
+        let mut byte = [0u8];
+        while byte[0] != b'\n' {
+            if stream.read(&mut byte)? == 0 {
+                return Err("The mail server closed the connection.".into());
+            }
+            line.push(byte[0]);
+        }
+        let line = String::from_utf8_lossy(&line).trim_end().to_string();
+        text.push_str(&line);
+        text.push('\n');
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        let code: u32 = line
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| format!("unexpected reply from the mail server: {}", line))?;
+        if code / 100 != expected / 100 {
+            return Err(format!("The mail server replied: {}", text.trim_end()).into());
+        }
+        return Ok(text);
+    }
+}
+
+fn command(
+    stream: &mut Stream,
+    command: &str,
+    expected: u32,
+) -> Result<String, Box<dyn std::error::Error>> {
+    stream.write_all(command.as_bytes())?;
+    stream.write_all(b"\r\n")?;
+    stream.flush()?;
+    reply(stream, expected)
+}
+
+// The bare address in "Name <address>", or the whole thing if there's no name.
+fn address(mailbox: &str) -> &str {
+    match (mailbox.find('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+// An HTML email, ready for `send`. The body is base64-encoded, so long lines and non-ASCII text
+// survive any relay.
+pub fn html_message(from: &str, to: &[String], subject: &str, html: &str) -> String {
+    let body = openssl::base64::encode_block(html.as_bytes());
+    let mut lines: Vec<&str> = Vec::new();
+    let mut rest = body.as_str();
+    while rest.len() > 76 {
+        let (line, tail) = rest.split_at(76);
+        lines.push(line);
+        rest = tail;
+    }
+    lines.push(rest);
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+         Content-Type: text/html; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+        from,
+        to.join(", "),
+        subject,
+        chrono::Utc::now().to_rfc2822(),
+        lines.join("\r\n")
+    )
+}
+
+// Sends `message` (headers and body, with CRLF line endings) from `from` to each of `to`.
+pub fn send(
+    server: &Server,
+    from: &str,
+    to: &[String],
+    message: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stream = Stream::connect(
+        &server.host,
+        server.port,
+        server.security == Security::Tls,
+        TIMEOUT,
+    )?;
+    // We have no hostname to greet the server with, so we use our address, as RFC 5321 allows.
+    let greeting = match stream.local_addr()?.ip() {
+        std::net::IpAddr::V4(ip) => format!("EHLO [{}]", ip),
+        std::net::IpAddr::V6(ip) => format!("EHLO [IPv6:{}]", ip),
+    };
+    reply(&mut stream, 220)?;
+    command(&mut stream, &greeting, 250)?;
+    if server.security == Security::StartTls {
+        command(&mut stream, "STARTTLS", 220)?;
+        stream = stream.start_tls(&server.host)?;
+        command(&mut stream, &greeting, 250)?;
+    }
+    if let Some(username) = &server.username {
+        let password = server.password.as_deref().unwrap_or_default();
+        let credentials = format!("\0{}\0{}", username, password);
+        command(
+            &mut stream,
+            &format!(
+                "AUTH PLAIN {}",
+                openssl::base64::encode_block(credentials.as_bytes())
+            ),
+            235,
+        )
+        .map_err(|err| format!("Couldn't log in to the mail server: {}", err))?;
+    }
+    command(&mut stream, &format!("MAIL FROM:<{}>", address(from)), 250)?;
+    for recipient in to {
+        command(
+            &mut stream,
+            &format!("RCPT TO:<{}>", address(recipient)),
+            250,
+        )?;
+    }
+    command(&mut stream, "DATA", 354)?;
+    // Lines starting with a dot get another, so none is taken for the end of the message.
+    let mut data = String::new();
+    for line in message.trim_end_matches("\r\n").split("\r\n") {
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push('.');
+    command(&mut stream, &data, 250)?;
+    // The message is accepted by now, so a failed goodbye doesn't matter.
+    let _ = command(&mut stream, "QUIT", 221);
+    Ok(())
+}
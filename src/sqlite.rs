@@ -1,73 +1,2219 @@
-use rusqlite::{params, Connection};
+use std::str::FromStr;
 
-use crate::overcast::Feed;
+use chrono::{NaiveDateTime, TimeZone};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
 
-// Creates tables for podcast feeds and episodes, if they don't already exist.
-pub fn create_tables(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS feeds (
+use crate::overcast::{Feed, ListedEpisode};
+use crate::overrides;
+use crate::rss::{Category, Channel, Funding, Item, Person};
+use crate::urls;
+
+// Pragmas set on every connection, unless overridden with --sqlite-pragma. WAL lets readers (like
+// Datasette) keep querying the archive while a run writes to it, and busy_timeout makes writers
+// wait for locks rather than failing immediately. SQLite only enforces foreign keys when asked to.
+const DEFAULT_PRAGMAS: [(&str, &str); 4] = [
+    ("journal_mode", "WAL"),
+    ("synchronous", "NORMAL"),
+    ("busy_timeout", "5000"),
+    ("foreign_keys", "ON"),
+];
+
+// A `name=value` pragma to set when opening the database.
+pub struct Pragma {
+    name: String,
+    value: String,
+}
+
+impl FromStr for Pragma {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected a pragma as name=value, got \"{}\"", s))?;
+        let (name, value) = (name.trim(), value.trim());
+        let valid = |text: &str| {
+            !text.is_empty()
+                && text
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        };
+        if !valid(name) || !valid(value) {
+            return Err(format!("invalid pragma \"{}\"", s));
+        }
+        Ok(Pragma {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl Pragma {
+    // The SQLCipher key to open the database with. Keys can be any text, so unlike other pragmas
+    // it's quoted.
+    pub fn key(key: &str) -> Pragma {
+        Pragma {
+            name: "key".to_string(),
+            value: format!("'{}'", key.replace('\'', "''")),
+        }
+    }
+}
+
+// A table `archive --tables` can limit a run to.
+#[derive(PartialEq)]
+pub enum Table {
+    Feeds,
+    Episodes,
+}
+
+impl FromStr for Table {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "feeds" => Ok(Table::Feeds),
+            "episodes" => Ok(Table::Episodes),
+            _ => Err(format!(
+                "unknown table \"{}\" (expected feeds or episodes)",
+                s
+            )),
+        }
+    }
+}
+
+// Opens (or creates) an archive, setting the default pragmas and then any given ones, and brings
+// its schema up to date. A SQLCipher `key` is set before anything else, since nothing can be read
+// without it.
+pub fn open(db_path: &str, pragmas: &[Pragma]) -> Result<Connection, Box<dyn std::error::Error>> {
+    let conn = Connection::open(db_path)?;
+    set_pragmas(&conn, pragmas, &DEFAULT_PRAGMAS)?;
+    create_tables(&conn)?;
+    Ok(conn)
+}
+
+// Opens an existing archive without writing to it: no schema changes, and no journal mode
+// switch. An archive whose schema is older than this version's has to be brought up to date by
+// another command first.
+pub fn open_read_only(
+    db_path: &str,
+    pragmas: &[Pragma],
+) -> Result<Connection, Box<dyn std::error::Error>> {
+    let (conn, outdated) = open_read_only_unmigrated(db_path, pragmas)?;
+    if outdated {
+        return Err(format!(
+            "{} was created by an older version. Run the `archive` subcommand to update it first.",
+            db_path
+        )
+        .into());
+    }
+    Ok(conn)
+}
+
+// Opens an existing archive read-only, whatever its schema version, along with whether that's
+// older than this version's.
+pub fn open_read_only_unmigrated(
+    db_path: &str,
+    pragmas: &[Pragma],
+) -> Result<(Connection, bool), Box<dyn std::error::Error>> {
+    if !std::path::Path::new(db_path).exists() {
+        return Err(format!(
+            "{} does not exist. Run the `archive` subcommand first.",
+            db_path
+        )
+        .into());
+    }
+    let conn = Connection::open_with_flags(
+        db_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let defaults: Vec<(&str, &str)> = DEFAULT_PRAGMAS
+        .iter()
+        .copied()
+        .filter(|(name, _)| *name != "journal_mode")
+        .collect();
+    set_pragmas(&conn, pragmas, &defaults)?;
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok((conn, version < SCHEMA_VERSION))
+}
+
+// A URI that opens `db_path` read-only, as with `ATTACH DATABASE`. '%', '?' and '#' mean
+// something in URIs, so they're escaped.
+pub fn read_only_uri(db_path: &str) -> String {
+    format!(
+        "file:{}?mode=ro",
+        db_path
+            .replace('%', "%25")
+            .replace('?', "%3f")
+            .replace('#', "%23")
+    )
+}
+
+// Sets a SQLCipher `key` first, since nothing can be read without it, then the defaults the
+// given pragmas don't override, then the given pragmas.
+fn set_pragmas(
+    conn: &Connection,
+    pragmas: &[Pragma],
+    defaults: &[(&str, &str)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (keys, pragmas): (Vec<&Pragma>, Vec<&Pragma>) = pragmas
+        .iter()
+        .partition(|p| p.name.eq_ignore_ascii_case("key"));
+    for key in keys {
+        conn.execute_batch(&format!("PRAGMA key = {}", key.value))?;
+    }
+    for (name, value) in defaults.iter() {
+        if !pragmas.iter().any(|p| p.name.eq_ignore_ascii_case(name)) {
+            conn.execute_batch(&format!("PRAGMA {} = {}", name, value))?;
+        }
+    }
+    for pragma in pragmas {
+        conn.execute_batch(&format!("PRAGMA {} = {}", pragma.name, pragma.value))?;
+    }
+    Ok(())
+}
+
+// Opens an archive created by a previous `archive` run, bringing its schema up to date.
+pub fn open_existing(
+    db_path: &str,
+    pragmas: &[Pragma],
+) -> Result<Connection, Box<dyn std::error::Error>> {
+    if !std::path::Path::new(db_path).exists() {
+        return Err(format!(
+            "{} does not exist. Run the `archive` subcommand first.",
+            db_path
+        )
+        .into());
+    }
+    open(db_path, pragmas)
+}
+
+// The archive's tables, parents before children. Each definition is the part of the CREATE TABLE
+// statement after the table name, so tables can be rebuilt under a temporary name by migrations.
+// Booleans are INTEGER 0 or 1, and timestamps are ISO 8601 TEXT, which SQLite's date and time
+// functions understand.
+const SCHEMA: &[(&str, &str)] = &[
+    (
+        "accounts",
+        "(
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE, -- the --profile name
+            username TEXT,
+            created_at TEXT NOT NULL -- ISO 8601, YYYY-MM-DD HH:MM:SS
+        )",
+    ),
+    (
+        "feeds",
+        "(
             id INTEGER PRIMARY KEY,
             title TEXT,
-            subscribed BOOLEAN,
-            feedUrl TEXT,
-            htmlUrl TEXT
+            subscribed INTEGER CHECK (subscribed IN (0, 1)),
+            feed_url TEXT,
+            html_url TEXT,
+            source TEXT NOT NULL DEFAULT 'overcast',
+            added_at TEXT, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            sort_order INTEGER,
+            raw_attributes TEXT, -- JSON object of OPML attributes without their own column
+            account_id INTEGER REFERENCES accounts(id) ON DELETE SET NULL
         )",
-        [],
-    )?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS episodes (
+    ),
+    (
+        "episodes",
+        "(
             id INTEGER PRIMARY KEY,
             title TEXT,
-            played BOOLEAN,
-            feedId INTEGER NOT NULL,
-            publishedAt TEXT,
-            updatedAt TEXT,
-            htmlUrl TEXT,
-            overcastUrl TEXT,
-            mp3Url TEXT,
+            played INTEGER CHECK (played IN (0, 1)),
+            feed_id INTEGER NOT NULL,
+            published_at TEXT, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            updated_at TEXT, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            html_url TEXT,
+            overcast_url TEXT,
+            mp3_url TEXT,
+            normalized_mp3_url TEXT,
+            normalized_html_url TEXT,
             progress INTEGER,
-            userDeleted BOOLEAN,
-            FOREIGN KEY(feedId) REFERENCES feeds(id)
+            user_deleted INTEGER CHECK (user_deleted IN (0, 1)),
+            starred INTEGER CHECK (starred IN (0, 1)),
+            starred_at TEXT, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            source TEXT NOT NULL DEFAULT 'overcast',
+            canonical_episode_id INTEGER,
+            raw_attributes TEXT, -- JSON object of OPML attributes without their own column
+            account_id INTEGER REFERENCES accounts(id) ON DELETE SET NULL,
+            FOREIGN KEY(feed_id) REFERENCES feeds(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "feed_details",
+        "(
+            feed_id INTEGER PRIMARY KEY,
+            description TEXT,
+            language TEXT,
+            author TEXT,
+            copyright TEXT,
+            link TEXT,
+            image_url TEXT,
+            fetched_at TEXT, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            FOREIGN KEY(feed_id) REFERENCES feeds(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "feed_schedule",
+        "(
+            feed_id INTEGER PRIMARY KEY REFERENCES feeds(id) ON DELETE CASCADE,
+            interval_seconds INTEGER NOT NULL, -- how long to wait between fetches of the feed
+            last_fetched_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            next_fetch_at TEXT NOT NULL -- ISO 8601, YYYY-MM-DD HH:MM:SS
+        )",
+    ),
+    (
+        "feed_cadence",
+        "(
+            feed_id INTEGER PRIMARY KEY REFERENCES feeds(id) ON DELETE CASCADE,
+            interval_seconds INTEGER NOT NULL, -- the median gap between its latest episodes
+            weekday INTEGER, -- 0 (Monday) to 6, in UTC, when most of them came out that day
+            last_published_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            next_expected_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            on_hiatus INTEGER NOT NULL CHECK (on_hiatus IN (0, 1)), -- 3 usual gaps without one
+            computed_at TEXT NOT NULL -- ISO 8601, YYYY-MM-DD HH:MM:SS
+        )",
+    ),
+    (
+        "sync_state",
+        "(
+            step TEXT NOT NULL, -- the per-feed pass, like 'enrich'
+            feed_id INTEGER NOT NULL REFERENCES feeds(id) ON DELETE CASCADE,
+            completed_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            PRIMARY KEY (step, feed_id)
+        )",
+    ),
+    (
+        "episode_details",
+        "(
+            episode_id INTEGER PRIMARY KEY,
+            duration INTEGER,
+            description TEXT,
+            transcript_url TEXT,
+            fetched_at TEXT, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            FOREIGN KEY(episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "listening_time",
+        "(
+            episode_id INTEGER PRIMARY KEY,
+            feed_id INTEGER NOT NULL,
+            seconds INTEGER NOT NULL,
+            duration_known INTEGER NOT NULL CHECK (duration_known IN (0, 1)),
+            listened_at TEXT, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            FOREIGN KEY(episode_id) REFERENCES episodes(id) ON DELETE CASCADE,
+            FOREIGN KEY(feed_id) REFERENCES feeds(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "listening_weekly",
+        "(
+            week_start TEXT NOT NULL, -- YYYY-MM-DD, the Monday the week starts on (UTC)
+            feed_id INTEGER NOT NULL,
+            episodes_played INTEGER NOT NULL,
+            hours REAL NOT NULL,
+            PRIMARY KEY(week_start, feed_id),
+            FOREIGN KEY(feed_id) REFERENCES feeds(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "listening_monthly",
+        "(
+            month TEXT NOT NULL, -- YYYY-MM (UTC)
+            feed_id INTEGER NOT NULL,
+            episodes_played INTEGER NOT NULL,
+            hours REAL NOT NULL,
+            PRIMARY KEY(month, feed_id),
+            FOREIGN KEY(feed_id) REFERENCES feeds(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "play_events",
+        "(
+            id INTEGER PRIMARY KEY,
+            episode_id INTEGER NOT NULL,
+            played_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            FOREIGN KEY(episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "progress_history",
+        "(
+            episode_id INTEGER NOT NULL,
+            run_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            progress INTEGER NOT NULL,
+            PRIMARY KEY(episode_id, run_at),
+            FOREIGN KEY(episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        // Titles feeds and episodes had before Overcast's changed, one row per change.
+        "renames",
+        "(
+            feed_id INTEGER,
+            episode_id INTEGER,
+            old_title TEXT,
+            new_title TEXT,
+            renamed_at TEXT NOT NULL, -- ISO 8601, when the run that saw the new title ran
+            CHECK ((feed_id IS NULL) != (episode_id IS NULL)),
+            FOREIGN KEY(feed_id) REFERENCES feeds(id) ON DELETE CASCADE,
+            FOREIGN KEY(episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        // Values set with `override set`, written back over Overcast's after every upsert.
+        "overrides",
+        "(
+            table_name TEXT NOT NULL CHECK (table_name IN ('feeds', 'episodes')),
+            row_id INTEGER NOT NULL,
+            column_name TEXT NOT NULL,
+            value TEXT, -- converted to the column's type when written
+            created_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            PRIMARY KEY(table_name, row_id, column_name)
+        )",
+    ),
+    (
+        "notion_pages",
+        "(
+            kind TEXT NOT NULL,
+            row_id INTEGER NOT NULL,
+            page_id TEXT NOT NULL,
+            properties TEXT NOT NULL,
+            synced_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            PRIMARY KEY(kind, row_id)
+        )",
+    ),
+    (
+        "readwise_highlights",
+        "(
+            kind TEXT NOT NULL, -- 'note' (row_id is a note's ID) or 'starred' (an episode's)
+            row_id INTEGER NOT NULL,
+            sent_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            PRIMARY KEY(kind, row_id)
+        )",
+    ),
+    (
+        "read_later",
+        "(
+            service TEXT NOT NULL, -- 'instapaper' or 'raindrop'
+            episode_id INTEGER NOT NULL REFERENCES episodes(id) ON DELETE CASCADE,
+            item_id TEXT, -- the service's ID for the bookmark, if it gives one
+            sent_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            PRIMARY KEY(service, episode_id)
+        )",
+    ),
+    (
+        "digest_episodes",
+        "(
+            episode_id INTEGER PRIMARY KEY REFERENCES episodes(id) ON DELETE CASCADE,
+            sent_at TEXT NOT NULL -- ISO 8601, YYYY-MM-DD HH:MM:SS
+        )",
+    ),
+    (
+        "categories",
+        "(
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            parent TEXT
+        )",
+    ),
+    (
+        "feed_categories",
+        "(
+            feed_id INTEGER NOT NULL,
+            category_id INTEGER NOT NULL,
+            PRIMARY KEY(feed_id, category_id),
+            FOREIGN KEY(feed_id) REFERENCES feeds(id) ON DELETE CASCADE,
+            FOREIGN KEY(category_id) REFERENCES categories(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "feed_settings",
+        "(
+            feed_id INTEGER NOT NULL REFERENCES feeds(id) ON DELETE CASCADE,
+            name TEXT NOT NULL, -- the OPML attribute, like 'notifications'
+            value TEXT NOT NULL,
+            updated_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS; when the value last changed
+            PRIMARY KEY(feed_id, name)
+        )",
+    ),
+    (
+        "funding",
+        "(
+            feed_id INTEGER NOT NULL REFERENCES feeds(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            kind TEXT NOT NULL, -- 'funding' for links, otherwise the value type, like 'lightning'
+            method TEXT, -- the value method, like 'keysend'
+            url TEXT, -- the funding page, or the value recipient's address
+            name TEXT, -- the link's text, or the value recipient's name
+            split INTEGER, -- the value recipient's share
+            PRIMARY KEY(feed_id, position)
+        )",
+    ),
+    (
+        "people",
+        "(
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            href TEXT,
+            img TEXT
+        )",
+    ),
+    (
+        "episode_people",
+        "(
+            episode_id INTEGER NOT NULL,
+            person_id INTEGER NOT NULL,
+            role TEXT NOT NULL,
+            person_group TEXT NOT NULL,
+            PRIMARY KEY(episode_id, person_id, role),
+            FOREIGN KEY(episode_id) REFERENCES episodes(id) ON DELETE CASCADE,
+            FOREIGN KEY(person_id) REFERENCES people(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "suggestions",
+        "(
+            source TEXT NOT NULL, -- the discovery API: 'podcastindex' or 'listennotes'
+            source_id TEXT NOT NULL, -- the show's ID in that API
+            based_on_feed_id INTEGER NOT NULL REFERENCES feeds(id) ON DELETE CASCADE,
+            title TEXT NOT NULL,
+            author TEXT,
+            feed_url TEXT,
+            website TEXT,
+            reason TEXT, -- e.g. 'trending in Technology' or 'similar to <feed title>'
+            suggested_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            PRIMARY KEY(source, source_id, based_on_feed_id)
+        )",
+    ),
+    (
+        "account_stats",
+        "(
+            run_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            name TEXT NOT NULL,
+            value REAL NOT NULL,
+            text TEXT,
+            PRIMARY KEY(run_at, name)
+        )",
+    ),
+    (
+        "downloads",
+        "(
+            episode_id INTEGER PRIMARY KEY REFERENCES episodes(id) ON DELETE CASCADE,
+            path TEXT NOT NULL, -- relative to the download directory or prefix
+            remote_url TEXT, -- the object's s3:// URL, for downloads stored in an object store
+            url TEXT NOT NULL,
+            bytes INTEGER NOT NULL,
+            sha256 TEXT NOT NULL,
+            downloaded_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            verified_at TEXT -- ISO 8601, YYYY-MM-DD HH:MM:SS
+        )",
+    ),
+    (
+        "episode_pages",
+        "(
+            episode_id INTEGER PRIMARY KEY REFERENCES episodes(id) ON DELETE CASCADE,
+            url TEXT NOT NULL,
+            status INTEGER, -- the HTTP status, or NULL if the request failed
+            html TEXT,
+            markdown TEXT,
+            fetched_at TEXT NOT NULL -- ISO 8601, YYYY-MM-DD HH:MM:SS
+        )",
+    ),
+    (
+        "fixity",
+        "(
+            kind TEXT NOT NULL, -- 'audio', 'asset' or 'warc'
+            name TEXT NOT NULL, -- the download path, sqlar name or WARC file path
+            sha256 TEXT NOT NULL,
+            bytes INTEGER,
+            mtime INTEGER, -- for assets, the sqlar mtime the checksum was taken at
+            recorded_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            verified_at TEXT, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            PRIMARY KEY(kind, name)
+        )",
+    ),
+    (
+        "warc_records",
+        "(
+            episode_id INTEGER NOT NULL REFERENCES episodes(id) ON DELETE CASCADE,
+            url TEXT NOT NULL,
+            status INTEGER NOT NULL, -- the HTTP status
+            warc_file TEXT NOT NULL, -- the path of the WARC file, as written
+            offset INTEGER NOT NULL, -- where the record starts in the file, in bytes
+            length INTEGER NOT NULL, -- the record's length, in bytes
+            record_id TEXT NOT NULL, -- the record's WARC-Record-ID
+            fetched_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            PRIMARY KEY(warc_file, offset)
+        )",
+    ),
+    (
+        "overcast_pages",
+        "(
+            episode_id INTEGER PRIMARY KEY REFERENCES episodes(id) ON DELETE CASCADE,
+            url TEXT NOT NULL,
+            status INTEGER, -- the HTTP status, or NULL if the request failed
+            description TEXT,
+            duration INTEGER, -- seconds
+            artwork_url TEXT,
+            fetched_at TEXT NOT NULL -- ISO 8601, YYYY-MM-DD HH:MM:SS
+        )",
+    ),
+    (
+        "links",
+        "(
+            episode_id INTEGER NOT NULL REFERENCES episodes(id) ON DELETE CASCADE,
+            source TEXT NOT NULL, -- 'show_notes' (from the feed) or 'page' (from episode_pages)
+            position INTEGER NOT NULL,
+            url TEXT NOT NULL,
+            text TEXT,
+            PRIMARY KEY(episode_id, source, position)
+        )",
+    ),
+    (
+        "link_checks",
+        "(
+            url TEXT NOT NULL,
+            checked_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            status INTEGER, -- the final HTTP status, or NULL if the request failed
+            final_url TEXT, -- where redirects led, if anywhere
+            error TEXT,
+            PRIMARY KEY(url, checked_at)
+        )",
+    ),
+    (
+        "wayback_snapshots",
+        "(
+            url TEXT PRIMARY KEY, -- an episode's html_url
+            snapshot_url TEXT, -- the Wayback Machine capture, or NULL if submitting failed
+            submitted_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            error TEXT
+        )",
+    ),
+    (
+        "audio_metadata",
+        "(
+            episode_id INTEGER PRIMARY KEY REFERENCES episodes(id) ON DELETE CASCADE,
+            format TEXT NOT NULL,
+            duration INTEGER, -- seconds
+            bitrate INTEGER, -- bits per second
+            title TEXT,
+            artwork_type TEXT,
+            artwork BLOB,
+            parsed_at TEXT NOT NULL -- ISO 8601, YYYY-MM-DD HH:MM:SS
+        )",
+    ),
+    (
+        "audio_chapters",
+        "(
+            episode_id INTEGER NOT NULL REFERENCES episodes(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            start_seconds REAL NOT NULL,
+            title TEXT,
+            PRIMARY KEY(episode_id, position)
+        )",
+    ),
+    (
+        "pruned_episodes",
+        "(
+            episode_id INTEGER PRIMARY KEY, -- an episode `prune` deleted, so it isn't archived again
+            pruned_at TEXT NOT NULL -- ISO 8601, YYYY-MM-DD HH:MM:SS
+        )",
+    ),
+    (
+        "runs",
+        "(
+            id INTEGER PRIMARY KEY,
+            command TEXT NOT NULL, -- the subcommand, like 'archive' or 'import pocketcasts'
+            started_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            finished_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            exit_code INTEGER NOT NULL, -- 0 on success; see the README's Exit codes
+            error TEXT,
+            counts TEXT, -- JSON object of feeds, episodes and downloads after the run, and new ones
+            version TEXT NOT NULL -- of overcast-to-sqlite
+        )",
+    ),
+    // The SQLite Archive format (https://www.sqlite.org/sqlar.html), so `sqlite3 -A` can list
+    // and extract stored assets.
+    (
+        "sqlar",
+        "(
+            name TEXT PRIMARY KEY,
+            mode INT,
+            mtime INT,
+            sz INT,
+            data BLOB
         )",
+    ),
+    // Tables from here on hold data the user writes themselves, so runs never modify them.
+    (
+        "notes",
+        "(
+            id INTEGER PRIMARY KEY,
+            episode_id INTEGER NOT NULL,
+            text TEXT NOT NULL,
+            created_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            FOREIGN KEY(episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        )",
+    ),
+    (
+        "tags",
+        "(
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE
+        )",
+    ),
+    (
+        "taggings",
+        "(
+            tag_id INTEGER NOT NULL,
+            feed_id INTEGER,
+            episode_id INTEGER,
+            created_at TEXT NOT NULL, -- ISO 8601, YYYY-MM-DD HH:MM:SS
+            CHECK ((feed_id IS NULL) != (episode_id IS NULL)),
+            FOREIGN KEY(tag_id) REFERENCES tags(id) ON DELETE CASCADE,
+            FOREIGN KEY(feed_id) REFERENCES feeds(id) ON DELETE CASCADE,
+            FOREIGN KEY(episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        )",
+    ),
+];
+
+// The schema version, stored in `PRAGMA user_version`. Bump it when adding a migration to
+// `migrate`.
+const SCHEMA_VERSION: i64 = 4;
+
+// The prefix of the compatibility views `archive --legacy-columns` creates.
+const LEGACY_PREFIX: &str = "legacy_";
+
+// Views over the tables for questions that keep coming up. They're recreated on every open, so
+// their definitions can change freely between versions.
+const VIEWS: &[(&str, &str)] = &[
+    (
+        // How far through each episode you are. Durations come from the downloaded audio or the
+        // feed (with --enrich); without one, completion and remaining time are NULL.
+        "episode_progress",
+        "SELECT
+            e.id AS episode_id,
+            e.feed_id,
+            e.title,
+            e.played,
+            e.user_deleted,
+            CASE WHEN e.played THEN e.duration ELSE MIN(COALESCE(e.progress, 0), e.duration) END
+                AS progress_seconds,
+            e.duration AS duration_seconds,
+            CASE
+                WHEN e.played THEN 100.0
+                WHEN e.duration > 0 THEN ROUND(
+                    100.0 * MIN(COALESCE(e.progress, 0), e.duration) / e.duration, 1
+                )
+            END AS completion_percent,
+            CASE
+                WHEN e.played THEN 0
+                ELSE e.duration - MIN(COALESCE(e.progress, 0), e.duration)
+            END AS remaining_seconds
+        FROM (
+            SELECT episodes.*, COALESCE(a.duration, d.duration) AS duration
+            FROM episodes
+            LEFT JOIN episode_details d ON d.episode_id = episodes.id
+            LEFT JOIN audio_metadata a ON a.episode_id = episodes.id
+        ) e",
+    ),
+    (
+        // Each feed's backlog: the episodes neither played nor deleted, and the listening left
+        // in them.
+        "feed_backlog",
+        "SELECT
+            f.id AS feed_id,
+            f.title,
+            f.subscribed,
+            COUNT(p.episode_id) AS unplayed_episodes,
+            COUNT(CASE WHEN p.progress_seconds > 0 THEN 1 END) AS started_episodes,
+            COALESCE(SUM(p.remaining_seconds), 0) AS remaining_seconds,
+            COUNT(p.episode_id) - COUNT(p.remaining_seconds) AS unknown_duration_episodes,
+            MIN(e.published_at) AS oldest_unplayed_at
+        FROM feeds f
+        LEFT JOIN episode_progress p ON p.feed_id = f.id AND NOT p.played AND NOT p.user_deleted
+        LEFT JOIN episodes e ON e.id = p.episode_id
+        GROUP BY f.id",
+    ),
+    (
+        // When episodes get finished, by weekday and hour (UTC), worked out as `stats --habits`
+        // does: the day from the episode's first play event, falling back to Overcast's last
+        // update, and the hour only from that update, since play events are dated by when the
+        // archive ran. Cross-posted episodes count once.
+        "habits",
+        "SELECT
+            CAST(strftime('%w', played_at) AS INTEGER) AS weekday,
+            CASE strftime('%w', played_at)
+                WHEN '0' THEN 'Sunday' WHEN '1' THEN 'Monday' WHEN '2' THEN 'Tuesday'
+                WHEN '3' THEN 'Wednesday' WHEN '4' THEN 'Thursday' WHEN '5' THEN 'Friday'
+                ELSE 'Saturday'
+            END AS weekday_name,
+            CAST(strftime('%H', updated_at) AS INTEGER) AS hour,
+            COUNT(*) AS episodes_played
+        FROM (
+            SELECT
+                MIN(COALESCE(
+                    (SELECT MIN(played_at) FROM play_events WHERE episode_id = e.id),
+                    e.updated_at
+                )) AS played_at,
+                MIN(e.updated_at) AS updated_at
+            FROM episodes e
+            WHERE e.played
+            GROUP BY COALESCE(e.canonical_episode_id, e.id)
+        )
+        WHERE played_at IS NOT NULL
+        GROUP BY weekday, hour",
+    ),
+];
+
+// Tables are STRICT, so values of the wrong type are rejected rather than stored as is, when the
+// SQLite library supports it (3.37 and newer).
+fn table_options() -> &'static str {
+    if rusqlite::version_number() >= 3_037_000 {
+        " STRICT"
+    } else {
+        ""
+    }
+}
+
+// Creates tables for podcast feeds and episodes, if they don't already exist, and migrates
+// archives created by older versions.
+pub fn create_tables(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'feeds'",
         [],
+        |row| row.get(0),
+    )?;
+    let legacy_columns = !legacy_views(conn)?.is_empty();
+    if exists {
+        rename_columns(conn)?;
+    }
+    for (name, definition) in SCHEMA.iter() {
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} {}{}",
+                name,
+                definition,
+                table_options()
+            ),
+            [],
+        )?;
+    }
+    add_column_if_missing(conn, "feeds", "source", "TEXT NOT NULL DEFAULT 'overcast'")?;
+    add_column_if_missing(
+        conn,
+        "episodes",
+        "starred",
+        "INTEGER CHECK (starred IN (0, 1))",
     )?;
+    add_column_if_missing(conn, "feeds", "added_at", "TEXT")?;
+    add_column_if_missing(conn, "feeds", "sort_order", "INTEGER")?;
+    add_column_if_missing(conn, "feeds", "raw_attributes", "TEXT")?;
+    add_column_if_missing(conn, "episodes", "starred_at", "TEXT")?;
+    add_column_if_missing(
+        conn,
+        "episodes",
+        "source",
+        "TEXT NOT NULL DEFAULT 'overcast'",
+    )?;
+    add_column_if_missing(conn, "episodes", "canonical_episode_id", "INTEGER")?;
+    add_column_if_missing(conn, "episodes", "normalized_mp3_url", "TEXT")?;
+    add_column_if_missing(conn, "episodes", "normalized_html_url", "TEXT")?;
+    add_column_if_missing(conn, "episodes", "raw_attributes", "TEXT")?;
+    for table in &["feeds", "episodes"] {
+        add_column_if_missing(
+            conn,
+            table,
+            "account_id",
+            "INTEGER REFERENCES accounts(id) ON DELETE SET NULL",
+        )?;
+    }
+    add_column_if_missing(conn, "episode_details", "description", "TEXT")?;
+    add_column_if_missing(conn, "episode_details", "transcript_url", "TEXT")?;
+    add_column_if_missing(conn, "downloads", "remote_url", "TEXT")?;
+    if exists {
+        migrate(conn)?;
+    } else {
+        conn.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+    }
+    create_views(conn)?;
+    if legacy_columns {
+        create_legacy_views(conn)?;
+    }
     Ok(())
 }
 
-// Upserts a list of feeds  and episodes into the database.
+// Creates the views, replacing any whose definition has changed.
+fn create_views(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    for (name, definition) in VIEWS.iter() {
+        replace_view(
+            conn,
+            name,
+            &format!("CREATE VIEW {} AS {}", name, definition),
+        )?;
+    }
+    Ok(())
+}
+
+fn replace_view(
+    conn: &Connection,
+    name: &str,
+    sql: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'view' AND name = ?",
+            params![name],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if existing.as_deref() != Some(sql) {
+        conn.execute(&format!("DROP VIEW IF EXISTS {}", name), [])?;
+        conn.execute(sql, [])?;
+    }
+    Ok(())
+}
+
+// Drops every view, for changes to the tables they select from; create_views (and
+// create_legacy_views, for archives that have them) put them back.
+fn drop_views(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let names = legacy_views(conn)?
+        .into_iter()
+        .chain(VIEWS.iter().rev().map(|(name, _)| name.to_string()));
+    for name in names {
+        conn.execute(&format!("DROP VIEW IF EXISTS {}", name), [])?;
+    }
+    Ok(())
+}
+
+fn legacy_views(conn: &Connection) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'view' AND substr(name, 1, length(?1)) = ?1",
+    )?;
+    let names = stmt
+        .query_map(params![LEGACY_PREFIX], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(names)
+}
+
+// The name a column had before version 3, when columns were camelCase: `publishedAt` for
+// `published_at`.
+pub fn legacy_column_name(column: &str) -> String {
+    let mut name = String::with_capacity(column.len());
+    let mut word_start = false;
+    for c in column.chars() {
+        if c == '_' {
+            word_start = true;
+        } else if word_start {
+            name.extend(c.to_uppercase());
+            word_start = false;
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+fn snake_case(column: &str) -> String {
+    let mut name = String::with_capacity(column.len() + 4);
+    for c in column.chars() {
+        if c.is_ascii_uppercase() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
+
+// Creates a view over each table and view with its columns under their names before version 3,
+// e.g. `legacy_episodes` with `feedId` and `publishedAt`, so queries and dashboards written for
+// older archives only need their table names changed. Once created, they're kept in step with
+// the tables on every open.
+pub fn create_legacy_views(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let names = SCHEMA.iter().chain(VIEWS.iter()).map(|(name, _)| name);
+    for name in names {
+        let columns = table_columns(conn, name)?
+            .iter()
+            .map(|column| {
+                let legacy = legacy_column_name(column);
+                if legacy == *column {
+                    format!("\"{}\"", column)
+                } else {
+                    format!("\"{}\" AS \"{}\"", column, legacy)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let view = format!("{}{}", LEGACY_PREFIX, name);
+        let sql = format!("CREATE VIEW {} AS SELECT {} FROM {}", view, columns, name);
+        replace_view(conn, &view, &sql)?;
+    }
+    Ok(())
+}
+
+// Version 3 renamed the columns from camelCase (`feedId`, `publishedAt`) to snake_case (`feed_id`,
+// `published_at`), like most SQLite and Datasette databases. It runs before columns are added to
+// older archives, rather than with the other migrations, so no table ends up with both spellings
+// of a column. Columns that aren't in SCHEMA, like ones added by hand, keep their names.
+fn rename_columns(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= 3 {
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction()?;
+    drop_views(conn)?;
+    let mut renamed = 0;
+    for (name, definition) in SCHEMA.iter() {
+        for column in table_columns(conn, name)? {
+            let new_name = snake_case(&column);
+            let defined = definition
+                .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .any(|word| word == new_name);
+            if new_name != column && defined {
+                conn.execute(
+                    &format!(
+                        "ALTER TABLE {} RENAME COLUMN \"{}\" TO {}",
+                        name, column, new_name
+                    ),
+                    [],
+                )?;
+                renamed += 1;
+            }
+        }
+    }
+    tx.commit()?;
+    if renamed > 0 {
+        eprintln!(
+            "Renamed {} columns to snake_case. Run `archive --legacy-columns` for views with the \
+            old names.",
+            renamed
+        );
+    }
+    Ok(())
+}
+
+// Brings an archive created by an older version up to SCHEMA_VERSION.
+fn migrate(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= SCHEMA_VERSION {
+        return Ok(());
+    }
+    // Tables can't be rebuilt with foreign keys enforced, and the pragma is a no-op inside a
+    // transaction, so it's switched off around the migration as a whole.
+    let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+    let result = apply_migrations(conn, version);
+    if foreign_keys {
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
+    }
+    result?;
+    for orphan in orphans(conn)? {
+        eprintln!(
+            "Warning: {} rows in {} reference missing rows in {}.",
+            orphan.count, orphan.table, orphan.parent
+        );
+    }
+    Ok(())
+}
+
+fn apply_migrations(conn: &Connection, version: i64) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    // Tables can't be rebuilt while views select from them.
+    drop_views(conn)?;
+    // Rebuilt tables get the latest definition from SCHEMA, so each table is rebuilt at most once
+    // however many versions behind the archive is.
+    for (name, definition) in SCHEMA.iter() {
+        // Version 1 added ON DELETE CASCADE to every foreign key, and version 2 made every table
+        // STRICT, with CHECK constraints on booleans.
+        if (version < 1 && definition.contains("REFERENCES")) || version < 2 {
+            rebuild_table(conn, name, definition)?;
+        }
+    }
+    if version < 4 {
+        let converted = dates_to_utc(conn)?;
+        rebuild_listening_time(conn)?;
+        if converted > 0 {
+            eprintln!(
+                "Converted {} dates from Overcast's export to UTC, taking them to be in this \
+                machine's timezone.",
+                converted
+            );
+        }
+    }
+    conn.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Version 4 converted the dates that come from Overcast's export to UTC. Older versions stored them
+// in the export's local time, without the offset, which is lost; they're taken to be in this
+// machine's timezone (where the account's usually is), with the daylight saving time in effect
+// on each date. Rows are updated by ID, so no date is converted twice. Returns how many were.
+fn dates_to_utc(conn: &Connection) -> Result<usize, Box<dyn std::error::Error>> {
+    let columns = [
+        ("feeds", "added_at"),
+        ("episodes", "published_at"),
+        ("episodes", "updated_at"),
+        ("episodes", "starred_at"),
+    ];
+    let mut converted = 0;
+    for (table, column) in columns {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, {} FROM {} WHERE {} IS NOT NULL",
+            column, table, column
+        ))?;
+        let dates = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (id, stored) in dates {
+            let utc = NaiveDateTime::parse_from_str(&stored, "%Y-%m-%d %H:%M:%S%.f")
+                .ok()
+                .and_then(|local| chrono::Local.from_local_datetime(&local).earliest())
+                .map(|local| local.naive_utc());
+            if let Some(utc) = utc {
+                conn.execute(
+                    &format!("UPDATE {} SET {} = ? WHERE id = ?", table, column),
+                    params![utc, id],
+                )?;
+                converted += 1;
+            }
+        }
+    }
+    Ok(converted)
+}
+
+// Recreates a table with a new definition and copies its rows over. SQLite can't alter
+// constraints or column types in place, so this is how they're changed. Columns that aren't in the
+// new definition (e.g. ones added by hand) are kept, with their type if a STRICT table allows it
+// and untyped otherwise, though not their constraints or defaults.
+fn rebuild_table(
+    conn: &Connection,
+    name: &str,
+    definition: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let options = table_options();
+    conn.execute(
+        &format!("CREATE TABLE {}_new {}{}", name, definition, options),
+        [],
+    )?;
+    let new_columns = table_columns(conn, &format!("{}_new", name))?;
+    let old_columns = table_columns(conn, name)?;
+    let mut stmt = conn.prepare("SELECT name, upper(type) FROM pragma_table_info(?)")?;
+    let declared_types = stmt
+        .query_map(params![name], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (column, declared) in declared_types
+        .iter()
+        .filter(|(c, _)| !new_columns.contains(c))
+    {
+        let strict_types = ["INT", "INTEGER", "REAL", "TEXT", "BLOB", "ANY"];
+        let column_type = if options.is_empty() || strict_types.contains(&declared.as_str()) {
+            declared.as_str()
+        } else {
+            "ANY"
+        };
+        conn.execute(
+            &format!(
+                "ALTER TABLE {}_new ADD COLUMN \"{}\" {}",
+                name, column, column_type
+            ),
+            [],
+        )?;
+        eprintln!(
+            "Kept {}.{}, which isn't part of the archive's schema.",
+            name, column
+        );
+    }
+    let columns = old_columns
+        .iter()
+        .map(|c| format!("\"{}\"", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    conn.execute_batch(&format!(
+        "INSERT INTO {name}_new({columns}) SELECT {columns} FROM {name};
+        DROP TABLE {name};
+        ALTER TABLE {name}_new RENAME TO {name};",
+        name = name,
+        columns = columns
+    ))?;
+    Ok(())
+}
+
+fn table_columns(
+    conn: &Connection,
+    table: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+// Rows whose foreign keys point at rows that don't exist, like episodes of a deleted feed.
+pub struct Orphans {
+    pub table: String,
+    pub parent: String,
+    pub count: i64,
+}
+
+// Finds rows that violate a foreign key, grouped by table. Archives written before foreign keys
+// were enforced can contain them.
+pub fn orphans(conn: &Connection) -> Result<Vec<Orphans>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT \"table\", parent, COUNT(*) FROM pragma_foreign_key_check GROUP BY 1, 2 ORDER BY 1, 2",
+    )?;
+    let orphans = stmt
+        .query_map([], |row| {
+            Ok(Orphans {
+                table: row.get(0)?,
+                parent: row.get(1)?,
+                count: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(orphans)
+}
+
+// Adds a column to a table created by an older version of this tool.
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !table_columns(conn, table)?.iter().any(|c| c == column) {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+// Returns the ID of the named account (a `--profile`), creating it if needed.
+pub fn upsert_account(
+    conn: &Connection,
+    name: &str,
+    username: Option<&str>,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO accounts(name, username, created_at) VALUES (?, ?, ?)
+        ON CONFLICT(name) DO UPDATE SET username = COALESCE(excluded.username, username)",
+        params![name, username, chrono::Utc::now().naive_utc()],
+    )?;
+    Ok(conn.query_row(
+        "SELECT id FROM accounts WHERE name = ?",
+        params![name],
+        |row| row.get(0),
+    )?)
+}
+
+// Marks feeds and their episodes as belonging to an account. Overcast's IDs are global, so a
+// podcast in several accounts belongs to whichever archived it last.
+pub fn assign_account(
+    conn: &Connection,
+    account_id: i64,
+    feeds: &[Feed],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for feed in feeds {
+        conn.execute(
+            "UPDATE feeds SET account_id = ? WHERE id = ?",
+            params![account_id, feed.id],
+        )?;
+        for episode in &feed.episodes {
+            conn.execute(
+                "UPDATE episodes SET account_id = ? WHERE id = ?",
+                params![account_id, episode.id],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Limits what this connection sees to one account's feeds and episodes, for read-only commands.
+// Temporary views named after the tables take precedence over them in unqualified queries, so
+// existing queries are filtered without changes. Views in `main` would still read `main`'s tables,
+// so every one of VIEWS gets a temporary copy too, which reads the filtered ones.
+pub fn scope_to_account(conn: &Connection, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let account_id: i64 = conn
+        .query_row(
+            "SELECT id FROM accounts WHERE name = ?",
+            params![name],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| format!("no account named \"{}\" in this archive", name))?;
+    let episodes = format!(
+        "SELECT id FROM main.episodes WHERE account_id = {}",
+        account_id
+    );
+    conn.execute_batch(&format!(
+        "CREATE TEMP VIEW feeds AS SELECT * FROM main.feeds WHERE account_id = {id};
+        CREATE TEMP VIEW episodes AS SELECT * FROM main.episodes WHERE account_id = {id};
+        CREATE TEMP VIEW listening_time AS
+            SELECT * FROM main.listening_time WHERE episode_id IN ({episodes});
+        CREATE TEMP VIEW play_events AS
+            SELECT * FROM main.play_events WHERE episode_id IN ({episodes});
+        CREATE TEMP VIEW progress_history AS
+            SELECT * FROM main.progress_history WHERE episode_id IN ({episodes});
+        CREATE TEMP VIEW listening_weekly AS
+            SELECT * FROM main.listening_weekly WHERE feed_id IN ({feeds});
+        CREATE TEMP VIEW listening_monthly AS
+            SELECT * FROM main.listening_monthly WHERE feed_id IN ({feeds});",
+        id = account_id,
+        episodes = episodes,
+        feeds = format!(
+            "SELECT id FROM main.feeds WHERE account_id = {}",
+            account_id
+        )
+    ))?;
+    for (name, definition) in VIEWS.iter() {
+        conn.execute(&format!("CREATE TEMP VIEW {} AS {}", name, definition), [])?;
+    }
+    Ok(())
+}
+
+// Runs `f` in a savepoint, so either all of its writes land or, if it fails (or the process dies
+// partway), none do. Savepoints nest, so this works inside a transaction or another savepoint.
+pub fn atomically<T>(
+    conn: &Connection,
+    f: impl FnOnce() -> Result<T, Box<dyn std::error::Error>>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    conn.execute_batch("SAVEPOINT atomically")?;
+    match f() {
+        Ok(value) => {
+            conn.execute_batch("RELEASE atomically")?;
+            Ok(value)
+        }
+        Err(err) => {
+            // The original error is the one worth reporting; if rolling back fails too, closing
+            // the connection rolls back anyway.
+            let _ = conn.execute_batch("ROLLBACK TO atomically; RELEASE atomically");
+            Err(err)
+        }
+    }
+}
+
+// Moves the rows of `table` matching `condition`, a SQL expression over its columns, into
+// `<table>_deleted`, stamped with `deleted_at`, instead of deleting them outright. The tombstone
+// table is created on first use, and gains (with their declared types, but no constraints) any
+// columns `table` has since grown, so nothing is lost until `purge` removes it for good. Returns
+// how many rows were moved.
+pub fn tombstone(
+    conn: &Connection,
+    table: &str,
+    condition: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let deleted = format!("{}_deleted", table);
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {}(deleted_at TEXT NOT NULL)",
+            deleted
+        ),
+        [],
+    )?;
+    let mut stmt = conn.prepare("SELECT name, type FROM pragma_table_info(?)")?;
+    let columns = stmt
+        .query_map(params![table], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let existing = table_columns(conn, &deleted)?;
+    for (column, declared) in columns
+        .iter()
+        .filter(|(column, _)| !existing.contains(column))
+    {
+        conn.execute(
+            &format!(
+                "ALTER TABLE {} ADD COLUMN \"{}\" {}",
+                deleted, column, declared
+            ),
+            [],
+        )?;
+    }
+    let columns = columns
+        .iter()
+        .map(|(column, _)| format!("\"{}\"", column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let now = chrono::Utc::now().naive_utc();
+    let insert_params: Vec<&dyn rusqlite::ToSql> = std::iter::once(&now as &dyn rusqlite::ToSql)
+        .chain(params.iter().copied())
+        .collect();
+    conn.execute(
+        &format!(
+            "INSERT INTO {deleted}(deleted_at, {columns})
+            SELECT ?, {columns} FROM {table} WHERE {condition}",
+            deleted = deleted,
+            columns = columns,
+            table = table,
+            condition = condition
+        ),
+        insert_params.as_slice(),
+    )?;
+    let moved = conn.execute(
+        &format!("DELETE FROM {} WHERE {}", table, condition),
+        params,
+    )?;
+    Ok(moved)
+}
+
+// Tombstones the episodes matching `condition`, along with every row that refers to them (their
+// details, history, downloads and so on), which deleting them would otherwise cascade to.
+pub fn tombstone_episodes(
+    conn: &Connection,
+    condition: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT m.name, f.\"from\" FROM sqlite_master m, pragma_foreign_key_list(m.name) f
+        WHERE m.type = 'table' AND f.\"table\" = 'episodes'
+        ORDER BY m.name",
+    )?;
+    let references = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (table, column) in references {
+        tombstone(
+            conn,
+            &table,
+            &format!(
+                "\"{}\" IN (SELECT id FROM episodes WHERE {})",
+                column, condition
+            ),
+            params,
+        )?;
+    }
+    tombstone(conn, "episodes", condition, params)
+}
+
+// Upserts a list of feeds  and episodes into the database. Existing rows are updated in place
+// rather than replaced, since replacing a row would cascade to the rows referencing it.
+//
+// Overcast doesn't export when an episode was played, so episodes that were archived as unplayed
+// and are now played get a play_events row timestamped with this run. Likewise, progress that
+// differs from the archived value is appended to progress_history. Run regularly, this gives the
+// listening history a time dimension.
+//
+// Columns set with `override set` keep their overridden values.
 pub fn upsert_feeds(conn: &Connection, feeds: &[Feed]) -> Result<(), Box<dyn std::error::Error>> {
+    let run_at = chrono::Utc::now().naive_utc();
     for feed in feeds {
+        upsert_feed_row(conn, feed, run_at)?;
+        upsert_feed_episodes(conn, feed, run_at)?;
+    }
+    overrides::apply(conn)?;
+    Ok(())
+}
+
+// Upserts feeds' episodes, like `upsert_feeds`, but leaves the feeds themselves as they are.
+// Feeds that aren't archived yet are still added, for their episodes to belong to.
+pub fn upsert_episodes(
+    conn: &Connection,
+    feeds: &[Feed],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let run_at = chrono::Utc::now().naive_utc();
+    for feed in feeds {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM feeds WHERE id = ?)",
+            params![feed.id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            upsert_feed_row(conn, feed, run_at)?;
+        }
+        upsert_feed_episodes(conn, feed, run_at)?;
+    }
+    overrides::apply(conn)?;
+    Ok(())
+}
+
+fn upsert_feed_row(
+    conn: &Connection,
+    feed: &Feed,
+    run_at: NaiveDateTime,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // An overridden title isn't one the feed had, so it's not a rename.
+    let previous_title = if overrides::is_overridden(conn, "feeds", &feed.id, "title")? {
+        None
+    } else {
+        conn.query_row(
+            "SELECT title FROM feeds WHERE id = ?",
+            params![feed.id],
+            |row| row.get::<_, Option<String>>(0),
+        )
+        .optional()?
+        .flatten()
+    };
+    if let Some(previous_title) = previous_title.filter(|title| *title != feed.title) {
         conn.execute(
-            "INSERT OR REPLACE INTO feeds(id, title, subscribed, feedUrl, htmlUrl)
-            VALUES (?, ?, ?, ?, ?)",
+            "INSERT INTO renames(feed_id, old_title, new_title, renamed_at) VALUES (?, ?, ?, ?)",
+            params![feed.id, previous_title, feed.title, run_at],
+        )?;
+    }
+    conn.execute(
+        "INSERT INTO feeds(
+            id, title, subscribed, feed_url, html_url, added_at, sort_order, raw_attributes
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            title = excluded.title,
+            subscribed = excluded.subscribed,
+            feed_url = excluded.feed_url,
+            html_url = excluded.html_url,
+            added_at = COALESCE(excluded.added_at, feeds.added_at),
+            sort_order = excluded.sort_order,
+            raw_attributes = excluded.raw_attributes",
+        params![
+            feed.id,
+            feed.title,
+            feed.subscribed,
+            feed.feed_url,
+            feed.html_url,
+            feed.added_at,
+            feed.sort_order,
+            feed.raw_attributes,
+        ],
+    )?;
+    for (name, value) in &feed.settings {
+        conn.execute(
+            "INSERT INTO feed_settings(feed_id, name, value, updated_at) VALUES (?, ?, ?, ?)
+            ON CONFLICT(feed_id, name) DO UPDATE SET
+                value = excluded.value,
+                updated_at = excluded.updated_at
+            WHERE value != excluded.value",
+            params![feed.id, name, value, run_at],
+        )?;
+    }
+    Ok(())
+}
+
+// Upserts a feed's episodes, recording play events, progress changes and new titles since the
+// last run.
+fn upsert_feed_episodes(
+    conn: &Connection,
+    feed: &Feed,
+    run_at: NaiveDateTime,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for episode in &feed.episodes {
+        let pruned: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pruned_episodes WHERE episode_id = ?)",
+            params![episode.id],
+            |row| row.get(0),
+        )?;
+        if pruned {
+            continue;
+        }
+        let previous = conn
+            .query_row(
+                "SELECT played, progress, title FROM episodes WHERE id = ?",
+                params![episode.id],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<bool>>(0)?,
+                        row.get::<_, Option<i64>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        conn.execute(
+            "INSERT INTO episodes(
+                id, title, played, feed_id, published_at, updated_at, html_url, overcast_url,
+                mp3_url, progress, user_deleted, starred, starred_at, raw_attributes
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                title = excluded.title,
+                played = excluded.played,
+                feed_id = excluded.feed_id,
+                published_at = excluded.published_at,
+                updated_at = excluded.updated_at,
+                html_url = excluded.html_url,
+                overcast_url = excluded.overcast_url,
+                mp3_url = excluded.mp3_url,
+                progress = excluded.progress,
+                user_deleted = excluded.user_deleted,
+                starred = excluded.starred,
+                starred_at = excluded.starred_at,
+                raw_attributes = excluded.raw_attributes",
             params![
+                episode.id,
+                episode.title,
+                episode.played,
                 feed.id,
-                feed.title,
-                feed.subscribed,
-                feed.feed_url,
-                feed.html_url,
+                episode.published_at,
+                episode.updated_at,
+                episode.html_url,
+                episode.overcast_url,
+                episode.mp3_url,
+                episode.progress,
+                episode.user_deleted,
+                episode.starred_at.is_some(),
+                episode.starred_at,
+                episode.raw_attributes,
             ],
         )?;
-        for episode in &feed.episodes {
+        let (previously_played, previous_progress, previous_title) =
+            previous.unwrap_or((None, None, None));
+        if let Some(previous_title) = previous_title.filter(|title| *title != episode.title) {
+            // An overridden title isn't one the episode had, so it's not a rename.
+            if !overrides::is_overridden(conn, "episodes", &episode.id, "title")? {
+                conn.execute(
+                    "INSERT INTO renames(episode_id, old_title, new_title, renamed_at)
+                    VALUES (?, ?, ?, ?)",
+                    params![episode.id, previous_title, episode.title, run_at],
+                )?;
+            }
+        }
+        if episode.played && previously_played == Some(false) {
+            conn.execute(
+                "INSERT INTO play_events(episode_id, played_at) VALUES (?, ?)",
+                params![episode.id, run_at],
+            )?;
+        }
+        if episode.progress.is_some() && episode.progress != previous_progress {
             conn.execute(
-                "INSERT OR REPLACE INTO episodes(
-                    id, title, played, feedId, publishedAt, updatedAt, htmlUrl, overcastUrl, mp3Url, progress, userDeleted
-                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-                params![
-                    episode.id,
-                    episode.title,
-                    episode.played,
-                    feed.id,
-                    episode.published_at,
-                    episode.updated_at,
-                    episode.html_url,
-                    episode.overcast_url,
-                    episode.mp3_url,
-                    episode.progress,
-                    episode.user_deleted,
-                ],
+                "INSERT OR REPLACE INTO progress_history(episode_id, run_at, progress)
+                VALUES (?, ?, ?)",
+                params![episode.id, run_at, episode.progress],
             )?;
         }
     }
     Ok(())
 }
+
+// Applies the played, deleted and progress states listed on overcast.fm's podcast pages to the
+// archived episodes with the same `overcast_url`, recording play events and progress changes as
+// `upsert_feeds` does. Progress comes from the time left, so it's only updated for episodes with a
+// known duration. Episodes that aren't archived yet are left for the next full export. Returns
+// how many episodes changed.
+pub fn apply_listed_episodes(
+    conn: &Connection,
+    listed: &[ListedEpisode],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let run_at = chrono::Utc::now().naive_utc();
+    let mut changed = 0;
+    for episode in listed {
+        let previous = conn
+            .query_row(
+                "SELECT e.id, e.played, e.user_deleted, e.progress, d.duration
+                FROM episodes e
+                LEFT JOIN episode_details d ON d.episode_id = e.id
+                WHERE e.overcast_url = ?",
+                params![episode.overcast_url],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, Option<bool>>(1)?.unwrap_or(false),
+                        row.get::<_, Option<bool>>(2)?.unwrap_or(false),
+                        row.get::<_, Option<i64>>(3)?,
+                        row.get::<_, Option<i64>>(4)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let (id, played, user_deleted, progress, duration) = match previous {
+            Some(previous) => previous,
+            None => continue,
+        };
+        let new_progress = match (episode.seconds_left, duration) {
+            (Some(left), Some(duration)) => Some((duration - left).max(0)),
+            _ => progress,
+        };
+        if (episode.played, episode.user_deleted, new_progress) == (played, user_deleted, progress)
+        {
+            continue;
+        }
+        changed += 1;
+        conn.execute(
+            "UPDATE episodes SET played = ?, user_deleted = ?, progress = ?, updated_at = ?
+            WHERE id = ?",
+            params![
+                episode.played,
+                episode.user_deleted,
+                new_progress,
+                run_at,
+                id
+            ],
+        )?;
+        if episode.played && !played {
+            conn.execute(
+                "INSERT INTO play_events(episode_id, played_at) VALUES (?, ?)",
+                params![id, run_at],
+            )?;
+        }
+        if new_progress.is_some() && new_progress != progress {
+            conn.execute(
+                "INSERT OR REPLACE INTO progress_history(episode_id, run_at, progress)
+                VALUES (?, ?, ?)",
+                params![id, run_at, new_progress],
+            )?;
+        }
+    }
+    overrides::apply(conn)?;
+    Ok(changed)
+}
+
+// Replaces the categories associated with a feed, creating any categories not seen before.
+pub fn upsert_feed_categories(
+    conn: &Connection,
+    feed_id: &str,
+    categories: &[Category],
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "DELETE FROM feed_categories WHERE feed_id = ?",
+        params![feed_id],
+    )?;
+    for category in categories {
+        let existing = conn
+            .query_row(
+                "SELECT id FROM categories WHERE name = ? AND parent IS ?",
+                params![category.name, category.parent],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+        let category_id = match existing {
+            Some(id) => id,
+            None => {
+                conn.execute(
+                    "INSERT INTO categories(name, parent) VALUES (?, ?)",
+                    params![category.name, category.parent],
+                )?;
+                conn.last_insert_rowid()
+            }
+        };
+        conn.execute(
+            "INSERT OR IGNORE INTO feed_categories(feed_id, category_id) VALUES (?, ?)",
+            params![feed_id, category_id],
+        )?;
+    }
+    Ok(())
+}
+
+// Replaces a feed's funding links and value recipients.
+pub fn upsert_feed_funding(
+    conn: &Connection,
+    feed_id: &str,
+    funding: &[Funding],
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute("DELETE FROM funding WHERE feed_id = ?", params![feed_id])?;
+    for (position, f) in funding.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO funding(feed_id, position, kind, method, url, name, split)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                feed_id,
+                position as i64,
+                f.kind,
+                f.method,
+                f.url,
+                f.name,
+                f.split
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+// Replaces the people credited on an episode. People are identified by name and profile URL, so
+// the same guest appearing on different shows maps to a single row.
+pub fn upsert_episode_people(
+    conn: &Connection,
+    episode_id: &str,
+    people: &[Person],
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "DELETE FROM episode_people WHERE episode_id = ?",
+        params![episode_id],
+    )?;
+    for person in people {
+        let existing = conn
+            .query_row(
+                "SELECT id FROM people WHERE name = ? AND href IS ?",
+                params![person.name, person.href],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+        let person_id = match existing {
+            Some(id) => {
+                if person.img.is_some() {
+                    conn.execute(
+                        "UPDATE people SET img = ? WHERE id = ?",
+                        params![person.img, id],
+                    )?;
+                }
+                id
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO people(name, href, img) VALUES (?, ?, ?)",
+                    params![person.name, person.href, person.img],
+                )?;
+                conn.last_insert_rowid()
+            }
+        };
+        conn.execute(
+            "INSERT OR IGNORE INTO episode_people(episode_id, person_id, role, person_group)
+            VALUES (?, ?, ?, ?)",
+            params![episode_id, person_id, person.role, person.group],
+        )?;
+    }
+    Ok(())
+}
+
+// Upserts the channel-level metadata for a feed.
+pub fn upsert_feed_details(
+    conn: &Connection,
+    feed_id: &str,
+    channel: &Channel,
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO feed_details(
+            feed_id, description, language, author, copyright, link, image_url, fetched_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(feed_id) DO UPDATE SET
+            description = excluded.description,
+            language = excluded.language,
+            author = excluded.author,
+            copyright = excluded.copyright,
+            link = excluded.link,
+            image_url = excluded.image_url,
+            fetched_at = excluded.fetched_at",
+        params![
+            feed_id,
+            channel.description,
+            channel.language,
+            channel.author,
+            channel.copyright,
+            channel.link,
+            channel.image_url,
+            chrono::Utc::now().naive_utc(),
+        ],
+    )?;
+    Ok(())
+}
+
+// Upserts the item-level metadata for an episode.
+pub fn upsert_episode_details(
+    conn: &Connection,
+    episode_id: &str,
+    item: &Item,
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO episode_details(
+            episode_id, duration, description, transcript_url, fetched_at
+        ) VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(episode_id) DO UPDATE SET
+            duration = excluded.duration,
+            description = excluded.description,
+            transcript_url = excluded.transcript_url,
+            fetched_at = excluded.fetched_at",
+        params![
+            episode_id,
+            item.duration,
+            item.description,
+            item.transcript_url,
+            chrono::Utc::now().naive_utc()
+        ],
+    )?;
+    Ok(())
+}
+
+// Updates the columns derived from episode URLs. `normalized_mp3_url` and `normalized_html_url` are
+// the URLs with tracking redirects and parameters stripped (see `urls::normalize`), which stay
+// the same between exports even when the raw URLs don't.
+//
+// This also links episodes that share the same audio, like an episode cross-posted to two feeds.
+// Each episode's `canonical_episode_id` is the ID of the earliest published episode with the same
+// enclosure (see `urls::enclosure_key`), which is its own ID if it isn't cross-posted.
+pub fn refresh_episode_urls(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, mp3_url, html_url, normalized_mp3_url, normalized_html_url, canonical_episode_id
+        FROM episodes
+        ORDER BY published_at IS NULL, published_at, id",
+    )?;
+    let episodes = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                (
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<i64>>(5)?,
+                ),
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut canonical_ids = std::collections::HashMap::new();
+    for (id, mp3_url, html_url, current) in episodes {
+        let canonical = match &mp3_url {
+            Some(url) => *canonical_ids.entry(urls::enclosure_key(url)).or_insert(id),
+            None => id,
+        };
+        let derived = (
+            mp3_url.as_deref().map(urls::normalize),
+            html_url.as_deref().map(urls::normalize),
+            Some(canonical),
+        );
+        if current != derived {
+            conn.execute(
+                "UPDATE episodes
+                SET normalized_mp3_url = ?, normalized_html_url = ?, canonical_episode_id = ?
+                WHERE id = ?",
+                params![derived.0, derived.1, derived.2, id],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Rebuilds the listening_time table from the current episode state. Played episodes count their
+// full duration, and partially played ones count their progress. Durations come from the
+// downloaded audio if there is any, and the feed otherwise. When the duration is unknown (the
+// feed hasn't been enriched), progress is the best available estimate. `listened_at` is the
+// episode's last update in Overcast, which is the closest thing the export has to a play date.
+// Cross-posted episodes are only counted once, for whichever copy was listened to the most. The
+// weekly and monthly summaries are rebuilt from it too, all at once, so readers never see them
+// empty or half-built.
+pub fn refresh_listening_time(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    atomically(conn, || rebuild_listening_time(conn))
+}
+
+fn rebuild_listening_time(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute("DELETE FROM listening_time", [])?;
+    conn.execute(
+        "INSERT INTO listening_time(episode_id, feed_id, seconds, duration_known, listened_at)
+        SELECT id, feed_id, seconds, duration_known, updated_at FROM (
+            SELECT *, ROW_NUMBER() OVER (
+                PARTITION BY canonicalId ORDER BY seconds DESC, id
+            ) AS copy
+            FROM (
+                SELECT
+                    e.id,
+                    COALESCE(e.canonical_episode_id, e.id) AS canonicalId,
+                    e.feed_id,
+                    CASE
+                        WHEN e.duration IS NULL THEN COALESCE(e.progress, 0)
+                        WHEN e.played THEN e.duration
+                        ELSE MIN(COALESCE(e.progress, 0), e.duration)
+                    END AS seconds,
+                    e.duration IS NOT NULL AS duration_known,
+                    e.updated_at
+                FROM (
+                    SELECT episodes.*, COALESCE(a.duration, d.duration) AS duration
+                    FROM episodes
+                    LEFT JOIN episode_details d ON d.episode_id = episodes.id
+                    LEFT JOIN audio_metadata a ON a.episode_id = episodes.id
+                ) e
+            )
+        )
+        WHERE seconds > 0 AND copy = 1",
+        [],
+    )?;
+    refresh_listening_summaries(conn)?;
+    Ok(())
+}
+
+// Rebuilds the per-feed weekly and monthly totals of listening_time, so dashboards can chart
+// them without aggregating every episode. Listens whose date SQLite can't read (like one edited by
+// hand) aren't counted in either.
+fn refresh_listening_summaries(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let periods = [
+        (
+            "listening_weekly",
+            "week_start",
+            "date(l.listened_at, 'weekday 0', '-6 days')",
+        ),
+        (
+            "listening_monthly",
+            "month",
+            "strftime('%Y-%m', l.listened_at)",
+        ),
+    ];
+    for (table, column, period) in periods.iter() {
+        conn.execute(&format!("DELETE FROM {}", table), [])?;
+        conn.execute(
+            &format!(
+                "INSERT INTO {table}({column}, feed_id, episodes_played, hours)
+                SELECT {period}, l.feed_id, COALESCE(SUM(e.played), 0), SUM(l.seconds) / 3600.0
+                FROM listening_time l
+                JOIN episodes e ON e.id = l.episode_id
+                WHERE {period} IS NOT NULL
+                GROUP BY 1, 2",
+                table = table,
+                column = column,
+                period = period
+            ),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pragma(conn: &Connection, name: &str) -> i64 {
+        conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn pragmas() {
+        let parsed: Pragma = " cache_size = -2000 ".parse().unwrap();
+        assert_eq!((parsed.name.as_str(), parsed.value.as_str()), ("cache_size", "-2000"));
+        for invalid in ["cache_size", "=1", "cache_size=", "a=1; DROP TABLE feeds", "a b=1"] {
+            assert!(invalid.parse::<Pragma>().is_err(), "{}", invalid);
+        }
+        assert_eq!(Pragma::key("it's").value, "'it''s'");
+    }
+
+    #[test]
+    fn pragmas_override_defaults() {
+        let conn = Connection::open_in_memory().unwrap();
+        let pragmas = vec!["Synchronous=OFF".parse().unwrap()];
+        set_pragmas(&conn, &pragmas, &DEFAULT_PRAGMAS).unwrap();
+        assert_eq!(pragma(&conn, "synchronous"), 0);
+        assert_eq!(pragma(&conn, "busy_timeout"), 5000);
+        assert_eq!(pragma(&conn, "foreign_keys"), 1);
+    }
+
+    // An in-memory archive as the first version created it, with a feed and two episodes.
+    fn original_archive() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        set_pragmas(&conn, &[], &DEFAULT_PRAGMAS).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE feeds (
+                id INTEGER PRIMARY KEY,
+                title TEXT,
+                subscribed BOOLEAN,
+                feedUrl TEXT,
+                htmlUrl TEXT
+            );
+            CREATE TABLE episodes (
+                id INTEGER PRIMARY KEY,
+                title TEXT,
+                played BOOLEAN,
+                feedId INTEGER NOT NULL,
+                publishedAt TEXT,
+                updatedAt TEXT,
+                htmlUrl TEXT,
+                overcastUrl TEXT,
+                mp3Url TEXT,
+                progress INTEGER,
+                userDeleted BOOLEAN,
+                FOREIGN KEY(feedId) REFERENCES feeds(id)
+            );
+            INSERT INTO feeds VALUES (1, 'A Show', 1, 'https://example.com/feed', NULL);
+            INSERT INTO episodes VALUES
+                (10, 'One', 1, 1, '2020-06-01 12:00:00', NULL, NULL, NULL, NULL, 0, 0),
+                (11, 'Two', 0, 1, '2020-12-01 12:00:00', NULL, NULL, NULL, NULL, 60, 0);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn migrated_foreign_keys_cascade() {
+        let conn = original_archive();
+        create_tables(&conn).unwrap();
+        assert_eq!(pragma(&conn, "user_version"), SCHEMA_VERSION);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM episodes"), 2);
+        conn.execute("DELETE FROM feeds WHERE id = 1", []).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM episodes"), 0);
+    }
+
+    #[test]
+    fn migrated_tables_check_types() {
+        let conn = original_archive();
+        create_tables(&conn).unwrap();
+        let played: bool = conn
+            .query_row("SELECT played FROM episodes WHERE id = 10", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(played);
+        assert!(conn
+            .execute("UPDATE episodes SET played = 2 WHERE id = 10", [])
+            .is_err());
+        if !table_options().is_empty() {
+            assert!(conn
+                .execute("UPDATE episodes SET progress = 'half' WHERE id = 10", [])
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn column_names() {
+        assert_eq!(snake_case("publishedAt"), "published_at");
+        assert_eq!(snake_case("title"), "title");
+        assert_eq!(legacy_column_name("normalized_mp3_url"), "normalizedMp3Url");
+        assert_eq!(legacy_column_name(&snake_case("userDeleted")), "userDeleted");
+    }
+
+    #[test]
+    fn migrated_columns_are_snake_case() {
+        let conn = original_archive();
+        conn.execute_batch("ALTER TABLE episodes ADD COLUMN myNote TEXT")
+            .unwrap();
+        create_tables(&conn).unwrap();
+        let columns = table_columns(&conn, "episodes").unwrap();
+        for renamed in ["feed_id", "published_at", "user_deleted", "mp3_url"] {
+            assert!(columns.iter().any(|c| c == renamed), "{}", renamed);
+        }
+        assert!(!columns.iter().any(|c| c == "feedId"));
+        // Not part of the schema, so not renamed.
+        assert!(columns.iter().any(|c| c == "myNote"));
+        assert_eq!(
+            count(&conn, "SELECT progress FROM episodes WHERE feed_id = 1 AND id = 11"),
+            60
+        );
+
+        create_legacy_views(&conn).unwrap();
+        assert_eq!(
+            count(&conn, "SELECT progress FROM legacy_episodes WHERE feedId = 1 AND id = 11"),
+            60
+        );
+    }
+
+    // An empty in-memory archive with the current schema.
+    fn archive() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        set_pragmas(&conn, &[], &DEFAULT_PRAGMAS).unwrap();
+        create_tables(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn listening_summaries_skip_unreadable_dates() {
+        let conn = archive();
+        conn.execute_batch(
+            "INSERT INTO feeds(id, title) VALUES (1, 'A Show');
+            INSERT INTO episodes(id, feed_id, played, progress, updated_at) VALUES
+                (10, 1, 0, 600, '2021-03-03 12:00:00'),
+                (11, 1, 0, 300, 'sometime');",
+        )
+        .unwrap();
+        refresh_listening_time(&conn).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM listening_time"), 2);
+        let weeks = conn
+            .prepare("SELECT week_start, hours FROM listening_weekly")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<Vec<(String, f64)>, _>>()
+            .unwrap();
+        assert_eq!(weeks, vec![("2021-03-01".to_string(), 600.0 / 3600.0)]);
+    }
+
+    #[test]
+    fn migrated_dates_are_utc() {
+        let conn = original_archive();
+        conn.execute_batch("UPDATE episodes SET updatedAt = 'sometime' WHERE id = 11")
+            .unwrap();
+        create_tables(&conn).unwrap();
+        let published = |id: i64| -> Option<NaiveDateTime> {
+            conn.query_row(
+                "SELECT published_at FROM episodes WHERE id = ?",
+                params![id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        let utc = |local: &str| {
+            let local = NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M:%S").unwrap();
+            chrono::Local
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|local| local.naive_utc())
+        };
+        assert_eq!(published(10), utc("2020-06-01 12:00:00"));
+        assert_eq!(published(11), utc("2020-12-01 12:00:00"));
+        let updated: String = conn
+            .query_row("SELECT updated_at FROM episodes WHERE id = 11", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(updated, "sometime");
+
+        // Nothing is converted twice.
+        create_tables(&conn).unwrap();
+        assert_eq!(published(10), utc("2020-06-01 12:00:00"));
+    }
+
+    #[test]
+    fn scoped_to_an_account() {
+        let conn = archive();
+        let mine = upsert_account(&conn, "mine", None).unwrap();
+        let theirs = upsert_account(&conn, "theirs", Some("them@example.com")).unwrap();
+        conn.execute_batch(&format!(
+            "INSERT INTO feeds(id, title, account_id) VALUES (1, 'Mine', {}), (2, 'Theirs', {});
+            INSERT INTO episodes(id, feed_id, progress, account_id, updated_at) VALUES
+                (10, 1, 60, {0}, '2021-03-03 12:00:00'),
+                (20, 2, 60, {1}, '2021-03-03 12:00:00');",
+            mine, theirs
+        ))
+        .unwrap();
+        refresh_listening_time(&conn).unwrap();
+        scope_to_account(&conn, "mine").unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM feeds"), 1);
+        assert_eq!(count(&conn, "SELECT MAX(id) FROM episodes"), 10);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM listening_time"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM listening_weekly"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM main.feeds"), 2);
+        assert!(scope_to_account(&conn, "nobody").is_err());
+    }
+
+    #[test]
+    fn tombstoned_episodes_keep_what_refers_to_them() {
+        let conn = archive();
+        conn.execute_batch(
+            "INSERT INTO feeds(id, title) VALUES (1, 'A Show');
+            INSERT INTO episodes(id, feed_id, title) VALUES (10, 1, 'One'), (11, 1, 'Two');
+            INSERT INTO episode_details(episode_id, description) VALUES (10, 'About one');",
+        )
+        .unwrap();
+        assert_eq!(tombstone_episodes(&conn, "id = ?", &[&10]).unwrap(), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM episodes"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM episode_details"), 0);
+        let deleted_description: String = conn
+            .query_row("SELECT description FROM episode_details_deleted", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(deleted_description, "About one");
+        assert_eq!(
+            count(&conn, "SELECT id FROM episodes_deleted WHERE deleted_at IS NOT NULL"),
+            10
+        );
+
+        // Columns added since the tombstone table was created are added to it too.
+        conn.execute_batch(
+            "ALTER TABLE episodes ADD COLUMN rating INTEGER;
+            UPDATE episodes SET rating = 5;",
+        )
+        .unwrap();
+        assert_eq!(tombstone(&conn, "episodes", "id = ?", &[&11]).unwrap(), 1);
+        assert_eq!(count(&conn, "SELECT rating FROM episodes_deleted WHERE id = 11"), 5);
+    }
+}
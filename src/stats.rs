@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use rusqlite::Connection;
+
+use crate::chart;
+use crate::report::format_seconds;
+use crate::timezone::DisplayTimezone;
+
+// The charts `stats --chart` can render.
+pub enum ChartKind {
+    ListeningPerWeek,
+    EpisodesPerMonth,
+    FeedTotals,
+    BacklogGrowth,
+}
+
+impl FromStr for ChartKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "listening-per-week" => Ok(ChartKind::ListeningPerWeek),
+            "episodes-per-month" => Ok(ChartKind::EpisodesPerMonth),
+            "feed-totals" => Ok(ChartKind::FeedTotals),
+            "backlog-growth" => Ok(ChartKind::BacklogGrowth),
+            _ => Err(format!(
+                "unknown chart \"{}\" (expected listening-per-week, episodes-per-month, feed-totals or backlog-growth)",
+                s
+            )),
+        }
+    }
+}
+
+// Prints a short summary of the archive.
+pub fn print_summary(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let (feeds, subscribed): (i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(subscribed), 0) FROM feeds",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    // Cross-posted episodes are counted once.
+    let (episodes, played, starred): (i64, i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(played), 0), COALESCE(SUM(starred), 0) FROM (
+            SELECT MAX(played) AS played, MAX(starred) AS starred
+            FROM episodes
+            GROUP BY COALESCE(canonical_episode_id, id)
+        )",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let cross_posted: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM episodes WHERE canonical_episode_id != id",
+        [],
+        |row| row.get(0),
+    )?;
+    let seconds: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(seconds), 0) FROM listening_time",
+        [],
+        |row| row.get(0),
+    )?;
+    println!("Feeds:     {} ({} subscribed)", feeds, subscribed);
+    println!(
+        "Episodes:  {} ({} played, {} starred, {} more cross-posted)",
+        episodes, played, starred, cross_posted
+    );
+    println!("Listened:  {}", format_seconds(seconds));
+    Ok(())
+}
+
+// Streaks and habits, from when episodes were played.
+pub struct Habits {
+    // Consecutive days, up to today or yesterday, with at least one episode played.
+    pub current_streak: i64,
+    // The longest run of such days, and the day it started.
+    pub longest_streak: Option<(i64, NaiveDate)>,
+    // The average number of episodes played on each weekday, Monday first.
+    pub per_weekday: [f64; 7],
+    // Episodes played in each hour of the day.
+    pub per_hour: [i64; 24],
+}
+
+// Works out listening habits in the given timezone. Days come from play events (when the archive
+// saw an episode become played), falling back to Overcast's last update; times of day only from
+// Overcast's updates, since play events are dated by when the archive ran.
+pub fn habits(
+    conn: &Connection,
+    tz: &DisplayTimezone,
+) -> Result<Habits, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT MIN(COALESCE(
+            (SELECT MIN(played_at) FROM play_events WHERE episode_id = e.id),
+            e.updated_at
+        )), MIN(e.updated_at)
+        FROM episodes e
+        WHERE e.played
+        GROUP BY COALESCE(e.canonical_episode_id, e.id)",
+    )?;
+    let plays = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, Option<NaiveDateTime>>(0)?,
+                row.get::<_, Option<NaiveDateTime>>(1)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut days: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+    let mut per_hour = [0; 24];
+    for (played_at, updated_at) in &plays {
+        if let Some(played_at) = played_at {
+            *days.entry(tz.local(played_at).date()).or_insert(0) += 1;
+        }
+        if let Some(updated_at) = updated_at {
+            per_hour[tz.local(updated_at).hour() as usize] += 1;
+        }
+    }
+
+    let mut longest_streak: Option<(i64, NaiveDate)> = None;
+    let mut streak: Option<(i64, NaiveDate, NaiveDate)> = None;
+    for day in days.keys() {
+        streak = match streak {
+            Some((length, start, last)) if last.succ_opt() == Some(*day) => {
+                Some((length + 1, start, *day))
+            }
+            _ => Some((1, *day, *day)),
+        };
+        if let Some((length, start, _)) = streak {
+            if longest_streak.map_or(0, |(longest, _)| longest) < length {
+                longest_streak = Some((length, start));
+            }
+        }
+    }
+    let today = tz.local(&chrono::Utc::now().naive_utc()).date();
+    let current_streak = match streak {
+        Some((length, _, last)) if last == today || last.succ_opt() == Some(today) => length,
+        _ => 0,
+    };
+
+    // Each weekday's plays are averaged over how many of that weekday the history spans.
+    let mut per_weekday = [0.0; 7];
+    if let (Some(first), Some(last)) = (days.keys().next(), days.keys().next_back()) {
+        let mut weekdays = [0; 7];
+        let mut day = *first;
+        while day <= *last {
+            weekdays[day.weekday().num_days_from_monday() as usize] += 1;
+            day = day.succ_opt().unwrap();
+        }
+        for (day, count) in &days {
+            per_weekday[day.weekday().num_days_from_monday() as usize] += *count as f64;
+        }
+        for (average, weekdays) in per_weekday.iter_mut().zip(weekdays.iter()) {
+            if *weekdays > 0 {
+                *average /= *weekdays as f64;
+            }
+        }
+    }
+    Ok(Habits {
+        current_streak,
+        longest_streak,
+        per_weekday,
+        per_hour,
+    })
+}
+
+// Prints streaks, the average episodes played per weekday, and the hours episodes get finished.
+pub fn print_habits(
+    conn: &Connection,
+    tz: &DisplayTimezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let habits = habits(conn, tz)?;
+    println!("Current streak:  {} days", habits.current_streak);
+    match habits.longest_streak {
+        Some((length, start)) => println!(
+            "Longest streak:  {} days, from {}",
+            length,
+            start.format("%Y-%m-%d")
+        ),
+        None => println!("Longest streak:  none yet"),
+    }
+    println!("\nEpisodes per weekday:");
+    for (name, average) in WEEKDAYS.iter().zip(habits.per_weekday.iter()) {
+        println!("  {:<10} {:.1}", name, average);
+    }
+    let total: i64 = habits.per_hour.iter().sum();
+    if total > 0 {
+        println!("\nFinished by time of day:");
+        for (name, hours) in TIMES_OF_DAY.iter() {
+            let count: i64 = hours.clone().map(|h| habits.per_hour[h]).sum();
+            println!(
+                "  {:<10} {:>3.0}%",
+                name,
+                100.0 * count as f64 / total as f64
+            );
+        }
+    }
+    Ok(())
+}
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+const TIMES_OF_DAY: [(&str, std::ops::Range<usize>); 4] = [
+    ("Night", 0..6),
+    ("Morning", 6..12),
+    ("Afternoon", 12..18),
+    ("Evening", 18..24),
+];
+
+// A subscribed feed's release schedule, from `feed_cadence`.
+pub struct Upcoming {
+    pub feed_title: Option<String>,
+    pub interval_seconds: i64,
+    pub last_published_at: NaiveDateTime,
+    pub next_expected_at: NaiveDateTime,
+    pub on_hiatus: bool,
+}
+
+// Subscribed feeds' cadences, the soonest expected first. They're as of the last `archive` run.
+pub fn upcoming(conn: &Connection) -> Result<Vec<Upcoming>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT f.title, c.interval_seconds, c.last_published_at, c.next_expected_at, c.on_hiatus
+        FROM feed_cadence c
+        JOIN feeds f ON f.id = c.feed_id
+        WHERE f.subscribed
+        ORDER BY c.next_expected_at, f.title COLLATE NOCASE",
+    )?;
+    let upcoming = stmt
+        .query_map([], |row| {
+            Ok(Upcoming {
+                feed_title: row.get(0)?,
+                interval_seconds: row.get(1)?,
+                last_published_at: row.get(2)?,
+                next_expected_at: row.get(3)?,
+                on_hiatus: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(upcoming)
+}
+
+// "daily", "weekly", "every 3 days" and so on, for a gap between episodes.
+fn describe_interval(seconds: i64) -> String {
+    let hours = (seconds as f64 / 3600.0).round() as i64;
+    let days = (seconds as f64 / 86400.0).round() as i64;
+    match days {
+        0 => format!("every {} hours", hours.max(1)),
+        1 => "daily".to_string(),
+        6..=8 => "weekly".to_string(),
+        13..=15 => "every 2 weeks".to_string(),
+        28..=31 => "monthly".to_string(),
+        _ => format!("every {} days", days),
+    }
+}
+
+// Prints when each subscribed feed's next episode is likely, and the feeds that look to be on
+// hiatus.
+pub fn print_upcoming(
+    conn: &Connection,
+    tz: &DisplayTimezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let upcoming = upcoming(conn)?;
+    if upcoming.is_empty() {
+        println!("No release schedules yet; `archive` works them out from episodes' dates.");
+        return Ok(());
+    }
+    let now = chrono::Utc::now().naive_utc();
+    println!("Next episodes:");
+    for feed in upcoming.iter().filter(|feed| !feed.on_hiatus) {
+        println!(
+            "  {}  {} ({}){}",
+            tz.format(&feed.next_expected_at, "%a %Y-%m-%d"),
+            feed.feed_title.as_deref().unwrap_or("Untitled feed"),
+            describe_interval(feed.interval_seconds),
+            if feed.next_expected_at < now {
+                ", overdue"
+            } else {
+                ""
+            }
+        );
+    }
+    let mut hiatus: Vec<&Upcoming> = upcoming.iter().filter(|feed| feed.on_hiatus).collect();
+    if !hiatus.is_empty() {
+        hiatus.sort_by_key(|feed| std::cmp::Reverse(feed.last_published_at));
+        println!("\nOn hiatus:");
+        for feed in hiatus {
+            println!(
+                "  {}  (last episode {}, usually {})",
+                feed.feed_title.as_deref().unwrap_or("Untitled feed"),
+                tz.format(&feed.last_published_at, "%Y-%m-%d"),
+                describe_interval(feed.interval_seconds)
+            );
+        }
+    }
+    Ok(())
+}
+
+// Renders the given chart as an SVG document, grouping by weeks and months in the given timezone.
+pub fn render_chart(
+    conn: &Connection,
+    kind: &ChartKind,
+    tz: &DisplayTimezone,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let tz = tz.sql_modifier();
+    let svg = match kind {
+        ChartKind::ListeningPerWeek => chart::bar_chart(
+            &query_series(
+                conn,
+                &format!(
+                    "SELECT * FROM (
+                        SELECT strftime('%Y-W%W', listened_at, '{}') AS week, SUM(seconds) / 3600.0
+                        FROM listening_time
+                        WHERE listened_at IS NOT NULL
+                        GROUP BY week
+                        ORDER BY week DESC
+                        LIMIT 52
+                    ) ORDER BY week",
+                    tz
+                ),
+            )?,
+            format_hours,
+        ),
+        ChartKind::EpisodesPerMonth => chart::bar_chart(
+            &query_series(
+                conn,
+                &format!(
+                    "SELECT * FROM (
+                        SELECT strftime('%Y-%m', played_at, '{}') AS month, COUNT(*) * 1.0
+                        FROM (
+                            SELECT MIN(COALESCE(
+                                (SELECT MIN(played_at) FROM play_events WHERE episode_id = e.id),
+                                e.updated_at
+                            )) AS played_at
+                            FROM episodes e
+                            WHERE e.played
+                            GROUP BY COALESCE(e.canonical_episode_id, e.id)
+                        )
+                        WHERE played_at IS NOT NULL
+                        GROUP BY month
+                        ORDER BY month DESC
+                        LIMIT 36
+                    ) ORDER BY month",
+                    tz
+                ),
+            )?,
+            format_count,
+        ),
+        ChartKind::FeedTotals => chart::bar_chart(
+            &query_series(
+                conn,
+                "SELECT f.title, SUM(l.seconds) / 3600.0 AS hours
+                FROM listening_time l
+                JOIN feeds f ON f.id = l.feed_id
+                GROUP BY f.id
+                ORDER BY hours DESC
+                LIMIT 20",
+            )?,
+            format_hours,
+        ),
+        ChartKind::BacklogGrowth => chart::bar_chart(&backlog_growth(conn, &tz)?, format_count),
+    };
+    Ok(svg)
+}
+
+// The number of unplayed, undeleted episodes at the end of each month. An episode joins the
+// backlog when it's published and leaves it when it's played or deleted, which the export only
+// dates via its last update.
+fn backlog_growth(
+    conn: &Connection,
+    tz: &str,
+) -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
+    let mut deltas: BTreeMap<String, i64> = BTreeMap::new();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT strftime('%Y-%m', published_at, '{tz}'),
+            CASE WHEN played OR user_deleted THEN strftime('%Y-%m', updated_at, '{tz}') END
+        FROM episodes
+        WHERE published_at IS NOT NULL",
+        tz = tz
+    ))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let published: String = row.get(0)?;
+        let removed: Option<String> = row.get(1)?;
+        *deltas.entry(published).or_insert(0) += 1;
+        if let Some(removed) = removed {
+            *deltas.entry(removed).or_insert(0) -= 1;
+        }
+    }
+    let mut backlog = 0;
+    let series: Vec<(String, f64)> = deltas
+        .into_iter()
+        .map(|(month, delta)| {
+            backlog += delta;
+            (month, backlog.max(0) as f64)
+        })
+        .collect();
+    let skip = series.len().saturating_sub(36);
+    Ok(series.into_iter().skip(skip).collect())
+}
+
+fn query_series(
+    conn: &Connection,
+    sql: &str,
+) -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(sql)?;
+    let series = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(series)
+}
+
+fn format_hours(hours: f64) -> String {
+    format!("{:.1}h", hours)
+}
+
+fn format_count(count: f64) -> String {
+    format!("{}", count.round() as i64)
+}
@@ -0,0 +1,224 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Where downloaded files are kept: a local directory or, with the `s3` feature, an S3-compatible
+// object store given as `s3://bucket/prefix`. Keys are `/`-separated paths relative to the
+// directory or prefix.
+pub enum Storage {
+    Local(PathBuf),
+    #[cfg(feature = "s3")]
+    Remote(remote::Remote),
+}
+
+impl Storage {
+    pub fn open(location: &str) -> Result<Storage, Box<dyn std::error::Error>> {
+        if location.starts_with("s3://") {
+            #[cfg(feature = "s3")]
+            return Ok(Storage::Remote(remote::Remote::open(location)?));
+            #[cfg(not(feature = "s3"))]
+            return Err("S3 storage needs a build with the `s3` feature".into());
+        }
+        Ok(Storage::Local(PathBuf::from(location)))
+    }
+
+    // The URL recorded for a stored key, or None for local files.
+    #[cfg_attr(not(feature = "s3"), allow(unused_variables))]
+    pub fn remote_url(&self, key: &str) -> Option<String> {
+        match self {
+            Storage::Local(_) => None,
+            #[cfg(feature = "s3")]
+            Storage::Remote(remote) => Some(remote.url(key)),
+        }
+    }
+
+    pub fn exists(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        match self {
+            Storage::Local(dir) => Ok(dir.join(key).exists()),
+            #[cfg(feature = "s3")]
+            Storage::Remote(remote) => remote.exists(key),
+        }
+    }
+
+    // Where to write a file before it's stored. For local storage this is its final location;
+    // remote files are written to a temporary directory and uploaded by `store`.
+    pub fn staging_path(&self, key: &str) -> PathBuf {
+        match self {
+            Storage::Local(dir) => dir.join(key),
+            #[cfg(feature = "s3")]
+            Storage::Remote(_) => std::env::temp_dir().join("overcast-to-sqlite").join(key),
+        }
+    }
+
+    // Stores the file at `staging_path(key)`.
+    #[cfg_attr(not(feature = "s3"), allow(unused_variables))]
+    pub fn store(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Storage::Local(_) => Ok(()),
+            #[cfg(feature = "s3")]
+            Storage::Remote(remote) => {
+                let staged = self.staging_path(key);
+                remote.upload(key, &staged)?;
+                std::fs::remove_file(staged)?;
+                Ok(())
+            }
+        }
+    }
+
+    // Deletes a stored file, if it exists.
+    pub fn remove(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Storage::Local(dir) => {
+                let path = dir.join(key);
+                if path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "s3")]
+            Storage::Remote(remote) => remote.remove(key),
+        }
+    }
+
+    // The hex-encoded SHA-256 of a stored file, or None if it's missing.
+    pub fn sha256(&self, key: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        match self {
+            Storage::Local(dir) => {
+                let path = dir.join(key);
+                if !path.exists() {
+                    return Ok(None);
+                }
+                Ok(Some(sha256_file(&path)?))
+            }
+            #[cfg(feature = "s3")]
+            Storage::Remote(remote) => remote.sha256(key),
+        }
+    }
+}
+
+// The hex-encoded SHA-256 of a file's contents.
+pub fn sha256_file(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    let mut hasher = openssl::sha::Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finish()))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(feature = "s3")]
+mod remote {
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+
+    use object_store::aws::AmazonS3Builder;
+    use object_store::path::Path as ObjectPath;
+    use object_store::{ObjectStore, WriteMultipart};
+
+    // Files are uploaded and hashed in parts of this size, so they're never held in memory whole.
+    const PART_BYTES: usize = 8 * 1024 * 1024;
+
+    // An S3 bucket, configured from the usual `AWS_*` environment variables. For MinIO and other
+    // S3-compatible stores, set `AWS_ENDPOINT` (and `AWS_ALLOW_HTTP=true` for plain HTTP).
+    pub struct Remote {
+        store: Box<dyn ObjectStore>,
+        bucket: String,
+        prefix: String,
+        // object_store is async; everything else here is blocking.
+        runtime: tokio::runtime::Runtime,
+    }
+
+    impl Remote {
+        pub fn open(location: &str) -> Result<Remote, Box<dyn std::error::Error>> {
+            let url = reqwest::Url::parse(location)?;
+            let bucket = url.host_str().ok_or("S3 URL has no bucket")?.to_string();
+            let store = AmazonS3Builder::from_env()
+                .with_bucket_name(&bucket)
+                .build()?;
+            Ok(Remote {
+                store: Box::new(store),
+                bucket,
+                prefix: url.path().trim_matches('/').to_string(),
+                runtime: tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?,
+            })
+        }
+
+        fn path(&self, key: &str) -> ObjectPath {
+            if self.prefix.is_empty() {
+                ObjectPath::from(key)
+            } else {
+                ObjectPath::from(format!("{}/{}", self.prefix, key))
+            }
+        }
+
+        pub fn url(&self, key: &str) -> String {
+            format!("s3://{}/{}", self.bucket, self.path(key))
+        }
+
+        pub fn exists(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+            match self.runtime.block_on(self.store.head(&self.path(key))) {
+                Ok(_) => Ok(true),
+                Err(object_store::Error::NotFound { .. }) => Ok(false),
+                Err(err) => Err(err.into()),
+            }
+        }
+
+        pub fn upload(&self, key: &str, file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+            let mut file = File::open(file)?;
+            self.runtime.block_on(async {
+                let upload = self.store.put_multipart(&self.path(key)).await?;
+                let mut writer = WriteMultipart::new_with_chunk_size(upload, PART_BYTES);
+                let mut buffer = vec![0; PART_BYTES];
+                loop {
+                    let read = file.read(&mut buffer)?;
+                    if read == 0 {
+                        break;
+                    }
+                    writer.wait_for_capacity(4).await?;
+                    writer.write(&buffer[..read]);
+                }
+                writer.finish().await?;
+                Ok::<_, Box<dyn std::error::Error>>(())
+            })
+        }
+
+        pub fn remove(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+            match self.runtime.block_on(self.store.delete(&self.path(key))) {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+                Err(err) => Err(err.into()),
+            }
+        }
+
+        pub fn sha256(&self, key: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+            let path = self.path(key);
+            let size = match self.runtime.block_on(self.store.head(&path)) {
+                Ok(meta) => meta.size,
+                Err(object_store::Error::NotFound { .. }) => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            let mut hasher = openssl::sha::Sha256::new();
+            let mut start = 0;
+            while start < size {
+                let end = (start + PART_BYTES).min(size);
+                let part = self
+                    .runtime
+                    .block_on(self.store.get_range(&path, start..end))?;
+                hasher.update(&part);
+                start = end;
+            }
+            Ok(Some(super::hex(&hasher.finish())))
+        }
+    }
+}
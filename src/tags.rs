@@ -0,0 +1,118 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+// What a tag is attached to.
+pub enum Target {
+    Feed(i64),
+    Episode(i64),
+}
+
+impl Target {
+    // The taggings column that refers to the target, and the table it refers to.
+    fn column(&self) -> (&'static str, &'static str, i64) {
+        match self {
+            Target::Feed(id) => ("feed_id", "feeds", *id),
+            Target::Episode(id) => ("episode_id", "episodes", *id),
+        }
+    }
+}
+
+// How many feeds and episodes have a tag.
+pub struct TagCount {
+    pub name: String,
+    pub feeds: i64,
+    pub episodes: i64,
+}
+
+// Tags a feed or episode, creating the tag if it's new. Returns false if it was already tagged.
+pub fn add(
+    conn: &Connection,
+    target: &Target,
+    name: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let (column, table, id) = target.column();
+    let exists = conn
+        .query_row(
+            &format!("SELECT 1 FROM {} WHERE id = ?", table),
+            params![id],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+    if exists.is_none() {
+        return Err(format!("No row with ID {} in {}.", id, table).into());
+    }
+    conn.execute("INSERT OR IGNORE INTO tags(name) VALUES (?)", params![name])?;
+    let added = conn.execute(
+        &format!(
+            "INSERT INTO taggings(tag_id, {column}, created_at)
+            SELECT t.id, ?2, ?3 FROM tags t
+            WHERE t.name = ?1 AND NOT EXISTS (
+                SELECT 1 FROM taggings WHERE tag_id = t.id AND {column} = ?2
+            )",
+            column = column
+        ),
+        params![name, id, chrono::Utc::now().naive_utc()],
+    )?;
+    Ok(added > 0)
+}
+
+// Removes a tag from a feed or episode, deleting the tag once nothing has it. Returns false if it
+// wasn't tagged.
+pub fn remove(
+    conn: &Connection,
+    target: &Target,
+    name: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let (column, _, id) = target.column();
+    let removed = conn.execute(
+        &format!(
+            "DELETE FROM taggings
+            WHERE {} = ? AND tag_id = (SELECT id FROM tags WHERE name = ?)",
+            column
+        ),
+        params![id, name],
+    )?;
+    conn.execute(
+        "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM taggings)",
+        [],
+    )?;
+    Ok(removed > 0)
+}
+
+// The tags on a feed or episode, alphabetically.
+pub fn tags_of(
+    conn: &Connection,
+    target: &Target,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let (column, _, id) = target.column();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT t.name FROM taggings g JOIN tags t ON t.id = g.tag_id
+        WHERE g.{} = ?
+        ORDER BY t.name",
+        column
+    ))?;
+    let names = stmt
+        .query_map(params![id], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(names)
+}
+
+// Every tag, alphabetically, with how many feeds and episodes have it.
+pub fn counts(conn: &Connection) -> Result<Vec<TagCount>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name, COUNT(g.feed_id), COUNT(g.episode_id)
+        FROM tags t
+        LEFT JOIN taggings g ON g.tag_id = t.id
+        GROUP BY t.id
+        ORDER BY t.name",
+    )?;
+    let counts = stmt
+        .query_map([], |row| {
+            Ok(TagCount {
+                name: row.get(0)?,
+                feeds: row.get(1)?,
+                episodes: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(counts)
+}
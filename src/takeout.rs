@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+use serde_json::json;
+
+use crate::storage::{hex, sha256_file};
+use crate::zip::ZipWriter;
+
+// Counts of what went into a takeout.
+#[derive(Default)]
+pub struct TakeoutCounts {
+    pub opml_snapshots: usize,
+    pub assets: usize,
+    // Assets compressed by `sqlite3 -A`, which are left in the database copy only.
+    pub skipped: usize,
+}
+
+// Packages the archive into one zip file at `output`: a consistent copy of the database (taken
+// with VACUUM INTO, so a run can keep writing meanwhile), the OPML snapshots and other assets
+// from `sqlar` extracted as files ("opml/..." and "assets/artwork/...", say), and a
+// `manifest.json` listing every file with its size and SHA-256.
+pub fn takeout(
+    conn: &Connection,
+    db_name: &str,
+    output: &Path,
+) -> Result<TakeoutCounts, Box<dyn std::error::Error>> {
+    let copy = output.with_extension("db.partial");
+    if copy.exists() {
+        std::fs::remove_file(&copy)?;
+    }
+    conn.execute("VACUUM INTO ?", params![copy.to_string_lossy()])?;
+    let result = write_zip(conn, db_name, &copy, output);
+    std::fs::remove_file(&copy)?;
+    result
+}
+
+fn write_zip(
+    conn: &Connection,
+    db_name: &str,
+    copy: &Path,
+    output: &Path,
+) -> Result<TakeoutCounts, Box<dyn std::error::Error>> {
+    let mut zip = ZipWriter::create(output)?;
+    let mut counts = TakeoutCounts::default();
+    let mut files = vec![json!({
+        "name": db_name,
+        "bytes": std::fs::metadata(copy)?.len(),
+        "sha256": sha256_file(copy)?,
+    })];
+    zip.add_file(db_name, copy)?;
+
+    let mut stmt = conn.prepare("SELECT name, sz, data FROM sqlar ORDER BY name")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let size: Option<i64> = row.get(1)?;
+        let data: Vec<u8> = row.get::<_, Option<Vec<u8>>>(2)?.unwrap_or_default();
+        if size != Some(data.len() as i64) {
+            counts.skipped += 1;
+            continue;
+        }
+        let path = if name.starts_with("opml/") {
+            counts.opml_snapshots += 1;
+            name
+        } else {
+            counts.assets += 1;
+            format!("assets/{}", name)
+        };
+        files.push(json!({
+            "name": path,
+            "bytes": data.len(),
+            "sha256": hex(&openssl::sha::sha256(&data)),
+        }));
+        zip.add_bytes(&path, &data)?;
+    }
+
+    let manifest = json!({
+        "software": format!("overcast-to-sqlite/{}", env!("CARGO_PKG_VERSION")),
+        "createdAt": chrono::Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string(),
+        "files": files,
+    });
+    zip.add_bytes("manifest.json", &serde_json::to_vec_pretty(&manifest)?)?;
+    zip.finish()?;
+    Ok(counts)
+}
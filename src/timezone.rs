@@ -0,0 +1,88 @@
+use std::str::FromStr;
+
+use chrono::{FixedOffset, NaiveDateTime, TimeZone};
+
+// The timezone to show timestamps in. The archive stores every timestamp in UTC; this only
+// affects output, like the dates in reports and the months and weeks charts group by.
+pub enum DisplayTimezone {
+    Utc,
+    Local,
+    // A fixed offset from UTC, in minutes.
+    Offset(i32),
+}
+
+impl FromStr for DisplayTimezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "utc" | "z" => return Ok(DisplayTimezone::Utc),
+            "local" => return Ok(DisplayTimezone::Local),
+            _ => {}
+        }
+        let error = || {
+            format!(
+                "unknown timezone \"{}\" (expected local, utc, or an offset like +05:30)",
+                s
+            )
+        };
+        let sign = match s.chars().next() {
+            Some('+') => 1,
+            Some('-') => -1,
+            _ => return Err(error()),
+        };
+        let (hours, minutes) = s[1..].split_once(':').unwrap_or((&s[1..], "0"));
+        let hours: i32 = hours.parse().map_err(|_| error())?;
+        let minutes: i32 = minutes.parse().map_err(|_| error())?;
+        if hours > 14 || minutes >= 60 {
+            return Err(error());
+        }
+        Ok(DisplayTimezone::Offset(sign * (hours * 60 + minutes)))
+    }
+}
+
+impl DisplayTimezone {
+    // The SQLite date and time function modifier that converts a stored UTC timestamp to this
+    // timezone.
+    pub fn sql_modifier(&self) -> String {
+        match self {
+            DisplayTimezone::Utc => "+0 minutes".to_string(),
+            DisplayTimezone::Local => "localtime".to_string(),
+            DisplayTimezone::Offset(minutes) => format!("{:+} minutes", minutes),
+        }
+    }
+
+    // Formats a UTC timestamp in this timezone.
+    pub fn format(&self, utc: &NaiveDateTime, format: &str) -> String {
+        match self {
+            DisplayTimezone::Utc => utc.format(format).to_string(),
+            DisplayTimezone::Local => chrono::Local
+                .from_utc_datetime(utc)
+                .format(format)
+                .to_string(),
+            DisplayTimezone::Offset(minutes) => FixedOffset::east_opt(minutes * 60)
+                .expect("offset is validated when parsed")
+                .from_utc_datetime(utc)
+                .format(format)
+                .to_string(),
+        }
+    }
+
+    // Converts a UTC timestamp to this timezone's wall-clock time.
+    pub fn local(&self, utc: &NaiveDateTime) -> NaiveDateTime {
+        match self {
+            DisplayTimezone::Utc => *utc,
+            DisplayTimezone::Local => chrono::Local.from_utc_datetime(utc).naive_local(),
+            DisplayTimezone::Offset(minutes) => *utc + chrono::Duration::minutes(*minutes as i64),
+        }
+    }
+
+    // Formats a timestamp as stored in the archive, or returns it unchanged if it can't be
+    // parsed.
+    pub fn format_stored(&self, stored: &str, format: &str) -> String {
+        match NaiveDateTime::parse_from_str(stored, "%Y-%m-%d %H:%M:%S%.f") {
+            Ok(utc) => self.format(&utc, format),
+            Err(_) => stored.to_string(),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use openssl::ssl::{SslConnector, SslMethod, SslStream};
+
+// A connection to a server, over TLS or not, for the protocols we speak ourselves (SMTP, MQTT).
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(Box<SslStream<TcpStream>>),
+}
+
+impl Stream {
+    // Connects to `host`, with TLS from the start if `tls` is set. Reads and writes time out
+    // after `timeout`.
+    pub fn connect(
+        host: &str,
+        port: u16,
+        tls: bool,
+        timeout: Duration,
+    ) -> Result<Stream, Box<dyn std::error::Error>> {
+        let tcp = TcpStream::connect((host, port))
+            .map_err(|err| format!("couldn't connect to {}:{}: {}", host, port, err))?;
+        tcp.set_read_timeout(Some(timeout))?;
+        tcp.set_write_timeout(Some(timeout))?;
+        let stream = Stream::Plain(tcp);
+        if tls {
+            stream.start_tls(host)
+        } else {
+            Ok(stream)
+        }
+    }
+
+    // Switches a plain connection to TLS, verifying the server's certificate for `host`.
+    pub fn start_tls(self, host: &str) -> Result<Stream, Box<dyn std::error::Error>> {
+        match self {
+            Stream::Plain(tcp) => {
+                let connector = SslConnector::builder(SslMethod::tls())?.build();
+                Ok(Stream::Tls(Box::new(connector.connect(host, tcp)?)))
+            }
+            tls => Ok(tls),
+        }
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        match self {
+            Stream::Plain(stream) => stream.local_addr(),
+            Stream::Tls(stream) => stream.get_ref().local_addr(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
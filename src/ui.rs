@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+use serde_json::Value;
+
+use crate::api::{self, EpisodeFilter, FeedFilter, Page};
+use crate::chart::{self, escape};
+use crate::http::{Request, Response};
+use crate::report::{self, format_seconds};
+use crate::timezone::DisplayTimezone;
+
+// The page layout; `{title}`, `{query}` (the search box's value) and `{body}` are filled in.
+const PAGE: &str = include_str!("templates/page.html");
+const EPISODES_PER_PAGE: i64 = 100;
+
+fn page(title: &str, query: &str, body: &str) -> Response {
+    // The body goes last, so text in it that happens to look like a placeholder is left alone.
+    let html = PAGE
+        .replace("{title}", &escape(title))
+        .replace("{query}", &escape(query))
+        .replace("{body}", body);
+    Response::new(200, "text/html; charset=utf-8", html)
+}
+
+fn not_found() -> Response {
+    let mut response = page("Not found", "", "<h1>Not found</h1>");
+    response.status = 404;
+    response
+}
+
+fn text(value: &Value) -> String {
+    escape(value.as_str().unwrap_or_default())
+}
+
+// A badge for how far along an episode is: played, a percentage (or time) into it, or unplayed.
+fn badge(episode: &Value) -> String {
+    let progress = episode["progress"].as_i64().unwrap_or(0);
+    if episode["played"].as_bool().unwrap_or(false) {
+        "<span class=\"badge played\">Played</span>".to_string()
+    } else if progress > 0 {
+        let label = match episode["durationSeconds"].as_i64() {
+            Some(duration) if duration > 0 => {
+                format!("{}%", (progress * 100 / duration).min(99))
+            }
+            _ => format_seconds(progress),
+        };
+        format!("<span class=\"badge progress\">{}</span>", label)
+    } else {
+        "<span class=\"badge unplayed\">Unplayed</span>".to_string()
+    }
+}
+
+// A table of episodes, with their feed's title when `feed_titles` is given.
+fn episode_table(
+    episodes: &[Value],
+    feed_titles: Option<&dyn Fn(i64) -> Option<String>>,
+    tz: &DisplayTimezone,
+) -> String {
+    let mut html = String::from("<table><tr><th>Episode</th>");
+    if feed_titles.is_some() {
+        html.push_str("<th>Show</th>");
+    }
+    html.push_str("<th>Published</th><th class=\"num\">Length</th><th></th></tr>\n");
+    for episode in episodes {
+        let title = text(&episode["title"]);
+        let title = match episode["htmlUrl"]
+            .as_str()
+            .or_else(|| episode["overcastUrl"].as_str())
+        {
+            Some(url) => format!("<a href=\"{}\">{}</a>", escape(url), title),
+            None => title,
+        };
+        html.push_str(&format!("<tr><td>{}</td>", title));
+        if let Some(feed_titles) = feed_titles {
+            let feed_id = episode["feedId"].as_i64().unwrap_or_default();
+            html.push_str(&format!(
+                "<td><a href=\"/feeds/{}\">{}</a></td>",
+                feed_id,
+                escape(&feed_titles(feed_id).unwrap_or_default())
+            ));
+        }
+        html.push_str(&format!(
+            "<td class=\"muted\">{}</td><td class=\"num\">{}</td><td>{}</td></tr>\n",
+            episode["publishedAt"]
+                .as_str()
+                .map(|published| escape(&tz.format_stored(published, "%Y-%m-%d")))
+                .unwrap_or_default(),
+            episode["durationSeconds"]
+                .as_i64()
+                .map(format_seconds)
+                .unwrap_or_default(),
+            badge(episode),
+        ));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+// Links to the previous and next pages of a listing, keeping the other query parameters.
+fn pager(base: &str, listing: &api::Listing, page: &Page) -> String {
+    let separator = if base.contains('?') { '&' } else { '?' };
+    let mut links = Vec::new();
+    if page.offset > 0 {
+        links.push(format!(
+            "<a href=\"{}{}offset={}\">← Newer</a>",
+            escape(base),
+            separator,
+            (page.offset - page.limit).max(0)
+        ));
+    }
+    if listing.has_next_page(page) {
+        links.push(format!(
+            "<a href=\"{}{}offset={}\">Older →</a>",
+            escape(base),
+            separator,
+            page.offset + page.limit
+        ));
+    }
+    if links.is_empty() {
+        String::new()
+    } else {
+        format!("<p>{}</p>\n", links.join(" · "))
+    }
+}
+
+fn overview(
+    conn: &Connection,
+    tz: &DisplayTimezone,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let stats = api::stats(conn)?;
+    let mut body = String::from("<div class=\"stats\">");
+    for (key, label) in &[
+        ("subscribedFeeds", "subscribed shows"),
+        ("episodes", "episodes"),
+        ("playedEpisodes", "played"),
+        ("starredEpisodes", "starred"),
+        ("hoursListened", "hours listened"),
+    ] {
+        body.push_str(&format!(
+            "<div><strong>{}</strong>{}</div>",
+            stats[*key], label
+        ));
+    }
+    body.push_str("</div>\n");
+
+    let months = report::monthly_listening(conn, tz)?;
+    if !months.is_empty() {
+        body.push_str("<h2>Listening per month</h2>\n");
+        body.push_str(&chart::bar_chart(&months, |hours| format!("{:.1}h", hours)));
+        body.push('\n');
+    }
+    let mut stmt = conn.prepare(
+        "SELECT f.title, SUM(l.seconds) / 3600.0 AS hours
+        FROM listening_time l
+        JOIN feeds f ON f.id = l.feed_id
+        GROUP BY f.id
+        ORDER BY hours DESC
+        LIMIT 15",
+    )?;
+    let shows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                row.get(1)?,
+            ))
+        })?
+        .collect::<Result<Vec<(String, f64)>, _>>()?;
+    if !shows.is_empty() {
+        body.push_str("<h2>Most listened shows</h2>\n");
+        body.push_str(&chart::bar_chart(&shows, |hours| format!("{:.1}h", hours)));
+        body.push('\n');
+    }
+
+    let feeds = api::feeds(
+        conn,
+        &FeedFilter {
+            subscribed: None,
+            search: None,
+        },
+        &Page::new(Some(api::MAX_LIMIT), None),
+    )?;
+    body.push_str(
+        "<h2>Shows</h2>\n<table><tr><th>Show</th><th class=\"num\">Episodes</th>\
+         <th class=\"num\">Unplayed</th></tr>\n",
+    );
+    // Subscribed shows first; the rest are only kept for their history.
+    let (subscribed, unsubscribed): (Vec<_>, Vec<_>) = feeds
+        .items
+        .iter()
+        .partition(|feed| feed["subscribed"].as_bool().unwrap_or(false));
+    for feed in subscribed.iter().chain(unsubscribed.iter()) {
+        let class = if feed["subscribed"].as_bool().unwrap_or(false) {
+            ""
+        } else {
+            " class=\"muted\""
+        };
+        body.push_str(&format!(
+            "<tr{}><td><a href=\"/feeds/{}\">{}</a></td><td class=\"num\">{}</td>\
+             <td class=\"num\">{}</td></tr>\n",
+            class,
+            feed["id"],
+            text(&feed["title"]),
+            feed["episodeCount"],
+            feed["unplayedCount"]
+        ));
+    }
+    body.push_str("</table>\n");
+    Ok(page("Overview", "", &body))
+}
+
+fn feed_page(
+    conn: &Connection,
+    tz: &DisplayTimezone,
+    id: i64,
+    offset: Option<i64>,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let feed = match api::feed(conn, id)? {
+        Some(feed) => feed,
+        None => return Ok(not_found()),
+    };
+    let title = feed["title"].as_str().unwrap_or_default();
+    let mut body = format!("<h1>{}</h1>\n<p class=\"muted\">", escape(title));
+    let mut details = vec![format!(
+        "{} episodes, {} unplayed",
+        feed["episodeCount"], feed["unplayedCount"]
+    )];
+    if !feed["subscribed"].as_bool().unwrap_or(false) {
+        details.push("not subscribed".to_string());
+    }
+    if let Some(url) = feed["htmlUrl"].as_str() {
+        details.push(format!("<a href=\"{}\">website</a>", escape(url)));
+    }
+    body.push_str(&details.join(" · "));
+    body.push_str("</p>\n");
+
+    let page_of = Page::new(Some(EPISODES_PER_PAGE), offset);
+    let filter = EpisodeFilter {
+        feed_id: Some(id),
+        played: None,
+        starred: None,
+        search: None,
+        published_after: None,
+        published_before: None,
+    };
+    let episodes = api::episodes(conn, &filter, &page_of)?;
+    body.push_str(&episode_table(&episodes.items, None, tz));
+    body.push_str(&pager(&format!("/feeds/{}", id), &episodes, &page_of));
+    Ok(page(title, "", &body))
+}
+
+fn search_page(
+    conn: &Connection,
+    tz: &DisplayTimezone,
+    query: &str,
+    offset: Option<i64>,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    if query.trim().is_empty() {
+        return Ok(page(
+            "Search",
+            "",
+            "<h1>Search</h1>\n<p>Search episode titles and show notes with the box above.</p>",
+        ));
+    }
+    let page_of = Page::new(Some(EPISODES_PER_PAGE), offset);
+    let filter = EpisodeFilter {
+        feed_id: None,
+        played: None,
+        starred: None,
+        search: Some(query.trim().to_string()),
+        published_after: None,
+        published_before: None,
+    };
+    let episodes = api::episodes(conn, &filter, &page_of)?;
+    let mut body = format!(
+        "<h1>Search</h1>\n<p class=\"muted\">Episodes matching “{}”: {}</p>\n",
+        escape(query),
+        episodes.total
+    );
+    let mut stmt = conn.prepare("SELECT id, title FROM feeds")?;
+    let titles = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<HashMap<i64, Option<String>>, _>>()?;
+    body.push_str(&episode_table(
+        &episodes.items,
+        Some(&|id| titles.get(&id).cloned().flatten()),
+        tz,
+    ));
+    let base = reqwest::Url::parse_with_params("http://localhost/search", &[("q", query)])?;
+    body.push_str(&pager(
+        &format!("/search?{}", base.query().unwrap_or_default()),
+        &episodes,
+        &page_of,
+    ));
+    Ok(page("Search", query, &body))
+}
+
+// Answers the web UI's pages: an overview with stats, charts and the list of shows at /, a show's
+// episodes at /feeds/{id}, and episode search at /search?q=.
+pub fn handle(conn: &Connection, tz: &DisplayTimezone, request: &Request) -> Response {
+    if request.method != "GET" {
+        return Response::error(405, "the UI is read-only");
+    }
+    let offset = request
+        .param("offset")
+        .and_then(|offset| offset.parse().ok());
+    let path: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let result = match path[..] {
+        [""] => overview(conn, tz),
+        ["feeds", id] => match id.parse() {
+            Ok(id) => feed_page(conn, tz, id, offset),
+            Err(_) => Ok(not_found()),
+        },
+        ["search"] => search_page(conn, tz, request.param("q").unwrap_or_default(), offset),
+        _ => Ok(not_found()),
+    };
+    result.unwrap_or_else(|err| {
+        let mut response = page(
+            "Error",
+            "",
+            &format!(
+                "<h1>Something went wrong</h1>\n<p>{}</p>",
+                escape(&err.to_string())
+            ),
+        );
+        response.status = 500;
+        response
+    })
+}
@@ -0,0 +1,179 @@
+use reqwest::Url;
+
+// Analytics services that wrap enclosure URLs in a redirect: (host, path prefix, number of path
+// segments after the prefix that identify the account). The rest of the path is the wrapped URL,
+// usually without its scheme.
+const TRACKING_PREFIXES: [(&str, &str, usize); 18] = [
+    ("dts.podtrac.com", "/redirect.mp3/", 0),
+    ("dts.podtrac.com", "/redirect.m4a/", 0),
+    ("www.podtrac.com", "/pts/redirect.mp3/", 0),
+    ("www.podtrac.com", "/pts/redirect.m4a/", 0),
+    ("podtrac.com", "/pts/redirect.mp3/", 0),
+    ("chtbl.com", "/track/", 1),
+    ("chrt.fm", "/track/", 1),
+    ("pdst.fm", "/e/", 0),
+    ("op3.dev", "/e/", 0),
+    // OP3 URLs can carry arguments, like "/e,pg=<guid>/".
+    ("op3.dev", "/e,", 1),
+    ("prfx.byspotify.com", "/e/", 0),
+    ("pfx.vpixl.com", "/", 1),
+    ("arttrk.com", "/p/", 1),
+    ("mgln.ai", "/e/", 1),
+    ("verifi.podscribe.com", "/rss/p/", 0),
+    ("pscrb.fm", "/rss/p/", 0),
+    ("claritaspod.com", "/measure/", 0),
+    ("tracking.swap.fm", "/track/", 1),
+];
+
+// Query parameters that vary between exports without changing what the URL points to.
+fn is_tracking_param(name: &str) -> bool {
+    name.starts_with("utm_")
+        // Adswizz (e.g. awCollectionId, awEpisodeId).
+        || name
+            .strip_prefix("aw")
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_uppercase()))
+        || ["updated", "aid", "fbclid", "gclid", "_"].contains(&name)
+}
+
+// Normalizes an episode URL: tracking redirects are unwrapped (repeatedly, since they're often
+// chained), tracking query parameters and the fragment are dropped, and the host is lowercased.
+// URLs that don't parse are returned trimmed but otherwise as is.
+pub fn normalize(url: &str) -> String {
+    let mut url = url.trim().to_string();
+    loop {
+        let parsed = match Url::parse(&url) {
+            Ok(parsed) => parsed,
+            Err(_) => return url,
+        };
+        match unwrap_redirect(&parsed) {
+            Some(wrapped) => url = wrapped,
+            None => return strip_query(parsed).to_string(),
+        }
+    }
+}
+
+// Returns the URL a tracking redirect wraps, or None if it isn't a known redirect.
+fn unwrap_redirect(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    // Path segments keep their percent-encoding, so the wrapped URL can be reassembled as is.
+    let path = url.path();
+    for (tracker, prefix, skip) in TRACKING_PREFIXES.iter() {
+        if host != *tracker {
+            continue;
+        }
+        let rest = match path.strip_prefix(prefix) {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let mut rest = rest;
+        for _ in 0..*skip {
+            rest = rest.split_once('/')?.1;
+        }
+        let mut wrapped = if rest.starts_with("http:/") || rest.starts_with("https:/") {
+            // Some servers collapse the double slash.
+            let (scheme, rest) = rest.split_once(':')?;
+            format!("{}://{}", scheme, rest.trim_start_matches('/'))
+        } else {
+            format!("{}://{}", url.scheme(), rest)
+        };
+        if let Some(query) = url.query() {
+            wrapped.push('?');
+            wrapped.push_str(query);
+        }
+        return Some(wrapped);
+    }
+    None
+}
+
+fn strip_query(mut url: Url) -> Url {
+    url.set_fragment(None);
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| !is_tracking_param(name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        url.set_query(None);
+    } else if url.query_pairs().count() != kept.len() {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    url
+}
+
+// Reduces an enclosure URL to a key that's the same for every copy of an episode's audio: the
+// normalized URL, without its scheme or a leading "www.". Cross-posted episodes (the same audio in
+// two feeds) share a key.
+pub fn enclosure_key(url: &str) -> String {
+    let url = normalize(url);
+    match Url::parse(&url) {
+        Ok(parsed) => {
+            let host = parsed.host_str().unwrap_or_default();
+            let mut key = host.strip_prefix("www.").unwrap_or(host).to_string();
+            key.push_str(parsed.path());
+            if let Some(query) = parsed.query() {
+                key.push('?');
+                key.push_str(query);
+            }
+            key
+        }
+        Err(_) => url,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracking_redirects_are_unwrapped() {
+        assert_eq!(
+            normalize("https://dts.podtrac.com/redirect.mp3/example.com/ep.mp3"),
+            "https://example.com/ep.mp3"
+        );
+        // Chained, with an account segment and a collapsed double slash.
+        assert_eq!(
+            normalize("https://chtbl.com/track/ABC123/pdst.fm/e/https:/cdn.example.com/ep.mp3"),
+            "https://cdn.example.com/ep.mp3"
+        );
+        assert_eq!(
+            normalize("https://op3.dev/e,pg=1234/example.com/ep.mp3"),
+            "https://example.com/ep.mp3"
+        );
+        // Not the redirect's path, so left alone.
+        assert_eq!(
+            normalize("https://dts.podtrac.com/other/example.com/ep.mp3"),
+            "https://dts.podtrac.com/other/example.com/ep.mp3"
+        );
+    }
+
+    #[test]
+    fn tracking_params_and_fragments_are_dropped() {
+        assert_eq!(
+            normalize(" https://Example.COM/ep.mp3?utm_source=rss&awEpisodeId=1&key=v#t=10 "),
+            "https://example.com/ep.mp3?key=v"
+        );
+        assert_eq!(
+            normalize("https://example.com/ep.mp3?updated=123"),
+            "https://example.com/ep.mp3"
+        );
+        // "away" isn't an Adswizz parameter.
+        assert_eq!(
+            normalize("https://example.com/ep.mp3?away=1"),
+            "https://example.com/ep.mp3?away=1"
+        );
+        assert_eq!(normalize(" not a url "), "not a url");
+    }
+
+    #[test]
+    fn enclosure_keys() {
+        assert_eq!(
+            enclosure_key("http://www.example.com/ep.mp3?utm_medium=x"),
+            "example.com/ep.mp3"
+        );
+        assert_eq!(
+            enclosure_key("https://dts.podtrac.com/redirect.mp3/example.com/ep.mp3?id=2"),
+            "example.com/ep.mp3?id=2"
+        );
+        assert_eq!(enclosure_key("ep.mp3"), "ep.mp3");
+    }
+}
@@ -0,0 +1,173 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+// Writes WARC 1.1 files, as read by replay tools like pywb. Each HTTP response is one
+// uncompressed `response` record.
+pub struct WarcWriter {
+    file: std::fs::File,
+    path: PathBuf,
+    // Where the next record starts.
+    offset: u64,
+}
+
+// Where a record was written.
+pub struct WarcRecord {
+    pub offset: u64,
+    pub length: u64,
+    pub record_id: String,
+}
+
+impl WarcWriter {
+    // Creates a new WARC file in `dir`, named after the current time, starting with a `warcinfo`
+    // record.
+    pub fn create(dir: &Path) -> Result<WarcWriter, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(dir)?;
+        let name = format!(
+            "overcast-to-sqlite-{}.warc",
+            chrono::Utc::now().format("%Y%m%d%H%M%S")
+        );
+        let path = dir.join(name);
+        let mut writer = WarcWriter {
+            file: std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)?,
+            path,
+            offset: 0,
+        };
+        let info = format!(
+            "software: overcast-to-sqlite/{}\r\nformat: WARC File Format 1.1\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        writer.write_record(
+            &[
+                ("WARC-Type", "warcinfo".to_string()),
+                (
+                    "WARC-Filename",
+                    writer.file_name().to_string_lossy().into_owned(),
+                ),
+                ("Content-Type", "application/warc-fields".to_string()),
+            ],
+            info.as_bytes(),
+        )?;
+        Ok(writer)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn file_name(&self) -> &std::ffi::OsStr {
+        self.path.file_name().unwrap_or_default()
+    }
+
+    // Writes a `response` record for `url`: the HTTP status line and headers in `head`, followed
+    // by the body.
+    pub fn write_response(
+        &mut self,
+        url: &str,
+        head: &[u8],
+        body: &[u8],
+    ) -> Result<WarcRecord, Box<dyn std::error::Error>> {
+        let block = [head, body].concat();
+        self.write_record(
+            &[
+                ("WARC-Type", "response".to_string()),
+                ("WARC-Target-URI", url.to_string()),
+                (
+                    "Content-Type",
+                    "application/http;msgtype=response".to_string(),
+                ),
+                ("WARC-Payload-Digest", sha1_digest(body)),
+            ],
+            &block,
+        )
+    }
+
+    fn write_record(
+        &mut self,
+        fields: &[(&str, String)],
+        block: &[u8],
+    ) -> Result<WarcRecord, Box<dyn std::error::Error>> {
+        let record_id = format!("<urn:uuid:{}>", uuid()?);
+        let mut header = format!(
+            "WARC/1.1\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+            record_id,
+            chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+        );
+        for (name, value) in fields {
+            header.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        header.push_str(&format!(
+            "WARC-Block-Digest: {}\r\nContent-Length: {}\r\n\r\n",
+            sha1_digest(block),
+            block.len()
+        ));
+        let record = [header.as_bytes(), block, b"\r\n\r\n"].concat();
+        self.file.write_all(&record)?;
+        let written = WarcRecord {
+            offset: self.offset,
+            length: record.len() as u64,
+            record_id,
+        };
+        self.offset += record.len() as u64;
+        Ok(written)
+    }
+}
+
+// The status line and headers of a response, as they'd have come over the wire. The body has
+// been de-chunked by the time it's read, so `Transfer-Encoding` is left out for it to replay.
+pub fn http_head(resp: &reqwest::blocking::Response) -> Vec<u8> {
+    let status = resp.status();
+    let mut head = format!(
+        "{:?} {} {}\r\n",
+        resp.version(),
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    )
+    .into_bytes();
+    for (name, value) in resp.headers() {
+        if name == reqwest::header::TRANSFER_ENCODING {
+            continue;
+        }
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+    head
+}
+
+// A random (version 4) UUID.
+fn uuid() -> Result<String, Box<dyn std::error::Error>> {
+    let mut bytes = [0u8; 16];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = crate::storage::hex(&bytes);
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+// A WARC digest: the SHA-1 of some bytes, in base 32.
+fn sha1_digest(bytes: &[u8]) -> String {
+    let digest = openssl::sha::sha1(bytes);
+    let mut out = String::from("sha1:");
+    // 20 bytes is exactly 32 five-bit groups, so there's no padding.
+    for chunk in digest.chunks(5) {
+        let n = chunk.iter().fold(0u64, |n, b| (n << 8) | *b as u64);
+        for i in (0..8).rev() {
+            out.push(BASE32_ALPHABET[((n >> (i * 5)) & 31) as usize] as char);
+        }
+    }
+    out
+}
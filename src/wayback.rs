@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+
+use crate::client;
+
+// Save Page Now allows only a few anonymous captures a minute, so each run submits a batch and
+// later runs work through the rest.
+const BATCH_SIZE: usize = 50;
+const ANONYMOUS_INTERVAL: Duration = Duration::from_secs(15);
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const POLL_ATTEMPTS: usize = 24;
+
+// A client for the Internet Archive's Save Page Now API. With an archive.org S3-style access key,
+// it uses the authenticated API (which has much higher limits); otherwise it makes anonymous
+// capture requests.
+pub struct WaybackClient {
+    client: reqwest::blocking::Client,
+    keys: Option<(String, String)>,
+}
+
+#[derive(Deserialize)]
+struct SaveResponse {
+    job_id: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StatusResponse {
+    status: String,
+    timestamp: Option<String>,
+    original_url: Option<String>,
+    message: Option<String>,
+}
+
+// Counts of pages submitted by a run.
+#[derive(Default)]
+pub struct WaybackCounts {
+    pub saved: usize,
+    pub failed: usize,
+    pub remaining: usize,
+}
+
+impl WaybackClient {
+    pub fn new(keys: Option<(String, String)>) -> WaybackClient {
+        WaybackClient {
+            client: client::builder()
+                .timeout(Duration::from_secs(120))
+                .build()
+                .unwrap(),
+            keys,
+        }
+    }
+
+    // Captures a page, returning the snapshot's URL.
+    pub fn save(&self, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let (access, secret) = match &self.keys {
+            Some(keys) => keys,
+            None => return self.save_anonymously(url),
+        };
+        let authorization = format!("LOW {}:{}", access, secret);
+        let resp: SaveResponse = self
+            .client
+            .post("https://web.archive.org/save")
+            .header(reqwest::header::ACCEPT, "application/json")
+            .header(reqwest::header::AUTHORIZATION, &authorization)
+            .form(&[("url", url)])
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let job_id = match resp.job_id {
+            Some(job_id) => job_id,
+            None => {
+                return Err(resp
+                    .message
+                    .unwrap_or_else(|| "no job ID".to_string())
+                    .into())
+            }
+        };
+        for _ in 0..POLL_ATTEMPTS {
+            std::thread::sleep(POLL_INTERVAL);
+            let status: StatusResponse = self
+                .client
+                .get(format!("https://web.archive.org/save/status/{}", job_id))
+                .header(reqwest::header::AUTHORIZATION, &authorization)
+                .send()?
+                .error_for_status()?
+                .json()?;
+            match status.status.as_str() {
+                "pending" => continue,
+                "success" => {
+                    return Ok(format!(
+                        "https://web.archive.org/web/{}/{}",
+                        status.timestamp.ok_or("no snapshot timestamp")?,
+                        status.original_url.as_deref().unwrap_or(url)
+                    ))
+                }
+                other => {
+                    let other = format!("capture {}", other);
+                    return Err(status.message.unwrap_or(other).into());
+                }
+            }
+        }
+        Err("capture is still pending; it'll be retried next run".into())
+    }
+
+    fn save_anonymously(&self, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let resp = self
+            .client
+            .get(format!("https://web.archive.org/save/{}", url))
+            .send()?
+            .error_for_status()?;
+        // The snapshot's path is in Content-Location, or in the URL redirects led to.
+        let location = resp
+            .headers()
+            .get(reqwest::header::CONTENT_LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .map(|path| format!("https://web.archive.org{}", path));
+        match location {
+            Some(location) => Ok(location),
+            None if resp.url().path().starts_with("/web/") => Ok(resp.url().to_string()),
+            None => Err("no snapshot URL in the response".into()),
+        }
+    }
+}
+
+// Submits a batch of episode pages that don't have a snapshot yet, recording the results in
+// `wayback_snapshots`. Pages that aren't archived locally (in `episode_pages`) go first, then
+// the most recent episodes.
+pub fn submit(
+    conn: &Connection,
+    client: &WaybackClient,
+) -> Result<WaybackCounts, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT e.html_url FROM episodes e
+        LEFT JOIN episode_pages p ON p.episode_id = e.id AND p.html IS NOT NULL
+        WHERE e.html_url IS NOT NULL
+            AND e.html_url NOT IN (
+                SELECT url FROM wayback_snapshots WHERE snapshot_url IS NOT NULL
+            )
+        GROUP BY e.html_url
+        ORDER BY MAX(p.episode_id IS NOT NULL), MAX(e.published_at) DESC",
+    )?;
+    let urls = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut counts = WaybackCounts {
+        remaining: urls.len().saturating_sub(BATCH_SIZE),
+        ..Default::default()
+    };
+    for (i, url) in urls.iter().take(BATCH_SIZE).enumerate() {
+        if i > 0 && client.keys.is_none() {
+            std::thread::sleep(ANONYMOUS_INTERVAL);
+        }
+        let (snapshot_url, error) = match client.save(url) {
+            Ok(snapshot_url) => {
+                counts.saved += 1;
+                (Some(snapshot_url), None)
+            }
+            Err(err) => {
+                eprintln!("Couldn't save {} to the Wayback Machine: {}", url, err);
+                counts.failed += 1;
+                (None, Some(err.to_string()))
+            }
+        };
+        conn.execute(
+            "INSERT OR REPLACE INTO wayback_snapshots(url, snapshot_url, submitted_at, error)
+            VALUES (?, ?, ?, ?)",
+            params![url, snapshot_url, chrono::Utc::now().naive_utc(), error],
+        )?;
+    }
+    Ok(counts)
+}
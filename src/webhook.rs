@@ -0,0 +1,101 @@
+use serde_json::json;
+
+use crate::client;
+use crate::runs::NewEpisode;
+
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+// Longer lists are cut short, to keep the message readable.
+const MAX_EPISODES: usize = 25;
+// Discord rejects messages over 2,000 characters.
+const DISCORD_MAX_LENGTH: usize = 2000;
+
+// The chat service a webhook URL posts to. Anything that isn't Discord's is taken to be Slack's
+// format, which Mattermost and Rocket.Chat also accept.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Service {
+    Slack,
+    Discord,
+}
+
+impl Service {
+    pub fn of(url: &str) -> Service {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_default();
+        if host == "discord.com" || host.ends_with(".discord.com") || host == "discordapp.com" {
+            Service::Discord
+        } else {
+            Service::Slack
+        }
+    }
+}
+
+// Slack's mrkdwn only needs &, < and > escaped; the rest would show up literally.
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// The line for one episode: its feed, then its title, linked if it has a URL.
+fn line(service: Service, episode: &NewEpisode) -> String {
+    let feed = episode.feed_title.as_deref().unwrap_or("Untitled feed");
+    let title = episode.title.as_deref().unwrap_or("Untitled episode");
+    match (service, &episode.url) {
+        (Service::Slack, Some(url)) => format!(
+            "• *{}*: <{}|{}>",
+            slack_escape(feed),
+            url,
+            slack_escape(title)
+        ),
+        (Service::Slack, None) => format!("• *{}*: {}", slack_escape(feed), slack_escape(title)),
+        // The angle brackets keep Discord from adding a preview for every link.
+        (Service::Discord, Some(url)) => format!("• **{}**: [{}](<{}>)", feed, title, url),
+        (Service::Discord, None) => format!("• **{}**: {}", feed, title),
+    }
+}
+
+// The message announcing `episodes`, as the service's webhook payload.
+pub fn message(service: Service, episodes: &[&NewEpisode]) -> serde_json::Value {
+    let heading = match episodes.len() {
+        1 => "1 new episode".to_string(),
+        n => format!("{} new episodes", n),
+    };
+    let mut text = match service {
+        Service::Slack => format!("*{}*", heading),
+        Service::Discord => format!("**{}**", heading),
+    };
+    let mut listed = 0;
+    for episode in episodes.iter().take(MAX_EPISODES) {
+        let line = line(service, episode);
+        // Room for the line and an "and N more" after it.
+        if service == Service::Discord
+            && text.chars().count() + line.chars().count() + 20 > DISCORD_MAX_LENGTH
+        {
+            break;
+        }
+        text.push('\n');
+        text.push_str(&line);
+        listed += 1;
+    }
+    if listed < episodes.len() {
+        text.push_str(&format!("\n…and {} more", episodes.len() - listed));
+    }
+    match service {
+        Service::Slack => json!({ "text": text }),
+        Service::Discord => json!({ "content": text }),
+    }
+}
+
+// Posts a message announcing `episodes` to the Slack or Discord webhook at `url`.
+pub fn notify(url: &str, episodes: &[&NewEpisode]) -> Result<(), Box<dyn std::error::Error>> {
+    client::builder()
+        .timeout(TIMEOUT)
+        .build()?
+        .post(url)
+        .json(&message(Service::of(url), episodes))
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use std::str::FromStr;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::chart::escape;
+use crate::report::{format_seconds, STYLE};
+use crate::timezone::DisplayTimezone;
+
+// The formats `report wrapped` can write.
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            _ => Err(format!(
+                "unknown format \"{}\" (expected markdown or html)",
+                s
+            )),
+        }
+    }
+}
+
+// A year of listening, summed up.
+pub struct Wrapped {
+    pub year: i32,
+    pub seconds: i64,
+    pub episodes_played: i64,
+    pub top_shows: Vec<TopShow>,
+    pub longest_episode: Option<LongestEpisode>,
+    // The month (YYYY-MM) with the most listening, and how much.
+    pub busiest_month: Option<(String, i64)>,
+    pub new_subscriptions: Vec<String>,
+}
+
+pub struct TopShow {
+    pub title: String,
+    pub seconds: i64,
+    pub episodes_played: i64,
+}
+
+pub struct LongestEpisode {
+    pub title: String,
+    pub feed_title: String,
+    pub seconds: i64,
+}
+
+// Sums up the listening in `year`, as counted in `listening_time`. Listening is dated by each
+// episode's last update, in the given timezone, and new subscriptions by when they were added to
+// Overcast.
+pub fn wrapped(
+    conn: &Connection,
+    year: i32,
+    tz: &DisplayTimezone,
+) -> Result<Wrapped, Box<dyn std::error::Error>> {
+    let in_year = format!(
+        "l.listened_at IS NOT NULL AND CAST(strftime('%Y', l.listened_at, '{}') AS INTEGER) = ?",
+        tz.sql_modifier()
+    );
+    let (seconds, episodes_played): (i64, i64) = conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(l.seconds), 0), COALESCE(SUM(e.played), 0)
+            FROM listening_time l
+            JOIN episodes e ON e.id = l.episode_id
+            WHERE {}",
+            in_year
+        ),
+        params![year],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT f.title, SUM(l.seconds) AS seconds, SUM(e.played)
+        FROM listening_time l
+        JOIN episodes e ON e.id = l.episode_id
+        JOIN feeds f ON f.id = l.feed_id
+        WHERE {}
+        GROUP BY f.id
+        ORDER BY seconds DESC
+        LIMIT 5",
+        in_year
+    ))?;
+    let top_shows = stmt
+        .query_map(params![year], |row| {
+            Ok(TopShow {
+                title: row.get(0)?,
+                seconds: row.get(1)?,
+                episodes_played: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let longest_episode = conn
+        .query_row(
+            &format!(
+                "SELECT e.title, f.title, l.seconds
+                FROM listening_time l
+                JOIN episodes e ON e.id = l.episode_id
+                JOIN feeds f ON f.id = l.feed_id
+                WHERE e.played AND l.duration_known AND {}
+                ORDER BY l.seconds DESC
+                LIMIT 1",
+                in_year
+            ),
+            params![year],
+            |row| {
+                Ok(LongestEpisode {
+                    title: row.get(0)?,
+                    feed_title: row.get(1)?,
+                    seconds: row.get(2)?,
+                })
+            },
+        )
+        .optional()?;
+
+    let busiest_month = conn
+        .query_row(
+            &format!(
+                "SELECT strftime('%Y-%m', l.listened_at, '{}') AS month, SUM(l.seconds) AS seconds
+                FROM listening_time l
+                WHERE {}
+                GROUP BY month
+                ORDER BY seconds DESC
+                LIMIT 1",
+                tz.sql_modifier(),
+                in_year
+            ),
+            params![year],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT title FROM feeds
+        WHERE added_at IS NOT NULL AND CAST(strftime('%Y', added_at, '{}') AS INTEGER) = ?
+        ORDER BY added_at",
+        tz.sql_modifier()
+    ))?;
+    let new_subscriptions = stmt
+        .query_map(params![year], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Wrapped {
+        year,
+        seconds,
+        episodes_played,
+        top_shows,
+        longest_episode,
+        busiest_month,
+        new_subscriptions,
+    })
+}
+
+// Renders the summary in the given format.
+pub fn render(wrapped: &Wrapped, format: &Format) -> String {
+    match format {
+        Format::Markdown => render_markdown(wrapped),
+        Format::Html => render_html(wrapped),
+    }
+}
+
+fn render_markdown(wrapped: &Wrapped) -> String {
+    let mut md = format!("# {} in podcasts\n\n", wrapped.year);
+    if wrapped.seconds == 0 {
+        md.push_str("No listening recorded this year.\n");
+        return md;
+    }
+    md.push_str(&format!(
+        "You listened to **{}** of podcasts, and finished **{}** episodes.\n",
+        format_seconds(wrapped.seconds),
+        wrapped.episodes_played
+    ));
+    md.push_str("\n## Top shows\n\n");
+    for (i, show) in wrapped.top_shows.iter().enumerate() {
+        md.push_str(&format!(
+            "{}. {}: {} ({} episodes)\n",
+            i + 1,
+            show.title,
+            format_seconds(show.seconds),
+            show.episodes_played
+        ));
+    }
+    if let Some(episode) = &wrapped.longest_episode {
+        md.push_str(&format!(
+            "\n## Longest episode finished\n\n**{}** from {} ({})\n",
+            episode.title,
+            episode.feed_title,
+            format_seconds(episode.seconds)
+        ));
+    }
+    if let Some((month, seconds)) = &wrapped.busiest_month {
+        md.push_str(&format!(
+            "\n## Busiest month\n\n**{}**, with {} of listening.\n",
+            month_name(month),
+            format_seconds(*seconds)
+        ));
+    }
+    if !wrapped.new_subscriptions.is_empty() {
+        md.push_str(&format!(
+            "\n## New subscriptions\n\nYou subscribed to {} new shows: {}.\n",
+            wrapped.new_subscriptions.len(),
+            wrapped.new_subscriptions.join(", ")
+        ));
+    }
+    md
+}
+
+fn render_html(wrapped: &Wrapped) -> String {
+    let title = format!("{} in podcasts", wrapped.year);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <style>{style}</style></head><body>\n<h1>{title}</h1>\n",
+        title = title,
+        style = STYLE
+    );
+    if wrapped.seconds == 0 {
+        html.push_str("<p>No listening recorded this year.</p>\n</body></html>\n");
+        return html;
+    }
+    html.push_str(&format!(
+        "<p>You listened to <strong>{}</strong> of podcasts, and finished <strong>{}</strong> \
+         episodes.</p>\n",
+        format_seconds(wrapped.seconds),
+        wrapped.episodes_played
+    ));
+    html.push_str("<h2>Top shows</h2>\n<ol>\n");
+    for show in &wrapped.top_shows {
+        html.push_str(&format!(
+            "<li>{}: {} ({} episodes)</li>\n",
+            escape(&show.title),
+            format_seconds(show.seconds),
+            show.episodes_played
+        ));
+    }
+    html.push_str("</ol>\n");
+    if let Some(episode) = &wrapped.longest_episode {
+        html.push_str(&format!(
+            "<h2>Longest episode finished</h2>\n<p><strong>{}</strong> from {} ({})</p>\n",
+            escape(&episode.title),
+            escape(&episode.feed_title),
+            format_seconds(episode.seconds)
+        ));
+    }
+    if let Some((month, seconds)) = &wrapped.busiest_month {
+        html.push_str(&format!(
+            "<h2>Busiest month</h2>\n<p><strong>{}</strong>, with {} of listening.</p>\n",
+            month_name(month),
+            format_seconds(*seconds)
+        ));
+    }
+    if !wrapped.new_subscriptions.is_empty() {
+        let titles: Vec<String> = wrapped
+            .new_subscriptions
+            .iter()
+            .map(|t| escape(t))
+            .collect();
+        html.push_str(&format!(
+            "<h2>New subscriptions</h2>\n<p>You subscribed to {} new shows: {}.</p>\n",
+            titles.len(),
+            titles.join(", ")
+        ));
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+// Turns a YYYY-MM month into its name, like "March".
+fn month_name(month: &str) -> String {
+    chrono::NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+        .map(|d| d.format("%B").to_string())
+        .unwrap_or_else(|_| month.to_string())
+}
@@ -0,0 +1,218 @@
+use std::io::{Read, Write};
+use std::path::Path;
+
+use chrono::{Datelike, Timelike};
+
+// Entries are stored uncompressed, streamed with a data descriptor after each one, so files are
+// read once and never held in memory whole.
+const STORED: u16 = 0;
+const DATA_DESCRIPTOR_FLAG: u16 = 1 << 3;
+// Entry names are UTF-8.
+const UTF8_FLAG: u16 = 1 << 11;
+const VERSION: u16 = 20;
+
+// Writes a zip file. There's no ZIP64 support, so entries and the whole file are limited to 4 GiB.
+pub struct ZipWriter {
+    file: std::fs::File,
+    offset: u64,
+    entries: Vec<Entry>,
+    // The MS-DOS date and time entries are stamped with.
+    dos_time: u16,
+    dos_date: u16,
+}
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+impl ZipWriter {
+    pub fn create(path: &Path) -> Result<ZipWriter, Box<dyn std::error::Error>> {
+        let now = chrono::Local::now();
+        Ok(ZipWriter {
+            file: std::fs::File::create(path)?,
+            offset: 0,
+            entries: Vec::new(),
+            dos_time: ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16,
+            dos_date: (((now.year() - 1980).max(0) as u32) << 9 | now.month() << 5 | now.day())
+                as u16,
+        })
+    }
+
+    pub fn add_bytes(&mut self, name: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.add(name, &mut &data[..])
+    }
+
+    pub fn add_file(&mut self, name: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.add(name, &mut std::fs::File::open(path)?)
+    }
+
+    fn add(&mut self, name: &str, data: &mut dyn Read) -> Result<(), Box<dyn std::error::Error>> {
+        let offset = self.checked_offset()?;
+        let mut header = Vec::new();
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&(DATA_DESCRIPTOR_FLAG | UTF8_FLAG).to_le_bytes());
+        header.extend_from_slice(&STORED.to_le_bytes());
+        header.extend_from_slice(&self.dos_time.to_le_bytes());
+        header.extend_from_slice(&self.dos_date.to_le_bytes());
+        // The CRC and sizes are in the data descriptor.
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        self.write(&header)?;
+
+        let mut crc = Crc32::new();
+        let mut size: u64 = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = data.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            crc.update(&buffer[..read]);
+            size += read as u64;
+            self.write(&buffer[..read])?;
+        }
+        if size > u32::MAX as u64 {
+            return Err(format!("{} is too large for a zip file (over 4 GiB).", name).into());
+        }
+        let crc = crc.finish();
+        let mut descriptor = Vec::new();
+        descriptor.extend_from_slice(&0x08074b50u32.to_le_bytes());
+        descriptor.extend_from_slice(&crc.to_le_bytes());
+        descriptor.extend_from_slice(&(size as u32).to_le_bytes());
+        descriptor.extend_from_slice(&(size as u32).to_le_bytes());
+        self.write(&descriptor)?;
+        self.entries.push(Entry {
+            name: name.to_string(),
+            crc,
+            size: size as u32,
+            offset,
+        });
+        Ok(())
+    }
+
+    // Writes the central directory, completing the file.
+    pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let start = self.checked_offset()?;
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            directory.extend_from_slice(&VERSION.to_le_bytes());
+            directory.extend_from_slice(&VERSION.to_le_bytes());
+            directory.extend_from_slice(&(DATA_DESCRIPTOR_FLAG | UTF8_FLAG).to_le_bytes());
+            directory.extend_from_slice(&STORED.to_le_bytes());
+            directory.extend_from_slice(&self.dos_time.to_le_bytes());
+            directory.extend_from_slice(&self.dos_date.to_le_bytes());
+            directory.extend_from_slice(&entry.crc.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            // Extra field, comment, disk number, internal and external attributes.
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&entry.offset.to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+        if self.entries.len() > u16::MAX as usize {
+            return Err("Too many files for a zip file.".into());
+        }
+        let count = self.entries.len() as u16;
+        let mut end = Vec::new();
+        end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        end.extend_from_slice(&start.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&directory)?;
+        self.checked_offset()?;
+        self.write(&end)?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn checked_offset(&self) -> Result<u32, Box<dyn std::error::Error>> {
+        if self.offset > u32::MAX as u64 {
+            return Err("The zip file is too large (over 4 GiB).".into());
+        }
+        Ok(self.offset as u32)
+    }
+}
+
+// The CRC-32 zip files use (IEEE 802.3, reflected).
+struct Crc32 {
+    table: [u32; 256],
+    crc: u32,
+}
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 {
+                    0xedb88320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+            }
+            *entry = c;
+        }
+        Crc32 {
+            table,
+            crc: 0xffffffff,
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.crc = self.table[((self.crc ^ *b as u32) & 0xff) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.crc ^ 0xffffffff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(bytes);
+        crc.finish()
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414fa339
+        );
+    }
+
+    #[test]
+    fn crc32_in_pieces() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), crc32(b"123456789"));
+    }
+}